@@ -0,0 +1,76 @@
+//! 收到文件后自动打开：只打开扩展名在 `auto_open_extensions` 白名单中的文件，
+//! 可执行文件与脚本无论配置如何都不会被打开。
+
+use std::path::Path;
+
+/// 永不自动打开的扩展名（小写）：各平台的可执行文件、安装包、脚本与快捷方式。
+const EXECUTABLE_EXTENSIONS: &[&str] = &[
+    "app", "appimage", "apk", "bash", "bat", "bin", "cmd", "com", "command", "cpl", "deb",
+    "desktop", "dmg", "elf", "exe", "hta", "jar", "js", "jse", "lnk", "msc", "msi", "msp", "pif",
+    "pkg", "pl", "ps1", "psm1", "py", "pyw", "rb", "reg", "rpm", "run", "scr", "sh", "url", "vbe",
+    "vbs", "ws", "wsf", "wsh", "zsh",
+];
+
+/// 文件扩展名是否属于可执行文件或脚本（不区分大小写）。
+pub fn is_executable(path: &Path) -> bool {
+    extension(path).is_some_and(|ext| EXECUTABLE_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// 文件是否可以自动打开：扩展名在白名单中（不区分大小写，可带前导 `.`）且不是可执行文件。
+pub fn should_auto_open(path: &Path, allowed: &[String]) -> bool {
+    let Some(ext) = extension(path) else {
+        return false;
+    };
+    !is_executable(path)
+        && allowed
+            .iter()
+            .any(|allowed| allowed.trim_start_matches('.').eq_ignore_ascii_case(&ext))
+}
+
+/// 用系统默认程序打开文件，失败只记录警告。
+pub fn open_with_default_handler(path: &Path) {
+    #[cfg(target_os = "windows")]
+    let opener = "explorer";
+    #[cfg(target_os = "macos")]
+    let opener = "open";
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let opener = "xdg-open";
+
+    tracing::info!("opening received file: {}", path.display());
+    if let Err(e) = std::process::Command::new(opener).arg(path).spawn() {
+        tracing::warn!("failed to launch {} for {}: {}", opener, path.display(), e);
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extension_matching_ignores_case_and_leading_dot() {
+        let allowed = vec!["txt".to_string(), ".PDF".to_string()];
+        assert!(should_auto_open(Path::new("notes.txt"), &allowed));
+        assert!(should_auto_open(Path::new("NOTES.TXT"), &allowed));
+        assert!(should_auto_open(Path::new("report.pdf"), &allowed));
+        assert!(!should_auto_open(Path::new("image.png"), &allowed));
+        assert!(!should_auto_open(Path::new("README"), &allowed));
+        assert!(!should_auto_open(Path::new("notes.txt"), &[]));
+    }
+
+    #[test]
+    fn executables_are_never_opened() {
+        let allowed: Vec<String> =
+            ["exe", "sh", "BAT", "txt"].iter().map(|s| s.to_string()).collect();
+        assert!(!should_auto_open(Path::new("setup.exe"), &allowed));
+        assert!(!should_auto_open(Path::new("install.SH"), &allowed));
+        assert!(!should_auto_open(Path::new("run.bat"), &allowed));
+        // 双扩展名按最后一个判断
+        assert!(!should_auto_open(Path::new("notes.txt.exe"), &allowed));
+        assert!(is_executable(Path::new("tool.AppImage")));
+        assert!(!is_executable(Path::new("notes.txt")));
+    }
+}
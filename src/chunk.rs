@@ -0,0 +1,159 @@
+//! 分块传输：按目标块大小切分大负载，并在接收端按偏移重组。
+//!
+//! 块大小在目标值附近自动调整，使各块大小尽量均匀、避免出现极短的末块；
+//! 重组端不假设固定块大小，只依据每块的偏移与长度。
+
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+
+/// 默认目标块大小（256 KiB）
+pub const DEFAULT_CHUNK_SIZE: usize = 256 * 1024;
+/// 允许配置的最小块大小
+pub const MIN_CHUNK_SIZE: usize = 16 * 1024;
+/// 允许配置的最大块大小
+pub const MAX_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+/// 按对端声明的长度预分配缓冲区时的上限，超出部分随数据到达再增长
+pub const MAX_PREALLOC: usize = 1024 * 1024;
+
+/// 根据负载总长度与目标块大小选择实际块大小：块数不变的前提下将负载均匀分配到各块。
+pub fn chunk_size_for(total: usize, target: usize) -> usize {
+    let target = target.clamp(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE);
+    if total <= target {
+        return total.max(1);
+    }
+    let count = total.div_ceil(target);
+    total.div_ceil(count)
+}
+
+/// 将负载切分为 `(偏移, 数据)` 块。
+pub fn split(data: &[u8], target: usize) -> impl Iterator<Item = (usize, &[u8])> {
+    let size = chunk_size_for(data.len(), target);
+    data.chunks(size).enumerate().map(move |(i, c)| (i * size, c))
+}
+
+/// 分块重组缓冲：接受任意大小、任意顺序的块，拒绝越界与重叠的块。
+///
+/// 声明的总长度只用于边界校验，不用于分配：初始容量不超过 [`MAX_PREALLOC`]，
+/// 缓冲区随实际到达的数据增长。
+#[derive(Debug)]
+pub struct Reassembler {
+    buf: Vec<u8>,
+    total: usize,
+    /// 已收到的区间：起始偏移 -> 长度
+    received: BTreeMap<usize, usize>,
+    received_bytes: usize,
+}
+
+impl Reassembler {
+    pub fn new(total: usize) -> Self {
+        Self {
+            buf: Vec::with_capacity(total.min(MAX_PREALLOC)),
+            total,
+            received: BTreeMap::new(),
+            received_bytes: 0,
+        }
+    }
+
+    /// 写入一个块，返回是否已收齐。
+    pub fn add(&mut self, offset: usize, data: &[u8]) -> Result<bool> {
+        let end = offset
+            .checked_add(data.len())
+            .filter(|end| *end <= self.total)
+            .ok_or_else(|| anyhow!("chunk at offset {} exceeds total size", offset))?;
+        if data.is_empty() {
+            return Err(anyhow!("empty chunk at offset {}", offset));
+        }
+        if let Some((&start, &len)) = self.received.range(..end).next_back() {
+            if start + len > offset {
+                return Err(anyhow!("chunk at offset {} overlaps a received chunk", offset));
+            }
+        }
+        if self.buf.len() < end {
+            self.buf.resize(end, 0);
+        }
+        self.buf[offset..end].copy_from_slice(data);
+        self.received.insert(offset, data.len());
+        self.received_bytes += data.len();
+        Ok(self.is_complete())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.received_bytes == self.total
+    }
+
+    /// 取出重组后的完整负载，尚未收齐时返回错误。
+    pub fn finish(self) -> Result<Vec<u8>> {
+        if !self.is_complete() {
+            return Err(anyhow!(
+                "incomplete transfer: {} of {} bytes",
+                self.received_bytes,
+                self.total
+            ));
+        }
+        Ok(self.buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn chunk_size_is_balanced_and_bounded() {
+        // 略超过目标时均分为两块，而不是一整块加一个极短的末块
+        assert_eq!(chunk_size_for(300 * 1024, DEFAULT_CHUNK_SIZE), 150 * 1024);
+        assert_eq!(chunk_size_for(100, DEFAULT_CHUNK_SIZE), 100);
+        assert_eq!(chunk_size_for(1 << 20, 1), MIN_CHUNK_SIZE);
+        assert_eq!(chunk_size_for(64 << 20, usize::MAX), MAX_CHUNK_SIZE);
+    }
+
+    #[test]
+    fn reassembles_varying_chunk_sizes() {
+        let data = payload(100_000);
+        // 大小不一的块，末块较短，乱序到达
+        let bounds = [(0, 40_000), (40_000, 70_000), (70_000, 99_000), (99_000, 100_000)];
+        let mut r = Reassembler::new(data.len());
+        for &(start, end) in bounds.iter().rev() {
+            let done = r.add(start, &data[start..end]).unwrap();
+            assert_eq!(done, start == 0);
+        }
+        assert_eq!(r.finish().unwrap(), data);
+
+        let mut r = Reassembler::new(data.len());
+        for (offset, chunk) in split(&data, MIN_CHUNK_SIZE) {
+            r.add(offset, chunk).unwrap();
+        }
+        assert_eq!(r.finish().unwrap(), data);
+    }
+
+    #[test]
+    fn rejects_overlapping_or_out_of_range_chunks() {
+        let data = payload(1000);
+        let mut r = Reassembler::new(data.len());
+        r.add(0, &data[..500]).unwrap();
+        assert!(r.add(499, &data[499..600]).is_err());
+        assert!(r.add(900, &payload(200)).is_err());
+        assert!(Reassembler::new(10).finish().is_err());
+    }
+
+    #[test]
+    fn declared_size_does_not_drive_allocation() {
+        let mut r = Reassembler::new(usize::MAX / 2);
+        assert!(r.buf.capacity() <= MAX_PREALLOC);
+        r.add(0, &payload(100)).unwrap();
+        assert!(r.buf.capacity() <= MAX_PREALLOC);
+        assert!(!r.is_complete());
+        assert!(r.finish().is_err());
+
+        // 块乱序到达时缓冲区按需增长
+        let data = payload(3000);
+        let mut r = Reassembler::new(data.len());
+        r.add(2000, &data[2000..]).unwrap();
+        r.add(0, &data[..2000]).unwrap();
+        assert_eq!(r.finish().unwrap(), data);
+    }
+}
@@ -7,9 +7,13 @@
 use anyhow::{anyhow, Result};
 use clipboard_rs::common::RustImage;
 use clipboard_rs::Clipboard;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 use tokio::sync::mpsc;
 
+use crate::config::DisplayBackend;
+
 /// 表示文件型剪贴板条目（仅保存路径，由上层负责读取内容与大小判断）
 #[derive(Debug, Clone)]
 pub struct ClipboardFile {
@@ -22,12 +26,301 @@ pub enum ClipboardItem {
     Text(String),
     Image(Vec<u8>), // PNG 字节
     Files(Vec<ClipboardFile>),
+    /// http(s) 链接，写回剪贴板时为普通文本
+    Url(String),
+    /// 同一内容的多种格式（MIME 类型, 数据），例如浏览器复制时的 HTML 与纯文本；
+    /// 写入时一次性提供所有格式，由粘贴的应用选择最丰富的一种
+    Multi(Vec<(String, Vec<u8>)>),
+}
+
+/// 多格式内容中纯文本格式的 MIME 类型
+pub const MIME_PLAIN_TEXT: &str = "text/plain;charset=utf-8";
+/// 多格式内容中 HTML 格式的 MIME 类型
+pub const MIME_HTML: &str = "text/html";
+/// 多格式内容中 SVG 矢量图的 MIME 类型
+pub const MIME_SVG: &str = "image/svg+xml";
+/// 多格式内容中 PNG 图片的 MIME 类型
+pub const MIME_PNG: &str = "image/png";
+/// SVG 栅格化后的最大边长（像素），超出时等比缩小
+const MAX_RASTER_SIDE: u32 = 4096;
+
+/// 取多格式内容中的纯文本（`text/plain*` 且为合法 UTF-8），用于预览与降级显示。
+pub fn plain_text_format(formats: &[(String, Vec<u8>)]) -> Option<&str> {
+    formats
+        .iter()
+        .find(|(mime, _)| mime.starts_with("text/plain"))
+        .and_then(|(_, data)| std::str::from_utf8(data).ok())
+}
+
+/// 由 HTML 与纯文本组成多格式条目（HTML 在前，表示更丰富的格式）。
+fn rich_text_item(html: String, text: String) -> ClipboardItem {
+    ClipboardItem::Multi(vec![
+        (MIME_HTML.to_string(), html.into_bytes()),
+        (MIME_PLAIN_TEXT.to_string(), text.into_bytes()),
+    ])
 }
 
-/// Linux 下检测是否为 Wayland 环境
+/// 由 SVG 与来源应用同时提供的 PNG（如有）组成多格式条目，SVG 在前。
+fn svg_item(svg: Vec<u8>, png: Option<Vec<u8>>) -> ClipboardItem {
+    let mut formats = vec![(MIME_SVG.to_string(), svg)];
+    formats.extend(png.map(|png| (MIME_PNG.to_string(), png)));
+    ClipboardItem::Multi(formats)
+}
+
+/// 多格式内容含 SVG 但没有 PNG 时需要补充栅格化的 PNG，供不接受 SVG 的应用粘贴。
+pub fn needs_png_fallback(formats: &[(String, Vec<u8>)]) -> bool {
+    formats.iter().any(|(mime, _)| mime == MIME_SVG)
+        && !formats.iter().any(|(mime, _)| mime == MIME_PNG)
+}
+
+/// 为含 SVG 的多格式内容补充栅格化的 PNG（放在 SVG 之后）；栅格化失败时原样返回。
+pub fn with_png_fallback(mut formats: Vec<(String, Vec<u8>)>) -> Vec<(String, Vec<u8>)> {
+    if !needs_png_fallback(&formats) {
+        return formats;
+    }
+    let Some((_, svg)) = formats.iter().find(|(mime, _)| mime == MIME_SVG) else {
+        return formats;
+    };
+    match rasterize_svg(svg) {
+        Ok(png) => formats.push((MIME_PNG.to_string(), png)),
+        Err(e) => tracing::warn!("failed to rasterize svg: {}", e),
+    }
+    formats
+}
+
+/// 将 SVG 渲染为 PNG，边长超过 [`MAX_RASTER_SIDE`] 时等比缩小（不渲染文字）。
+fn rasterize_svg(svg: &[u8]) -> Result<Vec<u8>> {
+    use resvg::{tiny_skia, usvg};
+
+    let tree = usvg::Tree::from_data(svg, &usvg::Options::default())?;
+    let size = tree.size();
+    let scale = (MAX_RASTER_SIDE as f32 / size.width().max(size.height())).min(1.0);
+    let width = (size.width() * scale).ceil() as u32;
+    let height = (size.height() * scale).ceil() as u32;
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)
+        .ok_or_else(|| anyhow!("invalid svg size {}x{}", width, height))?;
+    resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+    pixmap.encode_png().map_err(|e| anyhow!(e.to_string()))
+}
+
+/// 校验并规范化 http(s) 链接：须为单行、带主机名的完整 URL，否则返回 None。
+pub fn parse_url(text: &str) -> Option<String> {
+    let text = text.trim();
+    if text.is_empty() || text.chars().any(char::is_whitespace) {
+        return None;
+    }
+    let url = url::Url::parse(text).ok()?;
+    (matches!(url.scheme(), "http" | "https") && url.host_str().is_some()).then(|| text.to_string())
+}
+
+/// 将读到的文本归类：完整的 http(s) 链接视为 Url，其余为 Text。
+pub(crate) fn text_item(text: String) -> ClipboardItem {
+    match parse_url(&text) {
+        Some(url) => ClipboardItem::Url(url),
+        None => ClipboardItem::Text(text),
+    }
+}
+
+/// `text/uri-list` 中仅有一个 http(s) 链接时返回该链接（浏览器复制链接的常见形式）。
+fn uri_list_url(uri_list: &str) -> Option<String> {
+    let mut entries = uri_list
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'));
+    let first = entries.next()?;
+    if entries.next().is_some() {
+        return None;
+    }
+    parse_url(first)
+}
+
+/// 剪贴板后端：读写剪贴板内容并监听变化，便于在无图形环境的中继节点上替换为内存实现。
+pub trait ClipboardBackend {
+    /// 读取当前剪贴板内容
+    fn read(&self) -> Result<Option<ClipboardItem>>;
+    /// 只读取纯文本（两阶段读取的快速路径，见 `fast_text_read`）：不协商图片等较慢的格式，
+    /// 内容为文件或不支持快速读取时返回 None
+    fn read_text(&self) -> Result<Option<String>> {
+        Ok(None)
+    }
+    /// 写入剪贴板内容
+    fn write(&mut self, item: ClipboardItem) -> Result<()>;
+    /// 清空剪贴板，之后 `read` 返回 None；默认写入空文本（并非所有平台都会因此清空）
+    fn clear(&mut self) -> Result<()> {
+        self.write(ClipboardItem::Text(String::new()))
+    }
+    /// 开始监听变化：内容变化时向 `tx` 发送事件，返回后台 watcher 线程（若有）
+    fn watch(&self, tx: mpsc::Sender<()>, poll_interval: Duration) -> Option<thread::JoinHandle<()>>;
+    /// 能否把文件放入剪贴板；不能时收到的文件可改为以路径文本放入（见 `received_files_format`）
+    fn supports_files(&self) -> bool {
+        true
+    }
+    /// 当前内容是否带有「勿同步」提示格式（密码管理器等设置，见 [`SENSITIVE_HINT_FORMATS`]）
+    fn sensitive_hint(&self) -> bool {
+        false
+    }
+}
+
+/// 应用借以声明剪贴板内容敏感、不应被记录或同步的格式：KDE/KeePassXC 等密码管理器使用的
+/// `x-kde-passwordManagerHint`，以及 Windows 剪贴板历史排除格式。
+pub const SENSITIVE_HINT_FORMATS: &[&str] = &[
+    "x-kde-passwordManagerHint",
+    "ExcludeClipboardContentFromMonitorProcessing",
+];
+
+/// 格式列表中是否含有「勿同步」提示格式。
+fn has_sensitive_hint<'a>(mut formats: impl Iterator<Item = &'a str>) -> bool {
+    formats.any(|f| SENSITIVE_HINT_FORMATS.contains(&f))
+}
+
+/// 内存剪贴板：不接触系统剪贴板，供无图形环境的中继节点与测试使用。
+///
+/// 克隆后共享同一份内容，外部可通过 [`MemoryClipboard::set`] 模拟本机复制。
+#[derive(Debug, Clone, Default)]
+pub struct MemoryClipboard {
+    inner: Arc<Mutex<MemoryClipboardState>>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryClipboardState {
+    item: Option<ClipboardItem>,
+    watchers: Vec<mpsc::Sender<()>>,
+}
+
+impl MemoryClipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 当前内容
+    pub fn get(&self) -> Option<ClipboardItem> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).item.clone()
+    }
+
+    /// 设置内容并通知所有 watcher（相当于本机复制）
+    pub fn set(&self, item: ClipboardItem) {
+        self.replace(Some(item));
+    }
+
+    fn replace(&self, item: Option<ClipboardItem>) {
+        let mut state = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        state.item = item;
+        state.watchers.retain(|tx| !tx.is_closed());
+        for tx in &state.watchers {
+            let _ = tx.try_send(());
+        }
+    }
+}
+
+impl ClipboardBackend for MemoryClipboard {
+    fn read(&self) -> Result<Option<ClipboardItem>> {
+        Ok(self.get())
+    }
+
+    fn write(&mut self, item: ClipboardItem) -> Result<()> {
+        self.set(item);
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.replace(None);
+        Ok(())
+    }
+
+    fn watch(&self, tx: mpsc::Sender<()>, _poll_interval: Duration) -> Option<thread::JoinHandle<()>> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .watchers
+            .push(tx);
+        None
+    }
+}
+
+/// 展示模式的剪贴板后端：不接触系统剪贴板，收到的内容交给展示窗口渲染。
+///
+/// 只读：`read` 始终返回 None，本机不会发出任何内容；克隆后共享同一份内容。
+#[derive(Debug, Clone, Default)]
+pub struct DisplaySink {
+    inner: Arc<Mutex<DisplaySinkState>>,
+}
+
+#[derive(Debug, Default)]
+struct DisplaySinkState {
+    item: Option<ClipboardItem>,
+    /// 每次收到内容递增，窗口据此判断是否需要重新渲染
+    version: u64,
+}
+
+impl DisplaySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 最近收到的内容
+    pub fn get(&self) -> Option<ClipboardItem> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).item.clone()
+    }
+
+    /// 内容版本号，收到新内容后递增
+    pub fn version(&self) -> u64 {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).version
+    }
+}
+
+impl ClipboardBackend for DisplaySink {
+    fn read(&self) -> Result<Option<ClipboardItem>> {
+        Ok(None)
+    }
+
+    fn write(&mut self, item: ClipboardItem) -> Result<()> {
+        let mut state = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        state.item = Some(item);
+        state.version += 1;
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        let mut state = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        state.item = None;
+        state.version += 1;
+        Ok(())
+    }
+
+    fn watch(&self, _tx: mpsc::Sender<()>, _poll_interval: Duration) -> Option<thread::JoinHandle<()>> {
+        None
+    }
+}
+
+/// 进程内当前选择的 Linux 剪贴板后端，见 [`set_display_backend`]
+#[cfg(target_os = "linux")]
+static DISPLAY_BACKEND: Mutex<DisplayBackend> = Mutex::new(DisplayBackend::Auto);
+
+/// 设置之后创建的系统剪贴板与 watcher 使用的后端（仅 Linux 有效）。
+pub(crate) fn set_display_backend(backend: DisplayBackend) {
+    #[cfg(target_os = "linux")]
+    {
+        *DISPLAY_BACKEND.lock().unwrap_or_else(|e| e.into_inner()) = backend;
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = backend;
+}
+
+/// 根据后端选择与是否存在 Wayland 会话决定是否使用 Wayland 后端。
+#[cfg(target_os = "linux")]
+fn select_wayland(backend: DisplayBackend, wayland_session: bool) -> bool {
+    match backend {
+        DisplayBackend::Auto => wayland_session,
+        DisplayBackend::X11 => false,
+        DisplayBackend::Wayland => true,
+    }
+}
+
+/// Linux 下检测是否使用 Wayland 后端：未强制指定时按 WAYLAND_DISPLAY 判断
 #[cfg(target_os = "linux")]
 fn is_wayland() -> bool {
-    std::env::var_os("WAYLAND_DISPLAY").is_some()
+    let backend = *DISPLAY_BACKEND.lock().unwrap_or_else(|e| e.into_inner());
+    select_wayland(backend, std::env::var_os("WAYLAND_DISPLAY").is_some())
 }
 
 /// 系统剪贴板读写封装
@@ -36,6 +329,10 @@ pub struct SystemClipboard {
     backend: LinuxClipboardBackend,
     #[cfg(not(target_os = "linux"))]
     backend: ClipboardRsBackend,
+    /// 读取文本时若同时存在 HTML，则读取为 [`ClipboardItem::Multi`]
+    rich_text: bool,
+    /// 剪贴板中有 SVG 时读取为包含 SVG（及 PNG）的 [`ClipboardItem::Multi`]
+    svg: bool,
 }
 
 #[cfg(target_os = "linux")]
@@ -66,7 +363,11 @@ impl SystemClipboard {
                     clipboard_rs::ClipboardContext::new().map_err(|e| anyhow!(e.to_string()))?;
                 LinuxClipboardBackend::X11(ClipboardRsBackend { ctx })
             };
-            Ok(Self { backend })
+            Ok(Self {
+                backend,
+                rich_text: false,
+                svg: false,
+            })
         }
 
         #[cfg(not(target_os = "linux"))]
@@ -74,20 +375,47 @@ impl SystemClipboard {
             let ctx = clipboard_rs::ClipboardContext::new().map_err(|e| anyhow!(e.to_string()))?;
             Ok(Self {
                 backend: ClipboardRsBackend { ctx },
+                rich_text: false,
+                svg: false,
             })
         }
     }
 
+    /// 开启后，文本同时带有 HTML 格式时读取为包含两种格式的 [`ClipboardItem::Multi`]。
+    pub fn with_rich_text(mut self, enabled: bool) -> Self {
+        self.rich_text = enabled;
+        self
+    }
+
+    /// 开启后，剪贴板中有 `image/svg+xml` 时优先读取为包含 SVG 与 PNG 的
+    /// [`ClipboardItem::Multi`]，而不是只读取位图。
+    pub fn with_svg(mut self, enabled: bool) -> Self {
+        self.svg = enabled;
+        self
+    }
+
     /// 读取当前剪贴板内容（按 Files > Image > Text 优先级）
     pub fn read(&self) -> Result<Option<ClipboardItem>> {
         #[cfg(target_os = "linux")]
         match &self.backend {
-            LinuxClipboardBackend::Wayland(w) => w.read(),
-            LinuxClipboardBackend::X11(x) => x.read(),
+            LinuxClipboardBackend::Wayland(w) => w.read(self.rich_text, self.svg),
+            LinuxClipboardBackend::X11(x) => x.read(self.rich_text, self.svg),
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        self.backend.read(self.rich_text, self.svg)
+    }
+
+    /// 只读取纯文本，内容为文件时返回 None
+    pub fn read_text(&self) -> Result<Option<String>> {
+        #[cfg(target_os = "linux")]
+        match &self.backend {
+            LinuxClipboardBackend::Wayland(w) => w.read_text(),
+            LinuxClipboardBackend::X11(x) => x.read_text(),
         }
 
         #[cfg(not(target_os = "linux"))]
-        self.backend.read()
+        self.backend.read_text()
     }
 
     /// 将内容写入系统剪贴板
@@ -101,10 +429,77 @@ impl SystemClipboard {
         #[cfg(not(target_os = "linux"))]
         self.backend.write(item)
     }
+
+    /// 清空系统剪贴板（放弃剪贴板所有权，不提供任何格式）
+    pub fn clear(&mut self) -> Result<()> {
+        #[cfg(target_os = "linux")]
+        match &mut self.backend {
+            LinuxClipboardBackend::Wayland(w) => w.clear(),
+            LinuxClipboardBackend::X11(x) => x.clear(),
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        self.backend.clear()
+    }
 }
 
-impl ClipboardRsBackend {
+impl ClipboardBackend for SystemClipboard {
     fn read(&self) -> Result<Option<ClipboardItem>> {
+        SystemClipboard::read(self)
+    }
+
+    fn read_text(&self) -> Result<Option<String>> {
+        SystemClipboard::read_text(self)
+    }
+
+    fn supports_files(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        if let LinuxClipboardBackend::Wayland(w) = &self.backend {
+            return w.supports_files();
+        }
+        true
+    }
+
+    fn write(&mut self, item: ClipboardItem) -> Result<()> {
+        SystemClipboard::write(self, item)
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        SystemClipboard::clear(self)
+    }
+
+    fn watch(&self, tx: mpsc::Sender<()>, poll_interval: Duration) -> Option<thread::JoinHandle<()>> {
+        Some(spawn_clipboard_watcher(tx, poll_interval))
+    }
+
+    fn sensitive_hint(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        match &self.backend {
+            LinuxClipboardBackend::Wayland(w) => w.sensitive_hint(),
+            LinuxClipboardBackend::X11(x) => x.sensitive_hint(),
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        self.backend.sensitive_hint()
+    }
+}
+
+impl ClipboardRsBackend {
+    fn sensitive_hint(&self) -> bool {
+        let formats = self.ctx.available_formats().unwrap_or_default();
+        has_sensitive_hint(formats.iter().map(String::as_str))
+    }
+
+    fn read_text(&self) -> Result<Option<String>> {
+        use clipboard_rs::common::ContentFormat;
+
+        if self.ctx.has(ContentFormat::Files) || !self.ctx.has(ContentFormat::Text) {
+            return Ok(None);
+        }
+        Ok(self.ctx.get_text().ok().filter(|text| !text.is_empty()))
+    }
+
+    fn read(&self, rich_text: bool, svg: bool) -> Result<Option<ClipboardItem>> {
         use clipboard_rs::common::ContentFormat;
 
         // 文件
@@ -120,6 +515,21 @@ impl ClipboardRsBackend {
             }
         }
 
+        // SVG（设计工具通常同时提供 PNG，一并读取）
+        if svg {
+            let formats = self.ctx.available_formats().unwrap_or_default();
+            if formats.iter().any(|f| f == MIME_SVG) {
+                if let Ok(svg) = self.ctx.get_buffer(MIME_SVG) {
+                    let png = formats
+                        .iter()
+                        .find(|f| *f == MIME_PNG || *f == "PNG")
+                        .and_then(|f| self.ctx.get_buffer(f).ok());
+                    tracing::debug!("clipboard read: svg len={}", svg.len());
+                    return Ok(Some(svg_item(svg, png)));
+                }
+            }
+        }
+
         // 图片
         if self.ctx.has(ContentFormat::Image) {
             if let Ok(formats) = self.ctx.available_formats() {
@@ -143,8 +553,20 @@ impl ClipboardRsBackend {
         if self.ctx.has(ContentFormat::Text) {
             if let Ok(text) = self.ctx.get_text() {
                 if !text.is_empty() {
+                    if rich_text && self.ctx.has(ContentFormat::Html) {
+                        if let Ok(html) = self.ctx.get_html() {
+                            if !html.is_empty() {
+                                tracing::debug!(
+                                    "clipboard read: html len={} text len={}",
+                                    html.len(),
+                                    text.len()
+                                );
+                                return Ok(Some(rich_text_item(html, text)));
+                            }
+                        }
+                    }
                     tracing::debug!("clipboard read: text len={}", text.len());
-                    return Ok(Some(ClipboardItem::Text(text)));
+                    return Ok(Some(text_item(text)));
                 }
             }
         }
@@ -152,11 +574,16 @@ impl ClipboardRsBackend {
         Ok(None)
     }
 
+    fn clear(&mut self) -> Result<()> {
+        tracing::info!("clipboard clear");
+        self.ctx.clear().map_err(|e| anyhow!(e.to_string()))
+    }
+
     fn write(&mut self, item: ClipboardItem) -> Result<()> {
         use clipboard_rs::common::RustImageData;
 
         match item {
-            ClipboardItem::Text(text) => {
+            ClipboardItem::Text(text) | ClipboardItem::Url(text) => {
                 tracing::info!("clipboard write: text len={}", text.len());
                 self.ctx
                     .set_text(text)
@@ -178,15 +605,88 @@ impl ClipboardRsBackend {
                     .set_files(uris)
                     .map_err(|e| anyhow!(e.to_string()))?
             }
+            ClipboardItem::Multi(formats) => {
+                tracing::info!("clipboard write: {} format(s)", formats.len());
+                self.ctx
+                    .set(clipboard_rs_contents(formats))
+                    .map_err(|e| anyhow!(e.to_string()))?
+            }
         }
         Ok(())
     }
 }
 
+/// 将多格式内容转换为 clipboard-rs 的内容列表，一次性设置以同时提供所有格式。
+///
+/// PNG 作为系统图片格式设置，不接受 SVG 的应用可粘贴为位图。
+fn clipboard_rs_contents(formats: Vec<(String, Vec<u8>)>) -> Vec<clipboard_rs::ClipboardContent> {
+    use clipboard_rs::common::RustImageData;
+    use clipboard_rs::ClipboardContent;
+
+    formats
+        .into_iter()
+        .map(|(mime, data)| {
+            let text = || String::from_utf8_lossy(&data).into_owned();
+            match mime.split(';').next().unwrap_or_default() {
+                "text/plain" => ClipboardContent::Text(text()),
+                "text/html" => ClipboardContent::Html(text()),
+                "text/rtf" => ClipboardContent::Rtf(text()),
+                MIME_PNG => match RustImageData::from_bytes(&data) {
+                    Ok(image) => ClipboardContent::Image(image),
+                    Err(_) => ClipboardContent::Other(mime, data),
+                },
+                _ => ClipboardContent::Other(mime, data),
+            }
+        })
+        .collect()
+}
+
 // 修复 ClipboardRsBackend 的 read 中误用 ClipboardHandler
 #[cfg(target_os = "linux")]
 impl WaylandClipboardBackend {
-    fn read(&self) -> Result<Option<ClipboardItem>> {
+    fn sensitive_hint(&self) -> bool {
+        use wl_clipboard_rs::paste::{get_mime_types, ClipboardType, Seat};
+
+        get_mime_types(ClipboardType::Regular, Seat::Unspecified)
+            .is_ok_and(|mime_types| has_sensitive_hint(mime_types.iter().map(String::as_str)))
+    }
+
+    /// 文件以 `text/uri-list` 经 data-control 协议设置，合成器不提供该协议时无法放入文件。
+    fn supports_files(&self) -> bool {
+        use wl_clipboard_rs::paste::{get_mime_types, ClipboardType, Error, Seat};
+
+        !matches!(
+            get_mime_types(ClipboardType::Regular, Seat::Unspecified),
+            Err(Error::MissingProtocol { .. })
+        )
+    }
+
+    fn read_text(&self) -> Result<Option<String>> {
+        use std::io::Read;
+        use wl_clipboard_rs::paste::{
+            get_contents, get_mime_types, ClipboardType, Error, MimeType, Seat,
+        };
+
+        match get_mime_types(ClipboardType::Regular, Seat::Unspecified) {
+            Ok(mime_types) if mime_types.contains("text/uri-list") => return Ok(None),
+            Ok(_) => {}
+            Err(Error::NoSeats | Error::ClipboardEmpty | Error::MissingProtocol { .. }) => {
+                return Ok(None)
+            }
+            Err(e) => return Err(anyhow!("wayland clipboard read: {}", e)),
+        }
+        match get_contents(ClipboardType::Regular, Seat::Unspecified, MimeType::Text) {
+            Ok((mut pipe, _)) => {
+                let mut buf = Vec::new();
+                pipe.read_to_end(&mut buf)?;
+                Ok(String::from_utf8(buf).ok().filter(|text| !text.is_empty()))
+            }
+            Err(Error::NoSeats | Error::ClipboardEmpty | Error::NoMimeType) => Ok(None),
+            Err(e) => Err(anyhow!("wayland clipboard text read: {}", e)),
+        }
+    }
+
+    fn read(&self, rich_text: bool, svg: bool) -> Result<Option<ClipboardItem>> {
         use std::io::Read;
         use wl_clipboard_rs::paste::{
             get_contents, get_mime_types, ClipboardType, Error, MimeType, Seat,
@@ -209,6 +709,10 @@ impl WaylandClipboardBackend {
                 let mut buf = Vec::new();
                 if pipe.read_to_end(&mut buf).is_ok() && !buf.is_empty() {
                     let uri_list = String::from_utf8_lossy(&buf);
+                    if let Some(url) = uri_list_url(&uri_list) {
+                        tracing::debug!("wayland clipboard read: url len={}", url.len());
+                        return Ok(Some(ClipboardItem::Url(url)));
+                    }
                     let files: Vec<ClipboardFile> = uri_list
                         .lines()
                         .filter_map(|line| {
@@ -236,6 +740,18 @@ impl WaylandClipboardBackend {
             }
         }
 
+        // SVG（设计工具通常同时提供 PNG，一并读取）
+        if svg && mime_types.contains(MIME_SVG) {
+            if let Some(svg) = read_wayland_bytes(MIME_SVG) {
+                let png = mime_types
+                    .contains(MIME_PNG)
+                    .then(|| read_wayland_bytes(MIME_PNG))
+                    .flatten();
+                tracing::debug!("wayland clipboard read: svg len={}", svg.len());
+                return Ok(Some(svg_item(svg, png)));
+            }
+        }
+
         // 图片: 尝试 image/png
         let image_mime = mime_types
             .iter()
@@ -262,8 +778,18 @@ impl WaylandClipboardBackend {
                 if pipe.read_to_end(&mut buf).is_ok() {
                     if let Ok(text) = String::from_utf8(buf) {
                         if !text.is_empty() {
+                            if rich_text && mime_types.contains(MIME_HTML) {
+                                if let Some(html) = read_wayland_text(MIME_HTML) {
+                                    tracing::debug!(
+                                        "wayland clipboard read: html len={} text len={}",
+                                        html.len(),
+                                        text.len()
+                                    );
+                                    return Ok(Some(rich_text_item(html, text)));
+                                }
+                            }
                             tracing::debug!("wayland clipboard read: text len={}", text.len());
-                            return Ok(Some(ClipboardItem::Text(text)));
+                            return Ok(Some(text_item(text)));
                         }
                     }
                 }
@@ -275,12 +801,20 @@ impl WaylandClipboardBackend {
         Ok(None)
     }
 
+    fn clear(&self) -> Result<()> {
+        use wl_clipboard_rs::copy::{clear, ClipboardType, Seat};
+
+        tracing::info!("wayland clipboard clear");
+        clear(ClipboardType::Regular, Seat::All)
+            .map_err(|e| anyhow!("wayland clipboard clear: {}", e))
+    }
+
     fn write(&self, item: ClipboardItem) -> Result<()> {
         use wl_clipboard_rs::copy::{MimeType, Options, Source};
 
         let opts = Options::new();
         match item {
-            ClipboardItem::Text(text) => {
+            ClipboardItem::Text(text) | ClipboardItem::Url(text) => {
                 tracing::info!("wayland clipboard write: text len={}", text.len());
                 opts.copy(
                     Source::Bytes(text.into_bytes().into_boxed_slice()),
@@ -316,11 +850,49 @@ impl WaylandClipboardBackend {
                 )
                 .map_err(|e| anyhow!("wayland clipboard write: {}", e))?;
             }
+            ClipboardItem::Multi(formats) => {
+                use wl_clipboard_rs::copy::MimeSource;
+
+                tracing::info!("wayland clipboard write: {} format(s)", formats.len());
+                let sources = formats
+                    .into_iter()
+                    .map(|(mime, data)| MimeSource {
+                        source: Source::Bytes(data.into_boxed_slice()),
+                        // 纯文本使用 MimeType::Text，同时提供 UTF8_STRING 等常见文本类型
+                        mime_type: if mime.starts_with("text/plain") {
+                            MimeType::Text
+                        } else {
+                            MimeType::Specific(mime)
+                        },
+                    })
+                    .collect();
+                opts.copy_multi(sources)
+                    .map_err(|e| anyhow!("wayland clipboard write: {}", e))?;
+            }
         }
         Ok(())
     }
 }
 
+/// 读取 Wayland 剪贴板中指定 MIME 类型的 UTF-8 文本，失败或为空时返回 None。
+#[cfg(target_os = "linux")]
+fn read_wayland_text(mime: &str) -> Option<String> {
+    String::from_utf8(read_wayland_bytes(mime)?).ok()
+}
+
+/// 读取 Wayland 剪贴板中指定 MIME 类型的数据，失败或为空时返回 None。
+#[cfg(target_os = "linux")]
+fn read_wayland_bytes(mime: &str) -> Option<Vec<u8>> {
+    use std::io::Read;
+    use wl_clipboard_rs::paste::{get_contents, ClipboardType, MimeType, Seat};
+
+    let (mut pipe, _) =
+        get_contents(ClipboardType::Regular, Seat::Unspecified, MimeType::Specific(mime)).ok()?;
+    let mut buf = Vec::new();
+    pipe.read_to_end(&mut buf).ok()?;
+    Some(buf).filter(|b| !b.is_empty())
+}
+
 /// 简易 URI 解码（file:// 路径可能含 %XX）
 #[cfg(not(target_os = "windows"))]
 fn url_decode(input: &str) -> String {
@@ -342,21 +914,74 @@ fn url_decode(input: &str) -> String {
 }
 
 /// 剪贴板变化 watcher，向通道发送简单事件
-/// - X11/Windows: 使用 clipboard-rs 的原生监听
+/// - X11/Windows: 使用 clipboard-rs 的原生监听（异常退出时由 supervisor 重启，最终回退为轮询）
 /// - Wayland: 使用轮询（wl-clipboard-rs 无原生监听接口）
-pub fn spawn_clipboard_watcher(tx: mpsc::Sender<()>) -> thread::JoinHandle<()> {
+pub fn spawn_clipboard_watcher(
+    tx: mpsc::Sender<()>,
+    poll_interval: Duration,
+) -> thread::JoinHandle<()> {
     #[cfg(target_os = "linux")]
     {
         if is_wayland() {
-            return spawn_wayland_clipboard_watcher(tx);
+            return spawn_wayland_clipboard_watcher(tx, poll_interval);
         }
     }
 
-    spawn_clipboard_rs_watcher(tx)
+    spawn_clipboard_rs_watcher(tx, poll_interval)
+}
+
+/// 原生 watcher 的重启策略：指数退避，超过次数后回退为轮询。
+#[derive(Debug, Clone, Copy)]
+struct WatcherRestartPolicy {
+    max_restarts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for WatcherRestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+/// 监督原生 watcher：每次退出后按退避重启，重启次数耗尽后运行回退 watcher。
+///
+/// `run_native` 阻塞运行一次原生 watcher，返回 `false` 表示无需继续（例如接收端已关闭）。
+fn supervise_watcher<N, F>(mut run_native: N, run_fallback: F, policy: WatcherRestartPolicy)
+where
+    N: FnMut() -> bool,
+    F: FnOnce(),
+{
+    let mut backoff = policy.initial_backoff;
+    for attempt in 0..=policy.max_restarts {
+        if !run_native() {
+            return;
+        }
+        if attempt == policy.max_restarts {
+            break;
+        }
+        tracing::warn!(
+            "clipboard watcher exited, restarting in {:?} ({}/{})",
+            backoff,
+            attempt + 1,
+            policy.max_restarts
+        );
+        thread::sleep(backoff);
+        backoff = (backoff * 2).min(policy.max_backoff);
+    }
+    tracing::warn!("clipboard watcher kept exiting, falling back to polling");
+    run_fallback();
 }
 
 /// clipboard-rs 原生 watcher（X11/Windows）
-fn spawn_clipboard_rs_watcher(tx: mpsc::Sender<()>) -> thread::JoinHandle<()> {
+fn spawn_clipboard_rs_watcher(
+    tx: mpsc::Sender<()>,
+    poll_interval: Duration,
+) -> thread::JoinHandle<()> {
     use clipboard_rs::common::ClipboardHandler;
     use clipboard_rs::{ClipboardWatcher, ClipboardWatcherContext};
 
@@ -371,46 +996,214 @@ fn spawn_clipboard_rs_watcher(tx: mpsc::Sender<()>) -> thread::JoinHandle<()> {
         }
     }
 
-    thread::spawn(move || match ClipboardWatcherContext::<Handler>::new() {
-        Ok(mut watcher) => {
-            tracing::info!("clipboard watcher started (clipboard-rs)");
-            watcher.add_handler(Handler { tx });
-            watcher.start_watch();
-            tracing::warn!("clipboard watcher exited");
-        }
+    thread::spawn(move || {
+        let native_tx = tx.clone();
+        let run_native = move || {
+            if native_tx.is_closed() {
+                return false;
+            }
+            match ClipboardWatcherContext::<Handler>::new() {
+                Ok(mut watcher) => {
+                    tracing::info!("clipboard watcher started (clipboard-rs)");
+                    watcher.add_handler(Handler {
+                        tx: native_tx.clone(),
+                    });
+                    watcher.start_watch();
+                    tracing::warn!("clipboard watcher exited");
+                }
+                Err(e) => {
+                    tracing::error!("clipboard watcher failed to start: {}", e);
+                }
+            }
+            !native_tx.is_closed()
+        };
+        supervise_watcher(
+            run_native,
+            move || run_polling_watcher(tx, poll_interval),
+            WatcherRestartPolicy::default(),
+        );
+    })
+}
+
+/// 通用轮询 watcher：定期读取剪贴板并比较哈希，作为原生监听不可用时的回退。
+fn run_polling_watcher(tx: mpsc::Sender<()>, poll_interval: Duration) {
+    let clipboard = match SystemClipboard::new() {
+        Ok(c) => c,
         Err(e) => {
-            tracing::error!("clipboard watcher failed to start: {}", e);
+            tracing::error!("polling clipboard watcher failed to start: {}", e);
+            return;
         }
-    })
+    };
+    tracing::info!("clipboard watcher started (polling every {:?})", poll_interval);
+    let mut last_hash = clipboard
+        .read()
+        .ok()
+        .flatten()
+        .map(|item| content_fingerprint(&item));
+    while !tx.is_closed() {
+        thread::sleep(poll_interval);
+        let current = clipboard
+            .read()
+            .ok()
+            .flatten()
+            .map(|item| content_fingerprint(&item));
+        if current != last_hash {
+            last_hash = current;
+            let _ = tx.try_send(());
+        }
+    }
 }
 
 #[cfg(target_os = "linux")]
 /// Wayland 剪贴板轮询 watcher（wl-clipboard-rs 无原生监听，采用轮询）
-fn spawn_wayland_clipboard_watcher(tx: mpsc::Sender<()>) -> thread::JoinHandle<()> {
-    use std::time::Duration;
-
+fn spawn_wayland_clipboard_watcher(
+    tx: mpsc::Sender<()>,
+    poll_interval: Duration,
+) -> thread::JoinHandle<()> {
     thread::spawn(move || {
-        const POLL_INTERVAL: Duration = Duration::from_millis(500);
-
-        let mut last_hash: Option<u64> = None;
+        let mut source = WlPasteSource;
+        let mut detector = WaylandChangeDetector::default();
         tracing::info!("clipboard watcher started (Wayland polling)");
 
-        loop {
-            std::thread::sleep(POLL_INTERVAL);
+        while !tx.is_closed() {
+            std::thread::sleep(poll_interval);
 
-            let current = match read_wayland_for_watcher() {
-                Some(item) => hash_clipboard_item(&item),
-                None => None,
-            };
-
-            if current != last_hash {
-                last_hash = current;
+            if detector.poll(&mut source) {
                 let _ = tx.try_send(());
             }
         }
     })
 }
 
+/// Wayland 轮询所需的剪贴板访问接口，便于在测试中替换为假实现。
+#[cfg(target_os = "linux")]
+trait WaylandSource {
+    /// 当前剪贴板提供的 MIME 类型列表（剪贴板为空时返回 None）
+    fn mime_types(&mut self) -> Option<Vec<String>>;
+    /// 廉价探测：流式读取指定 MIME（None 表示文本）的内容，只返回长度与内容哈希（见 [`probe_reader`]）
+    fn probe(&mut self, mime: Option<&str>) -> Option<(u64, u64)>;
+    /// 完整读取剪贴板内容
+    fn read_full(&mut self) -> Option<ClipboardItem>;
+}
+
+/// 基于 wl-clipboard-rs 的真实 Wayland 剪贴板访问。
+#[cfg(target_os = "linux")]
+struct WlPasteSource;
+
+#[cfg(target_os = "linux")]
+impl WaylandSource for WlPasteSource {
+    fn mime_types(&mut self) -> Option<Vec<String>> {
+        use wl_clipboard_rs::paste::{get_mime_types, ClipboardType, Seat};
+
+        get_mime_types(ClipboardType::Regular, Seat::Unspecified)
+            .ok()
+            .map(|set| set.into_iter().collect())
+    }
+
+    fn probe(&mut self, mime: Option<&str>) -> Option<(u64, u64)> {
+        use wl_clipboard_rs::paste::{get_contents, ClipboardType, MimeType, Seat};
+
+        let mime_type = match mime {
+            Some(m) => MimeType::Specific(m),
+            None => MimeType::Text,
+        };
+        let (pipe, _) = get_contents(ClipboardType::Regular, Seat::Unspecified, mime_type).ok()?;
+        probe_reader(pipe)
+    }
+
+    fn read_full(&mut self) -> Option<ClipboardItem> {
+        read_wayland_for_watcher()
+    }
+}
+
+/// 流式读取全部内容，返回 (长度, 内容哈希)，不在内存中保留内容。
+///
+/// 每次探测本就要把管道读完，对全部字节求哈希几乎没有额外开销；只哈希前缀时，
+/// 前缀之后等长的修改会被误判为未变化。
+#[cfg(target_os = "linux")]
+fn probe_reader(mut reader: impl std::io::Read) -> Option<(u64, u64)> {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::Hasher;
+
+    let mut hasher = DefaultHasher::new();
+    let mut buf = [0u8; 8192];
+    let mut total: u64 = 0;
+    loop {
+        let n = reader.read(&mut buf).ok()?;
+        if n == 0 {
+            break;
+        }
+        hasher.write(&buf[..n]);
+        total += n as u64;
+    }
+    Some((total, hasher.finish()))
+}
+
+/// Wayland 变化检测器：缓存上一次的 MIME 集合签名与廉价探测结果，
+/// 二者均未变化时跳过完整内容读取，减少对剪贴板持有方的反复请求。
+#[cfg(target_os = "linux")]
+#[derive(Default)]
+struct WaylandChangeDetector {
+    last_mime_sig: Option<u64>,
+    last_probe: Option<(u64, u64)>,
+    last_hash: Option<u64>,
+}
+
+#[cfg(target_os = "linux")]
+impl WaylandChangeDetector {
+    /// 执行一次轮询，返回剪贴板内容是否发生变化。
+    fn poll<S: WaylandSource>(&mut self, source: &mut S) -> bool {
+        let mimes = source.mime_types();
+        let mime_sig = mimes.as_ref().map(|m| mime_signature(m));
+
+        let probe = mimes
+            .as_ref()
+            .and_then(|m| source.probe(preferred_probe_mime(m)));
+        if mime_sig == self.last_mime_sig && probe == self.last_probe {
+            return false;
+        }
+        self.last_mime_sig = mime_sig;
+        self.last_probe = probe;
+
+        let current = if mimes.is_some() {
+            source.read_full().map(|item| content_fingerprint(&item))
+        } else {
+            None
+        };
+        if current != self.last_hash {
+            self.last_hash = current;
+            return true;
+        }
+        false
+    }
+}
+
+/// 计算 MIME 集合的顺序无关签名。
+#[cfg(target_os = "linux")]
+fn mime_signature(mimes: &[String]) -> u64 {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut sorted: Vec<&String> = mimes.iter().collect();
+    sorted.sort();
+    let mut hasher = DefaultHasher::new();
+    sorted.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// 选择廉价探测使用的 MIME，与完整读取的优先级一致（None 表示文本）。
+#[cfg(target_os = "linux")]
+fn preferred_probe_mime(mimes: &[String]) -> Option<&str> {
+    if let Some(m) = mimes.iter().find(|m| m.as_str() == "text/uri-list") {
+        return Some(m.as_str());
+    }
+    mimes
+        .iter()
+        .find(|m| m.starts_with("image/png"))
+        .or_else(|| mimes.iter().find(|m| m.starts_with("image/")))
+        .map(|s| s.as_str())
+}
+
 #[cfg(target_os = "linux")]
 fn read_wayland_for_watcher() -> Option<ClipboardItem> {
     use std::io::Read;
@@ -429,6 +1222,9 @@ fn read_wayland_for_watcher() -> Option<ClipboardItem> {
             let mut buf = Vec::new();
             if pipe.read_to_end(&mut buf).is_ok() && !buf.is_empty() {
                 let uri_list = String::from_utf8_lossy(&buf);
+                if let Some(url) = uri_list_url(&uri_list) {
+                    return Some(ClipboardItem::Url(url));
+                }
                 let files: Vec<ClipboardFile> = uri_list
                     .lines()
                     .filter_map(|line| {
@@ -479,7 +1275,7 @@ fn read_wayland_for_watcher() -> Option<ClipboardItem> {
             if pipe.read_to_end(&mut buf).is_ok() {
                 if let Ok(text) = String::from_utf8(buf) {
                     if !text.is_empty() {
-                        return Some(ClipboardItem::Text(text));
+                        return Some(text_item(text));
                     }
                 }
             }
@@ -491,29 +1287,209 @@ fn read_wayland_for_watcher() -> Option<ClipboardItem> {
     None
 }
 
-#[cfg(target_os = "linux")]
-fn hash_clipboard_item(item: &ClipboardItem) -> Option<u64> {
+/// 剪贴板内容的规范指纹，剪贴板监听与核心去重统一使用此函数。
+///
+/// 规则：
+/// - 文本与链接：按字符串内容计算；
+/// - 图片：按编码后的图片字节计算；
+/// - 多格式：按（MIME 类型, 数据）对排序后计算，与格式顺序无关；
+/// - 文件：路径先去掉 `file://` 前缀并做 percent-decode，取（文件名, 文件大小）对，
+///   读取不到元数据时大小记为 0；按文件名排序后计算。因此结果与文件顺序和所在目录无关，
+///   发送端原始路径与接收端下载目录中的同名同大小文件得到相同指纹。
+pub fn content_fingerprint(item: &ClipboardItem) -> u64 {
     use std::collections::hash_map::DefaultHasher;
     use std::hash::{Hash, Hasher};
 
     let mut hasher = DefaultHasher::new();
     match item {
-        ClipboardItem::Text(t) => t.hash(&mut hasher),
+        ClipboardItem::Text(t) | ClipboardItem::Url(t) => t.hash(&mut hasher),
         ClipboardItem::Image(bytes) => bytes.hash(&mut hasher),
         ClipboardItem::Files(files) => {
             "files".hash(&mut hasher);
-            for f in files {
-                let p = std::path::Path::new(&f.path);
-                if let Some(name) = p.file_name() {
-                    name.hash(&mut hasher);
+            let mut entries: Vec<(std::ffi::OsString, u64)> = files
+                .iter()
+                .map(|f| {
+                    let path = std::path::PathBuf::from(local_file_path(&f.path));
+                    let name = path.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+                    let size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                    (name, size)
+                })
+                .collect();
+            entries.sort();
+            entries.hash(&mut hasher);
+        }
+        ClipboardItem::Multi(formats) => {
+            "multi".hash(&mut hasher);
+            let mut sorted: Vec<&(String, Vec<u8>)> = formats.iter().collect();
+            sorted.sort();
+            sorted.hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+/// 将剪贴板中的文件路径还原为本地路径：去掉 `file://` 前缀并解码 URL 编码的字符。
+pub(crate) fn local_file_path(raw: &str) -> String {
+    percent_decode(raw.strip_prefix("file://").unwrap_or(raw))
+}
+
+/// 简易 percent-decode：将 `%XX` 序列还原为原始字节并转回 UTF-8 字符串。
+fn percent_decode(input: &str) -> String {
+    let mut out = Vec::with_capacity(input.len());
+    let bytes = input.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Ok(val) = u8::from_str_radix(
+                &input[i + 1..i + 3],
+                16,
+            ) {
+                out.push(val);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// 读取 Linux PRIMARY 选区中的文本：Wayland 使用 wl-clipboard-rs，X11 调用 `xclip`。
+#[cfg(target_os = "linux")]
+pub fn read_primary_text() -> Result<Option<String>> {
+    if is_wayland() {
+        use std::io::Read;
+        use wl_clipboard_rs::paste::{get_contents, ClipboardType, Error, MimeType, Seat};
+
+        return match get_contents(ClipboardType::Primary, Seat::Unspecified, MimeType::Text) {
+            Ok((mut pipe, _)) => {
+                let mut buf = Vec::new();
+                pipe.read_to_end(&mut buf)?;
+                Ok(String::from_utf8(buf).ok().filter(|t| !t.is_empty()))
+            }
+            Err(Error::NoSeats) | Err(Error::ClipboardEmpty) | Err(Error::NoMimeType) => Ok(None),
+            Err(e) => Err(anyhow!("wayland primary selection read: {}", e)),
+        };
+    }
+    let output = std::process::Command::new("xclip")
+        .args(["-selection", "primary", "-o"])
+        .stderr(std::process::Stdio::null())
+        .output()
+        .map_err(|e| anyhow!("xclip unavailable for primary selection: {}", e))?;
+    if !output.status.success() {
+        // 选区为空时 xclip 返回非零
+        return Ok(None);
+    }
+    Ok(String::from_utf8(output.stdout).ok().filter(|t| !t.is_empty()))
+}
+
+/// 尽力获取当前剪贴板内容的来源应用名，仅用于展示。
+///
+/// X11 下读取 CLIPBOARD 选区所有者窗口的 `WM_CLASS`，缺失时回退到 `_NET_WM_PID`
+/// 对应进程名；Wayland 不暴露选区所有者，其他平台暂不支持，均返回 None。
+pub fn clipboard_source_app() -> Option<String> {
+    #[cfg(target_os = "linux")]
+    if !is_wayland() {
+        return x11_clipboard_owner_app();
+    }
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn x11_clipboard_owner_app() -> Option<String> {
+    use x11rb::protocol::xproto::{AtomEnum, ConnectionExt};
+
+    let (conn, _) = x11rb::connect(None).ok()?;
+    let clipboard = conn.intern_atom(false, b"CLIPBOARD").ok()?.reply().ok()?.atom;
+    let owner = conn.get_selection_owner(clipboard).ok()?.reply().ok()?.owner;
+    if owner == x11rb::NONE {
+        return None;
+    }
+    let class = conn
+        .get_property(false, owner, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 256)
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
+        .and_then(|reply| wm_class_name(&reply.value));
+    if class.is_some() {
+        return class;
+    }
+    let pid_atom = conn.intern_atom(false, b"_NET_WM_PID").ok()?.reply().ok()?.atom;
+    let pid = conn
+        .get_property(false, owner, pid_atom, AtomEnum::CARDINAL, 0, 1)
+        .ok()?
+        .reply()
+        .ok()?
+        .value32()?
+        .next()?;
+    let comm = std::fs::read_to_string(format!("/proc/{pid}/comm")).ok()?;
+    Some(comm.trim().to_string()).filter(|name| !name.is_empty())
+}
+
+/// `WM_CLASS` 为以 NUL 分隔的「实例名、类名」，优先取类名。
+#[cfg(target_os = "linux")]
+fn wm_class_name(value: &[u8]) -> Option<String> {
+    let mut parts = value
+        .split(|b| *b == 0)
+        .filter(|part| !part.is_empty())
+        .map(|part| String::from_utf8_lossy(part).into_owned());
+    let instance = parts.next();
+    parts.next().or(instance)
+}
+
+/// 将文本写入 Linux PRIMARY 选区。
+#[cfg(target_os = "linux")]
+pub fn write_primary_text(text: String) -> Result<()> {
+    tracing::info!("primary selection write: text len={}", text.len());
+    if is_wayland() {
+        use wl_clipboard_rs::copy::{ClipboardType, MimeType, Options, Source};
+
+        let mut opts = Options::new();
+        opts.clipboard(ClipboardType::Primary);
+        return opts
+            .copy(Source::Bytes(text.into_bytes().into_boxed_slice()), MimeType::Text)
+            .map_err(|e| anyhow!("wayland primary selection write: {}", e));
+    }
+    use std::io::Write;
+    let mut child = std::process::Command::new("xclip")
+        .args(["-selection", "primary", "-i"])
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("xclip unavailable for primary selection: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    // xclip 会在后台继续持有选区，这里只等待前台进程退出
+    child.wait()?;
+    Ok(())
+}
+
+/// PRIMARY 选区轮询 watcher：选区文本变化时将新文本发送到通道。
+#[cfg(target_os = "linux")]
+pub fn spawn_primary_watcher(
+    tx: mpsc::Sender<String>,
+    poll_interval: Duration,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        tracing::info!("primary selection watcher started (polling every {:?})", poll_interval);
+        let mut last = read_primary_text().ok().flatten();
+        while !tx.is_closed() {
+            thread::sleep(poll_interval);
+            let current = match read_primary_text() {
+                Ok(text) => text,
+                Err(e) => {
+                    tracing::warn!("primary selection watcher stopped: {}", e);
+                    return;
                 }
-                if let Ok(meta) = std::fs::metadata(p) {
-                    meta.len().hash(&mut hasher);
+            };
+            if let Some(text) = current {
+                if last.as_ref() != Some(&text) {
+                    last = Some(text.clone());
+                    let _ = tx.try_send(text);
                 }
             }
         }
-    }
-    Some(hasher.finish())
+    })
 }
 
 /// 将文本写入剪贴板（供托盘等模块使用，自动选择后端）
@@ -530,4 +1506,242 @@ mod tests {
     fn clipboard_item_debug() {
         let _ = format!("{:?}", ClipboardItem::Text("x".into()));
     }
+
+    #[test]
+    fn file_fingerprint_ignores_order_directory_and_uri_form() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a b.txt"), b"abc").unwrap();
+        std::fs::write(dir.path().join("c.txt"), b"defg").unwrap();
+        let files = |paths: &[String]| {
+            ClipboardItem::Files(
+                paths.iter().map(|p| ClipboardFile { path: p.clone() }).collect(),
+            )
+        };
+        let root = dir.path().display();
+        let plain = files(&[format!("{root}/a b.txt"), format!("{root}/c.txt")]);
+        let uri = files(&[format!("file://{root}/c.txt"), format!("file://{root}/a%20b.txt")]);
+        assert_eq!(content_fingerprint(&plain), content_fingerprint(&uri));
+
+        // 同名同大小的文件位于其他目录时指纹相同，大小不同则不同
+        let other = tempfile::tempdir().unwrap();
+        std::fs::write(other.path().join("a b.txt"), b"abc").unwrap();
+        std::fs::write(other.path().join("c.txt"), b"xyzw").unwrap();
+        let moved = files(&[
+            format!("{}/a b.txt", other.path().display()),
+            format!("{}/c.txt", other.path().display()),
+        ]);
+        assert_eq!(content_fingerprint(&plain), content_fingerprint(&moved));
+        std::fs::write(other.path().join("c.txt"), b"xyz").unwrap();
+        assert_ne!(content_fingerprint(&plain), content_fingerprint(&moved));
+    }
+
+    #[test]
+    fn multi_format_write_offers_every_format() {
+        use clipboard_rs::ClipboardContent;
+
+        let formats = vec![
+            (MIME_HTML.to_string(), b"<b>hi</b>".to_vec()),
+            (MIME_PLAIN_TEXT.to_string(), b"hi".to_vec()),
+            ("application/x-custom".to_string(), vec![1, 2]),
+        ];
+        let contents = clipboard_rs_contents(formats.clone());
+        assert_eq!(contents.len(), 3);
+        assert!(matches!(&contents[0], ClipboardContent::Html(h) if h == "<b>hi</b>"));
+        assert!(matches!(&contents[1], ClipboardContent::Text(t) if t == "hi"));
+        assert!(
+            matches!(&contents[2], ClipboardContent::Other(m, d) if m == "application/x-custom" && d == &[1, 2])
+        );
+
+        assert_eq!(plain_text_format(&formats), Some("hi"));
+        let mut reversed = formats.clone();
+        reversed.reverse();
+        assert_eq!(
+            content_fingerprint(&ClipboardItem::Multi(formats)),
+            content_fingerprint(&ClipboardItem::Multi(reversed))
+        );
+    }
+
+    #[test]
+    fn png_fallback_only_when_svg_lacks_png() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="6000" height="3000"/>"#;
+        let svg_only = vec![(MIME_SVG.to_string(), svg.to_vec())];
+        assert!(needs_png_fallback(&svg_only));
+        let with_png = vec![svg_only[0].clone(), (MIME_PNG.to_string(), vec![1, 2, 3])];
+        assert!(!needs_png_fallback(&with_png));
+        let html = vec![(MIME_HTML.to_string(), b"<b>hi</b>".to_vec())];
+        assert!(!needs_png_fallback(&html));
+
+        // 来源应用已提供 PNG 时不再栅格化
+        assert_eq!(with_png_fallback(with_png.clone()), with_png);
+        // 过大的 SVG 等比缩小到最大边长
+        let formats = with_png_fallback(svg_only);
+        let png = image::load_from_memory(&formats[1].1).unwrap();
+        assert_eq!((png.width(), png.height()), (MAX_RASTER_SIDE, MAX_RASTER_SIDE / 2));
+        // 无法解析的 SVG 原样返回
+        let broken = vec![(MIME_SVG.to_string(), b"not svg".to_vec())];
+        assert_eq!(with_png_fallback(broken.clone()), broken);
+    }
+
+    #[test]
+    fn memory_clipboard_clear_empties_content() {
+        let mut clipboard = MemoryClipboard::new();
+        clipboard.write(ClipboardItem::Text("secret".into())).unwrap();
+        ClipboardBackend::clear(&mut clipboard).unwrap();
+        assert!(clipboard.read().unwrap().is_none());
+
+        let mut sink = DisplaySink::new();
+        sink.write(ClipboardItem::Text("shown".into())).unwrap();
+        sink.clear().unwrap();
+        assert!(sink.get().is_none());
+        assert_eq!(sink.version(), 2);
+    }
+
+    /// 需要图形会话；无显示环境（如 CI）时跳过。
+    #[cfg(any(target_os = "linux", target_os = "windows", target_os = "macos"))]
+    #[test]
+    fn system_clipboard_clear_empties_content() {
+        #[cfg(target_os = "linux")]
+        if std::env::var_os("DISPLAY").is_none() && std::env::var_os("WAYLAND_DISPLAY").is_none() {
+            return;
+        }
+        let Ok(mut clipboard) = SystemClipboard::new() else {
+            return;
+        };
+        clipboard.write(ClipboardItem::Text("lan-clipboard clear test".into())).unwrap();
+        clipboard.clear().unwrap();
+        assert!(clipboard.read().unwrap().is_none());
+    }
+
+    #[test]
+    fn urls_are_detected() {
+        assert_eq!(
+            parse_url(" https://example.com/a?b=1#c\n").as_deref(),
+            Some("https://example.com/a?b=1#c")
+        );
+        assert!(parse_url("http://localhost:8080").is_some());
+        for text in ["example.com", "ftp://example.com", "https://", "see https://a.com", "mailto:a@b.c"] {
+            assert!(parse_url(text).is_none(), "{text}");
+        }
+        assert!(matches!(text_item("https://a.com".into()), ClipboardItem::Url(_)));
+        assert!(matches!(text_item("hello".into()), ClipboardItem::Text(_)));
+
+        assert_eq!(
+            uri_list_url("# comment\r\nhttps://a.com/x\r\n").as_deref(),
+            Some("https://a.com/x")
+        );
+        assert!(uri_list_url("file:///tmp/a.txt").is_none());
+        assert!(uri_list_url("https://a.com\nhttps://b.com").is_none());
+    }
+
+    #[test]
+    fn supervisor_restarts_then_falls_back() {
+        use std::cell::Cell;
+
+        let starts = Cell::new(0u32);
+        let fell_back = Cell::new(false);
+        let policy = WatcherRestartPolicy {
+            max_restarts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+        };
+        supervise_watcher(
+            || {
+                starts.set(starts.get() + 1);
+                true
+            },
+            || fell_back.set(true),
+            policy,
+        );
+        // 首次启动 + 3 次重启
+        assert_eq!(starts.get(), 4);
+        assert!(fell_back.get());
+
+        // 接收端关闭时不再重启，也不回退
+        let starts = Cell::new(0u32);
+        let fell_back = Cell::new(false);
+        supervise_watcher(
+            || {
+                starts.set(starts.get() + 1);
+                false
+            },
+            || fell_back.set(true),
+            policy,
+        );
+        assert_eq!(starts.get(), 1);
+        assert!(!fell_back.get());
+    }
+
+    #[cfg(target_os = "linux")]
+    struct FakeSource {
+        mimes: Vec<String>,
+        text: String,
+        full_reads: usize,
+    }
+
+    #[cfg(target_os = "linux")]
+    impl WaylandSource for FakeSource {
+        fn mime_types(&mut self) -> Option<Vec<String>> {
+            Some(self.mimes.clone())
+        }
+
+        fn probe(&mut self, _mime: Option<&str>) -> Option<(u64, u64)> {
+            probe_reader(self.text.as_bytes())
+        }
+
+        fn read_full(&mut self) -> Option<ClipboardItem> {
+            self.full_reads += 1;
+            Some(ClipboardItem::Text(self.text.clone()))
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn display_backend_override_beats_detection() {
+        for session in [false, true] {
+            assert_eq!(select_wayland(DisplayBackend::Auto, session), session);
+            assert!(!select_wayland(DisplayBackend::X11, session));
+            assert!(select_wayland(DisplayBackend::Wayland, session));
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn wayland_detector_sees_same_length_edit_past_4k() {
+        let text = "a".repeat(10_000);
+        let mut source = FakeSource {
+            mimes: vec!["text/plain;charset=utf-8".into()],
+            text: text.clone(),
+            full_reads: 0,
+        };
+        let mut detector = WaylandChangeDetector::default();
+        assert!(detector.poll(&mut source));
+
+        // 修改位于前 4 KB 之后且长度不变
+        source.text.replace_range(9_000..9_001, "b");
+        assert_eq!(source.text.len(), text.len());
+        assert!(detector.poll(&mut source));
+        assert_eq!(source.full_reads, 2);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn wayland_detector_skips_full_read_when_unchanged() {
+        let mut source = FakeSource {
+            mimes: vec!["text/plain;charset=utf-8".into(), "UTF8_STRING".into()],
+            text: "hello".into(),
+            full_reads: 0,
+        };
+        let mut detector = WaylandChangeDetector::default();
+        assert!(detector.poll(&mut source));
+        assert_eq!(source.full_reads, 1);
+
+        // 内容与 MIME 集合均未变化：不应触发完整读取
+        assert!(!detector.poll(&mut source));
+        assert!(!detector.poll(&mut source));
+        assert_eq!(source.full_reads, 1);
+
+        source.text = "world".into();
+        assert!(detector.poll(&mut source));
+        assert_eq!(source.full_reads, 2);
+    }
 }
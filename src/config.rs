@@ -1,10 +1,17 @@
 //! 配置模块：负责从 TOML/JSON 文件加载应用配置并做基础校验。
 
-use std::{fs, io, path::PathBuf};
+use std::net::{IpAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::chunk::{DEFAULT_CHUNK_SIZE, MAX_CHUNK_SIZE, MIN_CHUNK_SIZE};
+use crate::history::DEFAULT_HISTORY_SIZE;
+use crate::protocol::{FRAME_BODY_LIMIT, MAX_FRAME_BODY, MIN_FRAME_BODY};
+use crate::crypto::{decrypt, derive_passphrase_key, encrypt};
+
 /// 配置相关错误类型，统一封装 IO、解析与语义错误。
 #[derive(Debug, Error)]
 pub enum ConfigError {
@@ -21,17 +28,615 @@ pub enum ConfigError {
 pub struct PeerConfig {
     pub host: String,
     pub port: u16,
+    /// 是否接收该对端发来的文件（按入站连接的来源 IP 匹配）；
+    /// 用于 `[relay]` 时对经中继收到的所有设备统一生效
+    #[serde(default = "default_true")]
+    pub allow_files_from: bool,
+}
+
+/// 配置 UI 与示例中使用的公开示例密钥，严格模式下禁止使用
+const EXAMPLE_SECRET_KEY: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+/// 加密配置文件的魔数头，据此区分加密与明文配置
+const ENCRYPTED_CONFIG_MAGIC: &[u8; 8] = b"LCSCENC1";
+/// 由口令派生配置加密密钥时的 PBKDF2 迭代次数（写入文件头，读取时按文件中的值派生）
+const CONFIG_KDF_ITERATIONS: u32 = 200_000;
+const CONFIG_SALT_LEN: usize = 16;
+/// 提供配置文件口令的环境变量
+pub const CONFIG_PASSPHRASE_ENV: &str = "LAN_CLIPBOARD_CONFIG_PASSPHRASE";
+/// 当前配置格式版本；加载旧版本配置时补全默认值并以当前版本写回
+pub const CONFIG_VERSION: u32 = 2;
+/// `clipboard_channel_capacity` / `incoming_channel_capacity` 的上限
+pub const MAX_CHANNEL_CAPACITY: usize = 4096;
+/// `history_size` 的上限
+pub const MAX_HISTORY_SIZE: usize = 1000;
+/// 未写 `config_version` 的配置文件视为第 1 版
+const LEGACY_CONFIG_VERSION: u32 = 1;
+
+fn default_true() -> bool {
+    true
+}
+
+/// 一帧在内存中同时存在的副本数的估计值，用于判断 `max_frame_body` 是否过大
+const FRAME_MEMORY_COPIES: u64 = 4;
+
+fn frame_limit_exceeds_memory(max_frame_body: usize, total_memory: u64) -> bool {
+    (max_frame_body as u64).saturating_mul(FRAME_MEMORY_COPIES) > total_memory
+}
+
+/// 本机物理内存总量（字节），无法获取时返回 None（目前仅支持 Linux）。
+fn total_memory() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+        let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+        let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+        Some(kib * 1024)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+impl PeerConfig {
+    /// 以默认权限创建对端配置。
+    pub fn new(host: impl Into<String>, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            allow_files_from: true,
+        }
+    }
+
+    /// 异步解析对端地址：IP 直接返回，主机名经 DNS 解析（失败时记录警告并返回空）。
+    pub async fn lookup_ips(&self) -> Vec<IpAddr> {
+        if let Ok(ip) = self.host.parse::<IpAddr>() {
+            return vec![ip];
+        }
+        match tokio::net::lookup_host((self.host.as_str(), self.port)).await {
+            Ok(addrs) => addrs.map(|a| a.ip()).collect(),
+            Err(e) => {
+                tracing::warn!("failed to resolve peer {}: {}", self.host, e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// 解析对端地址：IP 直接返回，主机名做一次 DNS 解析（失败时返回空）。
+    pub fn resolve_ips(&self) -> Vec<IpAddr> {
+        if let Ok(ip) = self.host.parse::<IpAddr>() {
+            return vec![ip];
+        }
+        (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .map(|addrs| addrs.map(|a| a.ip()).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// 系统主机名：依次尝试环境变量与 `/etc/hostname`，均不可用时返回 "unknown"。
+fn system_hostname() -> String {
+    for var in ["HOSTNAME", "COMPUTERNAME"] {
+        if let Ok(name) = std::env::var(var) {
+            if !name.trim().is_empty() {
+                return name.trim().to_string();
+            }
+        }
+    }
+    #[cfg(target_os = "linux")]
+    if let Ok(name) = fs::read_to_string("/etc/hostname") {
+        if !name.trim().is_empty() {
+            return name.trim().to_string();
+        }
+    }
+    "unknown".to_string()
+}
+
+/// 尽力收集本机地址（IPv4 与 IPv6）：本机主机名解析结果，以及访问外部网络时使用的源地址。
+///
+/// 源地址通过对 UDP 套接字执行 connect 获取，不会实际发送数据。回环与未指定地址由调用方另行判断。
+pub fn local_addresses() -> Vec<IpAddr> {
+    let mut addrs: Vec<IpAddr> = Vec::new();
+    let probes = [("0.0.0.0:0", "192.0.2.1:9"), ("[::]:0", "[2001:db8::1]:9")];
+    for (bind, target) in probes {
+        let local = std::net::UdpSocket::bind(bind)
+            .and_then(|sock| sock.connect(target).map(|_| sock))
+            .and_then(|sock| sock.local_addr());
+        if let Ok(local) = local {
+            addrs.push(local.ip());
+        }
+    }
+    let hostname = system_hostname();
+    if let Ok(resolved) = (hostname.as_str(), 0).to_socket_addrs() {
+        addrs.extend(resolved.map(|a| a.ip()));
+    }
+    addrs.retain(|ip| !ip.is_unspecified());
+    addrs.sort();
+    addrs.dedup();
+    addrs
+}
+
+/// Linux 下系统剪贴板使用的显示协议后端。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayBackend {
+    /// 根据 `WAYLAND_DISPLAY` 环境变量自动选择
+    #[default]
+    Auto,
+    /// 强制使用 X11（clipboard-rs），适合 XWayland 下的部分应用
+    X11,
+    /// 强制使用 Wayland（wl-clipboard-rs）
+    Wayland,
+}
+
+/// 左键单击托盘图标时执行的动作。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrayClickAction {
+    /// 不做任何事，只通过菜单操作
+    #[default]
+    None,
+    /// 打开配置窗口
+    OpenConfig,
+    /// 暂停或恢复同步
+    TogglePause,
+    /// 立即发送当前剪贴板（同发送快捷键）
+    SyncNow,
+}
+
+/// 剪贴板后端类型。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardBackendKind {
+    /// 系统剪贴板
+    #[default]
+    System,
+    /// 内存剪贴板：无图形环境的中继节点，只接收、保存并转发
+    Memory,
+    /// 展示模式：不访问系统剪贴板，在窗口中显示收到的文本与图片，本机不发出内容
+    Display,
+}
+
+/// 收到的文件与接收目录中已有文件重名时的处理方式。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NameCollisionPolicy {
+    /// 覆盖已有文件
+    #[default]
+    Overwrite,
+    /// 以 `name (1).ext`、`name (2).ext` 等编号后缀另存
+    Rename,
+    /// 保留已有文件，跳过收到的文件
+    Skip,
+}
+
+/// 收到的文件放入剪贴板的形式。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReceivedFilesFormat {
+    /// 剪贴板后端支持文件时放入文件，否则退化为路径文本
+    #[default]
+    Auto,
+    /// 总是放入文件
+    Files,
+    /// 总是放入以换行分隔的文件路径文本
+    Paths,
+}
+
+/// 收到的文本的换行符处理方式。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NewlinePolicy {
+    /// 保持发送端的换行符
+    #[default]
+    Preserve,
+    /// 统一为 `\n`
+    Lf,
+    /// 统一为 `\r\n`
+    Crlf,
+    /// 统一为本机平台的惯例（Windows 为 `\r\n`，其他平台为 `\n`）
+    MatchReceiver,
+}
+
+/// 发出消息在加密前的 zstd 压缩级别。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    /// 不压缩
+    #[default]
+    Off,
+    /// 压缩速度优先
+    Fast,
+    /// 压缩率优先，适合带宽受限的网络
+    Best,
+}
+
+impl Compression {
+    /// 对应的 zstd 压缩级别，`off` 时为 None。
+    pub fn zstd_level(self) -> Option<i32> {
+        match self {
+            Compression::Off => None,
+            Compression::Fast => Some(1),
+            Compression::Best => Some(19),
+        }
+    }
+}
+
+/// 发出文本的 Unicode 规范化形式（不影响本机剪贴板）。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnicodeNormalization {
+    /// 保持原样
+    #[default]
+    None,
+    /// 组合形式（Linux、Windows 应用的惯例）
+    Nfc,
+    /// 分解形式（macOS 文件名等场景常见）
+    Nfd,
+}
+
+/// 发送前对文本依次执行的内置转换（不影响本机剪贴板）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextTransform {
+    /// 去除首尾空白
+    Trim,
+    /// 去除末尾的换行符（`\n` 或 `\r\n`，可有多个）
+    StripTrailingNewline,
+    /// 将连续空白（含换行）合并为单个空格
+    CollapseWhitespace,
+}
+
+/// 帧加密使用的 AEAD 算法，收发两端必须一致。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AeadAlgorithm {
+    /// ChaCha20-Poly1305（默认，无 AES 硬件加速的设备上更快）
+    #[default]
+    #[serde(rename = "chacha20-poly1305")]
+    ChaCha20Poly1305,
+    /// AES-256-GCM（支持 AES-NI 等硬件加速的设备上更快）
+    #[serde(rename = "aes-256-gcm")]
+    Aes256Gcm,
 }
 
 /// 应用整体配置：监听端口、共享密钥、大小限制与对端列表等。
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
+    /// 配置格式版本，由程序维护，无需手动修改
+    #[serde(default = "AppConfig::legacy_config_version")]
+    pub config_version: u32,
+    /// 监听端口；0 表示由系统分配空闲端口（用于临时/测试实例），实际端口见日志
     pub listen_port: u16,
+    /// 共享密钥（十六进制）；设置了 `secret_key_file` 或 `secret_key_keyring` 时以其中的密钥为准
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub secret_key: String,
+    /// 存放共享密钥的文件（内容为十六进制密钥），避免密钥随配置文件被提交或分享；
+    /// 相对路径相对于配置文件所在目录。优先级：密钥文件 > 系统密钥环 > `secret_key`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_key_file: Option<PathBuf>,
+    /// 系统密钥环（Secret Service、macOS 钥匙串、Windows 凭据管理器）中存放共享密钥的条目名，
+    /// 服务名为 `lan-clipboard-sync`；未设置 `secret_key_file` 时生效
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_key_keyring: Option<String>,
+    /// 除 `secret_key` 外同样接受的入站密钥（十六进制），用于密钥轮换过渡期
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub accepted_keys: Vec<String>,
     #[serde(default = "AppConfig::default_max_file_size")]
     pub max_file_size: u64,
     #[serde(default)]
     pub peers: Vec<PeerConfig>,
+    /// DNS SRV 记录名（如 `_lanclip._tcp.example.com`）：解析出的对端与 `peers` 合并，并定期刷新
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub srv: Option<String>,
+    /// 通过 mDNS/DNS-SD（`discovery_service`）广播本机并自动发现局域网内的对端，与 `peers` 合并；
+    /// 只接受 `channel` 相同的对端
+    #[serde(default)]
+    pub discovery: bool,
+    /// mDNS 广播与浏览的服务类型（形如 `_name._tcp`），同一局域网中的不同设备组可各用一个
+    #[serde(default = "AppConfig::default_discovery_service")]
+    pub discovery_service: String,
+    /// mDNS 广播的服务实例名，缺省为本机实例 ID；同一服务下各设备须互不相同
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discovery_instance: Option<String>,
+    /// 本机实例 ID（UUID，即消息中的 `sender_id`），缺省时首次启动生成并写回配置文件；
+    /// 复制配置文件到其他设备时应删除该项
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance_id: Option<String>,
+    /// 本机设备名（用于配对字符串等），缺省时使用主机名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_name: Option<String>,
+    /// 接收文件的保存目录，缺省时使用平台下载目录下的 `lan-clipboard`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_dir: Option<PathBuf>,
+    /// 将最近一次同步（收到或本机复制）的文本原子地写入该文件，供其他工具读取；非文本内容跳过
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirror_text_to_file: Option<PathBuf>,
+    /// 检测到远程桌面会话（RDP/VNC 等）时暂停同步
+    #[serde(default)]
+    pub disable_in_remote_session: bool,
+    /// 剪贴板轮询间隔（毫秒），用于 Wayland 及原生监听失效后的回退轮询
+    #[serde(default = "AppConfig::default_clipboard_poll_interval_ms")]
+    pub clipboard_poll_interval_ms: u64,
+    /// 剪贴板内容需保持不变多久（毫秒）才同步：期间的连续变化只同步最终内容，
+    /// 用于合并编辑器、剪贴板管理器产生的中间状态；0 表示每次变化立即同步
+    #[serde(default)]
+    pub change_settle_ms: u64,
+    /// 两阶段读取：剪贴板变化时先只读取纯文本并立即发送，再完整读取，
+    /// 内容还有图片、HTML 等更丰富的格式时随后补发（降低 Wayland 下协商传输的延迟）
+    #[serde(default)]
+    pub fast_text_read: bool,
+    /// 全局快捷键（如 `ctrl+shift+KeyS`）：按下时把当前选中的文本或剪贴板内容发给所有对端；
+    /// 仅 Linux/Windows 托盘模式可用
+    #[serde(default)]
+    pub send_hotkey: Option<String>,
+    /// 大段文本反复复制时只发送相对上一次的差异
+    #[serde(default)]
+    pub text_delta: bool,
+    /// 多台设备几乎同时复制时按写入时钟决定胜者（最后写入者胜出），忽略更旧的远端内容；
+    /// 需所有设备升级并开启
+    #[serde(default)]
+    pub last_writer_wins: bool,
+    /// 发送文件时附带原始所在目录（仅供接收端展示）
+    #[serde(default)]
+    pub include_source_path: bool,
+    /// 同步频道标签：作为加密附加数据参与认证，频道不同的消息会被丢弃
+    #[serde(default)]
+    pub channel: String,
+    /// 收到远端剪贴板内容时显示桌面通知（图片附带缩略图）
+    #[serde(default)]
+    pub notifications: bool,
+    /// 托盘初始化失败时直接退出，而不是以无托盘模式继续运行
+    #[serde(default)]
+    pub require_tray: bool,
+    /// 左键单击托盘图标的动作：`none`（默认）、`open_config`、`toggle_pause` 或 `sync_now`；
+    /// 托盘后端不区分左键单击时（目前各平台的托盘实现均如此）不生效
+    #[serde(default)]
+    pub tray_click_action: TrayClickAction,
+    /// 发送文件时先发送清单，对端只请求本地没有的文件（需所有设备均支持）
+    #[serde(default)]
+    pub file_manifest: bool,
+    /// 分块传输的目标块大小（字节），允许范围见 `chunk` 模块
+    #[serde(default = "AppConfig::default_chunk_size")]
+    pub chunk_size: usize,
+    /// 单帧帧体的最大字节数，收发两端都按此限制；同步大文件时可调大，
+    /// 开启断点续传后可调小（需大于 `chunk_size`）。允许范围见 `protocol` 模块
+    #[serde(default = "AppConfig::default_max_frame_body")]
+    pub max_frame_body: usize,
+    /// 超过 `chunk_size` 的剪贴板内容逐块发送并等待对端确认，连接中断后从最后确认的块继续
+    /// （对端需为支持断点续传的版本；配置了中继时不使用）
+    #[serde(default)]
+    pub resumable_transfers: bool,
+    /// 一组文件中有文件超过该字节数时，整组文件按 `chunk_size` 分块逐块从磁盘读出发送，
+    /// 接收端直接写入下载目录（只发给声明支持的对端，其他对端仍收到整条消息）；0 表示关闭
+    #[serde(default = "AppConfig::default_file_chunk_threshold")]
+    pub file_chunk_threshold: u64,
+    /// 同步 Linux PRIMARY 选区（选中即复制、中键粘贴），其他平台无效
+    #[serde(default)]
+    pub sync_primary: bool,
+    /// 剪贴板后端，无图形环境的中继节点可使用 `memory`
+    #[serde(default)]
+    pub clipboard_backend: ClipboardBackendKind,
+    /// 在 trace 日志级别输出收发帧的十六进制转储，用于排查与第三方实现的互通问题；
+    /// 也可通过环境变量 `LAN_CLIPBOARD_TRACE_FRAMES=1` 开启
+    #[serde(default)]
+    pub trace_frames: bool,
+    /// 收到的图片保存为下载目录中的 PNG 文件，并以文件形式放入剪贴板
+    #[serde(default)]
+    pub images_as_files: bool,
+    /// 收到的图片超过该字节数时按 `images_as_files` 的方式存为文件，避免大图写入剪贴板失败或过慢；
+    /// 0 表示不限制
+    #[serde(default)]
+    pub image_to_file_threshold: u64,
+    /// 去重哈希按内容类型归一化：图片哈希额外包含解析出的格式与尺寸
+    #[serde(default)]
+    pub normalized_hash: bool,
+    /// 收到的文件保存后是否放入剪贴板；关闭时只保存到下载目录并通知
+    #[serde(default = "default_true")]
+    pub set_received_files_on_clipboard: bool,
+    /// 收到的文件以何种形式放入剪贴板：`auto`（后端不支持文件时改为路径文本）、`files` 或 `paths`
+    #[serde(default)]
+    pub received_files_format: ReceivedFilesFormat,
+    /// 收到的文件保存后用系统默认程序打开（仅限 `auto_open_extensions` 中的扩展名，
+    /// 可执行文件与脚本始终不打开）
+    #[serde(default)]
+    pub auto_open_received: bool,
+    /// 允许自动打开的文件扩展名，例如 `["txt", "pdf"]`
+    #[serde(default)]
+    pub auto_open_extensions: Vec<String>,
+    /// 隧道友好模式：与每个对端保持一条开启 TCP keepalive 的长连接（适合经 SSH 端口转发同步）
+    #[serde(default)]
+    pub persistent_connections: bool,
+    /// 长连接空闲多少秒后开始发送 keepalive 探测
+    #[serde(default = "AppConfig::default_keepalive_time_secs")]
+    pub keepalive_time_secs: u64,
+    /// keepalive 探测间隔（秒）
+    #[serde(default = "AppConfig::default_keepalive_interval_secs")]
+    pub keepalive_interval_secs: u64,
+    /// 对端连接失败后在该秒数内的广播中跳过它，冷却期过后重新尝试，避免每次复制都为离线设备
+    /// 等待连接超时；0 表示关闭（每次都尝试所有对端）
+    #[serde(default = "AppConfig::default_peer_failure_cooldown_secs")]
+    pub peer_failure_cooldown_secs: u64,
+    /// 帧加密算法：`chacha20-poly1305`（默认）或 `aes-256-gcm`，所有设备需配置一致
+    #[serde(default)]
+    pub aead_algorithm: AeadAlgorithm,
+    /// 不加密、不认证地明文收发（仅限完全隔离且可信的网络）；所有设备需配置一致，
+    /// 与加密的对端互相拒绝，不会静默互通
+    #[serde(default)]
+    pub insecure_no_encryption: bool,
+    /// 发出的帧体（加密之后）整体以 gzip 包装，便于外部客户端用标准 gzip 工具解开；
+    /// 是否包装记录在帧头中，接收端两种帧都接受
+    #[serde(default)]
+    pub gzip_frames: bool,
+    /// 发出的消息在加密前以 zstd 压缩：`off`（默认）、`fast`、`best`，压缩后不更小时照常发送原文；
+    /// 接收端按消息首字节识别，无论自身配置如何都能解开
+    #[serde(default)]
+    pub compression: Compression,
+    /// 中继节点（具有公网地址）：本机主动连接并经其与同频道的其他设备同步，适合无法直连的设备
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relay: Option<PeerConfig>,
+    /// 中继服务模式：只在 `listen_port` 上为其他设备转发加密帧，不同步本机剪贴板
+    #[serde(default)]
+    pub relay_server: bool,
+    /// 收到的文件与已有文件重名时：`overwrite`（默认）、`rename` 或 `skip`
+    #[serde(default)]
+    pub on_name_collision: NameCollisionPolicy,
+    /// 收到的文本的换行符：`preserve`（默认）、`lf`、`crlf` 或 `match_receiver`
+    #[serde(default)]
+    pub newline_policy: NewlinePolicy,
+    /// 发送文本前依次执行的转换，如 `["trim", "collapse_whitespace"]`
+    #[serde(default)]
+    pub outbound_transforms: Vec<TextTransform>,
+    /// 发出的文本统一规范化为 `nfc` 或 `nfd`（默认 `none` 不转换），各设备配置一致时，
+    /// 外观相同、编码形式不同的文本不会被当作新内容来回同步
+    #[serde(default)]
+    pub unicode_normalize: UnicodeNormalization,
+    /// 复制的文本同时带有 HTML 时一并同步两种格式（对端需为支持多格式内容的版本）
+    #[serde(default)]
+    pub sync_rich_text: bool,
+    /// 复制的内容带有 SVG 矢量图时同步 SVG（及来源应用提供的 PNG），接收端在缺少 PNG 时
+    /// 栅格化补充（对端需为支持多格式内容的版本）
+    #[serde(default)]
+    pub sync_svg: bool,
+    /// 随剪贴板更新发送复制内容的来源应用名，供对端历史与通知展示
+    /// （对端需为支持来源应用名的版本）
+    #[serde(default)]
+    pub send_source_app: bool,
+    /// 不同步来自这些应用的本机剪贴板变化（按来源应用名匹配，不区分大小写，目前仅 X11 可识别）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignored_apps: Vec<String>,
+    /// 非空时只同步来自这些应用的本机剪贴板变化
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_apps: Vec<String>,
+    /// 自动删除下载目录中超过该天数的已接收文件，0 表示从不删除
+    #[serde(default)]
+    pub download_retention_days: u32,
+    /// 发出的文本（含链接与富文本）在对端保留的秒数，到期后对端清理；0 表示从不过期
+    #[serde(default)]
+    pub text_ttl: u64,
+    /// 发出的图片在对端保留的秒数，0 表示从不过期
+    #[serde(default)]
+    pub image_ttl: u64,
+    /// 发出的文件在对端保留的秒数，到期后对端删除收到的文件；0 表示从不过期
+    #[serde(default)]
+    pub files_ttl: u64,
+    /// 保留的剪贴板历史条数，0 表示不记录历史
+    #[serde(default = "AppConfig::default_history_size")]
+    pub history_size: usize,
+    /// 将收到的文本历史保存到数据目录，重启后恢复；图片、文件与带「勿同步」提示的内容不保存
+    #[serde(default)]
+    pub persist_history: bool,
+    /// 持久化历史的文件路径，缺省时使用平台数据目录下的 `history.json`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history_file: Option<PathBuf>,
+    /// 每隔该秒数在 INFO 日志中输出一行收发统计（吞吐量、消息数与各对端成功率），0 表示关闭
+    #[serde(default)]
+    pub stats_log_interval_secs: u64,
+    /// 严格模式：示例密钥、没有对端或关闭了通知时拒绝启动（也可通过 `--strict` 开启）
+    #[serde(default)]
+    pub strict: bool,
+    /// Linux 剪贴板后端：`auto`（默认，按 `WAYLAND_DISPLAY` 判断）、`x11` 或 `wayland`，
+    /// 可在托盘菜单中切换
+    #[serde(default)]
+    pub display_backend: DisplayBackend,
+    /// 同时处理的入站连接数上限（中继服务模式下为客户端连接数），达到上限后暂缓接受新连接，
+    /// 防止连接洪泛耗尽资源
+    #[serde(default = "AppConfig::default_max_inbound_connections")]
+    pub max_inbound_connections: usize,
+    /// 剪贴板变化通知通道的容量；通道满时 watcher 丢弃新的通知，连续快速复制时可调大
+    #[serde(default = "AppConfig::default_channel_capacity")]
+    pub clipboard_channel_capacity: usize,
+    /// 收到的消息等待处理的通道容量，大量入站消息集中到达时可调大
+    #[serde(default = "AppConfig::default_channel_capacity")]
+    pub incoming_channel_capacity: usize,
+    /// 保存时以口令加密配置文件（口令由环境变量 `LAN_CLIPBOARD_CONFIG_PASSPHRASE` 提供）；
+    /// 读取时根据文件头自动识别加密与明文配置
+    #[serde(default)]
+    pub encrypt_config: bool,
+}
+
+impl Default for AppConfig {
+    /// 配置 UI 与首次导入时使用的初始配置（示例密钥，需由用户替换）。
+    fn default() -> Self {
+        Self {
+            config_version: CONFIG_VERSION,
+            listen_port: 5000,
+            secret_key: EXAMPLE_SECRET_KEY.to_string(),
+            secret_key_file: None,
+            secret_key_keyring: None,
+            accepted_keys: vec![],
+            max_file_size: Self::default_max_file_size(),
+            peers: vec![],
+            device_name: None,
+            download_dir: None,
+            mirror_text_to_file: None,
+            disable_in_remote_session: false,
+            clipboard_poll_interval_ms: Self::default_clipboard_poll_interval_ms(),
+            change_settle_ms: 0,
+            fast_text_read: false,
+            send_hotkey: None,
+            text_delta: false,
+            last_writer_wins: false,
+            include_source_path: false,
+            channel: String::new(),
+            notifications: false,
+            require_tray: false,
+            tray_click_action: TrayClickAction::None,
+            file_manifest: false,
+            chunk_size: Self::default_chunk_size(),
+            max_frame_body: Self::default_max_frame_body(),
+            resumable_transfers: false,
+            file_chunk_threshold: Self::default_file_chunk_threshold(),
+            sync_primary: false,
+            clipboard_backend: ClipboardBackendKind::System,
+            trace_frames: false,
+            images_as_files: false,
+            image_to_file_threshold: 0,
+            normalized_hash: false,
+            set_received_files_on_clipboard: true,
+            received_files_format: ReceivedFilesFormat::Auto,
+            auto_open_received: false,
+            auto_open_extensions: Vec::new(),
+            persistent_connections: false,
+            keepalive_time_secs: AppConfig::default_keepalive_time_secs(),
+            keepalive_interval_secs: AppConfig::default_keepalive_interval_secs(),
+            peer_failure_cooldown_secs: AppConfig::default_peer_failure_cooldown_secs(),
+            aead_algorithm: AeadAlgorithm::ChaCha20Poly1305,
+            insecure_no_encryption: false,
+            gzip_frames: false,
+            compression: Compression::Off,
+            relay: None,
+            srv: None,
+            discovery: false,
+            discovery_service: Self::default_discovery_service(),
+            discovery_instance: None,
+            instance_id: None,
+            relay_server: false,
+            on_name_collision: NameCollisionPolicy::Overwrite,
+            newline_policy: NewlinePolicy::Preserve,
+            outbound_transforms: Vec::new(),
+            unicode_normalize: UnicodeNormalization::None,
+            sync_rich_text: false,
+            sync_svg: false,
+            send_source_app: false,
+            ignored_apps: Vec::new(),
+            allowed_apps: Vec::new(),
+            download_retention_days: 0,
+            text_ttl: 0,
+            image_ttl: 0,
+            files_ttl: 0,
+            history_size: Self::default_history_size(),
+            persist_history: false,
+            history_file: None,
+            stats_log_interval_secs: 0,
+            strict: false,
+            display_backend: DisplayBackend::Auto,
+            max_inbound_connections: Self::default_max_inbound_connections(),
+            clipboard_channel_capacity: Self::default_channel_capacity(),
+            incoming_channel_capacity: Self::default_channel_capacity(),
+            encrypt_config: false,
+        }
+    }
 }
 
 impl AppConfig {
@@ -40,6 +645,164 @@ impl AppConfig {
         10 * 1024 * 1024
     }
 
+    /// 默认的入站连接并发上限。
+    pub fn default_max_inbound_connections() -> usize {
+        64
+    }
+
+    /// 默认的同步循环通道容量。
+    pub fn default_discovery_service() -> String {
+        "_lanclip._tcp".into()
+    }
+
+    pub fn default_channel_capacity() -> usize {
+        32
+    }
+
+    pub fn default_history_size() -> usize {
+        DEFAULT_HISTORY_SIZE
+    }
+
+    /// 找出指向本机监听端口的对端（自环）：端口等于 `listen_port`，且地址为回环/未指定地址
+    /// 或属于 `local_addrs`。返回其在 `peers` 中的下标。
+    pub fn self_loop_peers(&self, local_addrs: &[IpAddr]) -> Vec<usize> {
+        self.peers
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.port == self.listen_port)
+            .filter(|(_, p)| {
+                p.resolve_ips().iter().any(|ip| {
+                    ip.is_loopback() || ip.is_unspecified() || local_addrs.contains(ip)
+                })
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// 移除指向本机的对端并逐条告警，避免自己连接自己造成回环与无效开销。返回移除数量。
+    pub fn drop_self_loop_peers(&mut self) -> usize {
+        let self_loops = self.self_loop_peers(&local_addresses());
+        for &i in self_loops.iter().rev() {
+            let peer = self.peers.remove(i);
+            tracing::warn!(
+                "peer {}:{} points to this machine's own listener, ignoring it",
+                peer.host,
+                peer.port
+            );
+        }
+        self_loops.len()
+    }
+
+    /// 默认剪贴板轮询间隔（500 毫秒）。
+    pub fn default_clipboard_poll_interval_ms() -> u64 {
+        500
+    }
+
+    /// 默认分块目标大小（256 KiB）。
+    pub fn default_chunk_size() -> usize {
+        DEFAULT_CHUNK_SIZE
+    }
+
+    /// 默认分块发送文件的阈值（4 MiB）。
+    pub fn default_file_chunk_threshold() -> u64 {
+        4 * 1024 * 1024
+    }
+
+    /// 默认单帧上限（50 MiB）。
+    pub fn default_max_frame_body() -> usize {
+        MAX_FRAME_BODY
+    }
+
+    /// 默认 keepalive 空闲时间（30 秒），短于常见 NAT/SSH 空闲断开时间。
+    pub fn default_keepalive_time_secs() -> u64 {
+        30
+    }
+
+    /// 默认 keepalive 探测间隔（10 秒）。
+    pub fn default_keepalive_interval_secs() -> u64 {
+        10
+    }
+
+    /// 默认连接失败冷却期（30 秒）。
+    pub fn default_peer_failure_cooldown_secs() -> u64 {
+        30
+    }
+
+    /// 返回本机设备名：优先使用配置值，否则回退到系统主机名。
+    pub fn resolved_device_name(&self) -> String {
+        if let Some(name) = self.device_name.as_deref().map(str::trim) {
+            if !name.is_empty() {
+                return name.to_string();
+            }
+        }
+        system_hostname()
+    }
+
+    /// 返回用于保存远端文件的下载目录：优先使用配置值，否则按平台选择合适的 `Downloads` 路径。
+    pub fn download_dir(&self) -> PathBuf {
+        if let Some(dir) = &self.download_dir {
+            if !dir.as_os_str().is_empty() {
+                return dir.clone();
+            }
+        }
+        Self::default_download_dir()
+    }
+
+    /// 平台默认下载目录。
+    pub fn default_download_dir() -> PathBuf {
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(home) = std::env::var_os("HOME") {
+                return PathBuf::from(home).join("Downloads").join("lan-clipboard");
+            }
+        }
+        #[cfg(target_os = "windows")]
+        {
+            if let Some(home) = std::env::var_os("USERPROFILE") {
+                return PathBuf::from(home).join("Downloads").join("lan-clipboard");
+            }
+        }
+        PathBuf::from("lan-clipboard-downloads")
+    }
+
+    /// 返回持久化历史文件的路径：优先使用配置值，否则使用平台数据目录。
+    pub fn history_file(&self) -> PathBuf {
+        if let Some(path) = &self.history_file {
+            if !path.as_os_str().is_empty() {
+                return path.clone();
+            }
+        }
+        Self::default_history_file()
+    }
+
+    /// 平台默认的持久化历史文件路径。
+    pub fn default_history_file() -> PathBuf {
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(dir) = std::env::var_os("XDG_DATA_HOME") {
+                return PathBuf::from(dir)
+                    .join("lan-clipboard-sync")
+                    .join("history.json");
+            }
+            if let Some(home) = std::env::var_os("HOME") {
+                return PathBuf::from(home)
+                    .join(".local")
+                    .join("share")
+                    .join("lan-clipboard-sync")
+                    .join("history.json");
+            }
+        }
+        #[cfg(target_os = "windows")]
+        {
+            if let Some(dir) = std::env::var_os("LOCALAPPDATA") {
+                return PathBuf::from(dir)
+                    .join("lan-clipboard-sync")
+                    .join("history.json");
+            }
+        }
+        PathBuf::from("lan-clipboard-history.json")
+    }
+
     /// 推导不同平台下的默认配置文件路径。
     pub fn default_path() -> PathBuf {
         #[cfg(target_os = "linux")]
@@ -70,9 +833,38 @@ impl AppConfig {
     }
 
     /// 从给定路径加载配置文件，并根据扩展名选择 TOML/JSON 解析。
+    /// 加密的配置文件使用环境变量 `LAN_CLIPBOARD_CONFIG_PASSPHRASE` 中的口令解密。
     pub fn load(path: PathBuf) -> Result<Self, ConfigError> {
-        let data = fs::read_to_string(&path)?;
-        let cfg = if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
+        Self::load_with_passphrase(path, config_passphrase().as_deref())
+    }
+
+    /// 使用给定口令加载配置文件；明文配置不需要口令。
+    pub fn load_with_passphrase(
+        path: PathBuf,
+        passphrase: Option<&str>,
+    ) -> Result<Self, ConfigError> {
+        Self::load_from(path, passphrase, &OsKeyring)
+    }
+
+    fn load_from(
+        path: PathBuf,
+        passphrase: Option<&str>,
+        keyring: &dyn SecretStore,
+    ) -> Result<Self, ConfigError> {
+        let raw = fs::read(&path)?;
+        let raw = if raw.starts_with(ENCRYPTED_CONFIG_MAGIC) {
+            let passphrase = passphrase.ok_or_else(|| {
+                ConfigError::Invalid(format!(
+                    "config file is encrypted, set {} to its passphrase",
+                    CONFIG_PASSPHRASE_ENV
+                ))
+            })?;
+            decrypt_config_data(&raw, passphrase)?
+        } else {
+            raw
+        };
+        let data = String::from_utf8(raw).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        let mut cfg = if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
             match ext {
                 "json" => serde_json::from_str::<AppConfig>(&data)
                     .map_err(|e| ConfigError::Parse(e.to_string()))?,
@@ -84,14 +876,83 @@ impl AppConfig {
         } else {
             toml::from_str::<AppConfig>(&data).map_err(|e| ConfigError::Parse(e.to_string()))?
         };
+        cfg.resolve_secret_key(path.parent(), keyring)?;
         cfg.validate()?;
+        cfg.migrate(&path, passphrase, keyring);
         Ok(cfg)
     }
 
+    fn legacy_config_version() -> u32 {
+        LEGACY_CONFIG_VERSION
+    }
+
+    /// 将旧版本配置升级到当前版本并写回文件（缺失字段已由默认值补全）。
+    ///
+    /// 写回失败（如配置文件只读）只记录警告，本次仍使用升级后的配置；
+    /// 比当前版本更新的配置原样尽力加载，不写回。
+    fn migrate(&mut self, path: &PathBuf, passphrase: Option<&str>, keyring: &dyn SecretStore) {
+        if self.config_version > CONFIG_VERSION {
+            tracing::warn!(
+                "config {} has version {} newer than supported {}, loading known settings only",
+                path.display(),
+                self.config_version,
+                CONFIG_VERSION
+            );
+            return;
+        }
+        if self.config_version == CONFIG_VERSION {
+            return;
+        }
+        tracing::info!(
+            "migrating config {} from version {} to {}",
+            path.display(),
+            self.config_version,
+            CONFIG_VERSION
+        );
+        self.config_version = CONFIG_VERSION;
+        if let Err(e) = self.save_to(path, passphrase, keyring) {
+            tracing::warn!("failed to write migrated config {}: {}", path.display(), e);
+        }
+    }
+
+    /// 密钥文件的实际路径：相对路径按配置文件所在目录解析。
+    fn secret_key_path(&self, config_dir: Option<&Path>) -> Option<PathBuf> {
+        let file = self.secret_key_file.as_ref()?;
+        Some(match config_dir {
+            Some(dir) if file.is_relative() => dir.join(file),
+            _ => file.clone(),
+        })
+    }
+
+    /// 确定生效的密钥：设置了 `secret_key_file` 时读取该文件（忽略首尾空白），
+    /// 否则设置了 `secret_key_keyring` 时读取系统密钥环，都未设置时使用 `secret_key`。
+    fn resolve_secret_key(
+        &mut self,
+        config_dir: Option<&Path>,
+        keyring: &dyn SecretStore,
+    ) -> Result<(), ConfigError> {
+        if let Some(path) = self.secret_key_path(config_dir) {
+            let key = fs::read_to_string(&path).map_err(|e| {
+                ConfigError::Invalid(format!(
+                    "cannot read secret_key_file {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+            self.secret_key = key.trim().to_string();
+        } else if let Some(name) = &self.secret_key_keyring {
+            self.secret_key = keyring.get(name)?.trim().to_string();
+        }
+        Ok(())
+    }
+
     /// 对关键字段做基础校验，尽早发现明显错误。
+    /// `listen_port = 0` 是合法的，表示监听时由系统分配端口。
     pub fn validate(&self) -> Result<(), ConfigError> {
-        if self.listen_port == 0 {
-            return Err(ConfigError::Invalid("listen_port must be > 0".into()));
+        if self.clipboard_poll_interval_ms < 50 {
+            return Err(ConfigError::Invalid(
+                "clipboard_poll_interval_ms must be >= 50".into(),
+            ));
         }
         let key_bytes = hex::decode(&self.secret_key)
             .map_err(|_| ConfigError::Invalid("secret_key must be valid hex string".into()))?;
@@ -100,22 +961,383 @@ impl AppConfig {
                 "secret_key must be exactly 32 bytes (64 hex chars)".into(),
             ));
         }
+        if !(MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&self.chunk_size) {
+            return Err(ConfigError::Invalid(format!(
+                "chunk_size must be between {} and {} bytes",
+                MIN_CHUNK_SIZE, MAX_CHUNK_SIZE
+            )));
+        }
+        if !(MIN_FRAME_BODY..=FRAME_BODY_LIMIT).contains(&self.max_frame_body) {
+            return Err(ConfigError::Invalid(format!(
+                "max_frame_body must be between {} and {} bytes",
+                MIN_FRAME_BODY, FRAME_BODY_LIMIT
+            )));
+        }
+        // DNS-SD 服务类型：`_` 开头、1 到 15 个字母数字或连字符的服务名，协议为 tcp
+        let service = self.discovery_service.strip_suffix("._tcp").and_then(|s| s.strip_prefix('_'));
+        if !service.is_some_and(|s| {
+            (1..=15).contains(&s.len()) && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        }) {
+            return Err(ConfigError::Invalid(format!(
+                "discovery_service must look like _name._tcp, got {:?}",
+                self.discovery_service
+            )));
+        }
+        if self
+            .discovery_instance
+            .as_ref()
+            .is_some_and(|name| name.is_empty() || name.len() > 63)
+        {
+            return Err(ConfigError::Invalid(
+                "discovery_instance must be 1 to 63 bytes".into(),
+            ));
+        }
+        if let Some(id) = &self.instance_id {
+            uuid::Uuid::parse_str(id).map_err(|e| {
+                ConfigError::Invalid(format!("instance_id must be a UUID, got {:?}: {}", id, e))
+            })?;
+        }
+        #[cfg(any(target_os = "linux", target_os = "windows"))]
+        if let Some(hotkey) = &self.send_hotkey {
+            crate::hotkey::parse_hotkey(hotkey).map_err(ConfigError::Invalid)?;
+        }
+        if self.resumable_transfers && self.chunk_size >= self.max_frame_body {
+            return Err(ConfigError::Invalid(
+                "chunk_size must be smaller than max_frame_body when resumable_transfers is on"
+                    .into(),
+            ));
+        }
+        if self.keepalive_time_secs == 0 || self.keepalive_interval_secs == 0 {
+            return Err(ConfigError::Invalid(
+                "keepalive_time_secs and keepalive_interval_secs must be > 0".into(),
+            ));
+        }
+        if self.max_inbound_connections == 0 {
+            return Err(ConfigError::Invalid(
+                "max_inbound_connections must be > 0".into(),
+            ));
+        }
+        for (name, capacity) in [
+            ("clipboard_channel_capacity", self.clipboard_channel_capacity),
+            ("incoming_channel_capacity", self.incoming_channel_capacity),
+        ] {
+            if !(1..=MAX_CHANNEL_CAPACITY).contains(&capacity) {
+                return Err(ConfigError::Invalid(format!(
+                    "{name} must be between 1 and {MAX_CHANNEL_CAPACITY}"
+                )));
+            }
+        }
+        if self.history_size > MAX_HISTORY_SIZE {
+            return Err(ConfigError::Invalid(format!(
+                "history_size must be at most {MAX_HISTORY_SIZE}"
+            )));
+        }
+        if self.relay_server && self.relay.is_some() {
+            return Err(ConfigError::Invalid(
+                "relay_server and relay cannot be used together".into(),
+            ));
+        }
+        if self.insecure_no_encryption && (self.relay_server || self.relay.is_some()) {
+            return Err(ConfigError::Invalid(
+                "insecure_no_encryption cannot be used with a relay".into(),
+            ));
+        }
+        for key in &self.accepted_keys {
+            if hex::decode(key).map(|b| b.len()).ok() != Some(32) {
+                return Err(ConfigError::Invalid(
+                    "accepted_keys entries must be 32-byte hex strings".into(),
+                ));
+            }
+        }
         Ok(())
     }
 
-    /// 将配置保存到指定路径（TOML 格式）。
+    /// `max_frame_body` 相对本机内存过大时返回警告说明：收发一帧时帧体会同时存在多份副本
+    /// （密文、明文与解码后的内容），过大的上限可能在收到大帧时耗尽内存。
+    pub fn frame_memory_warning(&self) -> Option<String> {
+        let total = total_memory()?;
+        frame_limit_exceeds_memory(self.max_frame_body, total).then(|| {
+            format!(
+                "max_frame_body ({} bytes) is large compared to system memory ({} bytes)",
+                self.max_frame_body, total
+            )
+        })
+    }
+
+    /// 严格模式的启动检查，返回每一项未通过检查的说明；为空表示全部通过。
+    pub fn strict_check_failures(&self) -> Vec<String> {
+        let mut failures = Vec::new();
+        if self.secret_key.eq_ignore_ascii_case(EXAMPLE_SECRET_KEY) {
+            failures.push("secret_key is the well-known example key, generate a new one".into());
+        }
+        if self.peers.is_empty() && self.relay.is_none() && self.srv.is_none() && !self.discovery
+        {
+            failures.push("no peers (or relay, srv, discovery) configured".into());
+        }
+        if !self.notifications {
+            failures.push(
+                "notifications are disabled, received files would be saved without any notice"
+                    .into(),
+            );
+        }
+        if self.insecure_no_encryption {
+            failures.push("insecure_no_encryption is enabled, clipboard is sent in plaintext".into());
+        }
+        failures
+    }
+
+    /// 生成随机的 32 字节共享密钥（十六进制）。
+    pub fn generate_secret_key() -> String {
+        use rand::RngCore;
+        let mut key = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+        hex::encode(key)
+    }
+
+    /// 加载配置；若文件不存在，则写入一份带随机密钥、空 peers 的默认配置后返回。
+    ///
+    /// 返回值第二项表示是否新建了配置文件。已存在的文件不会被覆盖。
+    pub fn load_or_create(path: PathBuf) -> Result<(Self, bool), ConfigError> {
+        if path.exists() {
+            return Ok((Self::load(path)?, false));
+        }
+        let cfg = AppConfig {
+            secret_key: Self::generate_secret_key(),
+            ..AppConfig::default()
+        };
+        cfg.validate()?;
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let toml = toml::to_string_pretty(&cfg).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        // create_new 保证在并发创建时也不会覆盖已有文件
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        io::Write::write_all(&mut file, toml.as_bytes())?;
+        Ok((cfg, true))
+    }
+
+    /// 导出为 TOML 文本（`--export-config`）；`redact_keys` 为 true 时清空共享密钥与
+    /// `accepted_keys`，便于在日志或工单中传递。
+    pub fn export_toml(&self, redact_keys: bool) -> Result<String, ConfigError> {
+        let mut exported = self.clone();
+        if redact_keys {
+            exported.secret_key.clear();
+            exported.accepted_keys.clear();
+        }
+        toml::to_string_pretty(&exported).map_err(|e| ConfigError::Parse(e.to_string()))
+    }
+
+    /// 解析并校验导入的 TOML 配置（`--import-config`）。导入内容没有密钥（例如导出时已清空）
+    /// 时沿用 `existing` 的共享密钥；旧版本配置按当前版本导入。
+    pub fn import_toml(text: &str, existing: Option<&AppConfig>) -> Result<Self, ConfigError> {
+        let mut cfg =
+            toml::from_str::<AppConfig>(text).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        if cfg.secret_key.is_empty() {
+            if let Some(existing) = existing {
+                cfg.secret_key = existing.secret_key.clone();
+            }
+        }
+        cfg.config_version = cfg.config_version.max(CONFIG_VERSION);
+        cfg.validate()?;
+        Ok(cfg)
+    }
+
+    /// 将配置保存到指定路径（`.json` 文件为 JSON，其余为 TOML）；开启 `encrypt_config` 时
+    /// 以环境变量中的口令加密。
     pub fn save(&self, path: &PathBuf) -> Result<(), ConfigError> {
+        self.save_with_passphrase(path, config_passphrase().as_deref())
+    }
+
+    /// 只把密钥写回配置文件：重新读取磁盘上的配置并仅替换 `secret_key` 与 `accepted_keys`，
+    /// 运行期间合并进来的对端（SRV、mDNS 发现）等临时状态不会写入文件。
+    pub fn persist_keys(
+        path: &PathBuf,
+        secret_key: String,
+        accepted_keys: Vec<String>,
+    ) -> Result<(), ConfigError> {
+        let mut stored = Self::load(path.clone())?;
+        stored.secret_key = secret_key;
+        stored.accepted_keys = accepted_keys;
+        stored.save(path)
+    }
+
+    /// 只把实例 ID 写回配置文件，其余内容保持磁盘上的原样（同 [`Self::persist_keys`]）。
+    pub fn persist_instance_id(path: &PathBuf, instance_id: String) -> Result<(), ConfigError> {
+        let mut stored = Self::load(path.clone())?;
+        stored.instance_id = Some(instance_id);
+        stored.save(path)
+    }
+
+    /// 使用给定口令保存配置；未开启 `encrypt_config` 时忽略口令，以明文保存。
+    pub fn save_with_passphrase(
+        &self,
+        path: &PathBuf,
+        passphrase: Option<&str>,
+    ) -> Result<(), ConfigError> {
+        self.save_to(path, passphrase, &OsKeyring)
+    }
+
+    fn save_to(
+        &self,
+        path: &PathBuf,
+        passphrase: Option<&str>,
+        keyring: &dyn SecretStore,
+    ) -> Result<(), ConfigError> {
         self.validate()?;
         let parent = path
             .parent()
             .ok_or_else(|| ConfigError::Invalid("config path has no parent directory".into()))?;
+        let passphrase = match (self.encrypt_config, passphrase) {
+            (true, None) => {
+                return Err(ConfigError::Invalid(format!(
+                    "encrypt_config is enabled but {} is not set",
+                    CONFIG_PASSPHRASE_ENV
+                )))
+            }
+            (true, passphrase) => passphrase,
+            (false, _) => None,
+        };
         fs::create_dir_all(parent)?;
-        let toml = toml::to_string_pretty(self).map_err(|e| ConfigError::Parse(e.to_string()))?;
-        fs::write(path, toml)?;
+        let mut stored = self.clone();
+        if let Some(key_path) = self.secret_key_path(Some(parent)) {
+            // 密钥只保存在密钥文件中（密钥轮换后同步写回），配置文件中不写入密钥
+            let current = fs::read_to_string(&key_path).unwrap_or_default();
+            if current.trim() != self.secret_key {
+                write_secret_file(&key_path, &self.secret_key)?;
+            }
+            stored.secret_key.clear();
+        } else if let Some(name) = &self.secret_key_keyring {
+            // 同上，密钥只保存在系统密钥环中
+            if keyring.get(name).ok().as_deref().map(str::trim) != Some(self.secret_key.as_str()) {
+                keyring.set(name, &self.secret_key)?;
+            }
+            stored.secret_key.clear();
+        }
+        let text = match path.extension().and_then(|s| s.to_str()) {
+            Some("json") => serde_json::to_string_pretty(&stored)
+                .map_err(|e| ConfigError::Parse(e.to_string()))?,
+            _ => toml::to_string_pretty(&stored).map_err(|e| ConfigError::Parse(e.to_string()))?,
+        };
+        match passphrase {
+            Some(passphrase) => {
+                let data = encrypt_config_data(text.as_bytes(), passphrase, CONFIG_KDF_ITERATIONS)?;
+                fs::write(path, data)?;
+            }
+            None => fs::write(path, text)?,
+        }
         Ok(())
     }
 }
 
+/// 系统密钥环中存放共享密钥的服务名
+const KEYRING_SERVICE: &str = "lan-clipboard-sync";
+
+/// 按条目名读写共享密钥的密钥环。
+trait SecretStore {
+    fn get(&self, name: &str) -> Result<String, ConfigError>;
+    fn set(&self, name: &str, secret: &str) -> Result<(), ConfigError>;
+}
+
+/// 系统密钥环（keyring crate 按平台选择后端）。
+struct OsKeyring;
+
+impl OsKeyring {
+    fn entry(name: &str) -> Result<keyring::Entry, ConfigError> {
+        keyring::Entry::new(KEYRING_SERVICE, name).map_err(|e| {
+            ConfigError::Invalid(format!("cannot open keyring entry {}: {}", name, e))
+        })
+    }
+}
+
+impl SecretStore for OsKeyring {
+    fn get(&self, name: &str) -> Result<String, ConfigError> {
+        Self::entry(name)?.get_password().map_err(|e| {
+            ConfigError::Invalid(format!("cannot read secret_key_keyring {}: {}", name, e))
+        })
+    }
+
+    fn set(&self, name: &str, secret: &str) -> Result<(), ConfigError> {
+        Self::entry(name)?.set_password(secret).map_err(|e| {
+            ConfigError::Invalid(format!("cannot write secret_key_keyring {}: {}", name, e))
+        })
+    }
+}
+
+/// 写入密钥文件：新建的文件仅当前用户可读写（Unix 下为 0600），已有文件同样收紧权限。
+fn write_secret_file(path: &Path, secret: &str) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        let mut file = options.open(path)?;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        io::Write::write_all(&mut file, secret.as_bytes())
+    }
+    #[cfg(not(unix))]
+    {
+        io::Write::write_all(&mut options.open(path)?, secret.as_bytes())
+    }
+}
+
+/// 从环境变量读取配置文件口令，未设置或为空时返回 None。
+fn config_passphrase() -> Option<String> {
+    std::env::var(CONFIG_PASSPHRASE_ENV).ok().filter(|p| !p.is_empty())
+}
+
+/// 加密配置内容。文件格式：魔数 | 迭代次数（u32 BE）| 盐 | nonce | 密文，
+/// 魔数、迭代次数与盐作为附加数据参与认证。
+fn encrypt_config_data(
+    plaintext: &[u8],
+    passphrase: &str,
+    iterations: u32,
+) -> Result<Vec<u8>, ConfigError> {
+    use rand::RngCore;
+
+    let mut header = ENCRYPTED_CONFIG_MAGIC.to_vec();
+    header.extend_from_slice(&iterations.to_be_bytes());
+    let mut salt = [0u8; CONFIG_SALT_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    header.extend_from_slice(&salt);
+    let key = derive_passphrase_key(passphrase.as_bytes(), &salt, iterations);
+    let (nonce, ciphertext) = encrypt(AeadAlgorithm::ChaCha20Poly1305, &key, plaintext, &header)
+        .map_err(|e| ConfigError::Invalid(format!("failed to encrypt config: {e}")))?;
+    let mut out = header;
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+/// 解密 [`encrypt_config_data`] 生成的内容，口令错误或内容被篡改时报错。
+fn decrypt_config_data(data: &[u8], passphrase: &str) -> Result<Vec<u8>, ConfigError> {
+    let header_len = ENCRYPTED_CONFIG_MAGIC.len() + 4 + CONFIG_SALT_LEN;
+    if data.len() < header_len + 12 {
+        return Err(ConfigError::Parse("encrypted config file is truncated".into()));
+    }
+    let (header, rest) = data.split_at(header_len);
+    let iterations_at = ENCRYPTED_CONFIG_MAGIC.len();
+    let iterations = u32::from_be_bytes(
+        header[iterations_at..iterations_at + 4]
+            .try_into()
+            .expect("4-byte slice"),
+    );
+    let salt = &header[iterations_at + 4..];
+    let (nonce, ciphertext) = rest.split_at(12);
+    let key = derive_passphrase_key(passphrase.as_bytes(), salt, iterations);
+    decrypt(
+        AeadAlgorithm::ChaCha20Poly1305,
+        &key,
+        nonce.try_into().expect("12-byte nonce"),
+        ciphertext,
+        header,
+    )
+    .map_err(|_| ConfigError::Invalid("wrong passphrase or corrupted encrypted config".into()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -134,5 +1356,446 @@ port = 5001
         let cfg: AppConfig = toml::from_str(toml).unwrap();
         assert_eq!(cfg.listen_port, 5000);
         assert_eq!(cfg.peers.len(), 1);
+        assert!(cfg.peers[0].allow_files_from);
+    }
+
+    #[test]
+    fn strict_checks_report_each_failure() {
+        let passing = AppConfig {
+            secret_key: AppConfig::generate_secret_key(),
+            peers: vec![PeerConfig::new("192.168.1.23", 5000)],
+            notifications: true,
+            ..AppConfig::default()
+        };
+        assert!(passing.strict_check_failures().is_empty());
+
+        let example_key = AppConfig {
+            secret_key: EXAMPLE_SECRET_KEY.into(),
+            ..passing.clone()
+        };
+        let no_peers = AppConfig {
+            peers: vec![],
+            ..passing.clone()
+        };
+        let silent = AppConfig {
+            notifications: false,
+            ..passing.clone()
+        };
+        let plaintext = AppConfig {
+            insecure_no_encryption: true,
+            ..passing.clone()
+        };
+        for (name, cfg) in [
+            ("example key", example_key),
+            ("no peers", no_peers),
+            ("silent", silent),
+            ("plaintext", plaintext),
+        ] {
+            assert_eq!(cfg.strict_check_failures().len(), 1, "{name}");
+        }
+
+        // 只配置中继时不算缺少对端
+        let relay_only = AppConfig {
+            peers: vec![],
+            relay: Some(PeerConfig::new("relay.example.com", 5000)),
+            ..passing.clone()
+        };
+        assert!(relay_only.strict_check_failures().is_empty());
+        assert_eq!(AppConfig::default().strict_check_failures().len(), 3);
+    }
+
+    #[test]
+    fn self_loop_peers_are_detected() {
+        let local: IpAddr = "192.168.1.10".parse().unwrap();
+        let cfg = AppConfig {
+            listen_port: 5000,
+            peers: vec![
+                PeerConfig::new("127.0.0.1", 5000),
+                PeerConfig::new("::1", 5000),
+                PeerConfig::new("192.168.1.10", 5000),
+                PeerConfig::new("fe80::1", 5000),
+                PeerConfig::new("192.168.1.10", 5001),
+                PeerConfig::new("192.168.1.20", 5000),
+            ],
+            ..AppConfig::default()
+        };
+        let local_v6: IpAddr = "fe80::1".parse().unwrap();
+        assert_eq!(cfg.self_loop_peers(&[local, local_v6]), vec![0, 1, 2, 3]);
+        assert_eq!(cfg.self_loop_peers(&[]), vec![0, 1]);
+    }
+
+    #[test]
+    fn insecure_no_encryption_is_rejected_with_relay() {
+        let relay_client = AppConfig {
+            insecure_no_encryption: true,
+            relay: Some(PeerConfig::new("relay.example.com", 5000)),
+            ..AppConfig::default()
+        };
+        assert!(relay_client.validate().is_err());
+        let relay_server = AppConfig {
+            insecure_no_encryption: true,
+            relay_server: true,
+            ..AppConfig::default()
+        };
+        assert!(relay_server.validate().is_err());
+    }
+
+    #[test]
+    fn chunk_size_is_validated() {
+        let mut cfg = AppConfig::default();
+        assert_eq!(cfg.chunk_size, DEFAULT_CHUNK_SIZE);
+        assert!(cfg.validate().is_ok());
+        cfg.chunk_size = MIN_CHUNK_SIZE - 1;
+        assert!(cfg.validate().is_err());
+        cfg.chunk_size = MAX_CHUNK_SIZE + 1;
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn config_round_trips_through_export_and_import() {
+        let cfg = AppConfig {
+            listen_port: 6001,
+            secret_key: AppConfig::generate_secret_key(),
+            peers: vec![PeerConfig::new("10.0.0.2", 6001)],
+            channel: "office".into(),
+            ..AppConfig::default()
+        };
+        let exported = cfg.export_toml(false).unwrap();
+        let imported = AppConfig::import_toml(&exported, None).unwrap();
+        assert_eq!(imported.export_toml(false).unwrap(), exported);
+
+        // 清空密钥的导出：导入时沿用目标设备已有的密钥，没有可沿用的密钥时校验失败
+        let redacted = cfg.export_toml(true).unwrap();
+        assert!(!redacted.contains(&cfg.secret_key));
+        assert!(AppConfig::import_toml(&redacted, None).is_err());
+        let existing = AppConfig {
+            secret_key: AppConfig::generate_secret_key(),
+            ..AppConfig::default()
+        };
+        let imported = AppConfig::import_toml(&redacted, Some(&existing)).unwrap();
+        assert_eq!(imported.secret_key, existing.secret_key);
+        assert_eq!(imported.channel, "office");
+        assert!(AppConfig::import_toml("listen_port = \"x\"", Some(&existing)).is_err());
+    }
+
+    #[test]
+    fn max_frame_body_is_validated() {
+        let mut cfg = AppConfig::default();
+        assert_eq!(cfg.max_frame_body, MAX_FRAME_BODY);
+        cfg.max_frame_body = MIN_FRAME_BODY - 1;
+        assert!(cfg.validate().is_err());
+        cfg.max_frame_body = FRAME_BODY_LIMIT + 1;
+        assert!(cfg.validate().is_err());
+        // 开启断点续传时块必须能放进一帧
+        cfg.max_frame_body = MIN_FRAME_BODY;
+        assert!(cfg.validate().is_ok());
+        cfg.resumable_transfers = true;
+        assert!(cfg.validate().is_err());
+        cfg.chunk_size = MIN_CHUNK_SIZE;
+        assert!(cfg.validate().is_ok());
+
+        assert!(!frame_limit_exceeds_memory(MAX_FRAME_BODY, 8 << 30));
+        assert!(frame_limit_exceeds_memory(FRAME_BODY_LIMIT, 512 << 20));
+    }
+
+    #[test]
+    fn channel_capacities_are_validated() {
+        let mut cfg = AppConfig::default();
+        assert_eq!(cfg.clipboard_channel_capacity, 32);
+        cfg.incoming_channel_capacity = MAX_CHANNEL_CAPACITY;
+        assert!(cfg.validate().is_ok());
+        cfg.incoming_channel_capacity = MAX_CHANNEL_CAPACITY + 1;
+        assert!(matches!(cfg.validate(), Err(ConfigError::Invalid(_))));
+        cfg.incoming_channel_capacity = 32;
+        cfg.clipboard_channel_capacity = 0;
+        assert!(matches!(cfg.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn history_settings_are_validated() {
+        let mut cfg = AppConfig::default();
+        assert_eq!(cfg.history_size, DEFAULT_HISTORY_SIZE);
+        assert_eq!(cfg.history_file(), AppConfig::default_history_file());
+        cfg.history_size = 0;
+        assert!(cfg.validate().is_ok());
+        cfg.history_size = MAX_HISTORY_SIZE + 1;
+        assert!(matches!(cfg.validate(), Err(ConfigError::Invalid(_))));
+        cfg.history_file = Some(PathBuf::from("/tmp/history.json"));
+        assert_eq!(cfg.history_file(), PathBuf::from("/tmp/history.json"));
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "windows"))]
+    #[test]
+    fn send_hotkey_is_validated() {
+        let mut cfg = AppConfig {
+            send_hotkey: Some("ctrl+alt+KeyV".into()),
+            ..AppConfig::default()
+        };
+        assert!(cfg.validate().is_ok());
+        cfg.send_hotkey = Some("F9".into());
+        assert!(matches!(cfg.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn load_or_create_writes_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("config.toml");
+
+        let (cfg, created) = AppConfig::load_or_create(path.clone()).unwrap();
+        assert!(created);
+        assert!(path.exists());
+        assert!(cfg.peers.is_empty());
+        assert_ne!(cfg.secret_key, AppConfig::default().secret_key);
+
+        // 再次调用应读取已有文件而不是覆盖
+        let (again, created) = AppConfig::load_or_create(path).unwrap();
+        assert!(!created);
+        assert_eq!(again.secret_key, cfg.secret_key);
+    }
+
+    #[test]
+    fn secret_key_is_read_from_key_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = AppConfig::generate_secret_key();
+        fs::write(dir.path().join("secret.key"), format!("{key}\n")).unwrap();
+        let path = dir.path().join("config.toml");
+        // 密钥文件优先于内联密钥，相对路径相对于配置文件所在目录
+        let toml = format!(
+            "listen_port = 5000\nsecret_key = \"{EXAMPLE_SECRET_KEY}\"\n\
+             secret_key_file = \"secret.key\"\n"
+        );
+        fs::write(&path, toml).unwrap();
+        let cfg = AppConfig::load(path.clone()).unwrap();
+        assert_eq!(cfg.secret_key, key);
+
+        // 保存时不把密钥写进配置文件
+        cfg.save(&path).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(!saved.contains(&key) && !saved.contains("secret_key ="));
+        assert_eq!(AppConfig::load(path).unwrap().secret_key, key);
+    }
+
+    #[test]
+    fn v1_config_is_migrated_to_current_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        // 第 1 版配置：没有 config_version，也没有后来新增的字段
+        let v1 = format!(
+            "listen_port = 5000\nsecret_key = \"{EXAMPLE_SECRET_KEY}\"\nmax_file_size = 1024\n\n\
+             [[peers]]\nhost = \"10.0.0.2\"\nport = 5001\n"
+        );
+        fs::write(&path, v1).unwrap();
+        let cfg = AppConfig::load(path.clone()).unwrap();
+        assert_eq!(cfg.config_version, CONFIG_VERSION);
+        assert_eq!(cfg.chunk_size, DEFAULT_CHUNK_SIZE);
+
+        // 文件已按当前版本写回，原有设置保留，新增字段写入默认值
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(saved.contains(&format!("config_version = {CONFIG_VERSION}")), "{saved}");
+        assert!(saved.contains("chunk_size"));
+        let reloaded: AppConfig = toml::from_str(&saved).unwrap();
+        assert_eq!(reloaded.listen_port, 5000);
+        assert_eq!(reloaded.max_file_size, 1024);
+        assert_eq!(reloaded.peers[0].host, "10.0.0.2");
+
+        // JSON 配置按 JSON 写回
+        let json_path = dir.path().join("config.json");
+        let v1 = format!(r#"{{"listen_port": 5000, "secret_key": "{EXAMPLE_SECRET_KEY}"}}"#);
+        fs::write(&json_path, v1).unwrap();
+        AppConfig::load(json_path.clone()).unwrap();
+        let saved: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&json_path).unwrap()).unwrap();
+        assert_eq!(saved["config_version"], CONFIG_VERSION);
+    }
+
+    #[test]
+    fn future_config_version_loads_without_rewrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let future = format!(
+            "config_version = {}\nlisten_port = 5000\nsecret_key = \"{EXAMPLE_SECRET_KEY}\"\n\
+             setting_from_the_future = true\n",
+            CONFIG_VERSION + 1
+        );
+        fs::write(&path, &future).unwrap();
+        let cfg = AppConfig::load(path.clone()).unwrap();
+        assert_eq!(cfg.config_version, CONFIG_VERSION + 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), future);
+    }
+
+    /// 内存中的密钥环，代替测试环境中不可用的系统密钥环
+    #[derive(Default)]
+    struct MemoryKeyring(std::sync::Mutex<std::collections::HashMap<String, String>>);
+
+    impl SecretStore for MemoryKeyring {
+        fn get(&self, name: &str) -> Result<String, ConfigError> {
+            self.0
+                .lock()
+                .unwrap()
+                .get(name)
+                .cloned()
+                .ok_or_else(|| ConfigError::Invalid(format!("no keyring entry {name}")))
+        }
+
+        fn set(&self, name: &str, secret: &str) -> Result<(), ConfigError> {
+            self.0.lock().unwrap().insert(name.into(), secret.into());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn secret_key_precedence_is_file_then_keyring_then_inline() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let keyring = MemoryKeyring::default();
+        let (file_key, keyring_key) =
+            (AppConfig::generate_secret_key(), AppConfig::generate_secret_key());
+        keyring.set("laptop", &keyring_key).unwrap();
+        fs::write(dir.path().join("secret.key"), &file_key).unwrap();
+
+        let write = |extra: &str| {
+            let toml = format!("listen_port = 5000
+secret_key = \"{EXAMPLE_SECRET_KEY}\"\n{extra}");
+            fs::write(&path, toml).unwrap();
+            AppConfig::load_from(path.clone(), None, &keyring)
+        };
+        assert_eq!(write("").unwrap().secret_key, EXAMPLE_SECRET_KEY);
+        let cfg = write("secret_key_keyring = \"laptop\"\n").unwrap();
+        assert_eq!(cfg.secret_key, keyring_key);
+        let both = "secret_key_file = \"secret.key\"\nsecret_key_keyring = \"laptop\"\n";
+        assert_eq!(write(both).unwrap().secret_key, file_key);
+
+        // 缺失的密钥环条目报错，而不是回退到内联密钥
+        match write("secret_key_keyring = \"desktop\"\n") {
+            Err(ConfigError::Invalid(msg)) => assert!(msg.contains("desktop"), "{msg}"),
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        // 保存时密钥只写入密钥环
+        let rotated = AppConfig {
+            secret_key: AppConfig::generate_secret_key(),
+            ..cfg
+        };
+        rotated.save_to(&path, None, &keyring).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(!saved.contains(&rotated.secret_key) && !saved.contains("secret_key ="));
+        assert_eq!(keyring.get("laptop").unwrap(), rotated.secret_key);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn secret_key_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let key_path = dir.path().join("secret.key");
+        let cfg = AppConfig {
+            secret_key: AppConfig::generate_secret_key(),
+            secret_key_file: Some("secret.key".into()),
+            ..AppConfig::default()
+        };
+        cfg.save(&path).unwrap();
+        let mode = fs::metadata(&key_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // 已存在的宽权限密钥文件在写入新密钥时被收紧
+        fs::set_permissions(&key_path, fs::Permissions::from_mode(0o644)).unwrap();
+        let rotated = AppConfig {
+            secret_key: AppConfig::generate_secret_key(),
+            ..cfg
+        };
+        rotated.save(&path).unwrap();
+        let mode = fs::metadata(&key_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn discovery_service_must_be_a_dns_sd_type() {
+        let with = |service: &str| AppConfig {
+            discovery_service: service.into(),
+            ..AppConfig::default()
+        };
+        assert!(with("_lanclip._tcp").validate().is_ok());
+        assert!(with("_team-a._tcp").validate().is_ok());
+        for bad in ["lanclip._tcp", "_lanclip._udp", "_._tcp", "_a_b._tcp", "_averyveryverylongname._tcp"] {
+            assert!(with(bad).validate().is_err(), "{bad}");
+        }
+        let empty_instance = AppConfig {
+            discovery_instance: Some(String::new()),
+            ..AppConfig::default()
+        };
+        assert!(empty_instance.validate().is_err());
+    }
+
+    #[test]
+    fn instance_id_must_be_a_uuid() {
+        let with = |id: &str| AppConfig {
+            instance_id: Some(id.into()),
+            ..AppConfig::default()
+        };
+        assert!(with("6f1c2d3e-4b5a-4c6d-8e7f-0123456789ab").validate().is_ok());
+        assert!(with("my-laptop").validate().is_err());
+    }
+
+    #[test]
+    fn missing_secret_key_file_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "listen_port = 5000\nsecret_key_file = \"missing.key\"\n").unwrap();
+        match AppConfig::load(path) {
+            Err(ConfigError::Invalid(msg)) => assert!(msg.contains("missing.key"), "{msg}"),
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn encrypted_config_roundtrips_and_rejects_wrong_passphrase() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let cfg = AppConfig {
+            secret_key: AppConfig::generate_secret_key(),
+            encrypt_config: true,
+            ..AppConfig::default()
+        };
+        assert!(matches!(cfg.save_with_passphrase(&path, None), Err(ConfigError::Invalid(_))));
+        cfg.save_with_passphrase(&path, Some("correct horse")).unwrap();
+
+        let raw = fs::read(&path).unwrap();
+        assert!(raw.starts_with(ENCRYPTED_CONFIG_MAGIC));
+        assert!(!String::from_utf8_lossy(&raw).contains(&cfg.secret_key));
+
+        let loaded = AppConfig::load_with_passphrase(path.clone(), Some("correct horse")).unwrap();
+        assert_eq!(loaded.secret_key, cfg.secret_key);
+        assert!(loaded.encrypt_config);
+        for passphrase in [Some("wrong"), None] {
+            assert!(matches!(
+                AppConfig::load_with_passphrase(path.clone(), passphrase),
+                Err(ConfigError::Invalid(_))
+            ));
+        }
+
+        // 未开启加密时仍以明文保存，且读取明文配置不需要口令
+        let plain = AppConfig {
+            encrypt_config: false,
+            ..loaded
+        };
+        plain.save_with_passphrase(&path, Some("correct horse")).unwrap();
+        let reloaded = AppConfig::load_with_passphrase(path, None).unwrap();
+        assert_eq!(reloaded.secret_key, cfg.secret_key);
+    }
+
+    #[test]
+    fn download_dir_resolution() {
+        let mut cfg = AppConfig::default();
+        assert_eq!(cfg.download_dir(), AppConfig::default_download_dir());
+        assert!(cfg.download_dir().ends_with("lan-clipboard")
+            || cfg.download_dir().ends_with("lan-clipboard-downloads"));
+
+        cfg.download_dir = Some(PathBuf::from("/tmp/recv"));
+        assert_eq!(cfg.download_dir(), PathBuf::from("/tmp/recv"));
+
+        // 空路径视为未配置
+        cfg.download_dir = Some(PathBuf::new());
+        assert_eq!(cfg.download_dir(), AppConfig::default_download_dir());
     }
 }
@@ -4,8 +4,8 @@ use crate::config::{AppConfig, PeerConfig};
 use eframe::egui;
 use std::path::PathBuf;
 
-/// 内嵌中文字体（Noto Sans SC），配置 UI 启动时设置。
-fn setup_chinese_font(ctx: &egui::Context) {
+/// 内嵌中文字体（Noto Sans SC），配置 UI 与展示窗口启动时设置。
+pub(crate) fn setup_chinese_font(ctx: &egui::Context) {
     let mut fonts = egui::FontDefinitions::default();
     fonts.font_data.insert(
         "NotoSansSC".to_owned(),
@@ -25,10 +25,10 @@ fn setup_chinese_font(ctx: &egui::Context) {
 }
 
 /// 构建 NativeOptions，在 Linux 下允许非主线程创建事件循环。
-fn native_options() -> eframe::NativeOptions {
+pub(crate) fn native_options(inner_size: [f32; 2], title: &str) -> eframe::NativeOptions {
     let viewport = egui::ViewportBuilder::default()
-        .with_inner_size([420.0, 380.0])
-        .with_title("LAN 剪贴板同步 - 配置");
+        .with_inner_size(inner_size)
+        .with_title(title);
 
     #[cfg(target_os = "linux")]
     let event_loop_builder = {
@@ -50,6 +50,8 @@ fn native_options() -> eframe::NativeOptions {
 /// 配置编辑器应用，在独立窗口中运行。
 pub struct ConfigApp {
     config_path: PathBuf,
+    /// 加载时的完整配置，保存时保留界面未涉及的字段
+    base: AppConfig,
     listen_port: String,
     secret_key: String,
     max_file_size: String,
@@ -67,19 +69,14 @@ impl ConfigApp {
     pub fn new(config_path: PathBuf) -> Self {
         let config = AppConfig::load(config_path.clone()).unwrap_or_else(|e| {
             tracing::warn!("failed to load config, using defaults: {}", e);
-            AppConfig {
-                listen_port: 5000,
-                secret_key: "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef"
-                    .to_string(),
-                max_file_size: 10 * 1024 * 1024,
-                peers: vec![],
-            }
+            AppConfig::default()
         });
         Self {
             config_path,
+            base: config.clone(),
             listen_port: config.listen_port.to_string(),
             secret_key: config.secret_key.clone(),
-            max_file_size: config.max_file_size.to_string(),
+            max_file_size: format_size(config.max_file_size),
             peers: config
                 .peers
                 .iter()
@@ -91,7 +88,7 @@ impl ConfigApp {
 
     fn collect_config(&self) -> Result<AppConfig, String> {
         let listen_port: u16 = self.listen_port.trim().parse().map_err(|_| "监听端口必须是 1-65535 的数字")?;
-        let max_file_size: u64 = self.max_file_size.trim().parse().map_err(|_| "最大文件大小必须是有效的数字（字节）")?;
+        let max_file_size = parse_size(&self.max_file_size).map_err(|e| format!("最大文件大小: {}", e))?;
         let mut peers = Vec::new();
         for (i, (host, port_str)) in self.peers.iter().enumerate() {
             let host = host.trim().to_string();
@@ -101,13 +98,22 @@ impl ConfigApp {
             let port: u16 = port_str.trim().parse().map_err(|_| {
                 format!("对端 #{} 的端口必须是有效数字", i + 1)
             })?;
-            peers.push(PeerConfig { host, port });
+            // 保留界面未涉及的对端字段（如文件接收权限）
+            let peer = match self.base.peers.iter().find(|p| p.host == host) {
+                Some(existing) => PeerConfig {
+                    port,
+                    ..existing.clone()
+                },
+                None => PeerConfig::new(host, port),
+            };
+            peers.push(peer);
         }
         let config = AppConfig {
             listen_port,
             secret_key: self.secret_key.trim().to_string(),
             max_file_size,
             peers,
+            ..self.base.clone()
         };
         config.validate().map_err(|e| e.to_string())?;
         Ok(config)
@@ -183,11 +189,11 @@ impl eframe::App for ConfigApp {
                 ui.add_space(4.0);
 
                 ui.horizontal(|ui| {
-                    ui.label("最大文件 (字节):");
+                    ui.label("最大文件:");
                     ui.add(
                         egui::TextEdit::singleline(&mut self.max_file_size)
                             .desired_width(120.0)
-                            .hint_text("10485760"),
+                            .hint_text("10MiB"),
                     );
                 });
                 ui.add_space(12.0);
@@ -220,9 +226,77 @@ impl eframe::App for ConfigApp {
     }
 }
 
+/// 大小单位：十进制（KB/MB/GB，1000 进制）与二进制（KiB/MiB/GiB，1024 进制）
+const SIZE_UNITS: [(&str, u64); 7] = [
+    ("B", 1),
+    ("KB", 1000),
+    ("MB", 1000 * 1000),
+    ("GB", 1000 * 1000 * 1000),
+    ("KiB", 1 << 10),
+    ("MiB", 1 << 20),
+    ("GiB", 1 << 30),
+];
+
+/// 解析带单位的大小（如 `10MB`、`512 KiB`、`1.5GiB`，不带单位时为字节），单位不区分大小写。
+///
+/// 只写 `K`/`M`/`G` 无法区分十进制与二进制，视为无效；结果必须是整数字节。
+pub fn parse_size(input: &str) -> Result<u64, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(input.len());
+    let (number, unit) = (&input[..split], input[split..].trim());
+    let multiplier = if unit.is_empty() {
+        1
+    } else {
+        SIZE_UNITS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(unit))
+            .map(|&(_, m)| m)
+            .ok_or_else(|| {
+                format!(
+                    "无法识别的单位 \"{unit}\"，请使用 B、KB/MB/GB（1000 进制）或 KiB/MiB/GiB（1024 进制）"
+                )
+            })?
+    };
+    let (int_part, frac_part) = number.split_once('.').unwrap_or((number, ""));
+    if int_part.is_empty() && frac_part.is_empty() || frac_part.contains('.') {
+        return Err(format!("\"{input}\" 不是有效的大小"));
+    }
+    let invalid = || format!("\"{input}\" 超出范围或不是整数字节");
+    let parse_digits = |digits: &str| -> Result<u128, String> {
+        if digits.is_empty() {
+            return Ok(0);
+        }
+        digits.parse().map_err(|_| invalid())
+    };
+    let (int_value, frac_value) = (parse_digits(int_part)?, parse_digits(frac_part)?);
+    let scale = 10u128.checked_pow(frac_part.len() as u32).ok_or_else(invalid)?;
+    let scaled = int_value
+        .checked_mul(scale)
+        .and_then(|v| v.checked_add(frac_value))
+        .and_then(|v| v.checked_mul(u128::from(multiplier)))
+        .ok_or_else(invalid)?;
+    if !scaled.is_multiple_of(scale) {
+        return Err(invalid());
+    }
+    u64::try_from(scaled / scale).map_err(|_| invalid())
+}
+
+/// 以能整除的最大单位显示大小（优先二进制单位），例如 10485760 显示为 `10MiB`。
+pub fn format_size(bytes: u64) -> String {
+    let binary = SIZE_UNITS[4..].iter().rev();
+    let decimal = SIZE_UNITS[1..4].iter().rev();
+    binary
+        .chain(decimal)
+        .find(|&&(_, m)| bytes != 0 && bytes.is_multiple_of(m))
+        .map(|&(name, m)| format!("{}{}", bytes / m, name))
+        .unwrap_or_else(|| bytes.to_string())
+}
+
 /// 在独立窗口中运行配置 UI（阻塞直到窗口关闭）。
 pub fn run(config_path: PathBuf) {
-    let options = native_options();
+    let options = native_options([420.0, 380.0], "LAN 剪贴板同步 - 配置");
 
     let _ = eframe::run_native(
         "LAN Clipboard Sync Config",
@@ -233,3 +307,36 @@ pub fn run(config_path: PathBuf) {
         }),
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_strings_use_decimal_and_binary_suffixes() {
+        assert_eq!(parse_size("10485760"), Ok(10_485_760));
+        assert_eq!(parse_size("10MB"), Ok(10_000_000));
+        assert_eq!(parse_size("10MiB"), Ok(10_485_760));
+        assert_eq!(parse_size(" 512 kb "), Ok(512_000));
+        assert_eq!(parse_size("512KiB"), Ok(524_288));
+        assert_eq!(parse_size("1GiB"), Ok(1 << 30));
+        assert_eq!(parse_size("1.5KiB"), Ok(1536));
+        assert_eq!(parse_size("2b"), Ok(2));
+
+        let invalid = ["", "10M", "10K", "abc", "1.2.3MB", "0.5B", "1.0001KB", "99999999999GiB", "MB"];
+        for bad in invalid {
+            assert!(parse_size(bad).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn sizes_are_displayed_in_friendly_units() {
+        assert_eq!(format_size(10_485_760), "10MiB");
+        assert_eq!(format_size(10_000_000), "10MB");
+        assert_eq!(format_size(1536), "1536");
+        assert_eq!(format_size(0), "0");
+        for bytes in [1, 1000, 1024, 123_456_789, 3 << 30] {
+            assert_eq!(parse_size(&format_size(bytes)), Ok(bytes));
+        }
+    }
+}
@@ -1,83 +1,659 @@
 //! 核心业务逻辑：连接剪贴板抽象与网络层，实现去重与防回声的同步流程。
 
-use crate::clipboard::{spawn_clipboard_watcher, ClipboardFile, ClipboardItem, SystemClipboard};
-use crate::config::AppConfig;
-use crate::network::{broadcast_to_peers, NetworkServer};
-use crate::protocol::{ContentType, FileEntry, ProtocolMessage};
+use crate::auto_open::{is_executable, open_with_default_handler, should_auto_open};
+use crate::clipboard::{
+    content_fingerprint, local_file_path, parse_url, plain_text_format, set_display_backend,
+    spawn_clipboard_watcher, text_item, with_png_fallback, ClipboardBackend, ClipboardFile, ClipboardItem, DisplaySink,
+    MemoryClipboard, SystemClipboard, MIME_PLAIN_TEXT,
+};
+use crate::config::{
+    AppConfig, ClipboardBackendKind, DisplayBackend, NameCollisionPolicy, NewlinePolicy, PeerConfig,
+    ReceivedFilesFormat, TextTransform, UnicodeNormalization,
+};
+use crate::delta::{apply_delta, compute_delta, TextDelta};
+use crate::echo::echo_reply;
+use crate::events::{CoreEvent, TransferPhase};
+use crate::expiry::{self, ExpiryQueue};
+use crate::file_chunks::{
+    plan_chunks, read_chunk, CompletedTransfer, IncomingChunk, IncomingFileChunks, OutgoingFile,
+};
+use crate::history::{ClipboardHistory, HistoryEntry};
+use crate::keyring::{key_id, KeyRing};
+use crate::lww::{self, LastWriter};
+use crate::manifest::{
+    file_hash, manifest_entries, FileIndex, OutgoingTransfers, PendingManifest, TRANSFER_TTL,
+};
+use crate::network::{
+    broadcast_body, send_to_addr, send_to_peer, BoundPort, ConnectionPool, IncomingMessage,
+    KeepaliveSettings, NetworkServer, PeerCooldown, PeerSendOutcome, PeerSendResult,
+    TransferProgress,
+};
+use crate::notify::{describe_item, Notification};
+use crate::presence::{DeviceRegistry, PRESENCE_INTERVAL, PRESENCE_TTL};
+use crate::protocol::{
+    decode_files_payload, encode_message, ContentType, FileEntry, FilesPayload, ManifestEntry,
+    ProtocolMessage, Selection, CAP_FILES, CAP_FILE_CHUNKS,
+    CAP_IMAGE, CAP_MULTI, CAP_RESUME, CAP_TEXT, CAP_TRANSFER_STATUS, CAP_URL, MAX_FRAME_BODY,
+};
+use crate::relay::{RelayClient, RelayHandle};
+use crate::remote_session::detect_remote_session;
+use crate::resume::{ChunkOutcome, OutgoingChunks, PartialStore};
+use crate::retention::{cleanup_download_dir, CLEANUP_INTERVAL};
+use crate::discovery::Discovery;
+use crate::srv::{merge_peers, refresh_srv_peers, resolve_srv};
+use crate::stats::Stats;
 use anyhow::Result;
 use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::hash::{Hash, Hasher};
+use std::net::{IpAddr, SocketAddr};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
-use std::time::{Duration, Instant};
-use tokio::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{broadcast, mpsc};
+use unicode_normalization::UnicodeNormalization as _;
 use uuid::Uuid;
 
 const SUPPRESS_WINDOW: Duration = Duration::from_millis(1500);
+/// 剪贴板读取缓存有效期：同一轮 watcher 事件内的多次读取复用结果
+const READ_CACHE_TTL: Duration = Duration::from_millis(50);
+/// 启用增量同步的最小文本长度（字节），短文本直接全量发送
+const DELTA_MIN_TEXT_LEN: usize = 4096;
+/// 连续发送该数量的增量后改发一次全量文本，接收端基准即使因故不一致也能及时恢复
+const DELTA_RESYNC_INTERVAL: u64 = 8;
+/// 记住的最近消息 ID 数量
+const SEEN_MESSAGES_CAPACITY: usize = 256;
+/// 记住的本机写入剪贴板的内容哈希数量
+const SELF_WRITES_CAPACITY: usize = 8;
+/// 记住的最近从对端应用的内容哈希数量
+const APPLIED_HASHES_CAPACITY: usize = 32;
+/// 从对端应用的内容在该时长内不会被本机再次广播
+const APPLIED_HASH_WINDOW: Duration = Duration::from_secs(30);
+/// 同步事件通道容量，订阅者落后超过该数量时丢弃最早的事件
+const EVENT_CHANNEL_CAPACITY: usize = 64;
+/// 断点续传的部分文件所在的下载目录子目录
+const PARTIAL_DIR: &str = ".partial";
+/// 分块接收中的文件所在的下载目录子目录（传输状态只在内存中，启动时清空）
+const FILE_CHUNK_DIR: &str = ".chunks";
+/// 超过该时长未更新的部分文件视为发送端已放弃，启动时删除
+const PARTIAL_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+/// 等待单个块确认的最长时间
+const CHUNK_ACK_TIMEOUT: Duration = Duration::from_secs(5);
+/// 单个块连续发送失败或未确认时的最大重试次数
+const CHUNK_MAX_RETRIES: u32 = 10;
+/// 达到该大小的负载在发送前后通知对端传输状态（[`ProtocolMessage::TransferStart`]）
+const TRANSFER_STATUS_MIN_SIZE: u64 = 1024 * 1024;
+/// PNG 文件签名
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+/// 重新解析不接收文件的对端主机名的间隔
+const FILES_DENIED_REFRESH_INTERVAL: Duration = Duration::from_secs(300);
 
 /// 核心服务：封装剪贴板监听、网络服务器与去重逻辑。
 pub struct CoreService {
     config: AppConfig,
-    /// 本实例唯一 ID，用于识别并忽略自己发出的回环消息
+    /// 本实例唯一 ID（即消息中的 `sender_id`），用于识别并忽略自己发出的回环消息。
+    /// 取自配置项 `instance_id`，缺省时生成并在设置配置文件路径时写回，重启后保持不变。
     instance_id: Uuid,
     clipboard_change_rx: mpsc::Receiver<()>,
-    incoming_msg_rx: mpsc::Receiver<ProtocolMessage>,
-    _clipboard_watcher: JoinHandle<()>,
+    /// PRIMARY 选区文本变化（仅 Linux 且开启 sync_primary 时有发送端）
+    primary_change_rx: mpsc::Receiver<String>,
+    incoming_msg_rx: mpsc::Receiver<IncomingMessage>,
+    /// 对端设备在线注册表（由 Presence 消息维护）
+    devices: DeviceRegistry,
+    /// 最近收到的远端剪贴板记录
+    history: ClipboardHistory,
+    /// 上一次成功送达所有对端的文本，作为增量同步的基准
+    text_delta_base: Mutex<Option<String>>,
+    /// 上一次全量文本之后连续发出的增量数
+    deltas_since_full: AtomicU64,
+    /// 各发送者最近一次同步的文本，用于重建收到的增量
+    remote_text_bases: HashMap<[u8; 16], String>,
+    /// 下一条剪贴板消息（及传输 ID）的序号：高 32 位为本次启动随机选取的纪元，
+    /// 低 32 位从 1 开始计数，实例 ID 不变时重启前后的序号也不会重复（0 保留为“未携带”）
+    next_seq: AtomicU64,
+    /// 最近一次应用或发出的剪贴板写入，用于最后写入者胜出的冲突解决
+    last_writer: Mutex<LastWriter>,
+    /// 最近处理过的消息 ID，用于忽略重复投递
+    seen_messages: SeenMessages,
+    /// 已广播清单、等待对端请求的文件
+    outgoing_transfers: Mutex<OutgoingTransfers>,
+    /// 已收到清单、等待文件数据的传输（按发送者与传输 ID 索引）
+    pending_manifests: HashMap<([u8; 16], u64), PendingManifest>,
+    /// 已接收文件的内容哈希索引，清单传输时跳过本地已有的文件
+    file_index: Mutex<FileIndex>,
+    /// 不接收文件的来源地址（peers 中 `allow_files_from = false` 的对端），主机名在后台解析
+    files_denied: HashSet<IpAddr>,
+    /// 与网络服务器共享的密钥环
+    keys: KeyRing,
+    /// 配置文件路径，密钥轮换后写回配置
+    config_path: Option<PathBuf>,
+    /// 网络服务实际监听的端口（`listen_port = 0` 时由系统分配）
+    bound_port: BoundPort,
+    /// 隧道友好模式下与对端的长连接池
+    connections: Option<ConnectionPool>,
+    /// 连接失败的对端在冷却期内不再尝试（`peer_failure_cooldown_secs` 为 0 时为 None）
+    unreachable: Option<PeerCooldown>,
+    /// 配置了中继时经中继发送的句柄
+    relay: Option<RelayHandle>,
+    /// 大帧的入站接收进度，由托盘等界面取走后读取
+    inbound_progress: Option<mpsc::Receiver<TransferProgress>>,
+    /// 自定义剪贴板后端，`run` 开始时取出；None 表示使用系统剪贴板
+    backend: Option<Box<dyn ClipboardBackend + Send>>,
+    /// 展示模式下收到内容的去处，由展示窗口读取
+    display: Option<DisplaySink>,
+    _clipboard_watcher: Option<JoinHandle<()>>,
+    /// 运行期间切换 Linux 剪贴板后端的请求（托盘菜单发出）
+    display_backend_tx: mpsc::Sender<DisplayBackend>,
+    display_backend_rx: mpsc::Receiver<DisplayBackend>,
+    /// 托盘“发送到”菜单的请求：把当前剪贴板单独发给指定地址（host:port）
+    single_peer_tx: mpsc::Sender<String>,
+    single_peer_rx: mpsc::Receiver<String>,
+    /// 立即发送请求（全局快捷键发出）：读取当前选区或剪贴板并广播
+    send_now_tx: mpsc::Sender<()>,
+    send_now_rx: mpsc::Receiver<()>,
+    /// 暂停或恢复同步的请求（托盘单击发出）
+    pause_toggle_tx: mpsc::Sender<()>,
+    pause_toggle_rx: mpsc::Receiver<()>,
+    /// 轮换共享密钥的请求（托盘菜单发出）：生成新的随机密钥并向所有对端宣布
+    rotate_key_tx: mpsc::Sender<()>,
+    rotate_key_rx: mpsc::Receiver<()>,
+    /// 收发剪贴板内容时发布的同步事件，无订阅者时直接丢弃
+    events: broadcast::Sender<CoreEvent>,
+    /// 断点续传的接收进度（持久化在下载目录中）
+    partials: PartialStore,
+    /// 进行中的分块发送，按传输 ID 转交收到的块确认
+    chunk_acks: Arc<Mutex<HashMap<u64, mpsc::Sender<u64>>>>,
+    /// 对端已宣布、尚未收齐的大负载传输（按发送者索引）：内容类型与宣布的字节数
+    incoming_transfers: HashMap<[u8; 16], (ContentType, u64)>,
+    /// 分块接收中的文件（[`ProtocolMessage::FileChunk`]）
+    incoming_file_chunks: IncomingFileChunks,
+    /// 自上次定期统计日志以来的收发统计
+    stats: Arc<Mutex<Stats>>,
 }
 
 impl CoreService {
-    /// 创建核心服务，启动剪贴板 watcher 与网络监听线程。
+    /// 创建核心服务，按配置选择剪贴板后端，启动剪贴板 watcher 与网络监听线程。
     pub fn new(config: AppConfig) -> Result<Self> {
-        let (clip_tx, clip_rx) = mpsc::channel(32);
-        let watcher = spawn_clipboard_watcher(clip_tx);
+        match config.clipboard_backend {
+            ClipboardBackendKind::System => Self::build(config, None),
+            ClipboardBackendKind::Memory => {
+                tracing::info!("using in-memory clipboard backend (relay mode)");
+                Self::with_backend(config, Box::new(MemoryClipboard::new()))
+            }
+            ClipboardBackendKind::Display => {
+                tracing::info!("using display backend (received content is shown in a window)");
+                let sink = DisplaySink::new();
+                let mut core = Self::with_backend(config, Box::new(sink.clone()))?;
+                core.display = Some(sink);
+                Ok(core)
+            }
+        }
+    }
+
+    /// 使用指定的剪贴板后端创建核心服务。
+    pub fn with_backend(
+        config: AppConfig,
+        backend: Box<dyn ClipboardBackend + Send>,
+    ) -> Result<Self> {
+        Self::build(config, Some(backend))
+    }
+
+    /// `backend` 为 None 时使用系统剪贴板（在 `run` 所在线程中创建）。
+    fn build(
+        mut config: AppConfig,
+        backend: Option<Box<dyn ClipboardBackend + Send>>,
+    ) -> Result<Self> {
+        config.drop_self_loop_peers();
+        set_display_backend(config.display_backend);
+        let (clip_tx, clip_rx) = mpsc::channel(config.clipboard_channel_capacity);
+        let poll_interval = Duration::from_millis(config.clipboard_poll_interval_ms);
+        let watcher = match &backend {
+            Some(backend) => backend.watch(clip_tx, poll_interval),
+            None => Some(spawn_clipboard_watcher(clip_tx, poll_interval)),
+        };
+        let (primary_tx, primary_rx) = mpsc::channel(8);
+        #[cfg(target_os = "linux")]
+        if config.sync_primary {
+            crate::clipboard::spawn_primary_watcher(
+                primary_tx,
+                Duration::from_millis(config.clipboard_poll_interval_ms),
+            );
+        }
+        #[cfg(not(target_os = "linux"))]
+        drop(primary_tx);
 
-        let (incoming_tx, incoming_rx) = mpsc::channel(32);
-        let server = NetworkServer::new(&config, incoming_tx)?;
+        let (incoming_tx, incoming_rx) = mpsc::channel(config.incoming_channel_capacity);
+        let keys = KeyRing::from_config(&config)?;
+        let (relay_client, relay) = match &config.relay {
+            Some(peer) => {
+                let (client, handle) = RelayClient::new(&config, peer, incoming_tx.clone())?;
+                (Some(client), Some(handle))
+            }
+            None => (None, None),
+        };
+        let (progress_tx, progress_rx) = mpsc::channel(16);
+        let server = NetworkServer::with_keyring(&config, keys.clone(), incoming_tx)?
+            .with_progress(progress_tx);
+        let bound_port = server.bound_port();
+        let connections = config
+            .persistent_connections
+            .then(|| ConnectionPool::new(KeepaliveSettings::from_config(&config)));
+        let unreachable = (config.peer_failure_cooldown_secs > 0)
+            .then(|| PeerCooldown::new(Duration::from_secs(config.peer_failure_cooldown_secs)));
 
         // 启动网络监听：单独线程内创建 Tokio runtime 运行异步服务器
         std::thread::spawn(move || {
             if let Ok(rt) = tokio::runtime::Runtime::new() {
-                rt.block_on(async {
-                    if let Err(e) = server.run().await {
-                        tracing::error!("network server error: {e}");
-                    }
-                });
+                if let Some(client) = relay_client {
+                    rt.spawn(client.run());
+                }
+                rt.block_on(server.run_supervised());
             } else {
                 tracing::error!("failed to create tokio runtime for network server");
             }
         });
 
-        let instance_id = Uuid::new_v4();
-        tracing::debug!("instance_id={}", instance_id);
+        let instance_id = match &config.instance_id {
+            Some(id) => Uuid::parse_str(id)?,
+            None => Uuid::new_v4(),
+        };
+        let epoch: u32 = rand::random();
+        tracing::debug!("instance_id={} epoch={:#010x}", instance_id, epoch);
+        if let Some(warning) = config.frame_memory_warning() {
+            tracing::warn!("{}", warning);
+        }
+        let (display_backend_tx, display_backend_rx) = mpsc::channel(4);
+        let (single_peer_tx, single_peer_rx) = mpsc::channel(4);
+        let (send_now_tx, send_now_rx) = mpsc::channel(1);
+        let (pause_toggle_tx, pause_toggle_rx) = mpsc::channel(1);
+        let (rotate_key_tx, rotate_key_rx) = mpsc::channel(1);
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        let partials = PartialStore::new(config.download_dir().join(PARTIAL_DIR));
+        let pruned = partials.prune(PARTIAL_MAX_AGE, SystemTime::now());
+        if pruned > 0 {
+            tracing::info!("removed {} abandoned partial transfer(s)", pruned);
+        }
+        let chunk_dir = config.download_dir().join(FILE_CHUNK_DIR);
+        let _ = std::fs::remove_dir_all(&chunk_dir);
+        let incoming_file_chunks = IncomingFileChunks::new(chunk_dir, config.max_file_size);
+
+        let history = if config.persist_history {
+            ClipboardHistory::persistent(config.history_size, config.history_file())
+        } else {
+            ClipboardHistory::new(config.history_size)
+        };
+        let files_denied = literal_files_denied(&config.peers);
 
         Ok(Self {
             config,
             instance_id,
             clipboard_change_rx: clip_rx,
+            primary_change_rx: primary_rx,
             incoming_msg_rx: incoming_rx,
+            devices: DeviceRegistry::new(),
+            history,
+            text_delta_base: Mutex::new(None),
+            deltas_since_full: AtomicU64::new(0),
+            remote_text_bases: HashMap::new(),
+            next_seq: AtomicU64::new(u64::from(epoch) << 32 | 1),
+            last_writer: Mutex::default(),
+            seen_messages: SeenMessages::new(SEEN_MESSAGES_CAPACITY),
+            outgoing_transfers: Mutex::new(OutgoingTransfers::default()),
+            pending_manifests: HashMap::new(),
+            file_index: Mutex::new(FileIndex::default()),
+            files_denied,
+            keys,
+            config_path: None,
+            bound_port,
+            connections,
+            unreachable,
+            relay,
+            inbound_progress: Some(progress_rx),
+            backend,
+            display: None,
             _clipboard_watcher: watcher,
+            display_backend_tx,
+            display_backend_rx,
+            single_peer_tx,
+            single_peer_rx,
+            send_now_tx,
+            send_now_rx,
+            pause_toggle_tx,
+            pause_toggle_rx,
+            rotate_key_tx,
+            rotate_key_rx,
+            events,
+            partials,
+            chunk_acks: Arc::default(),
+            incoming_transfers: HashMap::new(),
+            incoming_file_chunks,
+            stats: Arc::default(),
         })
     }
 
+    /// 设置配置文件路径，密钥轮换确认后会将新密钥写回该文件；
+    /// 配置中还没有实例 ID 时把本次生成的 ID 写入该文件。
+    pub fn set_config_path(&mut self, path: PathBuf) {
+        if self.config.instance_id.is_none() {
+            let id = self.instance_id.to_string();
+            if let Err(e) = AppConfig::persist_instance_id(&path, id.clone()) {
+                tracing::error!("failed to persist instance id to {}: {}", path.display(), e);
+            }
+            self.config.instance_id = Some(id);
+        }
+        self.config_path = Some(path);
+    }
+
+    /// 向所有对端宣布新密钥（在当前主密钥下认证）。
+    ///
+    /// 所有对端均送达时立即切换为新的主密钥；否则新密钥仅加入可接受集合，主密钥保持不变。
+    pub async fn rotate_key(&mut self, new_key: [u8; 32]) -> Result<Vec<PeerSendResult>> {
+        let msg = ProtocolMessage::KeyRotation {
+            sender_id: *self.instance_id.as_bytes(),
+            key_id: key_id(&new_key),
+            new_key,
+        };
+        let results = self.broadcast_message(msg).await?;
+        if results.iter().all(PeerSendResult::is_ok) {
+            self.keys.set_primary(new_key);
+            self.adopt_primary_key();
+        } else {
+            tracing::warn!("key rotation not delivered to all peers, keeping current primary key");
+        }
+        Ok(results)
+    }
+
+    /// 使用密钥环当前的主密钥发送，并只把密钥字段写回配置文件（换下的旧密钥只在宽限期内
+    /// 保留于内存中，不写入 accepted_keys）。
+    fn adopt_primary_key(&mut self) {
+        let key = self.keys.primary();
+        self.config.secret_key = hex::encode(key);
+        self.config.accepted_keys = self.keys.others().iter().map(hex::encode).collect();
+        tracing::info!("switched primary key to id={}", hex::encode(key_id(&key)));
+        if let Some(path) = &self.config_path {
+            let result = AppConfig::persist_keys(
+                path,
+                self.config.secret_key.clone(),
+                self.config.accepted_keys.clone(),
+            );
+            if let Err(e) = result {
+                tracing::error!("failed to persist rotated key to {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// 返回设备注册表句柄，可在服务运行期间由其他线程读取在线设备列表。
+    pub fn device_registry(&self) -> DeviceRegistry {
+        self.devices.clone()
+    }
+
+    /// 将消息广播给所有对端，隧道友好模式下经长连接发送；配置了中继时同时经中继发送。
+    ///
+    /// 消息只编码一次，编码后立即释放原消息，对端与中继共享同一份编码结果。
+    async fn broadcast_message(&self, msg: ProtocolMessage) -> Result<Vec<PeerSendResult>> {
+        let body = Arc::new(encode_message(&msg)?);
+        drop(msg);
+        let pool = self.connections.as_ref();
+        let cooldown = self.unreachable.as_ref();
+        let mut results = broadcast_body(&self.config, Arc::clone(&body), pool, cooldown).await?;
+        if let Some(pool) = pool {
+            for result in results.iter().filter(|r| r.outcome == PeerSendOutcome::Coalesced) {
+                tracing::info!(
+                    "message to {} superseded by a newer one ({} coalesced drops in total)",
+                    result.addr,
+                    pool.coalesced_drops()
+                );
+            }
+        }
+        if let Some(relay) = &self.relay {
+            results.push(relay.send(body));
+        }
+        Ok(results)
+    }
+
+    /// 订阅同步事件；订阅者处理过慢时会丢失最早的事件。
+    pub fn subscribe_events(&self) -> broadcast::Receiver<CoreEvent> {
+        self.events.subscribe()
+    }
+
+    /// 返回切换 Linux 剪贴板后端的发送端：收到后重新创建系统剪贴板并重启 watcher。
+    ///
+    /// 使用自定义剪贴板后端（如内存剪贴板）时忽略切换请求。
+    pub fn display_backend_switcher(&self) -> mpsc::Sender<DisplayBackend> {
+        self.display_backend_tx.clone()
+    }
+
+    /// 返回单独发送请求的发送端：收到对端地址（host:port）后读取当前剪贴板并只发给该对端。
+    pub fn single_peer_sender(&self) -> mpsc::Sender<String> {
+        self.single_peer_tx.clone()
+    }
+
+    /// 返回立即发送请求的发送端：收到后读取当前选中的文本（Linux 下为 PRIMARY 选区）
+    /// 或剪贴板内容，即使内容未变化也广播给所有对端。
+    pub fn send_now_sender(&self) -> mpsc::Sender<()> {
+        self.send_now_tx.clone()
+    }
+
+    /// 返回暂停开关的发送端：每收到一次在暂停与恢复同步之间切换（包括远程会话导致的暂停）。
+    pub fn pause_toggle_sender(&self) -> mpsc::Sender<()> {
+        self.pause_toggle_tx.clone()
+    }
+
+    /// 返回密钥轮换请求的发送端：每收到一次生成新的随机密钥并按 [`Self::rotate_key`] 宣布。
+    pub fn rotate_key_sender(&self) -> mpsc::Sender<()> {
+        self.rotate_key_tx.clone()
+    }
+
+    /// 展示模式（`clipboard_backend = "display"`）下收到内容的去处，其他模式为 None。
+    pub fn display_sink(&self) -> Option<DisplaySink> {
+        self.display.clone()
+    }
+
+    /// 取走入站接收进度的接收端（只能取一次），用于在界面上展示大文件/图片的接收进度。
+    pub fn take_inbound_progress(&mut self) -> Option<mpsc::Receiver<TransferProgress>> {
+        self.inbound_progress.take()
+    }
+
+    /// 网络服务实际监听的端口，监听尚未就绪时返回 None。
+    pub fn bound_port(&self) -> Option<u16> {
+        self.bound_port.get()
+    }
+
+    /// 对外通告的监听端口（用于回复端口等）：优先使用实际绑定的端口。
+    fn listen_port(&self) -> u16 {
+        self.bound_port.get().unwrap_or(self.config.listen_port)
+    }
+
+    /// 获取最近收到的剪贴板历史（可在其他线程读取）。
+    pub fn history(&self) -> ClipboardHistory {
+        self.history.clone()
+    }
+
+    /// 记录收到的条目到历史并按配置显示通知。
+    ///
+    /// 缩略图生成与通知在阻塞线程池中进行，不拖慢事件循环。`sensitive` 为发送端的
+    /// 「勿同步」提示，带提示的内容只保留在内存中，不写入持久化历史。
+    fn record_received(
+        &self,
+        item: &ClipboardItem,
+        from: IpAddr,
+        source_app: Option<String>,
+        sensitive: bool,
+    ) {
+        let item = item.clone();
+        let history = self.history.clone();
+        let notify = self.config.notifications;
+        tokio::task::spawn_blocking(move || {
+            let notification = Notification::for_item(&item, from, source_app.as_deref());
+            history.push(HistoryEntry {
+                received_at: chrono::Local::now(),
+                from,
+                summary: describe_item(&item),
+                thumbnail: notification.thumbnail.clone(),
+                source_app,
+                persist: !sensitive
+                    && matches!(item, ClipboardItem::Text(_) | ClipboardItem::Url(_)),
+            });
+            if notify {
+                crate::notify::show(&notification);
+            }
+        });
+    }
+
+    /// 按 `stats_log_interval_secs` 定期输出并清零收发统计（为 0 时不启动）。
+    fn spawn_stats_logging(&self) {
+        let secs = self.config.stats_log_interval_secs;
+        if secs == 0 {
+            return;
+        }
+        let stats = Arc::clone(&self.stats);
+        tokio::spawn(async move {
+            let period = Duration::from_secs(secs);
+            let mut tick = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            let mut since = Instant::now();
+            loop {
+                tick.tick().await;
+                let mut stats = stats.lock().unwrap_or_else(|e| e.into_inner());
+                tracing::info!("{}", stats.summary(since.elapsed()));
+                stats.reset();
+                since = Instant::now();
+            }
+        });
+    }
+
+    /// 按 `download_retention_days` 启动下载目录的定期清理任务（为 0 时不启动）。
+    fn spawn_download_cleanup(&self) {
+        let days = self.config.download_retention_days;
+        if days == 0 {
+            return;
+        }
+        let dir = self.config.download_dir();
+        let max_age = Duration::from_secs(u64::from(days) * 24 * 60 * 60);
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(CLEANUP_INTERVAL);
+            loop {
+                tick.tick().await;
+                let dir = dir.clone();
+                let summary = tokio::task::spawn_blocking(move || {
+                    cleanup_download_dir(&dir, max_age, SystemTime::now())
+                })
+                .await;
+                match summary {
+                    Ok(s) if s.files_removed > 0 || s.dirs_removed > 0 => tracing::info!(
+                        "download cleanup: removed {} file(s) and {} folder(s) \
+                         older than {} day(s), freed {} bytes",
+                        s.files_removed,
+                        s.dirs_removed,
+                        days,
+                        s.bytes_freed
+                    ),
+                    Ok(_) => tracing::debug!("download cleanup: nothing to remove"),
+                    Err(e) => tracing::warn!("download cleanup task failed: {}", e),
+                }
+            }
+        });
+    }
+
+    /// 按配置创建系统剪贴板（富文本与 SVG 读取开关）。
+    fn system_clipboard(&self) -> Result<SystemClipboard> {
+        Ok(SystemClipboard::new()?
+            .with_rich_text(self.config.sync_rich_text)
+            .with_svg(self.config.sync_svg))
+    }
+
     /// 主事件循环：在本地剪贴板与远端更新之间做同步与去重。
     pub async fn run(&mut self) -> Result<()> {
-        let mut clipboard = SystemClipboard::new()?;
+        // 系统剪贴板在本线程中创建（部分平台的剪贴板句柄不能跨线程移动）
+        let system_clipboard = self.backend.is_none();
+        let mut clipboard: Box<dyn ClipboardBackend> = match self.backend.take() {
+            Some(backend) => backend,
+            None => Box::new(self.system_clipboard()?),
+        };
+        let mut read_cache = ReadCache::new(READ_CACHE_TTL);
+        let format = self.config.received_files_format;
+        let mut files_as_paths = received_files_as_paths(format, clipboard.supports_files());
         let mut last_hash: Option<u64> = None;
+        // 两阶段读取时快速路径最近一次处理的纯文本哈希
+        let mut last_fast_hash: Option<u64> = None;
         // 远端写入后的屏蔽状态：记录写入时刻和写入内容的哈希
         let mut suppress_until: Option<Instant> = None;
         let mut suppress_hash: Option<u64> = None;
+        // 写入剪贴板的内容哈希，不依赖时间窗口：剪贴板管理器重新持有选区等造成的延迟事件也能识别
+        let mut self_writes = SelfWrites::new(SELF_WRITES_CAPACITY);
+        // 最近从对端应用的内容：环形拓扑中内容被弹回时，即使错过屏蔽窗口也不再广播
+        let mut applied = AppliedHashes::new(APPLIED_HASHES_CAPACITY, APPLIED_HASH_WINDOW);
+        let mut presence_tick = tokio::time::interval(PRESENCE_INTERVAL);
+        // 远程桌面会话中由远程客户端负责剪贴板同步，按配置暂停以免形成回环
+        let mut paused = if self.config.disable_in_remote_session {
+            detect_remote_session()
+        } else {
+            None
+        };
+        if let Some(reason) = &paused {
+            tracing::warn!("remote desktop session detected ({reason}), clipboard sync paused");
+        }
+        self.spawn_download_cleanup();
+        self.spawn_stats_logging();
         tracing::debug!("clipboard sync started");
+        // 配置了 srv 时在后台解析 SRV 记录，解析出的对端与配置中的 peers 合并
+        let configured_peers = self.config.peers.clone();
+        let (srv_tx, mut srv_peers_rx) = mpsc::channel(1);
+        match self.config.srv.clone() {
+            Some(name) => {
+                tokio::spawn(refresh_srv_peers(name, resolve_srv, srv_tx));
+            }
+            None => drop(srv_tx),
+        }
+        // 开启 discovery 时广播本机并浏览局域网内的对端，服务随运行循环结束而停止
+        let (mdns_tx, mut mdns_peers_rx) = mpsc::channel(1);
+        let _discovery = if self.config.discovery {
+            let id = *self.instance_id.as_bytes();
+            Discovery::start(&self.config, id, self.listen_port(), mdns_tx)
+                .inspect_err(|e| tracing::warn!("mdns discovery unavailable: {e}"))
+                .ok()
+        } else {
+            None
+        };
+        // 不接收文件的对端主机名在进入运行循环前解析一次，之后在后台定期刷新，收到消息时只查表
+        let (files_denied_tx, mut files_denied_rx) = mpsc::channel(1);
+        if configured_peers
+            .iter()
+            .any(|p| !p.allow_files_from && p.host.parse::<IpAddr>().is_err())
+        {
+            self.files_denied = resolve_files_denied(&configured_peers).await;
+            tokio::spawn(refresh_files_denied(configured_peers.clone(), files_denied_tx));
+        } else {
+            drop(files_denied_tx);
+        }
+        let mut srv_peers = Vec::new();
+        let mut mdns_peers = Vec::new();
+        // 最近一次同步（发送或写入）的 PRIMARY 文本，用于避免回环
+        let mut last_primary: Option<String> = None;
+        // 开启 change_settle_ms 时，最近一次变化后等待内容稳定的截止时刻
+        let settle = Duration::from_millis(self.config.change_settle_ms);
+        let mut settle_deadline: Option<tokio::time::Instant> = None;
+        // 对端为收到的内容附带了保留时长时，到期后在这里清理
+        let mut expiring = ExpiryQueue::default();
 
         loop {
             tokio::select! {
-                Some(_) = self.clipboard_change_rx.recv() => {
+                Some(_) = next_settled_change(
+                    &mut self.clipboard_change_rx,
+                    &mut settle_deadline,
+                    settle,
+                ) => {
                     tracing::debug!("clipboard changed");
+                    if paused.is_some() {
+                        continue;
+                    }
                     // 检查是否在屏蔽窗口内
                     if let Some(deadline) = suppress_until {
                         if Instant::now() < deadline {
                             // 读取当前剪贴板内容，对比哈希
-                            if let Some(item) = clipboard.read()? {
-                                let h = hash_item(&item);
+                            if let Some(item) = read_cache.read(Instant::now(), || clipboard.read())? {
+                                let h = hash_item(&item, self.config.normalized_hash);
                                 if h == suppress_hash {
                                     tracing::debug!("suppressed clipboard echo (within window, same hash)");
                                     continue;
@@ -95,50 +671,568 @@ impl CoreService {
                         suppress_hash = None;
                     }
 
-                    if let Some(item) = clipboard.read()? {
+                    // 两阶段读取：先发送廉价的纯文本，下面的完整读取得到更丰富的格式时再补发
+                    if self.config.fast_text_read {
+                        if let Some(text) = clipboard.read_text()? {
+                            let item = text_item(text);
+                            let h = content_fingerprint(&item);
+                            if self_writes.take(h) {
+                                tracing::debug!("ignoring fast text read of our own write");
+                                last_fast_hash = Some(h);
+                            } else if applied.contains(h, Instant::now()) {
+                                last_fast_hash = Some(h);
+                            } else if last_fast_hash != Some(h)
+                                && last_hash != Some(h)
+                                && self.local_change_allowed(clipboard.as_ref())
+                            {
+                                tracing::debug!("broadcasting fast text read before full read");
+                                last_fast_hash = Some(h);
+                                last_hash = Some(h);
+                                self_writes.clear();
+                                self.broadcast_with_hint(item, clipboard.sensitive_hint()).await?;
+                            }
+                        }
+                    }
+
+                    if let Some(item) = read_cache.read(Instant::now(), || clipboard.read())? {
                         match &item {
                             ClipboardItem::Text(t) => {
                                 tracing::debug!("local clipboard changed: text len={}", t.len());
                             }
+                            ClipboardItem::Url(url) => {
+                                tracing::debug!("local clipboard changed: url len={}", url.len());
+                            }
                             ClipboardItem::Image(bytes) => {
                                 tracing::debug!("local clipboard changed: image bytes={}", bytes.len());
                             }
                             ClipboardItem::Files(files) => {
                                 tracing::debug!("local clipboard changed: {} file(s)", files.len());
                             }
+                            ClipboardItem::Multi(formats) => {
+                                tracing::debug!("local clipboard changed: {} format(s)", formats.len());
+                            }
                         }
-                        if let Some(h) = hash_item(&item) {
+                        if let Some(h) = hash_item(&item, self.config.normalized_hash) {
+                            if self_writes.take(h) {
+                                tracing::debug!("ignoring clipboard change caused by our own write");
+                                last_hash = Some(h);
+                                continue;
+                            }
+                            if applied.contains(h, Instant::now()) {
+                                tracing::debug!("not re-broadcasting content recently applied from a peer");
+                                last_hash = Some(h);
+                                continue;
+                            }
                             if last_hash == Some(h) {
                                 continue;
                             }
                             last_hash = Some(h);
                         }
-                        if let Some(msg) = self.build_clipboard_message(&item)? {
-                            tracing::info!("broadcasting clipboard update to peers");
-                            broadcast_to_peers(&self.config, &msg).await?;
+                        if self.config.fast_text_read {
+                            last_fast_hash = fast_text_hash(&item);
+                        }
+                        if !self.local_change_allowed(clipboard.as_ref()) {
+                            continue;
                         }
+                        // 本机有了新内容，之前写入的内容再出现时视为用户的操作
+                        self_writes.clear();
+                        self.broadcast_with_hint(item, clipboard.sensitive_hint()).await?;
                     }
                 }
-                Some(msg) = self.incoming_msg_rx.recv() => {
-                    let ProtocolMessage::ClipboardUpdate { sender_id, content_type, payload_size: _, payload } = msg;
-                    // 忽略自己发出的回环消息（例如 peers 中包含本机时的广播）
-                    if sender_id == *self.instance_id.as_bytes() {
-                        tracing::debug!("ignoring self-echo message (sender_id matches instance_id)");
+                Some(text) = self.primary_change_rx.recv() => {
+                    if paused.is_some() || last_primary.as_deref() == Some(text.as_str()) {
                         continue;
                     }
-                    tracing::info!(
-                        "received remote clipboard type={:?} bytes={}",
-                        content_type,
-                        payload.len()
-                    );
-                    if let Some(item) = self.apply_remote_clipboard(content_type, &payload)? {
-                        let written_hash = hash_item(&item);
+                    tracing::debug!("primary selection changed: text len={}", text.len());
+                    let msg = self.clipboard_update(ContentType::Text, text.clone().into_bytes(), Selection::Primary);
+                    last_primary = Some(text);
+                    self.broadcast_message(msg).await?;
+                }
+                Some(kind) = self.display_backend_rx.recv() => {
+                    if !system_clipboard {
+                        continue;
+                    }
+                    tracing::info!("switching clipboard backend to {:?}", kind);
+                    set_display_backend(kind);
+                    match self.system_clipboard() {
+                        Ok(c) => clipboard = Box::new(c),
+                        Err(e) => {
+                            tracing::error!("failed to reinitialize clipboard: {}", e);
+                            continue;
+                        }
+                    }
+                    // 替换通道后旧 watcher 的发送端随之关闭，轮询 watcher 会自行退出
+                    let (clip_tx, clip_rx) = mpsc::channel(self.config.clipboard_channel_capacity);
+                    let poll_interval = Duration::from_millis(self.config.clipboard_poll_interval_ms);
+                    self._clipboard_watcher = Some(spawn_clipboard_watcher(clip_tx, poll_interval));
+                    self.clipboard_change_rx = clip_rx;
+                    read_cache = ReadCache::new(READ_CACHE_TTL);
+                    files_as_paths = received_files_as_paths(format, clipboard.supports_files());
+                }
+                _ = sleep_until(expiring.next_deadline()) => {
+                    for entry in expiring.take_expired(Instant::now()) {
+                        remove_files(entry.files.iter());
+                        let current = clipboard.read().ok().flatten();
+                        let current = current.and_then(|c| hash_item(&c, self.config.normalized_hash));
+                        if entry.hash.is_none() || current != entry.hash {
+                            continue;
+                        }
+                        tracing::info!("received clipboard content expired, clearing clipboard");
+                        // 清空后读取为空，不会触发广播；同样的内容再次复制时应重新同步
+                        last_hash = None;
+                        read_cache.invalidate();
+                        if let Err(e) = clipboard.clear() {
+                            tracing::warn!("failed to clear expired clipboard content: {}", e);
+                        }
+                    }
+                }
+                Some(denied) = files_denied_rx.recv() => {
+                    self.files_denied = denied;
+                }
+                Some(discovered) = srv_peers_rx.recv() => {
+                    srv_peers = discovered;
+                    let discovered = srv_peers.iter().chain(&mdns_peers).cloned().collect();
+                    self.config.peers = merge_peers(&configured_peers, discovered);
+                    tracing::debug!("peer set now has {} peer(s)", self.config.peers.len());
+                }
+                Some(discovered) = mdns_peers_rx.recv() => {
+                    mdns_peers = discovered;
+                    let discovered = srv_peers.iter().chain(&mdns_peers).cloned().collect();
+                    self.config.peers = merge_peers(&configured_peers, discovered);
+                    tracing::debug!("peer set now has {} peer(s)", self.config.peers.len());
+                }
+                Some(()) = self.pause_toggle_rx.recv() => {
+                    paused = match paused.take() {
+                        Some(reason) => {
+                            tracing::info!("clipboard sync resumed (was paused: {reason})");
+                            None
+                        }
+                        None => {
+                            tracing::info!("clipboard sync paused by user");
+                            Some("paused by user".to_string())
+                        }
+                    };
+                }
+                Some(()) = self.rotate_key_rx.recv() => {
+                    let new_key: [u8; 32] = rand::random();
+                    match self.rotate_key(new_key).await {
+                        Ok(results) => {
+                            let failed = results.iter().filter(|r| !r.is_ok()).count();
+                            tracing::info!(
+                                "announced new key id={} to {} peer(s), {} failed",
+                                hex::encode(key_id(&new_key)),
+                                results.len(),
+                                failed
+                            );
+                        }
+                        Err(e) => tracing::warn!("failed to announce new key: {}", e),
+                    }
+                }
+                Some(()) = self.send_now_rx.recv() => {
+                    if paused.is_some() {
+                        continue;
+                    }
+                    let item = match selected_item(system_clipboard, clipboard.as_ref()) {
+                        Ok(Some(item)) => item,
+                        Ok(None) => {
+                            tracing::info!("nothing selected or copied, nothing to send");
+                            continue;
+                        }
+                        Err(e) => {
+                            tracing::warn!("failed to read selection: {}", e);
+                            continue;
+                        }
+                    };
+                    tracing::info!("sending current selection on request");
+                    if let Some(h) = hash_item(&item, self.config.normalized_hash) {
+                        last_hash = Some(h);
+                    }
+                    self_writes.clear();
+                    self.broadcast(item).await?;
+                }
+                Some(addr) = self.single_peer_rx.recv() => {
+                    let item = match clipboard.read() {
+                        Ok(Some(item)) => item,
+                        Ok(None) => {
+                            tracing::info!("clipboard is empty, nothing to send to {}", addr);
+                            continue;
+                        }
+                        Err(e) => {
+                            tracing::warn!("failed to read clipboard for {}: {}", addr, e);
+                            continue;
+                        }
+                    };
+                    match self.send_to_peer(&addr, item).await {
+                        Ok(Some(result)) if result.is_ok() => {
+                            tracing::info!("sent clipboard to {}", addr);
+                        }
+                        Ok(Some(result)) => {
+                            tracing::warn!(
+                                "failed to send clipboard to {}: {:?}",
+                                addr,
+                                result.outcome
+                            );
+                        }
+                        Ok(None) => {}
+                        Err(e) => tracing::warn!("failed to send clipboard to {}: {}", addr, e),
+                    }
+                }
+                _ = presence_tick.tick() => {
+                    let removed = self.devices.prune(Instant::now(), PRESENCE_TTL);
+                    if removed > 0 {
+                        tracing::debug!("pruned {} stale device(s)", removed);
+                    }
+                    self.incoming_file_chunks.expire(Instant::now());
+                    self.broadcast_message(self.build_presence_message()).await?;
+                }
+                Some(IncomingMessage { from, key_id: auth_key_id, relayed, msg }) = self.incoming_msg_rx.recv() => {
+                    // 对端已开始使用待确认的新密钥：确认轮换
+                    if self.keys.confirm(&auth_key_id).is_some() {
+                        self.adopt_primary_key();
+                    }
+                    if relayed && !relayed_message_allowed(&self.config, &msg) {
+                        tracing::debug!("ignoring relayed message that needs a direct connection or file permission");
+                        continue;
+                    }
+                    let own_id = *self.instance_id.as_bytes();
+                    let mut source_app = None;
+                    let mut ttl_secs = 0;
+                    let mut sensitive = false;
+                    let item = match msg {
+                        ProtocolMessage::ClipboardUpdate {
+                            sender_id,
+                            content_type,
+                            payload_size: _,
+                            payload,
+                            seq,
+                            selection,
+                            source_app: app,
+                            clock,
+                            ttl_secs: ttl,
+                            sensitive: hint,
+                        } => {
+                            // 重试或多路径转发可能导致同一消息重复到达，只处理一次
+                            if !self.seen_messages.insert(sender_id, seq) {
+                                tracing::debug!("ignoring duplicate message seq={}", seq);
+                                continue;
+                            }
+                            // 忽略自己发出的回环消息（例如 peers 中包含本机时的广播）
+                            if sender_id == own_id {
+                                tracing::debug!("ignoring self-echo message (sender_id matches instance_id)");
+                                continue;
+                            }
+                            self.finish_incoming_transfer(&sender_id, from.ip());
+                            // 增量基准按收到的内容更新，不论随后是否因暂停、LWW 等原因丢弃：
+                            // 发送端只要送达就以该文本为下一次增量的基准
+                            let (content_type, payload) = match (selection, content_type) {
+                                (Selection::Clipboard, ContentType::TextDelta) => {
+                                    match self.resolve_text_delta(&sender_id, &payload) {
+                                        Ok(text) => (ContentType::Text, text.into_bytes()),
+                                        Err(e) => {
+                                            tracing::warn!("dropping text delta: {e}");
+                                            continue;
+                                        }
+                                    }
+                                }
+                                (_, other) => (other, payload),
+                            };
+                            // 增量基准使用发送端的原文，而不是换行规范化后写入剪贴板的文本
+                            if let (Selection::Clipboard, ContentType::Text, Ok(text)) =
+                                (selection, content_type, std::str::from_utf8(&payload))
+                            {
+                                self.remote_text_bases.insert(sender_id, text.to_string());
+                            }
+                            if paused.is_some() {
+                                tracing::debug!("sync paused in remote session, dropping remote update");
+                                continue;
+                            }
+                            match selection_target(selection, self.config.sync_primary) {
+                                Some(Selection::Clipboard) => {}
+                                Some(Selection::Primary) => {
+                                    if let (ContentType::Text, Ok(text)) = (content_type, String::from_utf8(payload)) {
+                                        apply_remote_primary(&text);
+                                        last_primary = Some(text);
+                                    }
+                                    continue;
+                                }
+                                None => {
+                                    tracing::debug!("ignoring remote primary selection update");
+                                    continue;
+                                }
+                            }
+                            // 比最近一次应用或发出的内容更旧的更新直接忽略，避免互相覆盖
+                            if self.config.last_writer_wins && clock != 0 {
+                                let mut last = self.last_writer.lock().unwrap_or_else(|e| e.into_inner());
+                                if !last.observe(clock, sender_id) {
+                                    tracing::info!("ignoring stale remote clipboard (clock={})", clock);
+                                    continue;
+                                }
+                            }
+                            tracing::info!(
+                                "received remote clipboard type={:?} bytes={}",
+                                content_type,
+                                payload.len()
+                            );
+                            source_app = app;
+                            ttl_secs = ttl;
+                            sensitive = hint;
+                            self.publish_inbound(content_type, payload.len() as u64, from.ip());
+                            // 对端发来的内容无法解析或落地时只丢弃这一条，不能让运行循环退出
+                            match self.apply_remote_clipboard(content_type, &payload, from.ip()) {
+                                Ok(item) => item,
+                                Err(e) => {
+                                    tracing::warn!("dropping clipboard update from {}: {}", from, e);
+                                    continue;
+                                }
+                            }
+                        }
+                        ProtocolMessage::Presence { sender_id, device_name, capabilities } => {
+                            if sender_id != own_id {
+                                tracing::debug!("presence from {} caps={:#x}", device_name, capabilities);
+                                self.devices.update(sender_id, device_name, capabilities, from.ip(), Instant::now());
+                            }
+                            continue;
+                        }
+                        ProtocolMessage::KeyRotation { sender_id, key_id: new_key_id, new_key } => {
+                            if sender_id != own_id {
+                                match self.keys.apply_rotation(&auth_key_id, &new_key_id, new_key) {
+                                    Ok(id) => tracing::info!(
+                                        "accepted key rotation from {}, new key id={}",
+                                        from,
+                                        hex::encode(id)
+                                    ),
+                                    Err(e) => tracing::warn!("rejected key rotation from {}: {}", from, e),
+                                }
+                            }
+                            continue;
+                        }
+                        ProtocolMessage::FileManifest { sender_id, transfer_id, reply_port, entries } => {
+                            if sender_id == own_id || paused.is_some() {
+                                continue;
+                            }
+                            tracing::info!("received file manifest from {}: {} file(s)", from, entries.len());
+                            match self.on_file_manifest(sender_id, transfer_id, entries, from.ip()) {
+                                ManifestAction::Request(request) => {
+                                    let addr = SocketAddr::new(from.ip(), reply_port);
+                                    send_to_peer(&self.config, addr, &request).await?;
+                                    continue;
+                                }
+                                ManifestAction::Complete(item) => self.received_files(item, from.ip()),
+                                ManifestAction::Ignore => continue,
+                            }
+                        }
+                        ProtocolMessage::FileRequest { sender_id, transfer_id, reply_port, indices } => {
+                            if sender_id == own_id {
+                                continue;
+                            }
+                            match self.on_file_request(transfer_id, &indices) {
+                                Some(data) => {
+                                    let addr = SocketAddr::new(from.ip(), reply_port);
+                                    tracing::info!("sending {} requested file(s) to {}", indices.len(), addr);
+                                    send_to_peer(&self.config, addr, &data).await?;
+                                }
+                                None => tracing::warn!("ignoring file request for unknown transfer from {}", from),
+                            }
+                            continue;
+                        }
+                        ProtocolMessage::EchoRequest { sender_id, echo_id, timestamp_us, reply_port } => {
+                            if sender_id == own_id {
+                                continue;
+                            }
+                            let addr = SocketAddr::new(from.ip(), reply_port);
+                            tracing::debug!("answering echo request from {}", addr);
+                            send_to_peer(&self.config, addr, &echo_reply(own_id, echo_id, timestamp_us)).await?;
+                            continue;
+                        }
+                        ProtocolMessage::ChunkData {
+                            sender_id,
+                            transfer_id,
+                            reply_port,
+                            content_type,
+                            total,
+                            offset,
+                            data,
+                        } => {
+                            if sender_id == own_id || paused.is_some() {
+                                continue;
+                            }
+                            let outcome = self
+                                .check_chunked_transfer(content_type, total, from.ip())
+                                .and_then(|()| {
+                                    self.partials.add(&sender_id, transfer_id, total, offset, &data)
+                                });
+                            let (next_offset, payload) = match outcome {
+                                Ok(ChunkOutcome::Ack(next)) => (next, None),
+                                Ok(ChunkOutcome::Complete(payload)) => (total, Some(payload)),
+                                Err(e) => {
+                                    tracing::warn!("dropping chunk from {}: {}", from, e);
+                                    continue;
+                                }
+                            };
+                            let ack = ProtocolMessage::ChunkAck {
+                                sender_id: own_id,
+                                transfer_id,
+                                next_offset,
+                            };
+                            let reply_to = SocketAddr::new(from.ip(), reply_port);
+                            send_to_peer(&self.config, reply_to, &ack).await?;
+                            let Some(payload) = payload else {
+                                continue;
+                            };
+                            self.finish_incoming_transfer(&sender_id, from.ip());
+                            tracing::info!(
+                                "received chunked clipboard type={:?} bytes={}",
+                                content_type,
+                                payload.len()
+                            );
+                            self.publish_inbound(content_type, payload.len() as u64, from.ip());
+                            // 对端发来的内容无法解析或落地时只丢弃这一条，不能让运行循环退出
+                            match self.apply_remote_clipboard(content_type, &payload, from.ip()) {
+                                Ok(item) => item,
+                                Err(e) => {
+                                    tracing::warn!("dropping clipboard update from {}: {}", from, e);
+                                    continue;
+                                }
+                            }
+                        }
+                        ProtocolMessage::ChunkAck { transfer_id, next_offset, .. } => {
+                            let acks = self.chunk_acks.lock().unwrap_or_else(|e| e.into_inner());
+                            if let Some(tx) = acks.get(&transfer_id) {
+                                let _ = tx.try_send(next_offset);
+                            }
+                            continue;
+                        }
+                        ProtocolMessage::EchoReply { .. } => {
+                            tracing::debug!("ignoring unsolicited echo reply from {}", from);
+                            continue;
+                        }
+                        ProtocolMessage::TransferStart { sender_id, content_type, total_size } => {
+                            if sender_id != own_id {
+                                tracing::info!(
+                                    "{} started sending {:?} ({} bytes)",
+                                    from,
+                                    content_type,
+                                    total_size
+                                );
+                                self.incoming_transfers.insert(sender_id, (content_type, total_size));
+                                let _ = self.events.send(CoreEvent::transfer(
+                                    TransferPhase::Start,
+                                    content_type,
+                                    total_size,
+                                    from.ip(),
+                                ));
+                            }
+                            continue;
+                        }
+                        ProtocolMessage::TransferEnd { sender_id } => {
+                            self.finish_incoming_transfer(&sender_id, from.ip());
+                            continue;
+                        }
+                        ProtocolMessage::FileChunk {
+                            sender_id,
+                            transfer_id,
+                            seq,
+                            total,
+                            file_index,
+                            name,
+                            file_size,
+                            offset,
+                            data,
+                        } => {
+                            if sender_id == own_id || paused.is_some() {
+                                continue;
+                            }
+                            if !self.files_allowed_from(from.ip()) {
+                                tracing::warn!("rejected file chunk from {}: peer not allowed to send files", from);
+                                continue;
+                            }
+                            let chunk = IncomingChunk {
+                                seq,
+                                total,
+                                file_index,
+                                name: &name,
+                                file_size,
+                                offset,
+                                data: &data,
+                            };
+                            let now = Instant::now();
+                            let completed = match self.incoming_file_chunks.add(sender_id, transfer_id, from.ip(), &chunk, now) {
+                                Ok(Some(completed)) => completed,
+                                Ok(None) => continue,
+                                Err(e) => {
+                                    tracing::warn!("dropping file chunk from {}: {}", from, e);
+                                    continue;
+                                }
+                            };
+                            tracing::info!(
+                                "received {} chunked file(s) ({} bytes) from {}",
+                                completed.files.len(),
+                                completed.size,
+                                from
+                            );
+                            self.publish_inbound(ContentType::Files, completed.size, from.ip());
+                            match self.save_chunked_files(completed) {
+                                Ok(Some(item)) => self.received_files(item, from.ip()),
+                                Ok(None) => continue,
+                                Err(e) => {
+                                    tracing::warn!("failed to save chunked files from {}: {}", from, e);
+                                    continue;
+                                }
+                            }
+                        }
+                        ProtocolMessage::FileData { sender_id, transfer_id, files } => {
+                            if sender_id == own_id || paused.is_some() {
+                                continue;
+                            }
+                            match self.on_file_data(sender_id, transfer_id, files, from.ip()) {
+                                Ok(item) => item,
+                                Err(e) => {
+                                    tracing::warn!("dropping file data from {}: {}", from, e);
+                                    continue;
+                                }
+                            }
+                        }
+                    };
+                    if let Some(item) = item {
+                        self.record_received(&item, from.ip(), source_app, sensitive);
+                        let received_files: Vec<PathBuf> = match &item {
+                            ClipboardItem::Files(files) if ttl_secs > 0 => files
+                                .iter()
+                                .map(|f| PathBuf::from(local_file_path(&f.path)))
+                                .collect(),
+                            _ => Vec::new(),
+                        };
+                        let item = match item {
+                            ClipboardItem::Files(files) if files_as_paths => file_paths_item(&files),
+                            other => other,
+                        };
+                        let written_hash = hash_item(&item, self.config.normalized_hash);
                         suppress_until = Some(Instant::now() + SUPPRESS_WINDOW);
                         suppress_hash = written_hash;
                         // 同时更新 last_hash 避免后续重复广播
                         last_hash = written_hash;
+                        if let Some(h) = written_hash {
+                            self_writes.record(h);
+                            applied.record(h, Instant::now());
+                        }
+                        if self.config.fast_text_read {
+                            // 快速路径只读到其中的纯文本，同样记为本机写入
+                            last_fast_hash = fast_text_hash(&item);
+                            if let Some(h) = last_fast_hash.filter(|h| Some(*h) != written_hash) {
+                                self_writes.record(h);
+                                applied.record(h, Instant::now());
+                            }
+                        }
                         tracing::debug!("set suppress window for {}ms", SUPPRESS_WINDOW.as_millis());
-                        clipboard.write(item)?;
+                        read_cache.invalidate();
+                        if let Some(path) = &self.config.mirror_text_to_file {
+                            mirror_text(path, &item);
+                        }
+                        if ttl_secs > 0 {
+                            let ttl = Duration::from_secs(ttl_secs);
+                            expiring.schedule(Instant::now(), ttl, written_hash, received_files);
+                        }
+                        write_received_item(clipboard.as_mut(), item, self.config.normalized_hash)?;
                     }
                 }
                 else => {
@@ -149,183 +1243,3736 @@ impl CoreService {
         Ok(())
     }
 
-    /// 将当前剪贴板内容构造成要广播给所有 peers 的协议消息。
-    fn build_clipboard_message(&self, item: &ClipboardItem) -> Result<Option<ProtocolMessage>> {
-        match item {
-            ClipboardItem::Text(text) => {
-                let payload = text.as_bytes().to_vec();
-                Ok(Some(ProtocolMessage::ClipboardUpdate {
-                    sender_id: *self.instance_id.as_bytes(),
-                    content_type: ContentType::Text,
-                    payload_size: payload.len() as u64,
-                    payload,
-                }))
+    /// 直接广播给定的剪贴板条目（不经过系统剪贴板），返回每个对端的发送结果。
+    ///
+    /// 内容为空（例如文件全部被跳过）时不发送，返回空列表。
+    pub async fn broadcast(&self, item: ClipboardItem) -> Result<Vec<PeerSendResult>> {
+        self.broadcast_with_hint(item, false).await
+    }
+
+    /// 广播本机剪贴板内容；`sensitive` 为内容带有的「勿同步」提示，随更新发给对端，
+    /// 对端据此不把它写入持久化历史（分块发送与文件清单不携带该提示）。
+    async fn broadcast_with_hint(
+        &self,
+        item: ClipboardItem,
+        sensitive: bool,
+    ) -> Result<Vec<PeerSendResult>> {
+        if let Some(path) = &self.config.mirror_text_to_file {
+            mirror_text(path, &item);
+        }
+        if let (true, ClipboardItem::Files(files)) = (self.config.file_manifest, &item) {
+            let entries = read_file_entries(&self.config, files)?;
+            if entries.is_empty() {
+                return Ok(Vec::new());
             }
-            ClipboardItem::Image(png) => {
-                let payload = png.clone();
-                Ok(Some(ProtocolMessage::ClipboardUpdate {
-                    sender_id: *self.instance_id.as_bytes(),
-                    content_type: ContentType::Image,
-                    payload_size: payload.len() as u64,
-                    payload,
-                }))
+            let size: u64 = entries.iter().map(|entry| entry.size).sum();
+            let msg = self.build_file_manifest(entries);
+            tracing::info!("broadcasting file manifest to peers");
+            let results = self.broadcast_message(msg).await?;
+            self.publish_outbound(ContentType::Files, size, &results);
+            return Ok(results);
+        }
+        if let ClipboardItem::Files(files) = &item {
+            if let Some(results) = self.broadcast_file_chunks(files).await? {
+                return Ok(results);
             }
-            ClipboardItem::Files(files) => {
-                let mut entries = Vec::new();
-                for f in files {
-                    let raw = &f.path;
-                    // 剪贴板返回的路径可能带 file:// 前缀，需要去掉
-                    let clean = if let Some(stripped) = raw.strip_prefix("file://") {
-                        stripped
-                    } else {
-                        raw.as_str()
-                    };
-                    // URL 编码的空格等字符需要解码
-                    let decoded = percent_decode(clean);
-                    let path = Path::new(&decoded);
-                    tracing::debug!("reading file: raw={} resolved={}", raw, path.display());
-                    let meta = match std::fs::metadata(path) {
-                        Ok(m) => m,
-                        Err(e) => {
-                            tracing::warn!("skip file {}: {}", path.display(), e);
-                            continue;
-                        }
-                    };
-                    if meta.is_dir() {
-                        tracing::debug!("skip directory: {}", path.display());
-                        continue;
-                    }
-                    let size = meta.len();
-                    if size > self.config.max_file_size {
-                        tracing::warn!("skip file {} larger than max_file_size", path.display());
-                        continue;
-                    }
-                    let content = std::fs::read(path)?;
-                    let name = path
-                        .file_name()
-                        .map(|s| s.to_string_lossy().to_string())
-                        .unwrap_or_else(|| "file".into());
-                    entries.push(FileEntry { name, size, content });
-                }
-                if entries.is_empty() {
-                    return Ok(None);
+        }
+        if let ClipboardItem::Url(url) = &item {
+            if let Some(results) = self.broadcast_url(url, sensitive).await? {
+                return Ok(results);
+            }
+        }
+        // 转换只作用于发出的内容，本机剪贴板保持原样
+        let item = match item {
+            ClipboardItem::Text(text) if !self.config.outbound_transforms.is_empty() => {
+                let text = self.outbound_text(text);
+                if text.is_empty() {
+                    tracing::debug!("text is empty after outbound transforms, not broadcasting");
+                    return Ok(Vec::new());
                 }
-                let payload = serde_json::to_vec(&entries)?;
-                Ok(Some(ProtocolMessage::ClipboardUpdate {
-                    sender_id: *self.instance_id.as_bytes(),
-                    content_type: ContentType::Files,
-                    payload_size: payload.len() as u64,
-                    payload,
-                }))
+                ClipboardItem::Text(text)
             }
+            ClipboardItem::Text(text) => ClipboardItem::Text(self.outbound_text(text)),
+            other => other,
+        };
+        let text = match &item {
+            ClipboardItem::Text(text) => Some(text.clone()),
+            _ => None,
+        };
+        // 条目按值移入消息，图片等大负载不会再复制一份
+        let Some(msg) = self.build_clipboard_message(item)? else {
+            return Ok(Vec::new());
+        };
+        let mut msg = match &text {
+            Some(text) => self.build_text_delta_message(text)?.unwrap_or(msg),
+            None => msg,
+        };
+        if let ProtocolMessage::ClipboardUpdate { sensitive: hint, .. } = &mut msg {
+            *hint = sensitive;
+        }
+        let msg = match msg {
+            ProtocolMessage::ClipboardUpdate { content_type, payload, .. }
+                if self.should_chunk(content_type, payload.len()) =>
+            {
+                // 分块发送在后台进行，完成前不能作为增量基准
+                *self.text_delta_base.lock().unwrap_or_else(|e| e.into_inner()) = None;
+                let size = payload.len() as u64;
+                let results = self.start_chunked_transfers(content_type, payload);
+                self.publish_outbound(content_type, size, &results);
+                return Ok(results);
+            }
+            other => other,
+        };
+        let sent = match &msg {
+            ProtocolMessage::ClipboardUpdate { content_type, payload_size, .. } => {
+                Some((*content_type, *payload_size))
+            }
+            _ => None,
+        };
+        // 大负载发送前后通知对端，对端可在数据到达前显示“正在接收”
+        let announced = sent.filter(|(_, size)| *size >= TRANSFER_STATUS_MIN_SIZE);
+        if let Some((content_type, total_size)) = announced {
+            self.broadcast_message(ProtocolMessage::TransferStart {
+                sender_id: *self.instance_id.as_bytes(),
+                content_type,
+                total_size,
+            })
+            .await?;
+        }
+        tracing::info!("broadcasting clipboard update to peers");
+        let results = self.broadcast_message(msg).await?;
+        if announced.is_some() {
+            self.broadcast_message(ProtocolMessage::TransferEnd {
+                sender_id: *self.instance_id.as_bytes(),
+            })
+            .await?;
+        }
+        if let Some((content_type, size)) = sent {
+            self.publish_outbound(content_type, size, &results);
         }
-    }
+
+        // 只有所有对端都收到了本次文本，才能把它作为下一次增量的基准
+        if let Some(text) = text {
+            let mut base = self.text_delta_base.lock().unwrap_or_else(|e| e.into_inner());
+            *base = results.iter().all(PeerSendResult::is_ok).then_some(text);
+        }
+        Ok(results)
+    }
+
+    /// 把一条剪贴板内容只发给 `addr`（host:port），不影响其他对端，也不作为增量同步的基准。
+    ///
+    /// 内容被过滤（例如超过大小限制）时返回 None。
+    pub async fn send_to_peer(
+        &self,
+        addr: &str,
+        item: ClipboardItem,
+    ) -> Result<Option<PeerSendResult>> {
+        let (msg, content_type, size) = match item {
+            ClipboardItem::Files(files) if self.config.file_manifest => {
+                let entries = read_file_entries(&self.config, &files)?;
+                if entries.is_empty() {
+                    return Ok(None);
+                }
+                let size: u64 = entries.iter().map(|entry| entry.size).sum();
+                (self.build_file_manifest(entries), ContentType::Files, size)
+            }
+            item => {
+                // 与广播一致，转换只作用于发出的内容
+                let item = match item {
+                    ClipboardItem::Text(text) => ClipboardItem::Text(self.outbound_text(text)),
+                    ClipboardItem::Url(url) if !self.addr_supports(addr, CAP_URL).await => {
+                        ClipboardItem::Text(url)
+                    }
+                    other => other,
+                };
+                let Some(msg) = self.build_clipboard_message(item)? else {
+                    return Ok(None);
+                };
+                let (content_type, size) = match &msg {
+                    ProtocolMessage::ClipboardUpdate { content_type, payload_size, .. } => {
+                        (*content_type, *payload_size)
+                    }
+                    _ => (ContentType::Files, 0),
+                };
+                (msg, content_type, size)
+            }
+        };
+        tracing::info!("sending clipboard to {}", addr);
+        let result = send_to_addr(&self.config, addr.to_string(), &msg).await?;
+        self.publish_outbound(content_type, size, std::slice::from_ref(&result));
+        Ok(Some(result))
+    }
+
+    /// 本机剪贴板变化是否按 `allowed_apps` / `ignored_apps` 同步；未配置时不做判断。
+    fn local_change_allowed(&self, clipboard: &dyn ClipboardBackend) -> bool {
+        let config = &self.config;
+        if config.allowed_apps.is_empty() && config.ignored_apps.is_empty() {
+            return true;
+        }
+        let app = crate::clipboard::clipboard_source_app();
+        let hint = app.is_none() && clipboard.sensitive_hint();
+        let allowed =
+            source_app_allowed(app.as_deref(), hint, &config.allowed_apps, &config.ignored_apps);
+        if !allowed {
+            match &app {
+                Some(app) => tracing::info!("ignored clipboard change from {}", app),
+                None => tracing::info!("ignored clipboard change marked as sensitive"),
+            }
+        }
+        allowed
+    }
+
+    /// 判断是否接收来自给定地址的文件：未在 peers 中限制的来源默认允许。
+    fn files_allowed_from(&self, ip: IpAddr) -> bool {
+        !self.files_denied.contains(&ip)
+    }
+
+    /// 是否以分块确认的方式发送：开启 `resumable_transfers`、未使用中继且负载超过块大小。
+    fn should_chunk(&self, content_type: ContentType, len: usize) -> bool {
+        self.config.resumable_transfers
+            && self.relay.is_none()
+            && !matches!(content_type, ContentType::TextDelta)
+            && len > self.config.chunk_size
+    }
+
+    /// 为每个对端启动一个后台分块发送任务（每个对端使用独立的传输 ID），返回已启动的对端。
+    fn start_chunked_transfers(
+        &self,
+        content_type: ContentType,
+        payload: Vec<u8>,
+    ) -> Vec<PeerSendResult> {
+        let data = Arc::new(payload);
+        let mut results = Vec::with_capacity(self.config.peers.len());
+        for peer in &self.config.peers {
+            let transfer = ChunkedTransfer {
+                config: self.config.clone(),
+                addr: format!("{}:{}", peer.host, peer.port),
+                sender_id: *self.instance_id.as_bytes(),
+                transfer_id: self.next_seq.fetch_add(1, Ordering::Relaxed),
+                reply_port: self.listen_port(),
+                content_type,
+            };
+            let (ack_tx, ack_rx) = mpsc::channel(8);
+            self.chunk_acks
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(transfer.transfer_id, ack_tx);
+            let chunks = OutgoingChunks::new(Arc::clone(&data), self.config.chunk_size);
+            let acks = Arc::clone(&self.chunk_acks);
+            results.push(PeerSendResult {
+                addr: transfer.addr.clone(),
+                outcome: PeerSendOutcome::Ok,
+            });
+            tokio::spawn(async move {
+                let (addr, transfer_id) = (transfer.addr.clone(), transfer.transfer_id);
+                let status = (chunks.total() >= TRANSFER_STATUS_MIN_SIZE)
+                    .then(|| (transfer.config.clone(), transfer.sender_id));
+                if let Some((config, sender_id)) = &status {
+                    let start = ProtocolMessage::TransferStart {
+                        sender_id: *sender_id,
+                        content_type,
+                        total_size: chunks.total(),
+                    };
+                    let _ = send_to_addr(config, addr.clone(), &start).await;
+                }
+                match send_chunks(transfer, chunks, ack_rx).await {
+                    Ok(()) => tracing::info!("chunked transfer to {} complete", addr),
+                    Err(e) => tracing::warn!("chunked transfer to {} failed: {}", addr, e),
+                }
+                if let Some((config, sender_id)) = &status {
+                    let end = ProtocolMessage::TransferEnd { sender_id: *sender_id };
+                    let _ = send_to_addr(config, addr.clone(), &end).await;
+                }
+                acks.lock().unwrap_or_else(|e| e.into_inner()).remove(&transfer_id);
+            });
+        }
+        tracing::info!(
+            "started chunked transfer of {} bytes to {} peer(s)",
+            data.len(),
+            results.len()
+        );
+        results
+    }
+
+    /// 一组文件中有文件超过 `file_chunk_threshold` 时，把整组文件分块发给声明支持的对端
+    /// （[`CAP_FILE_CHUNKS`]），其余对端仍收到整条文件消息。没有需要分块的文件、配置了中继
+    /// 或没有对端支持时返回 None，由调用方按原方式发送。
+    async fn broadcast_file_chunks(
+        &self,
+        files: &[ClipboardFile],
+    ) -> Result<Option<Vec<PeerSendResult>>> {
+        let threshold = self.config.file_chunk_threshold;
+        if threshold == 0 || self.relay.is_some() {
+            return Ok(None);
+        }
+        let outgoing = outgoing_files(&self.config, files);
+        if !outgoing.iter().any(|file| file.size > threshold) {
+            return Ok(None);
+        }
+        let mut chunked = Vec::new();
+        let mut whole = Vec::new();
+        for peer in &self.config.peers {
+            if self.peer_supports(peer, CAP_FILE_CHUNKS).await {
+                chunked.push(format!("{}:{}", peer.host, peer.port));
+            } else {
+                whole.push(peer.clone());
+            }
+        }
+        if chunked.is_empty() {
+            return Ok(None);
+        }
+        let size = outgoing.iter().map(|file| file.size).sum();
+        let mut results = self.start_file_chunk_transfers(outgoing, chunked);
+        if !whole.is_empty() {
+            if let Some(msg) = self.build_clipboard_message(ClipboardItem::Files(files.to_vec()))? {
+                let config = AppConfig {
+                    peers: whole,
+                    ..self.config.clone()
+                };
+                let body = Arc::new(encode_message(&msg)?);
+                let (pool, cooldown) = (self.connections.as_ref(), self.unreachable.as_ref());
+                results.extend(broadcast_body(&config, body, pool, cooldown).await?);
+            }
+        }
+        self.publish_outbound(ContentType::Files, size, &results);
+        Ok(Some(results))
+    }
+
+    /// 对端是否在最近的 Presence 中声明了 `capability`（主机名先解析为地址）。
+    async fn peer_supports(&self, peer: &PeerConfig, capability: u32) -> bool {
+        self.addr_supports(&format!("{}:{}", peer.host, peer.port), capability).await
+    }
+
+    /// 同 [`Self::peer_supports`]，地址为 `host:port` 形式。
+    async fn addr_supports(&self, addr: &str, capability: u32) -> bool {
+        match tokio::net::lookup_host(addr).await {
+            Ok(mut addrs) => addrs.any(|addr| self.devices.supports(addr.ip(), capability)),
+            Err(_) => false,
+        }
+    }
+
+    /// 链接只发给声明支持 [`CAP_URL`] 的对端，其余对端（以及无法得知能力的中继设备）
+    /// 收到同样内容的文本，旧版本对端会拒绝未知的内容类型。所有对端都支持且未使用中继时
+    /// 返回 None，由调用方按原方式发送。
+    async fn broadcast_url(&self, url: &str, sensitive: bool) -> Result<Option<Vec<PeerSendResult>>> {
+        let mut url_peers = Vec::new();
+        let mut text_peers = Vec::new();
+        for peer in &self.config.peers {
+            if self.peer_supports(peer, CAP_URL).await {
+                url_peers.push(peer.clone());
+            } else {
+                text_peers.push(peer.clone());
+            }
+        }
+        if text_peers.is_empty() && self.relay.is_none() {
+            return Ok(None);
+        }
+        let (pool, cooldown) = (self.connections.as_ref(), self.unreachable.as_ref());
+        let mut results = Vec::new();
+        for (content_type, peers) in [(ContentType::Url, url_peers), (ContentType::Text, text_peers)] {
+            let mut msg = self.clipboard_update(content_type, url.as_bytes().to_vec(), Selection::Clipboard);
+            if let ProtocolMessage::ClipboardUpdate { sensitive: hint, .. } = &mut msg {
+                *hint = sensitive;
+            }
+            let body = Arc::new(encode_message(&msg)?);
+            if let (ContentType::Text, Some(relay)) = (content_type, &self.relay) {
+                results.push(relay.send(Arc::clone(&body)));
+            }
+            if !peers.is_empty() {
+                let config = AppConfig {
+                    peers,
+                    ..self.config.clone()
+                };
+                results.extend(broadcast_body(&config, body, pool, cooldown).await?);
+            }
+        }
+        self.publish_outbound(ContentType::Url, url.len() as u64, &results);
+        Ok(Some(results))
+    }
+
+    /// 在后台把文件逐块从磁盘读出，依次发给 `addrs` 中的对端，返回已启动的对端。
+    ///
+    /// 块不等待确认：发给某个对端失败后不再向它发送，它收到的部分由接收端超时丢弃。
+    fn start_file_chunk_transfers(
+        &self,
+        files: Vec<OutgoingFile>,
+        addrs: Vec<String>,
+    ) -> Vec<PeerSendResult> {
+        let results = addrs
+            .iter()
+            .map(|addr| PeerSendResult {
+                addr: addr.clone(),
+                outcome: PeerSendOutcome::Ok,
+            })
+            .collect();
+        // 块连同消息头须能放进一帧
+        let chunk_size = self.config.chunk_size.min(self.config.max_frame_body / 2);
+        let plan = plan_chunks(&files, chunk_size);
+        let config = self.config.clone();
+        let sender_id = *self.instance_id.as_bytes();
+        let transfer_id = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        tracing::info!(
+            "sending {} file(s) in {} chunk(s) to {} peer(s)",
+            files.len(),
+            plan.len(),
+            addrs.len()
+        );
+        tokio::spawn(async move {
+            let mut addrs = addrs;
+            let total = plan.len() as u32;
+            for (seq, spec) in plan.into_iter().enumerate() {
+                let file = &files[spec.file_index as usize];
+                let data = match read_chunk(file, spec) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        tracing::warn!("chunked file transfer aborted: {}", e);
+                        return;
+                    }
+                };
+                let msg = ProtocolMessage::FileChunk {
+                    sender_id,
+                    transfer_id,
+                    seq: seq as u32,
+                    total,
+                    file_index: spec.file_index,
+                    name: file.name.clone(),
+                    file_size: file.size,
+                    offset: spec.offset,
+                    data,
+                };
+                let mut reachable = Vec::with_capacity(addrs.len());
+                for addr in addrs {
+                    match send_to_addr(&config, addr.clone(), &msg).await {
+                        Ok(result) if result.is_ok() => reachable.push(addr),
+                        Ok(result) => tracing::warn!(
+                            "chunked file transfer to {} failed: {:?}",
+                            addr,
+                            result.outcome
+                        ),
+                        Err(e) => tracing::warn!("chunked file transfer to {} failed: {}", addr, e),
+                    }
+                }
+                addrs = reachable;
+                if addrs.is_empty() {
+                    return;
+                }
+            }
+            tracing::info!("chunked file transfer to {} peer(s) complete", addrs.len());
+        });
+        results
+    }
+
+    /// 检查来自 `from` 的分块传输是否可以接收。
+    fn check_chunked_transfer(
+        &self,
+        content_type: ContentType,
+        total: u64,
+        from: IpAddr,
+    ) -> Result<()> {
+        // 分块传输的总大小不受单帧上限约束，调小 max_frame_body 后仍可接收默认上限以内的内容
+        if total > self.config.max_frame_body.max(MAX_FRAME_BODY) as u64 {
+            return Err(anyhow::anyhow!("chunked transfer of {} bytes exceeds size limit", total));
+        }
+        if matches!(content_type, ContentType::TextDelta) {
+            return Err(anyhow::anyhow!("text delta cannot be sent in chunks"));
+        }
+        if matches!(content_type, ContentType::Files) && !self.files_allowed_from(from) {
+            return Err(anyhow::anyhow!("peer {} not allowed to send files", from));
+        }
+        Ok(())
+    }
+
+    /// 发布一条发出事件。
+    fn publish_outbound(&self, content_type: ContentType, size: u64, results: &[PeerSendResult]) {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner()).record_sent(size, results);
+        let delivered = results.iter().filter(|r| r.is_ok()).count();
+        let _ = self.events.send(CoreEvent::outbound(content_type, size, delivered, results.len()));
+    }
+
+    /// 结束来自 `sender_id` 的已宣布传输（数据已到达或发送端宣布结束），发布结束事件。
+    fn finish_incoming_transfer(&mut self, sender_id: &[u8; 16], from: IpAddr) {
+        if let Some((content_type, size)) = self.incoming_transfers.remove(sender_id) {
+            let _ = self.events.send(CoreEvent::transfer(TransferPhase::End, content_type, size, from));
+        }
+    }
+
+    /// 记录并发布一条收到内容的事件。
+    fn publish_inbound(&self, content_type: ContentType, size: u64, from: IpAddr) {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner()).record_received(size);
+        let _ = self.events.send(CoreEvent::inbound(content_type, size, from));
+    }
+
+    /// 在启用增量同步且存在基准时，构造文本增量消息；差异不够小或已连续发送
+    /// [`DELTA_RESYNC_INTERVAL`] 次增量时返回 None（改为全量发送）。
+    fn build_text_delta_message(&self, text: &str) -> Result<Option<ProtocolMessage>> {
+        let payload = match self.text_delta_payload(text)? {
+            Some(payload) if self.deltas_since_full.load(Ordering::Relaxed) < DELTA_RESYNC_INTERVAL => {
+                payload
+            }
+            _ => {
+                self.deltas_since_full.store(0, Ordering::Relaxed);
+                return Ok(None);
+            }
+        };
+        self.deltas_since_full.fetch_add(1, Ordering::Relaxed);
+        tracing::debug!("sending text delta: {} bytes for text len={}", payload.len(), text.len());
+        Ok(Some(self.clipboard_update(ContentType::TextDelta, payload, Selection::Clipboard)))
+    }
+
+    /// 相对增量基准编码 `text` 的差异；未启用、没有基准或差异不够小时返回 None。
+    fn text_delta_payload(&self, text: &str) -> Result<Option<Vec<u8>>> {
+        if !self.config.text_delta || text.len() < DELTA_MIN_TEXT_LEN {
+            return Ok(None);
+        }
+        let base = self.text_delta_base.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(base) = base.as_deref() else {
+            return Ok(None);
+        };
+        let payload = serde_json::to_vec(&compute_delta(base, text))?;
+        Ok((payload.len() * 2 <= text.len()).then_some(payload))
+    }
+
+    /// 使用该发送者的上一次文本重建收到的增量。
+    fn resolve_text_delta(&self, sender_id: &[u8; 16], payload: &[u8]) -> Result<String> {
+        let delta: TextDelta = serde_json::from_slice(payload)?;
+        let base = self
+            .remote_text_bases
+            .get(sender_id)
+            .ok_or_else(|| anyhow::anyhow!("no base text for sender"))?;
+        apply_delta(base, &delta)
+    }
+
+    /// 构造带本机 sender_id 与新序号的剪贴板更新消息。
+    fn clipboard_update(
+        &self,
+        content_type: ContentType,
+        payload: Vec<u8>,
+        selection: Selection,
+    ) -> ProtocolMessage {
+        ProtocolMessage::ClipboardUpdate {
+            sender_id: *self.instance_id.as_bytes(),
+            content_type,
+            payload_size: payload.len() as u64,
+            payload,
+            seq: self.next_seq.fetch_add(1, Ordering::Relaxed),
+            selection,
+            source_app: match selection {
+                Selection::Clipboard if self.config.send_source_app => local_source_app(),
+                _ => None,
+            },
+            clock: match selection {
+                Selection::Clipboard if self.config.last_writer_wins => self
+                    .last_writer
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .stamp(*self.instance_id.as_bytes(), lww::now_ms()),
+                _ => 0,
+            },
+            ttl_secs: match selection {
+                Selection::Clipboard => expiry::ttl_secs(&self.config, content_type),
+                Selection::Primary => 0,
+            },
+            sensitive: false,
+        }
+    }
+
+    /// 构造本机的 Presence 广播消息。
+    fn build_presence_message(&self) -> ProtocolMessage {
+        let mut device_name = self.config.resolved_device_name();
+        while device_name.len() > u8::MAX as usize {
+            device_name.pop();
+        }
+        ProtocolMessage::Presence {
+            sender_id: *self.instance_id.as_bytes(),
+            device_name,
+            capabilities: CAP_TEXT
+                | CAP_IMAGE
+                | CAP_FILES
+                | CAP_URL
+                | CAP_MULTI
+                | CAP_RESUME
+                | CAP_TRANSFER_STATUS
+                | CAP_FILE_CHUNKS,
+        }
+    }
+
+    /// 对发出的文本依次执行 `outbound_transforms` 与 `unicode_normalize`。
+    fn outbound_text(&self, text: String) -> String {
+        let text = apply_text_transforms(text, &self.config.outbound_transforms);
+        normalize_unicode(text, self.config.unicode_normalize)
+    }
+
+    /// 将当前剪贴板内容构造成要广播给所有 peers 的协议消息。
+    fn build_clipboard_message(&self, item: ClipboardItem) -> Result<Option<ProtocolMessage>> {
+        match item {
+            ClipboardItem::Text(text) => {
+                let payload = text.into_bytes();
+                Ok(Some(self.clipboard_update(ContentType::Text, payload, Selection::Clipboard)))
+            }
+            ClipboardItem::Url(url) => {
+                let payload = url.into_bytes();
+                Ok(Some(self.clipboard_update(ContentType::Url, payload, Selection::Clipboard)))
+            }
+            ClipboardItem::Image(png) => {
+                Ok(Some(self.clipboard_update(ContentType::Image, png, Selection::Clipboard)))
+            }
+            ClipboardItem::Files(files) => {
+                let Some(payload) = read_files_payload(&self.config, &files)? else {
+                    return Ok(None);
+                };
+                let payload = payload.into_bytes();
+                Ok(Some(self.clipboard_update(ContentType::Files, payload, Selection::Clipboard)))
+            }
+            ClipboardItem::Multi(formats) => {
+                let payload = serde_json::to_vec(&formats)?;
+                Ok(Some(self.clipboard_update(ContentType::Multi, payload, Selection::Clipboard)))
+            }
+        }
+    }
 
     /// 将远端收到的协议消息解析并落地成本机剪贴板条目（文件会写入下载目录）。
+    /// 来自未被授权发送文件的对端的文件消息会被丢弃。
     fn apply_remote_clipboard(
         &self,
         content_type: ContentType,
         payload: &[u8],
+        from: IpAddr,
     ) -> Result<Option<ClipboardItem>> {
         match content_type {
             ContentType::Text => {
                 let text = String::from_utf8(payload.to_vec())?;
-                Ok(Some(ClipboardItem::Text(text)))
+                Ok(Some(ClipboardItem::Text(normalize_newlines(text, self.config.newline_policy))))
+            }
+            ContentType::Url => {
+                let Some(url) = std::str::from_utf8(payload).ok().and_then(parse_url) else {
+                    tracing::warn!("dropping malformed url from {}", from);
+                    return Ok(None);
+                };
+                Ok(Some(ClipboardItem::Url(url)))
+            }
+            ContentType::Image if self.config.images_as_files => {
+                let path = self.save_received_image(payload)?;
+                Ok(Some(files_item(std::iter::once(path))))
+            }
+            ContentType::Image if self.image_too_large(payload.len()) => {
+                tracing::info!(
+                    "image of {} bytes exceeds image_to_file_threshold, saving to disk",
+                    payload.len()
+                );
+                let path = self.save_received_image(payload)?;
+                Ok(Some(files_item(std::iter::once(path))))
             }
             ContentType::Image => Ok(Some(ClipboardItem::Image(payload.to_vec()))),
+            ContentType::TextDelta => Err(anyhow::anyhow!("text delta must be resolved before apply")),
+            ContentType::Multi => {
+                let formats: Vec<(String, Vec<u8>)> = serde_json::from_slice(payload)?;
+                if formats.is_empty() {
+                    return Ok(None);
+                }
+                Ok(Some(ClipboardItem::Multi(with_png_fallback(formats))))
+            }
             ContentType::Files => {
-                let entries: Vec<FileEntry> = serde_json::from_slice(payload)?;
-                let base = self.download_dir();
-                std::fs::create_dir_all(&base)?;
-                
-                // 创建时间戳子目录
-                let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
-                let timestamp_dir = base.join(&timestamp);
-                std::fs::create_dir_all(&timestamp_dir)?;
-                tracing::info!("created download directory: {}", timestamp_dir.display());
-                
-                let mut files = Vec::new();
-                for e in entries {
-                    let path = timestamp_dir.join(&e.name);
-                    std::fs::write(&path, &e.content)?;
-                    files.push(ClipboardFile {
-                        path: path.to_string_lossy().to_string(),
-                    });
-                    tracing::debug!("saved file: {}", path.display());
+                if !self.files_allowed_from(from) {
+                    tracing::warn!("rejected files from {}: peer not allowed to send files", from);
+                    return Ok(None);
+                }
+                let entries = decode_files_payload(payload)?;
+                if let Err(e) = validate_received_files(&entries, self.config.max_file_size) {
+                    tracing::warn!("rejected files from {}: {}", from, e);
+                    return Ok(None);
                 }
-                Ok(Some(ClipboardItem::Files(files)))
+                let dir = self.create_receive_dir()?;
+                let paths = self.save_received_files(&dir, &entries)?;
+                let item = files_item(paths.into_iter().flatten());
+                if matches!(&item, ClipboardItem::Files(files) if files.is_empty()) {
+                    return Ok(None);
+                }
+                Ok(self.received_files(item, from))
             }
         }
     }
 
-    /// 返回用于保存远端文件的下载目录，按平台选择合适的 `Downloads` 路径。
-    fn download_dir(&self) -> PathBuf {
-        #[cfg(target_os = "linux")]
-        {
-            if let Some(home) = std::env::var_os("HOME") {
-                return PathBuf::from(home).join("Downloads").join("lan-clipboard");
+    /// 图片是否超过 `image_to_file_threshold`（为 0 时不限制）。
+    fn image_too_large(&self, len: usize) -> bool {
+        let threshold = self.config.image_to_file_threshold;
+        threshold > 0 && len as u64 > threshold
+    }
+
+    /// 在下载目录下创建本次接收的时间戳子目录。
+    fn create_receive_dir(&self) -> Result<PathBuf> {
+        let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+        let dir = self.config.download_dir().join(timestamp);
+        std::fs::create_dir_all(&dir)?;
+        tracing::info!("created download directory: {}", dir.display());
+        Ok(dir)
+    }
+
+    /// 将收到的图片以带时间戳的 PNG 文件保存到下载目录，非 PNG 格式会先转换为 PNG。
+    fn save_received_image(&self, payload: &[u8]) -> Result<PathBuf> {
+        let content = if payload.starts_with(PNG_SIGNATURE) {
+            payload.to_vec()
+        } else {
+            let mut png = Vec::new();
+            image::load_from_memory(payload)?
+                .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)?;
+            png
+        };
+        let dir = self.config.download_dir();
+        std::fs::create_dir_all(&dir)?;
+        let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S%.3f");
+        let entry = FileEntry {
+            name: format!("image-{}.png", timestamp),
+            size: content.len() as u64,
+            content,
+            source_path: None,
+        };
+        self.save_received_file(&dir, &entry)?
+            .ok_or_else(|| anyhow::anyhow!("image {} already exists, skipped", entry.name))
+    }
+
+    /// 将收到的单个文件写入接收目录，跳过时返回 None。
+    fn save_received_file(&self, dir: &Path, entry: &FileEntry) -> Result<Option<PathBuf>> {
+        Ok(self
+            .save_received_files(dir, std::slice::from_ref(entry))?
+            .pop()
+            .flatten())
+    }
+
+    /// 将一组收到的文件写入接收目录并记录到哈希索引，返回与 `entries` 一一对应的保存路径。
+    ///
+    /// 先全部写入临时文件，全部成功后再逐个重命名为最终文件名；任一步失败时删除已写入的
+    /// 临时文件和已重命名的文件，不留下不完整的一组文件。
+    /// 与已有文件重名时按 `on_name_collision` 处理，跳过的文件对应 None。
+    fn save_received_files(
+        &self,
+        dir: &Path,
+        entries: &[FileEntry],
+    ) -> Result<Vec<Option<PathBuf>>> {
+        let mut temps = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let temp = dir.join(format!(".{}.part", Uuid::new_v4().simple()));
+            if let Err(e) = std::fs::write(&temp, &entry.content) {
+                remove_files(temps.iter().chain(std::iter::once(&temp)));
+                return Err(anyhow::anyhow!("failed to write {}: {}", entry.name, e));
             }
+            temps.push(temp);
         }
-        #[cfg(target_os = "windows")]
-        {
-            if let Some(home) = std::env::var_os("USERPROFILE") {
-                return PathBuf::from(home).join("Downloads").join("lan-clipboard");
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        let paths = self.place_received_files(dir, &names, &temps)?;
+
+        let mut index = self.file_index.lock().unwrap_or_else(|e| e.into_inner());
+        for (entry, path) in entries.iter().zip(&paths) {
+            let Some(path) = path else { continue };
+            match &entry.source_path {
+                Some(src) => tracing::info!("received {} (from {})", entry.name, src),
+                None => tracing::debug!("saved file: {}", path.display()),
+            }
+            index.insert(file_hash(&entry.content), path.clone());
+        }
+        drop(index);
+        self.auto_open_received(&paths);
+        Ok(paths)
+    }
+
+    /// 把已写好的临时文件逐个重命名为 `names` 中对应的文件名，返回一一对应的保存路径。
+    ///
+    /// 与已有文件重名时按 `on_name_collision` 处理，跳过的文件对应 None；重命名失败时删除
+    /// 剩余的临时文件和已重命名的文件。
+    fn place_received_files(
+        &self,
+        dir: &Path,
+        names: &[&str],
+        temps: &[PathBuf],
+    ) -> Result<Vec<Option<PathBuf>>> {
+        let mut paths: Vec<Option<PathBuf>> = Vec::with_capacity(names.len());
+        for (i, (name, temp)) in names.iter().zip(temps).enumerate() {
+            // 只取文件名部分，防止路径穿越；source_path 仅用于展示
+            let target = dir.join(safe_file_name(name));
+            let Some(path) = collision_target(target, self.config.on_name_collision) else {
+                tracing::info!("skipped received file {}: name already exists", name);
+                remove_files(std::iter::once(temp));
+                paths.push(None);
+                continue;
+            };
+            if let Err(e) = std::fs::rename(temp, &path) {
+                remove_files(temps[i..].iter().chain(paths.iter().flatten()));
+                return Err(anyhow::anyhow!("failed to save {}: {}", name, e));
+            }
+            paths.push(Some(path));
+        }
+        Ok(paths)
+    }
+
+    /// 按 `auto_open_received` 用默认程序打开已保存的文件（可执行文件除外）。
+    fn auto_open_received(&self, paths: &[Option<PathBuf>]) {
+        if !self.config.auto_open_received {
+            return;
+        }
+        for path in paths.iter().flatten() {
+            if should_auto_open(path, &self.config.auto_open_extensions) {
+                open_with_default_handler(path);
+            } else if is_executable(path) {
+                tracing::info!("not auto-opening executable {}", path.display());
+            }
+        }
+    }
+
+    /// 把收齐的分块文件从暂存目录移入新的接收目录；全部因重名被跳过时返回 None。
+    fn save_chunked_files(&self, completed: CompletedTransfer) -> Result<Option<ClipboardItem>> {
+        let dir = self.create_receive_dir()?;
+        let names: Vec<&str> = completed.files.iter().map(|f| f.name.as_str()).collect();
+        let temps: Vec<PathBuf> = completed.files.iter().map(|f| f.path.clone()).collect();
+        let paths = self.place_received_files(&dir, &names, &temps)?;
+        self.auto_open_received(&paths);
+        let item = files_item(paths.into_iter().flatten());
+        Ok((!matches!(&item, ClipboardItem::Files(files) if files.is_empty())).then_some(item))
+    }
+
+    /// 记录待请求的文件并构造清单消息。
+    fn build_file_manifest(&self, files: Vec<FileEntry>) -> ProtocolMessage {
+        let transfer_id = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        let entries = manifest_entries(&files);
+        self.outgoing_transfers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(transfer_id, files, Instant::now());
+        ProtocolMessage::FileManifest {
+            sender_id: *self.instance_id.as_bytes(),
+            transfer_id,
+            reply_port: self.listen_port(),
+            entries,
+        }
+    }
+
+    /// 处理收到的清单：本地已有全部文件时直接完成，否则记录待接收状态并构造文件请求。
+    fn on_file_manifest(
+        &mut self,
+        sender_id: [u8; 16],
+        transfer_id: u64,
+        entries: Vec<ManifestEntry>,
+        from: IpAddr,
+    ) -> ManifestAction {
+        if !self.files_allowed_from(from) {
+            tracing::warn!("rejected file manifest from {}: peer not allowed to send files", from);
+            return ManifestAction::Ignore;
+        }
+        if entries.is_empty() {
+            return ManifestAction::Ignore;
+        }
+        let local: Vec<Option<PathBuf>> = {
+            let index = self.file_index.lock().unwrap_or_else(|e| e.into_inner());
+            entries.iter().map(|e| index.lookup(e)).collect()
+        };
+        let pending = PendingManifest {
+            from,
+            entries,
+            local,
+            received_at: Instant::now(),
+        };
+        let missing = pending.missing();
+        if missing.is_empty() {
+            tracing::info!("all {} manifest file(s) already present locally", pending.entries.len());
+            return ManifestAction::Complete(files_item(pending.local.into_iter().flatten()));
+        }
+        tracing::info!(
+            "requesting {} of {} manifest file(s)",
+            missing.len(),
+            pending.entries.len()
+        );
+        let now = Instant::now();
+        self.pending_manifests
+            .retain(|_, p| now.saturating_duration_since(p.received_at) <= TRANSFER_TTL);
+        self.pending_manifests.insert((sender_id, transfer_id), pending);
+        ManifestAction::Request(ProtocolMessage::FileRequest {
+            sender_id: *self.instance_id.as_bytes(),
+            transfer_id,
+            reply_port: self.listen_port(),
+            indices: missing,
+        })
+    }
+
+    /// 响应对端的文件请求，传输不存在或下标无效时返回 None。
+    fn on_file_request(&self, transfer_id: u64, indices: &[u32]) -> Option<ProtocolMessage> {
+        let files = self
+            .outgoing_transfers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .select(transfer_id, indices)?;
+        Some(ProtocolMessage::FileData {
+            sender_id: *self.instance_id.as_bytes(),
+            transfer_id,
+            files,
+        })
+    }
+
+    /// 处理收到的文件数据：校验哈希后写入接收目录，并与本地已有文件按清单顺序组合。
+    fn on_file_data(
+        &mut self,
+        sender_id: [u8; 16],
+        transfer_id: u64,
+        files: Vec<(u32, FileEntry)>,
+        from: IpAddr,
+    ) -> Result<Option<ClipboardItem>> {
+        let key = (sender_id, transfer_id);
+        let Some(mut pending) = self
+            .pending_manifests
+            .remove(&key)
+            .filter(|p| p.from == from)
+        else {
+            return Err(anyhow::anyhow!("no pending manifest for transfer {}", transfer_id));
+        };
+        let mut indices = Vec::with_capacity(files.len());
+        let mut received = Vec::with_capacity(files.len());
+        for (index, file) in files {
+            let Some(entry) = pending.entries.get(index as usize) else {
+                return Err(anyhow::anyhow!("file index {} out of range", index));
+            };
+            if file_hash(&file.content) != entry.hash {
+                return Err(anyhow::anyhow!("file {} does not match manifest hash", entry.name));
+            }
+            indices.push(index);
+            received.push(FileEntry {
+                name: entry.name.clone(),
+                source_path: entry.source_path.clone(),
+                ..file
+            });
+        }
+        validate_received_files(&received, self.config.max_file_size)?;
+        let dir = self.create_receive_dir()?;
+        let mut skipped = Vec::new();
+        for (index, path) in indices.into_iter().zip(self.save_received_files(&dir, &received)?) {
+            match path {
+                Some(path) => pending.local[index as usize] = Some(path),
+                None => skipped.push(index),
             }
         }
-        PathBuf::from("lan-clipboard-downloads")
+        if pending.missing().iter().any(|i| !skipped.contains(i)) {
+            return Err(anyhow::anyhow!("file data is missing requested files"));
+        }
+        Ok(self.received_files(files_item(pending.local.into_iter().flatten()), from))
+    }
+
+    /// 按 `set_received_files_on_clipboard` 决定已保存的文件是否放入剪贴板；
+    /// 不放入时直接记录历史并通知，返回 None。
+    fn received_files(&self, item: ClipboardItem, from: IpAddr) -> Option<ClipboardItem> {
+        if self.config.set_received_files_on_clipboard {
+            return Some(item);
+        }
+        tracing::info!("received files from {} saved to disk, not placed on the clipboard", from);
+        self.record_received(&item, from, None, false);
+        None
+    }
+}
+
+/// 按来源应用决定是否同步本机剪贴板变化：来源应用在 `ignored` 中，或 `allowed` 非空且
+/// 不含该应用时不同步（均不区分大小写）。来源应用未知时（如 Wayland）只看内容是否带有
+/// 「勿同步」提示。
+fn source_app_allowed(
+    app: Option<&str>,
+    sensitive_hint: bool,
+    allowed: &[String],
+    ignored: &[String],
+) -> bool {
+    let Some(app) = app else {
+        return !sensitive_hint;
+    };
+    let listed = |list: &[String]| list.iter().any(|name| name.eq_ignore_ascii_case(app));
+    !listed(ignored) && (allowed.is_empty() || listed(allowed))
+}
+
+/// 本机剪贴板内容的来源应用名，按协议限制截断到 255 字节以内（保持 UTF-8 字符完整）。
+fn local_source_app() -> Option<String> {
+    let mut app = crate::clipboard::clipboard_source_app()?;
+    while app.len() > u8::MAX as usize {
+        app.pop();
+    }
+    Some(app)
+}
+
+/// 立即发送的内容：Linux 下使用系统剪贴板时优先取 PRIMARY 选区中选中的文本，
+/// 没有选中文本时读取剪贴板。
+fn selected_item(
+    system_clipboard: bool,
+    clipboard: &dyn ClipboardBackend,
+) -> Result<Option<ClipboardItem>> {
+    #[cfg(target_os = "linux")]
+    if system_clipboard {
+        match crate::clipboard::read_primary_text() {
+            Ok(Some(text)) => return Ok(Some(text_item(text))),
+            Ok(None) => {}
+            Err(e) => tracing::debug!("primary selection unavailable: {}", e),
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = system_clipboard;
+    clipboard.read()
+}
+
+/// 决定收到的内容写入哪个选区：PRIMARY 内容仅在 Linux 且开启 `sync_primary` 时写入 PRIMARY，
+/// 其他情况下丢弃（不会误写入常规剪贴板）。
+fn selection_target(selection: Selection, sync_primary: bool) -> Option<Selection> {
+    match selection {
+        Selection::Clipboard => Some(Selection::Clipboard),
+        Selection::Primary if sync_primary && cfg!(target_os = "linux") => Some(Selection::Primary),
+        Selection::Primary => None,
+    }
+}
+
+/// 将远端 PRIMARY 选区文本写入本机 PRIMARY 选区（仅 Linux）。
+fn apply_remote_primary(text: &str) {
+    #[cfg(target_os = "linux")]
+    if let Err(e) = crate::clipboard::write_primary_text(text.to_string()) {
+        tracing::warn!("failed to write primary selection: {e}");
     }
+    #[cfg(not(target_os = "linux"))]
+    let _ = text;
+}
+
+/// 清单处理结果。
+enum ManifestAction {
+    /// 需要向发送端请求缺少的文件
+    Request(ProtocolMessage),
+    /// 本地已有全部文件
+    Complete(ClipboardItem),
+    Ignore,
+}
+
+/// 读取剪贴板中的文件，跳过目录、无法访问或超过 `max_file_size` 的文件。
+pub(crate) fn read_file_entries(
+    config: &AppConfig,
+    files: &[ClipboardFile],
+) -> Result<Vec<FileEntry>> {
+    let mut entries = Vec::new();
+    for_each_file_entry(config, files, |entry| entries.push(entry))?;
+    Ok(entries)
+}
+
+/// 读取剪贴板中的文件并直接编码为文件负载，每个文件读入后即写入负载，不保留条目列表。
+/// 没有可发送的文件时返回 None。
+pub(crate) fn read_files_payload(
+    config: &AppConfig,
+    files: &[ClipboardFile],
+) -> Result<Option<FilesPayload>> {
+    let mut payload = FilesPayload::new();
+    for_each_file_entry(config, files, |entry| payload.push(&entry))?;
+    Ok((!payload.is_empty()).then_some(payload))
+}
 
+/// 按 [`read_file_entries`] 的规则逐个读取文件，交给 `f` 处理。
+fn for_each_file_entry(
+    config: &AppConfig,
+    files: &[ClipboardFile],
+    mut f: impl FnMut(FileEntry),
+) -> Result<()> {
+    for file in outgoing_files(config, files) {
+        let content = std::fs::read(&file.path)?;
+        let source_path = if config.include_source_path {
+            display_source_dir(&file.path)
+        } else {
+            None
+        };
+        f(FileEntry {
+            name: file.name,
+            size: file.size,
+            content,
+            source_path,
+        });
+    }
+    Ok(())
 }
 
-/// 简易 percent-decode：将 `%XX` 序列还原为原始字节并转回 UTF-8 字符串。
-fn percent_decode(input: &str) -> String {
-    let mut out = Vec::with_capacity(input.len());
-    let bytes = input.as_bytes();
-    let mut i = 0;
-    while i < bytes.len() {
-        if bytes[i] == b'%' && i + 2 < bytes.len() {
-            if let Ok(val) = u8::from_str_radix(
-                &input[i + 1..i + 3],
-                16,
-            ) {
-                out.push(val);
-                i += 3;
+/// 剪贴板中可以发送的文件（不读取内容）：跳过不存在的路径、目录与超过 `max_file_size` 的文件。
+fn outgoing_files(config: &AppConfig, files: &[ClipboardFile]) -> Vec<OutgoingFile> {
+    let mut outgoing = Vec::with_capacity(files.len());
+    for file in files {
+        let raw = &file.path;
+        // 剪贴板返回的路径可能带 file:// 前缀与 URL 编码，需要还原
+        let decoded = local_file_path(raw);
+        let path = Path::new(&decoded);
+        tracing::debug!("reading file: raw={} resolved={}", raw, path.display());
+        let meta = match std::fs::metadata(path) {
+            Ok(m) => m,
+            Err(e) => {
+                tracing::warn!("skip file {}: {}", path.display(), e);
                 continue;
             }
+        };
+        if meta.is_dir() {
+            tracing::debug!("skip directory: {}", path.display());
+            continue;
+        }
+        let size = meta.len();
+        if size > config.max_file_size {
+            tracing::warn!("skip file {} larger than max_file_size", path.display());
+            continue;
         }
-        out.push(bytes[i]);
-        i += 1;
+        let name = path
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "file".into());
+        outgoing.push(OutgoingFile {
+            name,
+            path: path.to_path_buf(),
+            size,
+        });
     }
-    String::from_utf8_lossy(&out).into_owned()
+    outgoing
 }
 
-/// 根据剪贴板内容计算粗粒度哈希，用于去重与抑制回环更新。
-fn hash_item(item: &ClipboardItem) -> Option<u64> {
-    let mut hasher = DefaultHasher::new();
-    match item {
-        ClipboardItem::Text(t) => t.hash(&mut hasher),
-        ClipboardItem::Image(bytes) => bytes.hash(&mut hasher),
-ClipboardItem::Files(files) => {
-            "files".hash(&mut hasher);
-            for f in files {
-                // 只用文件名（不含目录）+ 文件大小来算哈希，
-                // 这样发送端（file:///原始/路径/foo.txt）和
-                // 接收端（/Downloads/lan-clipboard/foo.txt）能得到相同结果
-                let path_str = if let Some(stripped) = f.path.strip_prefix("file://") {
-                    stripped
-                } else {
-                    &f.path
-                };
-                let p = std::path::Path::new(path_str);
-                if let Some(name) = p.file_name() {
-                    name.hash(&mut hasher);
-                }
-                if let Ok(meta) = std::fs::metadata(p) {
-                    meta.len().hash(&mut hasher);
-                }
+/// 将收到的条目写入剪贴板。
+///
+/// 剪贴板当前内容与收到的条目相同时不写入：重复写入会夺走设置该内容的应用的剪贴板所有权。
+///
+/// 文件在此之前已落盘，写剪贴板失败（例如 Wayland 下文件剪贴板支持不完整）只记录警告，
+/// 保留已下载的文件，不中断同步。
+fn write_received_item(
+    clipboard: &mut dyn ClipboardBackend,
+    item: ClipboardItem,
+    normalized_hash: bool,
+) -> Result<()> {
+    let incoming = hash_item(&item, normalized_hash);
+    if incoming.is_some() {
+        let current = clipboard.read().ok().flatten();
+        if current.and_then(|c| hash_item(&c, normalized_hash)) == incoming {
+            tracing::debug!("received content identical to clipboard, skipping write");
+            return Ok(());
+        }
+    }
+    let files = match &item {
+        ClipboardItem::Files(files) => files.clone(),
+        _ => return clipboard.write(item),
+    };
+    if let Err(e) = clipboard.write(item) {
+        let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        tracing::warn!(
+            "received files were saved but could not be put on the clipboard: {e}; files: {}",
+            paths.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// 等待下一次需要处理的剪贴板变化。
+///
+/// `settle` 为 0 时每次变化立即返回；否则每次变化把截止时刻推迟到 `settle` 之后，
+/// 直到内容保持不变满 `settle` 才返回一次（静默计时，而不是固定频率节流）。
+/// 截止时刻保存在调用方，select 取消本 future 时不会丢失等待中的变化。
+async fn next_settled_change(
+    rx: &mut mpsc::Receiver<()>,
+    deadline: &mut Option<tokio::time::Instant>,
+    settle: Duration,
+) -> Option<()> {
+    if settle.is_zero() {
+        return rx.recv().await;
+    }
+    loop {
+        tokio::select! {
+            changed = rx.recv() => {
+                changed?;
+                *deadline = Some(tokio::time::Instant::now() + settle);
+            }
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(tokio::time::Instant::now)),
+                if deadline.is_some() => {
+                *deadline = None;
+                return Some(());
+            }
+        }
+    }
+}
+
+fn files_item(paths: impl Iterator<Item = PathBuf>) -> ClipboardItem {
+    ClipboardItem::Files(
+        paths
+            .map(|p| ClipboardFile {
+                path: clipboard_file_path(&p),
+            })
+            .collect(),
+    )
+}
+
+/// 写入剪贴板的文件路径：转换为绝对路径（下载目录可配置为相对路径）。
+///
+/// Windows 的文件剪贴板要求绝对路径，且不接受正斜杠与 `\\?\` 前缀：
+/// `std::path::absolute` 会统一为反斜杠，前缀在此去掉。
+fn clipboard_file_path(path: &Path) -> String {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let path = absolute.to_string_lossy();
+    if cfg!(target_os = "windows") {
+        if let Some(unc) = path.strip_prefix(r"\\?\UNC\") {
+            return format!(r"\\{unc}");
+        }
+        if let Some(local) = path.strip_prefix(r"\\?\") {
+            return local.to_string();
+        }
+    }
+    path.into_owned()
+}
+
+/// 最近处理过的消息 ID（sender_id + 序号），容量满时淘汰最早记录的 ID。
+///
+/// 序号的高 32 位是发送端每次启动的纪元，同一实例 ID 重启后发出的消息不会被当作重复。
+struct SeenMessages {
+    capacity: usize,
+    order: VecDeque<([u8; 16], u64)>,
+    ids: HashSet<([u8; 16], u64)>,
+}
+
+impl SeenMessages {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            order: VecDeque::with_capacity(capacity),
+            ids: HashSet::with_capacity(capacity),
+        }
+    }
+
+    /// 记录消息 ID，首次出现返回 true，重复返回 false。未携带序号（0）的消息总是返回 true。
+    fn insert(&mut self, sender_id: [u8; 16], seq: u64) -> bool {
+        if seq == 0 {
+            return true;
+        }
+        let id = (sender_id, seq);
+        if !self.ids.insert(id) {
+            return false;
+        }
+        self.order.push_back(id);
+        while self.order.len() > self.capacity {
+            if let Some(old) = self.order.pop_front() {
+                self.ids.remove(&old);
             }
         }
+        true
     }
-    Some(hasher.finish())
 }
 
+/// 最近写入剪贴板的内容哈希，容量满时淘汰最早的记录；每条记录在对应的变化事件出现后清除。
+struct SelfWrites {
+    capacity: usize,
+    hashes: VecDeque<u64>,
+}
+
+impl SelfWrites {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            hashes: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    fn record(&mut self, hash: u64) {
+        self.hashes.retain(|h| *h != hash);
+        self.hashes.push_back(hash);
+        while self.hashes.len() > self.capacity {
+            self.hashes.pop_front();
+        }
+    }
+
+    /// 哈希属于本机的写入时移除该记录并返回 true。
+    fn take(&mut self, hash: u64) -> bool {
+        match self.hashes.iter().position(|h| *h == hash) {
+            Some(i) => {
+                self.hashes.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.hashes.clear();
+    }
+}
+
+/// 最近从对端应用的内容哈希：容量满时淘汰最早的记录，超过时间窗口的记录视为失效。
+///
+/// 与 [`SelfWrites`] 不同，记录不会在观察到一次后清除，作为时间屏蔽之外的确定性兜底。
+struct AppliedHashes {
+    capacity: usize,
+    window: Duration,
+    entries: VecDeque<(u64, Instant)>,
+}
+
+impl AppliedHashes {
+    fn new(capacity: usize, window: Duration) -> Self {
+        Self {
+            capacity,
+            window,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    fn record(&mut self, hash: u64, now: Instant) {
+        self.entries.retain(|(h, _)| *h != hash);
+        self.entries.push_back((hash, now));
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    /// 哈希是否在时间窗口内从对端应用过（顺带清除已失效的记录）。
+    fn contains(&mut self, hash: u64, now: Instant) -> bool {
+        let window = self.window;
+        self.entries.retain(|(_, at)| now.saturating_duration_since(*at) < window);
+        self.entries.iter().any(|(h, _)| *h == hash)
+    }
+}
+
+/// 短时剪贴板读取缓存：避免 watcher 连续触发时重复调用开销较大的后端读取（如 Wayland）。
+struct ReadCache {
+    ttl: Duration,
+    entry: Option<(Instant, Option<ClipboardItem>)>,
+}
+
+impl ReadCache {
+    fn new(ttl: Duration) -> Self {
+        Self { ttl, entry: None }
+    }
+
+    /// 缓存有效时直接返回缓存内容，否则调用 `read` 读取并缓存。
+    fn read<F>(&mut self, now: Instant, read: F) -> Result<Option<ClipboardItem>>
+    where
+        F: FnOnce() -> Result<Option<ClipboardItem>>,
+    {
+        if let Some((at, item)) = &self.entry {
+            if now.saturating_duration_since(*at) < self.ttl {
+                return Ok(item.clone());
+            }
+        }
+        let item = read()?;
+        self.entry = Some((now, item.clone()));
+        Ok(item)
+    }
+
+    /// 写入剪贴板后使缓存失效。
+    fn invalidate(&mut self) {
+        self.entry = None;
+    }
+}
+
+/// 生成文件所在目录的展示用描述：家目录替换为 `~`，去除控制字符并限制长度。
+fn display_source_dir(path: &Path) -> Option<String> {
+    const MAX_LEN: usize = 256;
+
+    let parent = path.parent()?;
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+    let shown = match home.as_ref().and_then(|h| parent.strip_prefix(h).ok()) {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(rest) => format!("~{}{}", std::path::MAIN_SEPARATOR, rest.display()),
+        None => parent.display().to_string(),
+    };
+    let mut cleaned: String = shown.chars().filter(|c| !c.is_control()).collect();
+    if cleaned.len() > MAX_LEN {
+        let mut end = MAX_LEN;
+        while !cleaned.is_char_boundary(end) {
+            end -= 1;
+        }
+        cleaned.truncate(end);
+    }
+    (!cleaned.is_empty()).then_some(cleaned)
+}
+
+/// 将远端给出的文件名收敛为单一路径分量，去除目录与 `..` 等危险部分。
+fn safe_file_name(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or("");
+    match base {
+        "" | "." | ".." => "file".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// 写入磁盘前检查收到的一组文件：声明大小须与内容一致且不超过 `max_file_size`，
+/// 文件名不能为空、不能只是目录或含控制字符。任一文件不合格时整组拒绝，不写入任何文件。
+fn validate_received_files(entries: &[FileEntry], max_file_size: u64) -> Result<()> {
+    for entry in entries {
+        let len = entry.content.len() as u64;
+        if entry.size != len {
+            return Err(anyhow::anyhow!(
+                "file {:?} declares {} bytes but carries {}",
+                entry.name,
+                entry.size,
+                len
+            ));
+        }
+        if len > max_file_size {
+            return Err(anyhow::anyhow!(
+                "file {:?} of {} bytes exceeds max_file_size",
+                entry.name,
+                len
+            ));
+        }
+        let base = entry.name.rsplit(['/', '\\']).next().unwrap_or("");
+        if matches!(base.trim(), "" | "." | "..") || entry.name.chars().any(char::is_control) {
+            return Err(anyhow::anyhow!("invalid file name {:?}", entry.name));
+        }
+    }
+    Ok(())
+}
+
+/// 不接收文件的对端中直接以 IP 配置的地址；主机名留待运行时异步解析。
+fn literal_files_denied(peers: &[PeerConfig]) -> HashSet<IpAddr> {
+    peers
+        .iter()
+        .filter(|p| !p.allow_files_from)
+        .filter_map(|p| p.host.parse().ok())
+        .collect()
+}
+
+/// 解析不接收文件的对端的全部地址，解析失败的主机名暂不生效。
+async fn resolve_files_denied(peers: &[PeerConfig]) -> HashSet<IpAddr> {
+    let mut denied = HashSet::new();
+    for peer in peers.iter().filter(|p| !p.allow_files_from) {
+        denied.extend(peer.lookup_ips().await);
+    }
+    denied
+}
+
+/// 定期重新解析不接收文件的对端，主机名指向的地址变化（如 DHCP 续租）后限制仍然生效。
+async fn refresh_files_denied(peers: Vec<PeerConfig>, tx: mpsc::Sender<HashSet<IpAddr>>) {
+    loop {
+        tokio::time::sleep(FILES_DENIED_REFRESH_INTERVAL).await;
+        if tx.send(resolve_files_denied(&peers).await).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// 经中继到达的消息能否处理：`from` 只是中继的地址，无法按来源 IP 区分房间内的设备。
+/// 需要按来源地址回复或直连传输的文件、分块与回显消息一律忽略；
+/// 随剪贴板更新发来的文件是否接收统一由 `[relay]` 的 `allow_files_from` 决定。
+fn relayed_message_allowed(config: &AppConfig, msg: &ProtocolMessage) -> bool {
+    match msg {
+        ProtocolMessage::FileManifest { .. }
+        | ProtocolMessage::FileRequest { .. }
+        | ProtocolMessage::FileData { .. }
+        | ProtocolMessage::EchoRequest { .. }
+        | ProtocolMessage::ChunkData { .. }
+        | ProtocolMessage::FileChunk { .. } => false,
+        ProtocolMessage::ClipboardUpdate { content_type: ContentType::Files, .. } => {
+            config.relay.as_ref().is_some_and(|relay| relay.allow_files_from)
+        }
+        _ => true,
+    }
+}
+
+/// 收到的文件是否以路径文本放入剪贴板：`auto` 时取决于剪贴板后端能否放入文件。
+fn received_files_as_paths(format: ReceivedFilesFormat, backend_supports_files: bool) -> bool {
+    match format {
+        ReceivedFilesFormat::Files => false,
+        ReceivedFilesFormat::Paths => true,
+        ReceivedFilesFormat::Auto if backend_supports_files => false,
+        ReceivedFilesFormat::Auto => {
+            tracing::warn!(
+                "clipboard backend cannot hold files, received files will be placed on the \
+                 clipboard as newline-separated paths"
+            );
+            true
+        }
+    }
+}
+
+/// 以换行分隔的本地路径文本代替文件条目。
+fn file_paths_item(files: &[ClipboardFile]) -> ClipboardItem {
+    let paths: Vec<String> = files.iter().map(|f| local_file_path(&f.path)).collect();
+    ClipboardItem::Text(paths.join("\n"))
+}
+
+/// 将条目中的文本写入镜像文件（`mirror_text_to_file`），非文本内容跳过，失败只记录日志。
+fn mirror_text(path: &Path, item: &ClipboardItem) {
+    let text = match item {
+        ClipboardItem::Text(text) | ClipboardItem::Url(text) => text.as_str(),
+        ClipboardItem::Multi(formats) => match plain_text_format(formats) {
+            Some(text) => text,
+            None => return,
+        },
+        ClipboardItem::Image(_) | ClipboardItem::Files(_) => return,
+    };
+    if let Err(e) = write_atomically(path, text.as_bytes()) {
+        tracing::warn!("failed to mirror text to {}: {}", path.display(), e);
+    }
+}
+
+/// 先写入同目录下的临时文件再重命名覆盖目标，读取方不会读到写了一半的内容。
+pub(crate) fn write_atomically(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let temp = dir.join(format!(".{}.part", Uuid::new_v4().simple()));
+    std::fs::write(&temp, data)?;
+    std::fs::rename(&temp, path).inspect_err(|_| remove_files(std::iter::once(&temp)))
+}
+
+/// 尽力删除一组文件（用于失败后的清理），删除失败只记录日志。
+fn remove_files<'a>(paths: impl Iterator<Item = &'a PathBuf>) {
+    for path in paths {
+        if let Err(e) = std::fs::remove_file(path) {
+            tracing::debug!("cleanup of {} failed: {}", path.display(), e);
+        }
+    }
+}
+
+/// 按换行策略转换收到的文本。
+///
+/// 只转换 `\r\n` 与 `\n`，单独的 `\r` 保持不变；含 NUL 字符的文本多半不是普通文本，原样保留。
+fn normalize_newlines(text: String, policy: NewlinePolicy) -> String {
+    let crlf = match policy {
+        NewlinePolicy::Preserve => return text,
+        NewlinePolicy::Lf => false,
+        NewlinePolicy::Crlf => true,
+        NewlinePolicy::MatchReceiver => cfg!(windows),
+    };
+    if text.contains('\0') {
+        return text;
+    }
+    let lf = text.replace("\r\n", "\n");
+    if crlf {
+        lf.replace('\n', "\r\n")
+    } else {
+        lf
+    }
+}
+
+/// 一个对端的分块发送参数。
+struct ChunkedTransfer {
+    config: AppConfig,
+    addr: String,
+    sender_id: [u8; 16],
+    transfer_id: u64,
+    reply_port: u16,
+    content_type: ContentType,
+}
+
+/// 逐块发送并等待确认（每次只有一块在途）；发送失败或确认超时时按退避重试，
+/// 重试总是从接收端最后确认的偏移开始，不会从头重发。
+async fn send_chunks(
+    transfer: ChunkedTransfer,
+    mut chunks: OutgoingChunks,
+    mut acks: mpsc::Receiver<u64>,
+) -> Result<()> {
+    let mut retries = 0;
+    while let Some((offset, data)) = chunks.next_chunk() {
+        // 丢弃上一次超时后迟到的确认，避免误认为是本块的确认
+        while acks.try_recv().is_ok() {}
+        let msg = ProtocolMessage::ChunkData {
+            sender_id: transfer.sender_id,
+            transfer_id: transfer.transfer_id,
+            reply_port: transfer.reply_port,
+            content_type: transfer.content_type,
+            total: chunks.total(),
+            offset,
+            data: data.to_vec(),
+        };
+        let sent = send_to_addr(&transfer.config, transfer.addr.clone(), &msg).await?;
+        let ack = match sent.is_ok() {
+            true => tokio::time::timeout(CHUNK_ACK_TIMEOUT, acks.recv()).await.ok().flatten(),
+            false => None,
+        };
+        match ack {
+            Some(next_offset) => {
+                chunks.ack(next_offset)?;
+                retries = 0;
+            }
+            None if retries < CHUNK_MAX_RETRIES => {
+                retries += 1;
+                tracing::debug!(
+                    "chunk at offset {} to {} not acknowledged, retry {}",
+                    offset,
+                    transfer.addr,
+                    retries
+                );
+                tokio::time::sleep(Duration::from_millis(500) * retries).await;
+            }
+            None => {
+                return Err(anyhow::anyhow!(
+                    "no acknowledgement after {} retries at offset {}",
+                    retries,
+                    offset
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 睡眠到 `deadline`；为 None 时永不返回。
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// 按顺序对要发送的文本执行内置转换。
+fn apply_text_transforms(text: String, transforms: &[TextTransform]) -> String {
+    transforms.iter().fold(text, |text, transform| match transform {
+        TextTransform::Trim => text.trim().to_string(),
+        TextTransform::StripTrailingNewline => {
+            text.trim_end_matches(['\r', '\n']).to_string()
+        }
+        TextTransform::CollapseWhitespace => text.split_whitespace().collect::<Vec<_>>().join(" "),
+    })
+}
+
+/// 把文本转换为指定的 Unicode 规范化形式；已是该形式时不重新分配。
+fn normalize_unicode(text: String, form: UnicodeNormalization) -> String {
+    match form {
+        UnicodeNormalization::None => text,
+        UnicodeNormalization::Nfc if unicode_normalization::is_nfc(&text) => text,
+        UnicodeNormalization::Nfc => text.nfc().collect(),
+        UnicodeNormalization::Nfd if unicode_normalization::is_nfd(&text) => text,
+        UnicodeNormalization::Nfd => text.nfd().collect(),
+    }
+}
+
+/// 按重名策略确定保存路径：目标不存在时原样返回；`skip` 且目标已存在时返回 None。
+fn collision_target(path: PathBuf, policy: NameCollisionPolicy) -> Option<PathBuf> {
+    if !path.exists() {
+        return Some(path);
+    }
+    match policy {
+        NameCollisionPolicy::Overwrite => Some(path),
+        NameCollisionPolicy::Skip => None,
+        NameCollisionPolicy::Rename => {
+            let stem = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let ext = path
+                .extension()
+                .map(|e| format!(".{}", e.to_string_lossy()))
+                .unwrap_or_default();
+            (1u32..)
+                .map(|n| path.with_file_name(format!("{stem} ({n}){ext}")))
+                .find(|candidate| !candidate.exists())
+        }
+    }
+}
+
+/// 根据剪贴板内容计算粗粒度哈希，用于去重与抑制回环更新。
+///
+/// 默认与剪贴板监听使用同一指纹（见 [`content_fingerprint`]）；
+/// `normalized` 为 true 时图片哈希额外包含格式标记与解析出的尺寸（只解析头部）。
+fn hash_item(item: &ClipboardItem, normalized: bool) -> Option<u64> {
+    match item {
+        ClipboardItem::Image(bytes) if normalized => {
+            let mut hasher = DefaultHasher::new();
+            "image".hash(&mut hasher);
+            image_signature(bytes).hash(&mut hasher);
+            bytes.hash(&mut hasher);
+            Some(hasher.finish())
+        }
+        _ => Some(content_fingerprint(item)),
+    }
+}
+
+/// 两阶段读取的快速路径读到该内容时得到的纯文本哈希：文本即自身，多格式内容取其中的纯文本。
+fn fast_text_hash(item: &ClipboardItem) -> Option<u64> {
+    match item {
+        ClipboardItem::Text(_) | ClipboardItem::Url(_) => Some(content_fingerprint(item)),
+        ClipboardItem::Multi(formats) => formats
+            .iter()
+            .find(|(mime, _)| mime == MIME_PLAIN_TEXT)
+            .map(|(_, data)| {
+                content_fingerprint(&ClipboardItem::Text(String::from_utf8_lossy(data).into()))
+            }),
+        ClipboardItem::Image(_) | ClipboardItem::Files(_) => None,
+    }
+}
+
+/// 解析图片头部得到格式与尺寸，无法识别时返回 None。
+fn image_signature(bytes: &[u8]) -> Option<(image::ImageFormat, u32, u32)> {
+    let reader = image::ImageReader::new(std::io::Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?;
+    let format = reader.format()?;
+    let (w, h) = reader.into_dimensions().ok()?;
+    Some((format, w, h))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::broadcast_to_peers;
+
+    fn free_port() -> u16 {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    /// 在独立线程的运行时中运行核心服务（运行循环独占服务，直到测试进程结束）。
+    fn run_in_background(mut core: CoreService) {
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            let _ = rt.block_on(core.run());
+        });
+    }
+
+    /// 以内存剪贴板为后端、在空闲端口上运行核心服务，等它开始监听后返回剪贴板与端口。
+    async fn spawn_core(config: AppConfig) -> (MemoryClipboard, u16) {
+        let port = free_port();
+        let clipboard = MemoryClipboard::new();
+        let config = AppConfig { listen_port: port, ..config };
+        run_in_background(CoreService::with_backend(config, Box::new(clipboard.clone())).unwrap());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        (clipboard, port)
+    }
+
+    /// 在空闲端口上运行只接收消息的对端，返回收到的消息与端口。
+    async fn spawn_peer() -> (mpsc::Receiver<crate::network::IncomingMessage>, u16) {
+        let port = free_port();
+        let config = AppConfig { listen_port: port, ..AppConfig::default() };
+        let (tx, rx) = mpsc::channel(16);
+        let keys = KeyRing::from_config(&config).unwrap();
+        tokio::spawn(NetworkServer::with_keyring(&config, keys, tx).unwrap().run());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        (rx, port)
+    }
+
+    /// 只向本机 `port` 发送的配置。
+    fn sender_for(port: u16) -> AppConfig {
+        AppConfig {
+            peers: vec![PeerConfig::new("127.0.0.1", port)],
+            ..AppConfig::default()
+        }
+    }
+
+    /// 发送者 ID 为 `[sender; 16]`、不带扩展字段的剪贴板更新。
+    fn remote_update(sender: u8, seq: u64, content_type: ContentType, payload: Vec<u8>) -> ProtocolMessage {
+        ProtocolMessage::ClipboardUpdate {
+            sender_id: [sender; 16],
+            content_type,
+            payload_size: payload.len() as u64,
+            payload,
+            seq,
+            selection: Selection::Clipboard,
+            source_app: None,
+            clock: 0,
+            ttl_secs: 0,
+            sensitive: false,
+        }
+    }
+
+    fn text_update(sender: u8, seq: u64, text: &str) -> ProtocolMessage {
+        remote_update(sender, seq, ContentType::Text, text.as_bytes().to_vec())
+    }
+
+    /// 等待剪贴板内容满足 `want` 并返回该内容，5 秒内未满足则测试失败。
+    async fn wait_for_item(
+        clipboard: &MemoryClipboard,
+        want: impl Fn(&ClipboardItem) -> bool,
+    ) -> ClipboardItem {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match clipboard.get() {
+                    Some(item) if want(&item) => return item,
+                    _ => tokio::time::sleep(Duration::from_millis(20)).await,
+                }
+            }
+        })
+        .await
+        .expect("clipboard never held the expected item")
+    }
+
+    async fn wait_for_text(clipboard: &MemoryClipboard, text: &str) {
+        wait_for_item(clipboard, |item| matches!(item, ClipboardItem::Text(t) if t == text)).await;
+    }
+
+    #[tokio::test]
+    async fn broadcast_reaches_loopback_listener() {
+        let (mut rx, peer_port) = spawn_peer().await;
+        let config = AppConfig {
+            listen_port: free_port(),
+            ..sender_for(peer_port)
+        };
+        let core = CoreService::new(config).unwrap();
+        let results = core
+            .broadcast(ClipboardItem::Text("hello peer".into()))
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].is_ok(), "{:?}", results[0]);
+
+        let msg = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap()
+            .msg;
+        match msg {
+            ProtocolMessage::ClipboardUpdate {
+                sender_id,
+                content_type,
+                payload,
+                ..
+            } => {
+                assert_eq!(sender_id, *core.instance_id.as_bytes());
+                assert!(matches!(content_type, ContentType::Text));
+                assert_eq!(payload, b"hello peer");
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn message_with_own_sender_id_is_dropped() {
+        let port = free_port();
+        let clipboard = MemoryClipboard::new();
+        let core = CoreService::with_backend(
+            AppConfig { listen_port: port, ..AppConfig::default() },
+            Box::new(clipboard.clone()),
+        )
+        .unwrap();
+        let history = core.history();
+        // 经过编解码后 sender_id 仍是本实例的 16 字节 ID
+        let own = core.build_clipboard_message(ClipboardItem::Text("echo".into())).unwrap().unwrap();
+        let own = crate::protocol::decode_message(&encode_message(&own).unwrap()).unwrap();
+        let ProtocolMessage::ClipboardUpdate { sender_id, .. } = &own else {
+            unreachable!();
+        };
+        assert_eq!(sender_id, core.instance_id.as_bytes());
+        let config = core.config.clone();
+        run_in_background(core);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let addr = format!("127.0.0.1:{port}");
+        send_to_addr(&config, addr.clone(), &own).await.unwrap();
+        send_to_addr(&config, addr, &text_update(2, 1, "from peer")).await.unwrap();
+        let item = wait_for_item(&clipboard, |_| true).await;
+        assert!(matches!(item, ClipboardItem::Text(t) if t == "from peer"));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(history.snapshot().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn delayed_self_write_event_is_suppressed() {
+        let (mut rx, peer_port) = spawn_peer().await;
+        let (memory, core_port) = spawn_core(AppConfig {
+            clipboard_poll_interval_ms: 50,
+            ..sender_for(peer_port)
+        })
+        .await;
+
+        let sender = sender_for(core_port);
+        for (seq, text) in [(1, "first"), (2, "second")] {
+            broadcast_to_peers(&sender, &text_update(7, seq, text)).await.unwrap();
+            wait_for_text(&memory, text).await;
+        }
+
+        // 屏蔽窗口过后，剪贴板管理器重新放回我们之前写入的内容：不应作为本机复制广播
+        tokio::time::sleep(SUPPRESS_WINDOW + Duration::from_millis(100)).await;
+        memory.set(ClipboardItem::Text("first".into()));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        memory.set(ClipboardItem::Text("typed locally".into()));
+
+        let broadcasts = tokio::time::timeout(Duration::from_secs(3), async {
+            let mut payloads = Vec::new();
+            while let Some(incoming) = rx.recv().await {
+                if let ProtocolMessage::ClipboardUpdate { payload, .. } = incoming.msg {
+                    let done = payload == b"typed locally";
+                    payloads.push(payload);
+                    if done {
+                        break;
+                    }
+                }
+            }
+            payloads
+        })
+        .await
+        .unwrap();
+        assert_eq!(broadcasts, vec![b"typed locally".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn synced_text_is_mirrored_to_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mirror = dir.path().join("clipboard.txt");
+        let (memory, core_port) = spawn_core(AppConfig {
+            mirror_text_to_file: Some(mirror.clone()),
+            ..AppConfig::default()
+        })
+        .await;
+
+        let wait_for = |expected: &'static str| {
+            let mirror = mirror.clone();
+            async move {
+                tokio::time::timeout(Duration::from_secs(3), async {
+                    while std::fs::read_to_string(&mirror).ok().as_deref() != Some(expected) {
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                    }
+                })
+                .await
+                .unwrap();
+            }
+        };
+        let sender = sender_for(core_port);
+        for (seq, text) in [(1, "first remote"), (2, "second remote")] {
+            broadcast_to_peers(&sender, &text_update(7, seq, text)).await.unwrap();
+            wait_for(text).await;
+        }
+
+        // 图片不覆盖镜像文件，本机复制的文本同样写入
+        tokio::time::sleep(SUPPRESS_WINDOW).await;
+        memory.set(ClipboardItem::Image(vec![1, 2, 3]));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(std::fs::read_to_string(&mirror).unwrap(), "second remote");
+        memory.set(ClipboardItem::Text("copied locally".into()));
+        wait_for("copied locally").await;
+
+        // 写入经由临时文件重命名完成，目录中不留下临时文件
+        let names: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["clipboard.txt"]);
+    }
+
+    #[tokio::test]
+    async fn received_image_reaches_backend_as_png() {
+        let (memory, core_port) = spawn_core(AppConfig::default()).await;
+
+        let encode_png = |image: image::RgbaImage| {
+            let mut png = Vec::new();
+            image
+                .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+                .unwrap();
+            png
+        };
+        // 随机像素的图块纵向重复：间隔超出 PNG（deflate）的窗口，但在 zstd 的窗口内，
+        // 消息体压缩确实生效
+        let compressible = || {
+            let tile: Vec<u8> = (0..256 * 128 * 4).map(|_| rand::random::<u8>()).collect();
+            encode_png(image::RgbaImage::from_raw(256, 256, tile.repeat(2)).unwrap())
+        };
+        let solid = encode_png(image::RgbaImage::from_pixel(3, 2, image::Rgba([200, 10, 10, 255])));
+        let cases = [
+            (solid, (3, 2), crate::config::Compression::Off, false),
+            (compressible(), (256, 256), crate::config::Compression::Fast, false),
+            (compressible(), (256, 256), crate::config::Compression::Off, true),
+            (compressible(), (256, 256), crate::config::Compression::Best, true),
+        ];
+        for (round, (png, size, compression, gzip_frames)) in cases.into_iter().enumerate() {
+            let msg = remote_update(7, round as u64 + 1, ContentType::Image, png.clone());
+            if let Some(level) = compression.zstd_level() {
+                let body = encode_message(&msg).unwrap();
+                assert!(crate::protocol::compress_body(&body, level).unwrap().is_some());
+            }
+            let sender = AppConfig {
+                compression,
+                gzip_frames,
+                ..sender_for(core_port)
+            };
+            let results = broadcast_to_peers(&sender, &msg).await.unwrap();
+            assert!(results[0].is_ok(), "{:?}", results[0]);
+
+            // 后端拿到的是原始 PNG 字节，而不是传输时的任何编码形式
+            let want = |item: &ClipboardItem| matches!(item, ClipboardItem::Image(bytes) if *bytes == png);
+            let ClipboardItem::Image(written) = wait_for_item(&memory, want).await else {
+                unreachable!();
+            };
+            assert!(written.starts_with(PNG_SIGNATURE));
+            let decoded = image::load_from_memory(&written).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), size);
+        }
+    }
+
+    #[tokio::test]
+    async fn bounced_content_is_not_rebroadcast_after_suppress_window() {
+        let (mut rx, peer_port) = spawn_peer().await;
+        let (memory, core_port) = spawn_core(sender_for(peer_port)).await;
+
+        broadcast_to_peers(&sender_for(core_port), &text_update(7, 1, "from A!")).await.unwrap();
+        wait_for_text(&memory, "from A!").await;
+
+        // 本机复制了别的内容后，刚应用的内容在屏蔽窗口之后被弹回：不应再次广播
+        tokio::time::sleep(SUPPRESS_WINDOW + Duration::from_millis(100)).await;
+        memory.set(ClipboardItem::Text("local".into()));
+        assert_eq!(next_update(&mut rx).await.1, b"local");
+        // 等读取缓存过期，确保下面读到的是弹回的内容
+        tokio::time::sleep(READ_CACHE_TTL * 2).await;
+        memory.set(ClipboardItem::Text("from A!".into()));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        memory.set(ClipboardItem::Text("later".into()));
+        assert_eq!(next_update(&mut rx).await.1, b"later");
+    }
+
+    #[test]
+    fn burst_of_changes_fits_configured_channel_capacity() {
+        let config = AppConfig {
+            listen_port: free_port(),
+            clipboard_channel_capacity: 128,
+            incoming_channel_capacity: 64,
+            ..AppConfig::default()
+        };
+        let memory = MemoryClipboard::new();
+        let mut core = CoreService::with_backend(config, Box::new(memory.clone())).unwrap();
+        assert_eq!(core.incoming_msg_rx.max_capacity(), 64);
+
+        // 同步循环尚未运行：默认容量（32）下后面的通知会被丢弃
+        for i in 0..100 {
+            memory.set(ClipboardItem::Text(format!("copy {i}")));
+        }
+        let mut received = 0;
+        while core.clipboard_change_rx.try_recv().is_ok() {
+            received += 1;
+        }
+        assert_eq!(received, 100);
+    }
+
+    #[test]
+    fn applied_hashes_expire_after_window() {
+        let start = Instant::now();
+        let mut applied = AppliedHashes::new(2, Duration::from_secs(10));
+        applied.record(1, start);
+        applied.record(2, start);
+        applied.record(3, start + Duration::from_secs(5));
+        // 超出容量时淘汰最早的记录；窗口内可多次命中
+        assert!(!applied.contains(1, start));
+        assert!(applied.contains(2, start + Duration::from_secs(1)));
+        assert!(applied.contains(2, start + Duration::from_secs(2)));
+        assert!(!applied.contains(2, start + Duration::from_secs(10)));
+        assert!(applied.contains(3, start + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn self_writes_are_cleared_once_observed() {
+        let mut writes = SelfWrites::new(2);
+        writes.record(1);
+        writes.record(2);
+        writes.record(3);
+        // 超出容量时淘汰最早的记录
+        assert!(!writes.take(1));
+        assert!(writes.take(2));
+        assert!(!writes.take(2));
+        writes.clear();
+        assert!(!writes.take(3));
+    }
+
+    #[tokio::test]
+    async fn memory_backend_relays_both_directions() {
+        let (mut rx, peer_port) = spawn_peer().await;
+        let (memory, core_port) = spawn_core(AppConfig {
+            clipboard_poll_interval_ms: 50,
+            ..sender_for(peer_port)
+        })
+        .await;
+
+        // 出站：内存剪贴板变化被广播给对端
+        memory.set(ClipboardItem::Text("from relay".into()));
+        let payload = tokio::time::timeout(Duration::from_secs(3), async {
+            loop {
+                if let ProtocolMessage::ClipboardUpdate { payload, .. } =
+                    rx.recv().await.unwrap().msg
+                {
+                    return payload;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(payload, b"from relay");
+
+        // 入站：对端更新写入内存剪贴板
+        broadcast_to_peers(&sender_for(core_port), &text_update(9, 1, "from peer")).await.unwrap();
+        wait_for_text(&memory, "from peer").await;
+    }
+
+    #[tokio::test]
+    async fn large_text_is_sent_in_acknowledged_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let (receiver_clipboard, receiver_port) = spawn_core(AppConfig {
+            download_dir: Some(dir.path().to_path_buf()),
+            resumable_transfers: true,
+            ..AppConfig::default()
+        })
+        .await;
+        let (sender_clipboard, _) = spawn_core(AppConfig {
+            chunk_size: crate::chunk::MIN_CHUNK_SIZE,
+            resumable_transfers: true,
+            clipboard_poll_interval_ms: 50,
+            ..sender_for(receiver_port)
+        })
+        .await;
+
+        let text = "chunk ".repeat(10_000);
+        sender_clipboard.set(ClipboardItem::Text(text.clone()));
+        wait_for_text(&receiver_clipboard, &text).await;
+        // 收齐后部分文件已删除
+        let partial_dir = dir.path().join(PARTIAL_DIR);
+        assert_eq!(std::fs::read_dir(partial_dir).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn large_transfer_is_announced_with_start_and_end_events() {
+        let receiver_port = free_port();
+        let receiver_config = AppConfig {
+            listen_port: receiver_port,
+            ..AppConfig::default()
+        };
+        let receiver =
+            CoreService::with_backend(receiver_config, Box::new(MemoryClipboard::new())).unwrap();
+        let mut events = receiver.subscribe_events();
+        run_in_background(receiver);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let sender = CoreService::new(AppConfig {
+            listen_port: free_port(),
+            ..sender_for(receiver_port)
+        })
+        .unwrap();
+        async fn next_event(rx: &mut broadcast::Receiver<CoreEvent>) -> CoreEvent {
+            tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap()
+        }
+
+        // 小负载不发送传输状态
+        sender.broadcast(ClipboardItem::Text("small".into())).await.unwrap();
+        let event = next_event(&mut events).await;
+        assert_eq!(event.size, 5);
+        assert!(event.transfer.is_none());
+
+        let text = "x".repeat(TRANSFER_STATUS_MIN_SIZE as usize + 1);
+        sender.broadcast(ClipboardItem::Text(text.clone())).await.unwrap();
+        let start = next_event(&mut events).await;
+        assert_eq!(start.transfer, Some(TransferPhase::Start));
+        assert_eq!(start.size, text.len() as u64);
+        // 数据到达时结束传输状态，随后是收到内容的事件；发送端随后的 TransferEnd 不再重复结束
+        assert_eq!(next_event(&mut events).await.transfer, Some(TransferPhase::End));
+        assert_eq!(next_event(&mut events).await.transfer, None);
+        sender.broadcast(ClipboardItem::Text("after".into())).await.unwrap();
+        let event = next_event(&mut events).await;
+        assert_eq!((event.transfer, event.size), (None, 5));
+    }
+
+    #[tokio::test]
+    async fn received_files_expire_while_text_persists() {
+        let download = tempfile::tempdir().unwrap();
+        let (receiver_clipboard, receiver_port) = spawn_core(AppConfig {
+            download_dir: Some(download.path().to_path_buf()),
+            ..AppConfig::default()
+        })
+        .await;
+
+        // 发送端决定保留时长：文件 1 秒后过期，文本不过期
+        let sender = CoreService::with_backend(
+            AppConfig {
+                listen_port: free_port(),
+                files_ttl: 1,
+                ..sender_for(receiver_port)
+            },
+            Box::new(MemoryClipboard::new()),
+        )
+        .unwrap();
+        let source = tempfile::tempdir().unwrap();
+        let file = source.path().join("report.txt");
+        std::fs::write(&file, b"report").unwrap();
+        sender.broadcast(files_item(std::iter::once(file))).await.unwrap();
+        let ClipboardItem::Files(files) =
+            wait_for_item(&receiver_clipboard, |item| matches!(item, ClipboardItem::Files(_))).await
+        else {
+            unreachable!();
+        };
+        let saved = PathBuf::from(local_file_path(&files[0].path));
+        assert!(saved.exists());
+
+        sender.broadcast(ClipboardItem::Text("keep me".into())).await.unwrap();
+        wait_for_text(&receiver_clipboard, "keep me").await;
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(!saved.exists(), "expired file was not removed");
+        assert!(matches!(receiver_clipboard.get(), Some(ClipboardItem::Text(t)) if t == "keep me"));
+    }
+
+    #[tokio::test]
+    async fn pause_toggle_drops_updates_until_resumed() {
+        let receiver_port = free_port();
+        let receiver_clipboard = MemoryClipboard::new();
+        let receiver = CoreService::with_backend(
+            AppConfig { listen_port: receiver_port, ..AppConfig::default() },
+            Box::new(receiver_clipboard.clone()),
+        )
+        .unwrap();
+        let pause_toggle = receiver.pause_toggle_sender();
+        run_in_background(receiver);
+        pause_toggle.send(()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let sender = CoreService::with_backend(
+            AppConfig { listen_port: free_port(), ..sender_for(receiver_port) },
+            Box::new(MemoryClipboard::new()),
+        )
+        .unwrap();
+        sender.broadcast(ClipboardItem::Text("while paused".into())).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(receiver_clipboard.get().is_none());
+
+        pause_toggle.send(()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        sender.broadcast(ClipboardItem::Text("resumed".into())).await.unwrap();
+        let item = wait_for_item(&receiver_clipboard, |_| true).await;
+        assert!(matches!(item, ClipboardItem::Text(t) if t == "resumed"));
+    }
+
+    #[tokio::test]
+    async fn rotate_key_request_announces_and_persists_new_key() {
+        let (mut rx, peer_port) = spawn_peer().await;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let config = AppConfig { listen_port: free_port(), ..sender_for(peer_port) };
+        config.save(&path).unwrap();
+        let old_key = config.secret_key.clone();
+        let mut core = CoreService::with_backend(config, Box::new(MemoryClipboard::new())).unwrap();
+        core.set_config_path(path.clone());
+        let rotate_key = core.rotate_key_sender();
+        run_in_background(core);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        rotate_key.send(()).await.unwrap();
+        let announced = tokio::time::timeout(Duration::from_secs(3), async {
+            loop {
+                if let ProtocolMessage::KeyRotation { new_key, .. } = rx.recv().await.unwrap().msg {
+                    return new_key;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        // 对端已确认送达，新密钥成为主密钥并写回配置文件
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while AppConfig::load(path.clone()).unwrap().secret_key != hex::encode(announced) {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_ne!(hex::encode(announced), old_key);
+    }
+
+    #[tokio::test]
+    async fn text_delta_after_dropped_update_still_applies() {
+        let receiver_port = free_port();
+        let receiver_clipboard = MemoryClipboard::new();
+        let receiver = CoreService::with_backend(
+            AppConfig { listen_port: receiver_port, ..AppConfig::default() },
+            Box::new(receiver_clipboard.clone()),
+        )
+        .unwrap();
+        let pause_toggle = receiver.pause_toggle_sender();
+        run_in_background(receiver);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let sender = CoreService::with_backend(
+            AppConfig {
+                listen_port: free_port(),
+                text_delta: true,
+                ..sender_for(receiver_port)
+            },
+            Box::new(MemoryClipboard::new()),
+        )
+        .unwrap();
+        let first: String = (0..400).map(|i| format!("line {i} of the shared document\n")).collect();
+        sender.broadcast(ClipboardItem::Text(first.clone())).await.unwrap();
+        wait_for_text(&receiver_clipboard, &first).await;
+
+        // 暂停期间接收端丢弃第二次更新，但第三次增量仍以第二次为基准
+        pause_toggle.send(()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let second = first.replace("line 10 of", "line ten of");
+        sender.broadcast(ClipboardItem::Text(second.clone())).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(matches!(receiver_clipboard.get(), Some(ClipboardItem::Text(t)) if t == first));
+
+        pause_toggle.send(()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let third = second.replace("line 20 of", "line twenty of");
+        sender.broadcast(ClipboardItem::Text(third.clone())).await.unwrap();
+        wait_for_text(&receiver_clipboard, &third).await;
+    }
+
+    #[test]
+    fn text_delta_periodically_falls_back_to_full_text() {
+        let service = CoreService::with_backend(
+            AppConfig { text_delta: true, ..AppConfig::default() },
+            Box::new(MemoryClipboard::new()),
+        )
+        .unwrap();
+        let base: String = (0..800).map(|i| format!("line {i}\n")).collect();
+        *service.text_delta_base.lock().unwrap() = Some(base.clone());
+        let edited = base.replace("line 7\n", "line seven\n");
+        for _ in 0..DELTA_RESYNC_INTERVAL {
+            assert!(service.build_text_delta_message(&edited).unwrap().is_some());
+        }
+        assert!(service.build_text_delta_message(&edited).unwrap().is_none());
+        assert!(service.build_text_delta_message(&edited).unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn expired_text_is_cleared_from_clipboard() {
+        let (receiver_clipboard, receiver_port) = spawn_core(AppConfig::default()).await;
+        let sender = CoreService::with_backend(
+            AppConfig {
+                listen_port: free_port(),
+                text_ttl: 1,
+                ..sender_for(receiver_port)
+            },
+            Box::new(MemoryClipboard::new()),
+        )
+        .unwrap();
+        sender.broadcast(ClipboardItem::Text("one-time code".into())).await.unwrap();
+        wait_for_text(&receiver_clipboard, "one-time code").await;
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(receiver_clipboard.get().is_none(), "expired text was not cleared");
+    }
+
+    #[tokio::test]
+    async fn large_files_are_sent_in_chunks_to_capable_peers() {
+        let download = tempfile::tempdir().unwrap();
+        let (receiver_clipboard, receiver_port) = spawn_core(AppConfig {
+            download_dir: Some(download.path().to_path_buf()),
+            ..AppConfig::default()
+        })
+        .await;
+
+        let src = tempfile::tempdir().unwrap();
+        let big: Vec<u8> = (0..200 * 1024u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(src.path().join("big.bin"), &big).unwrap();
+        std::fs::write(src.path().join("note.txt"), b"small").unwrap();
+        let sender = CoreService::with_backend(
+            AppConfig {
+                listen_port: free_port(),
+                file_chunk_threshold: 64 * 1024,
+                chunk_size: crate::chunk::MIN_CHUNK_SIZE,
+                ..sender_for(receiver_port)
+            },
+            Box::new(MemoryClipboard::new()),
+        )
+        .unwrap();
+        let localhost = "127.0.0.1".parse().unwrap();
+        sender.devices.update([9; 16], "receiver".into(), CAP_FILE_CHUNKS, localhost, Instant::now());
+        let item = files_item([src.path().join("big.bin"), src.path().join("note.txt")].into_iter());
+        let results = sender.broadcast(item).await.unwrap();
+        assert!(results[0].is_ok(), "{:?}", results[0]);
+
+        let ClipboardItem::Files(files) =
+            wait_for_item(&receiver_clipboard, |item| matches!(item, ClipboardItem::Files(_))).await
+        else {
+            unreachable!();
+        };
+        assert_eq!(files.len(), 2);
+        assert_eq!(std::fs::read(local_file_path(&files[0].path)).unwrap(), big);
+        assert_eq!(std::fs::read(local_file_path(&files[1].path)).unwrap(), b"small");
+        // 暂存目录只在分块接收时创建，收齐后其中的传输子目录已删除
+        let staging = download.path().join(FILE_CHUNK_DIR);
+        assert_eq!(std::fs::read_dir(staging).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn url_is_sent_as_text_to_peers_without_url_support() {
+        let mut receivers = Vec::new();
+        let mut peers = Vec::new();
+        for host in ["127.0.0.1", "127.0.0.2"] {
+            let (rx, port) = spawn_peer().await;
+            receivers.push(rx);
+            peers.push(PeerConfig::new(host, port));
+        }
+        let sender = CoreService::with_backend(
+            AppConfig { listen_port: free_port(), peers, ..AppConfig::default() },
+            Box::new(MemoryClipboard::new()),
+        )
+        .unwrap();
+        // 只有 127.0.0.1 上的对端声明了 URL 能力
+        let capable = "127.0.0.1".parse().unwrap();
+        sender.devices.update([9; 16], "new".into(), CAP_TEXT | CAP_URL, capable, Instant::now());
+
+        let results = sender.broadcast(ClipboardItem::Url("https://example.com/x".into())).await.unwrap();
+        assert!(results.iter().all(PeerSendResult::is_ok), "{results:?}");
+        for (rx, as_url) in receivers.iter_mut().zip([true, false]) {
+            let msg = tokio::time::timeout(Duration::from_secs(3), rx.recv()).await.unwrap().unwrap().msg;
+            let ProtocolMessage::ClipboardUpdate { content_type, payload, .. } = msg else {
+                panic!("unexpected message {msg:?}");
+            };
+            assert_eq!(matches!(content_type, ContentType::Url), as_url);
+            assert!(matches!(content_type, ContentType::Url | ContentType::Text));
+            assert_eq!(payload, b"https://example.com/x");
+        }
+    }
+
+    #[tokio::test]
+    async fn sensitive_content_is_kept_out_of_persisted_history() {
+        let data = tempfile::tempdir().unwrap();
+        let receiver_port = free_port();
+        let receiver_config = AppConfig {
+            listen_port: receiver_port,
+            persist_history: true,
+            history_file: Some(data.path().join("history.json")),
+            ..AppConfig::default()
+        };
+        let receiver = CoreService::with_backend(
+            receiver_config.clone(),
+            Box::new(MemoryClipboard::new()),
+        )
+        .unwrap();
+        let history = receiver.history();
+        run_in_background(receiver);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let sender = CoreService::with_backend(
+            AppConfig { listen_port: free_port(), ..sender_for(receiver_port) },
+            Box::new(MemoryClipboard::new()),
+        )
+        .unwrap();
+        sender
+            .broadcast_with_hint(ClipboardItem::Text("hunter2".into()), true)
+            .await
+            .unwrap();
+        sender.broadcast(ClipboardItem::Text("meeting notes".into())).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while history.snapshot().len() < 2 {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        // 重启后只恢复未带提示的文本
+        let restarted =
+            CoreService::with_backend(receiver_config, Box::new(MemoryClipboard::new())).unwrap();
+        let restored = restarted.history().snapshot();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored[0].summary, "meeting notes");
+        let saved = std::fs::read_to_string(data.path().join("history.json")).unwrap();
+        assert!(!saved.contains("hunter2"));
+    }
+
+    #[tokio::test]
+    async fn send_to_peer_reaches_only_the_target() {
+        let (target, target_port) = spawn_core(AppConfig::default()).await;
+        let (other, other_port) = spawn_core(AppConfig::default()).await;
+
+        let sender_config = AppConfig {
+            listen_port: free_port(),
+            peers: vec![
+                PeerConfig::new("127.0.0.1", target_port),
+                PeerConfig::new("127.0.0.1", other_port),
+            ],
+            ..AppConfig::default()
+        };
+        let sender = CoreService::with_backend(sender_config, Box::new(MemoryClipboard::new()))
+            .unwrap();
+
+        let addr = format!("127.0.0.1:{}", target_port);
+        let item = ClipboardItem::Text("only for you".to_string());
+        let result = sender.send_to_peer(&addr, item).await.unwrap().unwrap();
+        assert!(result.is_ok());
+        wait_for_text(&target, "only for you").await;
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(other.get().is_none());
+    }
+
+    #[tokio::test]
+    async fn display_mode_routes_received_items_to_sink() {
+        let port = free_port();
+        let config = AppConfig {
+            listen_port: port,
+            clipboard_backend: ClipboardBackendKind::Display,
+            ..AppConfig::default()
+        };
+        let core = CoreService::new(config).unwrap();
+        let sink = core.display_sink().unwrap();
+        run_in_background(core);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        broadcast_to_peers(&sender_for(port), &text_update(3, 1, "on wall")).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while sink.version() == 0 {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(matches!(sink.get(), Some(ClipboardItem::Text(t)) if t == "on wall"));
+        // 展示端只读：收到的内容不会作为本机剪贴板再发出
+        assert!(sink.read().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn stale_remote_update_loses_to_newer_clock() {
+        let (memory, port) = spawn_core(AppConfig {
+            last_writer_wins: true,
+            ..AppConfig::default()
+        })
+        .await;
+
+        let sender = sender_for(port);
+        let update = |seq: u64, stamp: u64, text: &str| {
+            let mut msg = text_update(seq as u8, seq, text);
+            if let ProtocolMessage::ClipboardUpdate { clock, .. } = &mut msg {
+                *clock = stamp;
+            }
+            msg
+        };
+
+        broadcast_to_peers(&sender, &update(1, 2_000, "newer")).await.unwrap();
+        wait_for_text(&memory, "newer").await;
+        // 时钟更旧的更新晚到也不会覆盖
+        broadcast_to_peers(&sender, &update(2, 1_000, "older")).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(matches!(memory.get(), Some(ClipboardItem::Text(t)) if t == "newer"));
+        broadcast_to_peers(&sender, &update(3, 3_000, "newest")).await.unwrap();
+        wait_for_text(&memory, "newest").await;
+    }
+
+    #[test]
+    fn source_app_filter_applies_deny_then_allow() {
+        let names = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let ignored = names(&["KeePassXC", "flameshot"]);
+        assert!(!source_app_allowed(Some("keepassxc"), false, &[], &ignored));
+        assert!(source_app_allowed(Some("Firefox"), false, &[], &ignored));
+
+        // 白名单非空时只同步名单中的应用，黑名单优先
+        let allowed = names(&["firefox", "keepassxc"]);
+        assert!(source_app_allowed(Some("Firefox"), false, &allowed, &ignored));
+        assert!(!source_app_allowed(Some("gimp"), false, &allowed, &ignored));
+        assert!(!source_app_allowed(Some("KeePassXC"), false, &allowed, &ignored));
+
+        // 来源未知时按「勿同步」提示判断
+        assert!(source_app_allowed(None, false, &allowed, &ignored));
+        assert!(!source_app_allowed(None, true, &allowed, &ignored));
+    }
+
+    #[tokio::test]
+    async fn rapid_changes_settle_into_one_broadcast() {
+        let (mut rx, peer_port) = spawn_peer().await;
+        let (memory, _) = spawn_core(AppConfig {
+            change_settle_ms: 150,
+            ..sender_for(peer_port)
+        })
+        .await;
+
+        // 快速连续的中间状态，随后停顿
+        for text in ["d", "dr", "dra", "draft"] {
+            memory.set(ClipboardItem::Text(text.into()));
+            tokio::time::sleep(Duration::from_millis(30)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let mut sent = Vec::new();
+        while let Ok(incoming) = rx.try_recv() {
+            if let ProtocolMessage::ClipboardUpdate { payload, .. } = incoming.msg {
+                sent.push(String::from_utf8(payload).unwrap());
+            }
+        }
+        assert_eq!(sent, vec!["draft"]);
+    }
+
+    #[test]
+    fn files_from_restricted_peer_are_dropped() {
+        let mut restricted = PeerConfig::new("127.0.0.2", 5000);
+        restricted.allow_files_from = false;
+        let config = AppConfig {
+            listen_port: free_port(),
+            peers: vec![restricted],
+            download_dir: Some(std::env::temp_dir().join("lan-clipboard-test-restricted")),
+            ..AppConfig::default()
+        };
+        let core = CoreService::new(config).unwrap();
+        let from: IpAddr = "127.0.0.2".parse().unwrap();
+
+        let entries = vec![FileEntry {
+            name: "evil.exe".into(),
+            size: 3,
+            content: b"bad".to_vec(),
+            source_path: None,
+        }];
+        let payload = serde_json::to_vec(&entries).unwrap();
+        let item = core
+            .apply_remote_clipboard(ContentType::Files, &payload, from)
+            .unwrap();
+        assert!(item.is_none());
+
+        let item = core
+            .apply_remote_clipboard(ContentType::Text, b"hi", from)
+            .unwrap();
+        assert!(matches!(item, Some(ClipboardItem::Text(t)) if t == "hi"));
+    }
+
+    #[tokio::test]
+    async fn relayed_files_follow_relay_allow_files_from() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let relay_port = listener.local_addr().unwrap().port();
+        tokio::spawn(crate::relay::RelayServer::new().serve(listener));
+
+        // 房间内另一台设备与中继同在 127.0.0.1，按 IP 放行也不能绕过 [relay] 的限制
+        let mut relay = PeerConfig::new("127.0.0.1", relay_port);
+        relay.allow_files_from = false;
+        let download = tempfile::tempdir().unwrap();
+        let config = AppConfig {
+            peers: vec![PeerConfig::new("127.0.0.1", free_port())],
+            relay: Some(relay.clone()),
+            download_dir: Some(download.path().to_path_buf()),
+            ..AppConfig::default()
+        };
+        let (receiver_clipboard, _) = spawn_core(config.clone()).await;
+        let (tx, _rx) = mpsc::channel(4);
+        let (client, handle) = RelayClient::new(&config, &relay, tx).unwrap();
+        tokio::spawn(client.run());
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        let entries = vec![FileEntry {
+            name: "evil.exe".into(),
+            size: 3,
+            content: b"bad".to_vec(),
+            source_path: None,
+        }];
+        let files = remote_update(9, 1, ContentType::Files, serde_json::to_vec(&entries).unwrap());
+        assert!(handle.send(Arc::new(encode_message(&files).unwrap())).is_ok());
+        let text = text_update(9, 2, "after files");
+        assert!(handle.send(Arc::new(encode_message(&text).unwrap())).is_ok());
+
+        let item = wait_for_item(&receiver_clipboard, |_| true).await;
+        assert!(matches!(item, ClipboardItem::Text(t) if t == "after files"));
+        assert_eq!(std::fs::read_dir(download.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn relayed_messages_needing_a_direct_reply_are_ignored() {
+        let config = AppConfig {
+            relay: Some(PeerConfig::new("relay.example.com", 5000)),
+            ..AppConfig::default()
+        };
+        let manifest = ProtocolMessage::FileManifest {
+            sender_id: [1; 16],
+            transfer_id: 1,
+            reply_port: 5000,
+            entries: Vec::new(),
+        };
+        assert!(!relayed_message_allowed(&config, &manifest));
+        let echo = ProtocolMessage::EchoRequest {
+            sender_id: [1; 16],
+            echo_id: 1,
+            timestamp_us: 0,
+            reply_port: 5000,
+        };
+        assert!(!relayed_message_allowed(&config, &echo));
+        let transfer_end = ProtocolMessage::TransferEnd { sender_id: [1; 16] };
+        assert!(relayed_message_allowed(&config, &transfer_end));
+    }
+
+    #[tokio::test]
+    async fn restricted_peer_hostnames_are_resolved_ahead_of_time() {
+        let mut by_name = PeerConfig::new("localhost", 5000);
+        by_name.allow_files_from = false;
+        let mut by_ip = PeerConfig::new("127.0.0.2", 5000);
+        by_ip.allow_files_from = false;
+        let peers = vec![by_name, by_ip, PeerConfig::new("127.0.0.3", 5000)];
+        let restricted: IpAddr = "127.0.0.2".parse().unwrap();
+        let allowed: IpAddr = "127.0.0.3".parse().unwrap();
+
+        assert_eq!(literal_files_denied(&peers), HashSet::from([restricted]));
+        let denied = resolve_files_denied(&peers).await;
+        assert!(denied.contains(&restricted));
+        assert!(!denied.contains(&allowed));
+        let localhost = ["127.0.0.1", "::1"].map(|ip| ip.parse::<IpAddr>().unwrap());
+        assert!(localhost.iter().any(|ip| denied.contains(ip)));
+    }
+
+    #[test]
+    fn invalid_file_in_batch_writes_nothing() {
+        let download = tempfile::tempdir().unwrap();
+        let config = AppConfig {
+            listen_port: free_port(),
+            download_dir: Some(download.path().to_path_buf()),
+            max_file_size: 16,
+            ..AppConfig::default()
+        };
+        let core = CoreService::new(config).unwrap();
+        let from: IpAddr = "127.0.0.1".parse().unwrap();
+        let file = |name: &str, content: &[u8]| FileEntry {
+            name: name.into(),
+            size: content.len() as u64,
+            content: content.to_vec(),
+            source_path: None,
+        };
+
+        let batches = [
+            vec![file("ok.txt", b"fine"), file("big.bin", &[0; 17])],
+            vec![file("ok.txt", b"fine"), file("..", b"dir")],
+            vec![file("ok.txt", b"fine"), FileEntry { size: 1, ..file("short.txt", b"abc") }],
+        ];
+        for entries in batches {
+            let payload = serde_json::to_vec(&entries).unwrap();
+            assert!(core.apply_remote_clipboard(ContentType::Files, &payload, from).unwrap().is_none());
+        }
+        assert_eq!(std::fs::read_dir(download.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn undecodable_updates_are_dropped_without_stopping_the_receiver() {
+        let download = tempfile::tempdir().unwrap();
+        let (clipboard, port) = spawn_core(AppConfig {
+            images_as_files: true,
+            download_dir: Some(download.path().to_path_buf()),
+            ..AppConfig::default()
+        })
+        .await;
+        let sender = sender_for(port);
+        let bad = [
+            remote_update(6, 1, ContentType::Multi, b"not json".to_vec()),
+            remote_update(6, 2, ContentType::Image, b"not an image".to_vec()),
+            remote_update(6, 3, ContentType::Text, vec![0xff, 0xfe]),
+        ];
+        for msg in &bad {
+            broadcast_to_peers(&sender, msg).await.unwrap();
+        }
+        broadcast_to_peers(&sender, &text_update(6, 4, "still decoding")).await.unwrap();
+
+        wait_for_text(&clipboard, "still decoding").await;
+        assert_eq!(std::fs::read_dir(download.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn receiver_keeps_running_after_rejected_file_batch() {
+        let download = tempfile::tempdir().unwrap();
+        let (clipboard, port) = spawn_core(AppConfig {
+            download_dir: Some(download.path().to_path_buf()),
+            ..AppConfig::default()
+        })
+        .await;
+        let entries = vec![FileEntry {
+            name: "..".into(),
+            size: 3,
+            content: b"dir".to_vec(),
+            source_path: None,
+        }];
+        let files = remote_update(4, 1, ContentType::Files, serde_json::to_vec(&entries).unwrap());
+        let sender = sender_for(port);
+        broadcast_to_peers(&sender, &files).await.unwrap();
+        broadcast_to_peers(&sender, &text_update(4, 2, "still here")).await.unwrap();
+
+        wait_for_text(&clipboard, "still here").await;
+        assert_eq!(std::fs::read_dir(download.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn received_file_names_cannot_escape_the_download_dir() {
+        let root = tempfile::tempdir().unwrap();
+        let inbox = root.path().join("inbox");
+        let config = AppConfig {
+            listen_port: free_port(),
+            download_dir: Some(inbox.clone()),
+            ..AppConfig::default()
+        };
+        let core = CoreService::new(config).unwrap();
+        let from: IpAddr = "127.0.0.1".parse().unwrap();
+        let file = |name: &str| FileEntry {
+            name: name.into(),
+            size: 1,
+            content: b"x".to_vec(),
+            source_path: None,
+        };
+        let entries = vec![file("../../escape.txt"), file("..\\evil.txt"), file("/tmp/abs.txt")];
+        let payload = serde_json::to_vec(&entries).unwrap();
+        core.apply_remote_clipboard(ContentType::Files, &payload, from).unwrap();
+
+        // 下载目录之外没有写入任何文件，收到的文件只保留文件名部分
+        let outside: Vec<_> = std::fs::read_dir(root.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(outside, vec![std::ffi::OsString::from("inbox")]);
+        let mut saved = Vec::new();
+        for dir in std::fs::read_dir(&inbox).unwrap() {
+            let dir = dir.unwrap().path();
+            if dir.is_dir() {
+                saved.extend(std::fs::read_dir(dir).unwrap().map(|e| e.unwrap().file_name()));
+            }
+        }
+        saved.sort();
+        assert_eq!(saved, ["abs.txt", "escape.txt", "evil.txt"].map(std::ffi::OsString::from));
+    }
+
+    #[test]
+    fn remote_url_is_validated() {
+        let config = AppConfig {
+            listen_port: free_port(),
+            ..AppConfig::default()
+        };
+        let core = CoreService::new(config).unwrap();
+        let from: IpAddr = "127.0.0.1".parse().unwrap();
+
+        let item = core
+            .apply_remote_clipboard(ContentType::Url, b"https://example.com/x", from)
+            .unwrap();
+        assert!(matches!(item, Some(ClipboardItem::Url(u)) if u == "https://example.com/x"));
+        let item = core
+            .apply_remote_clipboard(ContentType::Url, b"not a url", from)
+            .unwrap();
+        assert!(item.is_none());
+
+        let msg = core
+            .build_clipboard_message(ClipboardItem::Url("https://example.com".into()))
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            msg,
+            ProtocolMessage::ClipboardUpdate { content_type: ContentType::Url, .. }
+        ));
+    }
+
+    #[test]
+    fn image_payload_is_moved_into_message() {
+        let config = AppConfig {
+            listen_port: free_port(),
+            ..AppConfig::default()
+        };
+        let core = CoreService::new(config).unwrap();
+        let png = vec![0u8; 1024 * 1024];
+        let buffer = png.as_ptr();
+        let msg = core.build_clipboard_message(ClipboardItem::Image(png)).unwrap().unwrap();
+        let ProtocolMessage::ClipboardUpdate { payload, .. } = msg else {
+            panic!("unexpected message");
+        };
+        // 构造消息不复制图片数据
+        assert_eq!(payload.as_ptr(), buffer);
+    }
+
+    #[test]
+    fn images_as_files_saves_png_and_puts_path_on_clipboard() {
+        let download = tempfile::tempdir().unwrap();
+        let config = AppConfig {
+            listen_port: free_port(),
+            download_dir: Some(download.path().to_path_buf()),
+            images_as_files: true,
+            ..AppConfig::default()
+        };
+        let core = CoreService::new(config).unwrap();
+        let img = image::RgbaImage::from_pixel(4, 3, image::Rgba([1, 2, 3, 255]));
+        let mut png = Vec::new();
+        img.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+
+        let item = core
+            .apply_remote_clipboard(ContentType::Image, &png, "127.0.0.1".parse().unwrap())
+            .unwrap();
+        let Some(ClipboardItem::Files(files)) = item else {
+            panic!("expected a file path on the clipboard");
+        };
+        assert_eq!(files.len(), 1);
+        let saved = Path::new(&files[0].path);
+        assert!(saved.starts_with(download.path()));
+        assert_eq!(saved.extension().unwrap(), "png");
+        assert_eq!(std::fs::read(saved).unwrap(), png);
+    }
+
+    #[test]
+    fn large_images_are_saved_as_files() {
+        let download = tempfile::tempdir().unwrap();
+        let img = image::RgbaImage::from_pixel(4, 3, image::Rgba([1, 2, 3, 255]));
+        let mut png = Vec::new();
+        img.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let apply = |threshold: u64| {
+            let config = AppConfig {
+                listen_port: free_port(),
+                download_dir: Some(download.path().to_path_buf()),
+                image_to_file_threshold: threshold,
+                ..AppConfig::default()
+            };
+            CoreService::new(config)
+                .unwrap()
+                .apply_remote_clipboard(ContentType::Image, &png, "127.0.0.1".parse().unwrap())
+                .unwrap()
+        };
+
+        let Some(ClipboardItem::Files(files)) = apply(png.len() as u64 - 1) else {
+            panic!("expected an image above the threshold to be saved as a file");
+        };
+        assert_eq!(std::fs::read(&files[0].path).unwrap(), png);
+
+        for threshold in [png.len() as u64, 0] {
+            assert!(matches!(apply(threshold), Some(ClipboardItem::Image(bytes)) if bytes == png));
+        }
+    }
+
+    #[test]
+    fn normalized_image_hash_includes_dimensions() {
+        let png = |w, h| {
+            let img = image::RgbaImage::from_pixel(w, h, image::Rgba([5, 5, 5, 255]));
+            let mut out = Vec::new();
+            img.write_to(&mut std::io::Cursor::new(&mut out), image::ImageFormat::Png)
+                .unwrap();
+            ClipboardItem::Image(out)
+        };
+        let sizes = [(1, 1), (2, 1), (1, 2), (16, 16), (17, 16), (64, 32)];
+        let hashes: HashSet<u64> = sizes
+            .iter()
+            .map(|&(w, h)| hash_item(&png(w, h), true).unwrap())
+            .collect();
+        assert_eq!(hashes.len(), sizes.len());
+        for &(w, h) in &sizes {
+            assert_eq!(hash_item(&png(w, h), true), hash_item(&png(w, h), true));
+        }
+        // 开关关闭时保持原有的纯字节哈希
+        assert_eq!(hash_item(&png(4, 4), false), hash_item(&png(4, 4), false));
+        assert_ne!(hash_item(&png(4, 4), false), hash_item(&png(4, 4), true));
+    }
+
+    #[test]
+    fn multi_format_item_roundtrips_to_clipboard() {
+        let formats = vec![
+            (crate::clipboard::MIME_HTML.to_string(), b"<i>rich</i>".to_vec()),
+            (MIME_PLAIN_TEXT.to_string(), b"rich".to_vec()),
+        ];
+        let core = CoreService::new(AppConfig {
+            listen_port: free_port(),
+            ..AppConfig::default()
+        })
+        .unwrap();
+        let msg = core
+            .build_clipboard_message(ClipboardItem::Multi(formats.clone()))
+            .unwrap()
+            .unwrap();
+        let decoded = crate::protocol::decode_message(&encode_message(&msg).unwrap()).unwrap();
+        let ProtocolMessage::ClipboardUpdate { content_type, payload, .. } = decoded else {
+            panic!("unexpected message");
+        };
+        assert!(matches!(content_type, ContentType::Multi));
+        let item = core
+            .apply_remote_clipboard(content_type, &payload, "127.0.0.1".parse().unwrap())
+            .unwrap()
+            .unwrap();
+
+        // 写入（内存）剪贴板后两种格式都应可供粘贴
+        let mut clipboard = MemoryClipboard::new();
+        write_received_item(&mut clipboard, item, false).unwrap();
+        let Some(ClipboardItem::Multi(written)) = clipboard.get() else {
+            panic!("expected multi-format item");
+        };
+        assert_eq!(written, formats);
+    }
+
+    #[test]
+    fn svg_roundtrips_with_png_fallback() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="8" height="4">
+            <rect width="8" height="4" fill="red"/></svg>"#;
+        let core = CoreService::new(AppConfig {
+            listen_port: free_port(),
+            sync_svg: true,
+            ..AppConfig::default()
+        })
+        .unwrap();
+        let svg_format = (crate::clipboard::MIME_SVG.to_string(), svg.to_vec());
+        let item = ClipboardItem::Multi(vec![svg_format.clone()]);
+        let msg = core.build_clipboard_message(item).unwrap().unwrap();
+        let decoded = crate::protocol::decode_message(&encode_message(&msg).unwrap()).unwrap();
+        let ProtocolMessage::ClipboardUpdate { content_type, payload, .. } = decoded else {
+            panic!("unexpected message");
+        };
+        let Some(ClipboardItem::Multi(formats)) = core
+            .apply_remote_clipboard(content_type, &payload, "127.0.0.1".parse().unwrap())
+            .unwrap()
+        else {
+            panic!("expected multi-format item");
+        };
+
+        // SVG 原样保留在首位，其后补充栅格化的 PNG
+        assert_eq!(formats[0], svg_format);
+        assert_eq!(formats[1].0, crate::clipboard::MIME_PNG);
+        assert_eq!(image_signature(&formats[1].1), Some((image::ImageFormat::Png, 8, 4)));
+    }
+
+    #[test]
+    fn core_hash_matches_watcher_fingerprint() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.pdf");
+        std::fs::write(&path, b"%PDF").unwrap();
+        let items = [
+            ClipboardItem::Text("hello".into()),
+            ClipboardItem::Url("https://example.com".into()),
+            ClipboardItem::Image(vec![1, 2, 3]),
+            ClipboardItem::Files(vec![ClipboardFile {
+                path: format!("file://{}", path.display()),
+            }]),
+        ];
+        for item in &items {
+            assert_eq!(hash_item(item, false), Some(content_fingerprint(item)), "{item:?}");
+        }
+        // 接收端写入剪贴板的是普通路径，与发送端的 file:// 形式应视为同一内容
+        let plain = ClipboardItem::Files(vec![ClipboardFile {
+            path: path.display().to_string(),
+        }]);
+        assert_eq!(hash_item(&plain, false), hash_item(&items[3], false));
+    }
+
+    /// 完整读取很慢（模拟 Wayland 下图片格式的传输协商）、纯文本可快速读取的剪贴板后端
+    struct SlowRichClipboard(MemoryClipboard);
+
+    impl ClipboardBackend for SlowRichClipboard {
+        fn read(&self) -> Result<Option<ClipboardItem>> {
+            std::thread::sleep(Duration::from_millis(500));
+            self.0.read()
+        }
+
+        fn read_text(&self) -> Result<Option<String>> {
+            Ok(match self.0.get() {
+                Some(ClipboardItem::Text(text)) => Some(text),
+                Some(ClipboardItem::Multi(formats)) => formats
+                    .into_iter()
+                    .find(|(mime, _)| mime == MIME_PLAIN_TEXT)
+                    .map(|(_, data)| String::from_utf8(data).unwrap()),
+                _ => None,
+            })
+        }
+
+        fn write(&mut self, item: ClipboardItem) -> Result<()> {
+            self.0.write(item)
+        }
+
+        fn watch(&self, tx: mpsc::Sender<()>, poll_interval: Duration) -> Option<JoinHandle<()>> {
+            self.0.watch(tx, poll_interval)
+        }
+    }
+
+    #[tokio::test]
+    async fn send_now_rebroadcasts_unchanged_clipboard() {
+        let (mut rx, peer_port) = spawn_peer().await;
+        let config = AppConfig { listen_port: free_port(), ..sender_for(peer_port) };
+        let memory = MemoryClipboard::new();
+        let core = CoreService::with_backend(config, Box::new(memory.clone())).unwrap();
+        let send_now = core.send_now_sender();
+        run_in_background(core);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        memory.set(ClipboardItem::Text("snippet".into()));
+        let first = tokio::time::timeout(Duration::from_secs(3), next_update(&mut rx)).await;
+        assert_eq!(first.unwrap().1, b"snippet");
+        // 内容未变化，快捷键请求仍会再次发送
+        send_now.send(()).await.unwrap();
+        let again = tokio::time::timeout(Duration::from_secs(3), next_update(&mut rx)).await;
+        assert_eq!(again.unwrap().1, b"snippet");
+    }
+
+    async fn next_update(
+        rx: &mut mpsc::Receiver<crate::network::IncomingMessage>,
+    ) -> (ContentType, Vec<u8>) {
+        loop {
+            let incoming = rx.recv().await.unwrap();
+            if let ProtocolMessage::ClipboardUpdate { content_type, payload, .. } = incoming.msg {
+                return (content_type, payload);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn fast_text_read_sends_text_before_slow_rich_formats() {
+        let (mut rx, peer_port) = spawn_peer().await;
+        let config = AppConfig {
+            listen_port: free_port(),
+            fast_text_read: true,
+            ..sender_for(peer_port)
+        };
+        let memory = MemoryClipboard::new();
+        let backend = Box::new(SlowRichClipboard(memory.clone()));
+        run_in_background(CoreService::with_backend(config, backend).unwrap());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let formats = vec![
+            (MIME_PLAIN_TEXT.to_string(), b"caption".to_vec()),
+            ("image/png".to_string(), vec![0x89, b'P', b'N', b'G']),
+        ];
+        memory.set(ClipboardItem::Multi(formats));
+
+        // 纯文本在慢速的完整读取完成之前就已发出
+        let (content_type, payload) =
+            tokio::time::timeout(Duration::from_millis(400), next_update(&mut rx)).await.unwrap();
+        assert!(matches!(content_type, ContentType::Text));
+        assert_eq!(payload, b"caption");
+        let (content_type, _) =
+            tokio::time::timeout(Duration::from_secs(3), next_update(&mut rx)).await.unwrap();
+        assert!(matches!(content_type, ContentType::Multi));
+
+        // 同一内容再次触发变化事件时两个阶段都不再重复发送
+        memory.set(memory.get().unwrap());
+        let repeated = tokio::time::timeout(Duration::from_millis(1000), next_update(&mut rx)).await;
+        assert!(repeated.is_err());
+    }
+
+    /// 写入文件时总是失败的剪贴板后端
+    struct NoFilesClipboard(MemoryClipboard);
+
+    impl ClipboardBackend for NoFilesClipboard {
+        fn read(&self) -> Result<Option<ClipboardItem>> {
+            self.0.read()
+        }
+
+        fn write(&mut self, item: ClipboardItem) -> Result<()> {
+            match item {
+                ClipboardItem::Files(_) => Err(anyhow::anyhow!("set_files not supported")),
+                other => self.0.write(other),
+            }
+        }
+
+        fn watch(&self, tx: mpsc::Sender<()>, poll_interval: Duration) -> Option<JoinHandle<()>> {
+            self.0.watch(tx, poll_interval)
+        }
+
+        fn supports_files(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn received_files_fall_back_to_paths_without_backend_support() {
+        use ReceivedFilesFormat::*;
+
+        assert!(!received_files_as_paths(Auto, MemoryClipboard::new().supports_files()));
+        let no_files = NoFilesClipboard(MemoryClipboard::new());
+        assert!(received_files_as_paths(Auto, no_files.supports_files()));
+        // 显式配置时不看后端能力
+        assert!(!received_files_as_paths(Files, false));
+        assert!(received_files_as_paths(Paths, true));
+
+        let files = vec![
+            ClipboardFile { path: "file:///tmp/a%20b.txt".into() },
+            ClipboardFile { path: "/tmp/c.txt".into() },
+        ];
+        assert!(matches!(
+            file_paths_item(&files),
+            ClipboardItem::Text(text) if text == "/tmp/a b.txt\n/tmp/c.txt"
+        ));
+    }
+
+    #[test]
+    fn clipboard_file_write_failure_keeps_downloads() {
+        let download = tempfile::tempdir().unwrap();
+        let config = AppConfig {
+            listen_port: free_port(),
+            download_dir: Some(download.path().to_path_buf()),
+            ..AppConfig::default()
+        };
+        let core = CoreService::new(config).unwrap();
+        let entries = vec![FileEntry {
+            name: "kept.txt".into(),
+            size: 4,
+            content: b"kept".to_vec(),
+            source_path: None,
+        }];
+        let payload = serde_json::to_vec(&entries).unwrap();
+        let item = core
+            .apply_remote_clipboard(ContentType::Files, &payload, "127.0.0.1".parse().unwrap())
+            .unwrap()
+            .unwrap();
+        let ClipboardItem::Files(files) = item.clone() else {
+            panic!("expected files");
+        };
+
+        let mut clipboard = NoFilesClipboard(MemoryClipboard::new());
+        assert!(write_received_item(&mut clipboard, item, false).is_ok());
+        assert_eq!(std::fs::read(&files[0].path).unwrap(), b"kept");
+        assert!(clipboard.read().unwrap().is_none());
+        // 其他类型的写入照常进行
+        write_received_item(&mut clipboard, ClipboardItem::Text("t".into()), false).unwrap();
+        assert!(matches!(clipboard.read().unwrap(), Some(ClipboardItem::Text(_))));
+    }
+
+    /// 记录写入次数的剪贴板后端
+    #[derive(Default)]
+    struct CountingClipboard {
+        inner: MemoryClipboard,
+        writes: usize,
+    }
+
+    impl ClipboardBackend for CountingClipboard {
+        fn read(&self) -> Result<Option<ClipboardItem>> {
+            self.inner.read()
+        }
+
+        fn write(&mut self, item: ClipboardItem) -> Result<()> {
+            self.writes += 1;
+            self.inner.write(item)
+        }
+
+        fn watch(&self, tx: mpsc::Sender<()>, poll_interval: Duration) -> Option<JoinHandle<()>> {
+            self.inner.watch(tx, poll_interval)
+        }
+    }
+
+    #[test]
+    fn identical_incoming_item_is_not_written() {
+        let mut clipboard = CountingClipboard::default();
+        clipboard.inner.set(ClipboardItem::Text("same".into()));
+
+        write_received_item(&mut clipboard, ClipboardItem::Text("same".into()), false).unwrap();
+        assert_eq!(clipboard.writes, 0);
+
+        write_received_item(&mut clipboard, ClipboardItem::Text("new".into()), false).unwrap();
+        assert_eq!(clipboard.writes, 1);
+        assert!(matches!(clipboard.read().unwrap(), Some(ClipboardItem::Text(t)) if t == "new"));
+    }
+
+    #[tokio::test]
+    async fn received_files_can_stay_off_the_clipboard() {
+        let download = tempfile::tempdir().unwrap();
+        let config = AppConfig {
+            listen_port: free_port(),
+            download_dir: Some(download.path().to_path_buf()),
+            set_received_files_on_clipboard: false,
+            ..AppConfig::default()
+        };
+        let core = CoreService::new(config).unwrap();
+        let entries = vec![FileEntry {
+            name: "saved.txt".into(),
+            size: 5,
+            content: b"saved".to_vec(),
+            source_path: None,
+        }];
+        let payload = serde_json::to_vec(&entries).unwrap();
+        let item = core
+            .apply_remote_clipboard(ContentType::Files, &payload, "127.0.0.1".parse().unwrap())
+            .unwrap();
+        assert!(item.is_none());
+
+        // 文件已保存在下载目录的时间戳子目录中
+        let saved: Vec<PathBuf> = std::fs::read_dir(download.path())
+            .unwrap()
+            .flat_map(|d| std::fs::read_dir(d.unwrap().path()).unwrap())
+            .map(|f| f.unwrap().path())
+            .collect();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(std::fs::read(&saved[0]).unwrap(), b"saved");
+    }
+
+    #[test]
+    fn source_path_is_display_only() {
+        let download = tempfile::tempdir().unwrap();
+        let config = AppConfig {
+            listen_port: free_port(),
+            download_dir: Some(download.path().to_path_buf()),
+            ..AppConfig::default()
+        };
+        let core = CoreService::new(config).unwrap();
+
+        let entries = vec![FileEntry {
+            name: "../report.pdf".into(),
+            size: 4,
+            content: b"data".to_vec(),
+            source_path: Some("/tmp/should-not-be-used".into()),
+        }];
+        let payload = crate::protocol::encode_files_payload(&entries);
+        let decoded = decode_files_payload(&payload).unwrap();
+        assert_eq!(decoded[0].source_path.as_deref(), Some("/tmp/should-not-be-used"));
+
+        let item = core
+            .apply_remote_clipboard(ContentType::Files, &payload, "127.0.0.1".parse().unwrap())
+            .unwrap();
+        let Some(ClipboardItem::Files(files)) = item else {
+            panic!("expected files");
+        };
+        let written = Path::new(&files[0].path);
+        assert!(written.starts_with(download.path()));
+        assert_eq!(written.file_name().unwrap(), "report.pdf");
+        assert!(!Path::new("/tmp/should-not-be-used/report.pdf").exists());
+    }
+
+    #[test]
+    fn manifest_transfers_only_missing_files() {
+        let sender_download = tempfile::tempdir().unwrap();
+        let receiver_download = tempfile::tempdir().unwrap();
+        let sender = CoreService::new(AppConfig {
+            listen_port: free_port(),
+            download_dir: Some(sender_download.path().to_path_buf()),
+            file_manifest: true,
+            ..AppConfig::default()
+        })
+        .unwrap();
+        let mut receiver = CoreService::new(AppConfig {
+            listen_port: free_port(),
+            download_dir: Some(receiver_download.path().to_path_buf()),
+            ..AppConfig::default()
+        })
+        .unwrap();
+        let from: IpAddr = "127.0.0.1".parse().unwrap();
+        let file = |name: &str, content: &[u8]| FileEntry {
+            name: name.into(),
+            size: content.len() as u64,
+            content: content.to_vec(),
+            source_path: None,
+        };
+
+        // 接收端此前已收到过 a.txt
+        let payload = serde_json::to_vec(&vec![file("a.txt", b"unchanged")]).unwrap();
+        receiver
+            .apply_remote_clipboard(ContentType::Files, &payload, from)
+            .unwrap();
+
+        let manifest = sender.build_file_manifest(vec![
+            file("a.txt", b"unchanged"),
+            file("b.txt", b"new content"),
+        ]);
+        let ProtocolMessage::FileManifest { sender_id, transfer_id, entries, .. } = manifest else {
+            panic!("expected manifest");
+        };
+        let request = match receiver.on_file_manifest(sender_id, transfer_id, entries, from) {
+            ManifestAction::Request(request) => request,
+            _ => panic!("expected file request"),
+        };
+        let ProtocolMessage::FileRequest { indices, .. } = &request else {
+            panic!("expected file request");
+        };
+        assert_eq!(indices, &vec![1]);
+
+        let data = sender.on_file_request(transfer_id, indices).unwrap();
+        let ProtocolMessage::FileData { files, .. } = data else {
+            panic!("expected file data");
+        };
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].1.name, "b.txt");
+
+        let item = receiver
+            .on_file_data(sender_id, transfer_id, files, from)
+            .unwrap();
+        let Some(ClipboardItem::Files(paths)) = item else {
+            panic!("expected files");
+        };
+        assert_eq!(paths.len(), 2);
+        assert_eq!(std::fs::read(&paths[0].path).unwrap(), b"unchanged");
+        assert_eq!(std::fs::read(&paths[1].path).unwrap(), b"new content");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn primary_selection_written_only_when_enabled() {
+        assert_eq!(selection_target(Selection::Primary, true), Some(Selection::Primary));
+        assert_eq!(selection_target(Selection::Primary, false), None);
+        assert_eq!(selection_target(Selection::Clipboard, false), Some(Selection::Clipboard));
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    #[test]
+    fn primary_selection_is_ignored_off_linux() {
+        assert_eq!(selection_target(Selection::Primary, true), None);
+        assert_eq!(selection_target(Selection::Clipboard, true), Some(Selection::Clipboard));
+    }
+
+    #[test]
+    fn newline_policies_convert_text() {
+        let crlf = "a\r\nb\r\n".to_string();
+        let lf = "a\nb\n".to_string();
+        let mixed = "a\r\nb\nc\rd".to_string();
+
+        assert_eq!(normalize_newlines(crlf.clone(), NewlinePolicy::Preserve), crlf);
+        assert_eq!(normalize_newlines(lf.clone(), NewlinePolicy::Preserve), lf);
+
+        assert_eq!(normalize_newlines(crlf.clone(), NewlinePolicy::Lf), lf);
+        assert_eq!(normalize_newlines(lf.clone(), NewlinePolicy::Lf), lf);
+        assert_eq!(normalize_newlines(mixed.clone(), NewlinePolicy::Lf), "a\nb\nc\rd");
+
+        assert_eq!(normalize_newlines(lf.clone(), NewlinePolicy::Crlf), crlf);
+        assert_eq!(normalize_newlines(crlf.clone(), NewlinePolicy::Crlf), crlf);
+        assert_eq!(normalize_newlines(mixed, NewlinePolicy::Crlf), "a\r\nb\r\nc\rd");
+
+        let native = if cfg!(windows) { &crlf } else { &lf };
+        assert_eq!(&normalize_newlines(crlf.clone(), NewlinePolicy::MatchReceiver), native);
+        assert_eq!(&normalize_newlines(lf.clone(), NewlinePolicy::MatchReceiver), native);
+
+        // 含 NUL 的二进制样文本不做转换
+        let binary = "x\0\r\ny".to_string();
+        assert_eq!(normalize_newlines(binary.clone(), NewlinePolicy::Lf), binary);
+    }
+
+    #[test]
+    fn text_transforms_apply_in_order() {
+        let text = || "  Hello,\n\t world  \r\n\n".to_string();
+        let apply = |transforms: &[TextTransform]| apply_text_transforms(text(), transforms);
+
+        assert_eq!(apply(&[]), text());
+        assert_eq!(apply(&[TextTransform::Trim]), "Hello,\n\t world");
+        assert_eq!(apply(&[TextTransform::StripTrailingNewline]), "  Hello,\n\t world  ");
+        assert_eq!(apply(&[TextTransform::CollapseWhitespace]), "Hello, world");
+
+        // 链式执行：先去掉末尾换行，保留的首尾空格再由 trim 去除
+        assert_eq!(
+            apply(&[TextTransform::StripTrailingNewline, TextTransform::Trim]),
+            "Hello,\n\t world"
+        );
+        let transforms: Vec<TextTransform> =
+            serde_json::from_str(r#"["trim", "collapse_whitespace"]"#).unwrap();
+        assert_eq!(apply(&transforms), "Hello, world");
+    }
+
+    #[tokio::test]
+    async fn outbound_transforms_leave_local_clipboard_untouched() {
+        let peer_port = free_port();
+        let peer_config = AppConfig {
+            listen_port: peer_port,
+            ..AppConfig::default()
+        };
+        let (tx, mut rx) = mpsc::channel(4);
+        let keys = KeyRing::from_config(&peer_config).unwrap();
+        let server = NetworkServer::with_keyring(&peer_config, keys, tx).unwrap();
+        tokio::spawn(server.run());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let config = AppConfig {
+            listen_port: free_port(),
+            peers: vec![PeerConfig::new("127.0.0.1", peer_port)],
+            outbound_transforms: vec![TextTransform::Trim],
+            ..AppConfig::default()
+        };
+        let memory = MemoryClipboard::new();
+        memory.set(ClipboardItem::Text("  padded  ".into()));
+        let core = CoreService::with_backend(config, Box::new(memory.clone())).unwrap();
+        let results = core.broadcast(ClipboardItem::Text("  padded  ".into())).await.unwrap();
+        assert!(results[0].is_ok(), "{:?}", results[0]);
+
+        let msg = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap()
+            .msg;
+        let ProtocolMessage::ClipboardUpdate { payload, .. } = msg else {
+            panic!("unexpected message {:?}", msg);
+        };
+        assert_eq!(payload, b"padded");
+        assert!(matches!(memory.get(), Some(ClipboardItem::Text(t)) if t == "  padded  "));
+    }
+
+    #[test]
+    fn unicode_normalization_makes_equivalent_text_dedupe() {
+        // "é"、"한" 的组合与分解形式
+        let nfc = "caf\u{e9} \u{d55c}".to_string();
+        let nfd = "cafe\u{301} \u{1112}\u{1161}\u{11ab}".to_string();
+        assert_ne!(nfc, nfd);
+        let hash = |text: String| hash_item(&ClipboardItem::Text(text), false);
+        assert_ne!(hash(nfc.clone()), hash(nfd.clone()));
+
+        for form in [UnicodeNormalization::Nfc, UnicodeNormalization::Nfd] {
+            let a = normalize_unicode(nfc.clone(), form);
+            let b = normalize_unicode(nfd.clone(), form);
+            assert_eq!(a, b);
+            assert_eq!(hash(a), hash(b));
+        }
+        assert_eq!(normalize_unicode(nfd.clone(), UnicodeNormalization::Nfc), nfc);
+        assert_eq!(normalize_unicode(nfc.clone(), UnicodeNormalization::Nfd), nfd);
+        assert_eq!(normalize_unicode(nfd.clone(), UnicodeNormalization::None), nfd);
+
+        let form: UnicodeNormalization = serde_json::from_str(r#""nfc""#).unwrap();
+        assert_eq!(form, UnicodeNormalization::Nfc);
+    }
+
+    #[tokio::test]
+    async fn unicode_normalize_applies_to_outbound_text_only() {
+        let peer_port = free_port();
+        let peer_config = AppConfig {
+            listen_port: peer_port,
+            ..AppConfig::default()
+        };
+        let (tx, mut rx) = mpsc::channel(4);
+        let keys = KeyRing::from_config(&peer_config).unwrap();
+        let server = NetworkServer::with_keyring(&peer_config, keys, tx).unwrap();
+        tokio::spawn(server.run());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let config = AppConfig {
+            listen_port: free_port(),
+            peers: vec![PeerConfig::new("127.0.0.1", peer_port)],
+            unicode_normalize: UnicodeNormalization::Nfc,
+            ..AppConfig::default()
+        };
+        let nfd = "cafe\u{301}";
+        let memory = MemoryClipboard::new();
+        memory.set(ClipboardItem::Text(nfd.into()));
+        let core = CoreService::with_backend(config, Box::new(memory.clone())).unwrap();
+        let results = core.broadcast(ClipboardItem::Text(nfd.into())).await.unwrap();
+        assert!(results[0].is_ok(), "{:?}", results[0]);
+
+        let msg = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap()
+            .msg;
+        let ProtocolMessage::ClipboardUpdate { payload, .. } = msg else {
+            panic!("unexpected message {:?}", msg);
+        };
+        assert_eq!(payload, "caf\u{e9}".as_bytes());
+        assert!(matches!(memory.get(), Some(ClipboardItem::Text(t)) if t == nfd));
+    }
+
+    #[test]
+    fn adopted_key_does_not_persist_discovered_peers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let config = AppConfig {
+            listen_port: free_port(),
+            peers: vec![PeerConfig::new("192.168.1.10", 5000)],
+            ..AppConfig::default()
+        };
+        config.save(&path).unwrap();
+        let mut core = CoreService::new(config).unwrap();
+        core.set_config_path(path.clone());
+        // 运行期间合并进来的 SRV/mDNS 对端
+        core.config.peers.push(PeerConfig::new("192.168.1.77", 5000));
+
+        let new_key = [5u8; 32];
+        core.keys.set_primary(new_key);
+        core.adopt_primary_key();
+
+        let stored = AppConfig::load(path).unwrap();
+        assert_eq!(stored.secret_key, hex::encode(new_key));
+        assert!(stored.accepted_keys.is_empty());
+        let hosts: Vec<&str> = stored.peers.iter().map(|p| p.host.as_str()).collect();
+        assert_eq!(hosts, ["192.168.1.10"]);
+    }
+
+    #[test]
+    fn failed_file_set_leaves_no_partial_files() {
+        let download = tempfile::tempdir().unwrap();
+        let dir = download.path().to_path_buf();
+        // 第二个文件的目标位置是非空目录，重命名必然失败
+        std::fs::create_dir_all(dir.join("b.txt").join("occupied")).unwrap();
+        let core = CoreService::new(AppConfig {
+            listen_port: free_port(),
+            ..AppConfig::default()
+        })
+        .unwrap();
+        let entry = |name: &str| FileEntry {
+            name: name.into(),
+            size: 3,
+            content: b"abc".to_vec(),
+            source_path: None,
+        };
+        let entries = [entry("a.txt"), entry("b.txt"), entry("c.txt")];
+        assert!(core.save_received_files(&dir, &entries).is_err());
+
+        let mut left: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        left.sort();
+        assert_eq!(left, vec!["b.txt"]);
+
+        // 成功时全部落盘，不残留临时文件
+        let ok_dir = download.path().join("ok");
+        std::fs::create_dir_all(&ok_dir).unwrap();
+        let paths = core.save_received_files(&ok_dir, &entries).unwrap();
+        assert_eq!(paths.iter().flatten().count(), 3);
+        assert_eq!(std::fs::read_dir(&ok_dir).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn name_collision_policies() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("report.pdf");
+        let fresh = dir.path().join("fresh.pdf");
+        for policy in [
+            NameCollisionPolicy::Overwrite,
+            NameCollisionPolicy::Rename,
+            NameCollisionPolicy::Skip,
+        ] {
+            assert_eq!(collision_target(fresh.clone(), policy), Some(fresh.clone()));
+        }
+        std::fs::write(&existing, b"first").unwrap();
+
+        assert_eq!(
+            collision_target(existing.clone(), NameCollisionPolicy::Overwrite),
+            Some(existing.clone())
+        );
+        assert_eq!(collision_target(existing.clone(), NameCollisionPolicy::Skip), None);
+        let renamed = collision_target(existing.clone(), NameCollisionPolicy::Rename).unwrap();
+        assert_eq!(renamed, dir.path().join("report (1).pdf"));
+        std::fs::write(&renamed, b"second").unwrap();
+        assert_eq!(
+            collision_target(existing.clone(), NameCollisionPolicy::Rename),
+            Some(dir.path().join("report (2).pdf"))
+        );
+        // 无扩展名的文件同样编号
+        std::fs::write(dir.path().join("README"), b"x").unwrap();
+        assert_eq!(
+            collision_target(dir.path().join("README"), NameCollisionPolicy::Rename),
+            Some(dir.path().join("README (1)"))
+        );
+    }
+
+    #[test]
+    fn received_file_respects_collision_policy() {
+        let download = tempfile::tempdir().unwrap();
+        let entry = |content: &[u8]| FileEntry {
+            name: "../report.pdf".into(),
+            size: content.len() as u64,
+            content: content.to_vec(),
+            source_path: None,
+        };
+        for (policy, expected) in [
+            (NameCollisionPolicy::Overwrite, Some("report.pdf")),
+            (NameCollisionPolicy::Rename, Some("report (1).pdf")),
+            (NameCollisionPolicy::Skip, None),
+        ] {
+            let dir = download.path().join(format!("{policy:?}"));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("report.pdf"), b"earlier").unwrap();
+            let core = CoreService::new(AppConfig {
+                listen_port: free_port(),
+                on_name_collision: policy,
+                ..AppConfig::default()
+            })
+            .unwrap();
+            let saved = core.save_received_file(&dir, &entry(b"later")).unwrap();
+            assert_eq!(saved, expected.map(|name| dir.join(name)));
+            let kept = if policy == NameCollisionPolicy::Overwrite { "later" } else { "earlier" };
+            assert_eq!(std::fs::read(dir.join("report.pdf")).unwrap(), kept.as_bytes());
+        }
+    }
+
+    #[test]
+    fn clipboard_file_paths_are_absolute() {
+        let item = files_item(std::iter::once(PathBuf::from("recv/20240101-000000/a.txt")));
+        let ClipboardItem::Files(files) = item else {
+            panic!("expected files");
+        };
+        let path = Path::new(&files[0].path);
+        assert!(path.is_absolute(), "{}", files[0].path);
+        assert!(path.ends_with("recv/20240101-000000/a.txt"));
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn windows_clipboard_file_paths_use_backslashes() {
+        let raws = ["recv/sub/a.txt", r"C:/Users/me/Downloads/a.txt", r"\\?\C:\Downloads\a.txt"];
+        for raw in raws {
+            let path = clipboard_file_path(Path::new(raw));
+            assert!(Path::new(&path).is_absolute(), "{path}");
+            assert!(!path.contains('/') && !path.starts_with(r"\\?\"), "{path}");
+        }
+        let unc = clipboard_file_path(Path::new(r"\\?\UNC\nas\share\a.txt"));
+        assert_eq!(unc, r"\\nas\share\a.txt");
+    }
+
+    #[test]
+    fn safe_file_name_strips_directories() {
+        assert_eq!(safe_file_name("a/b/c.txt"), "c.txt");
+        assert_eq!(safe_file_name("..\\..\\win.ini"), "win.ini");
+        assert_eq!(safe_file_name(".."), "file");
+    }
+
+    #[test]
+    fn duplicate_message_id_is_applied_once() {
+        let mut seen = SeenMessages::new(2);
+        assert!(seen.insert([1; 16], 1));
+        assert!(!seen.insert([1; 16], 1));
+        // 不同发送者的相同序号互不影响
+        assert!(seen.insert([2; 16], 1));
+        // 未携带序号的旧消息不去重
+        assert!(seen.insert([1; 16], 0));
+        assert!(seen.insert([1; 16], 0));
+
+        // 超出容量后最早的 ID 被淘汰
+        assert!(seen.insert([1; 16], 2));
+        assert!(seen.insert([1; 16], 1));
+    }
+
+    #[test]
+    fn source_app_is_ignored_for_dedup() {
+        let config = AppConfig {
+            listen_port: free_port(),
+            ..AppConfig::default()
+        };
+        let core = CoreService::new(config).unwrap();
+        let from: IpAddr = "192.168.1.2".parse().unwrap();
+        let mut seen = SeenMessages::new(8);
+        let mut fresh = Vec::new();
+        let expected = hash_item(&ClipboardItem::Text("hello".into()), false);
+        for app in [Some("firefox"), Some("code"), None] {
+            let mut msg = text_update(5, 9, "hello");
+            if let ProtocolMessage::ClipboardUpdate { source_app, .. } = &mut msg {
+                *source_app = app.map(String::from);
+            }
+            let decoded = crate::protocol::decode_message(&encode_message(&msg).unwrap()).unwrap();
+            let ProtocolMessage::ClipboardUpdate { sender_id, content_type, payload, seq, .. } =
+                decoded
+            else {
+                panic!("unexpected message");
+            };
+            fresh.push(seen.insert(sender_id, seq));
+            let item = core.apply_remote_clipboard(content_type, &payload, from).unwrap().unwrap();
+            assert_eq!(hash_item(&item, false), expected);
+        }
+        // 仅第一条被视为新消息，来源应用名不同也不会绕过消息去重
+        assert_eq!(fresh, [true, false, false]);
+    }
+
+    #[test]
+    fn outgoing_messages_carry_increasing_seq() {
+        let config = AppConfig {
+            listen_port: free_port(),
+            ..AppConfig::default()
+        };
+        let core = CoreService::new(config).unwrap();
+        let seq_of = |msg: ProtocolMessage| match msg {
+            ProtocolMessage::ClipboardUpdate { seq, .. } => seq,
+            other => panic!("unexpected message {:?}", other),
+        };
+        let update = |text: &[u8]| {
+            core.clipboard_update(ContentType::Text, text.to_vec(), Selection::Clipboard)
+        };
+        let a = seq_of(update(b"a"));
+        let b = seq_of(update(b"b"));
+        assert!(a > 0 && b > a);
+    }
+
+    #[test]
+    fn generated_instance_id_is_persisted_and_reused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let config = AppConfig { listen_port: free_port(), ..AppConfig::default() };
+        config.save(&path).unwrap();
+
+        let mut first = CoreService::new(config).unwrap();
+        first.set_config_path(path.clone());
+        let stored = AppConfig::load(path.clone()).unwrap();
+        assert_eq!(stored.instance_id, Some(first.instance_id.to_string()));
+
+        let restarted = CoreService::new(AppConfig { listen_port: free_port(), ..stored }).unwrap();
+        assert_eq!(restarted.instance_id, first.instance_id);
+    }
+
+    #[tokio::test]
+    async fn restarted_sender_with_persisted_id_is_not_deduplicated() {
+        let (clipboard, port) = spawn_core(AppConfig::default()).await;
+        let config = AppConfig {
+            instance_id: Some(Uuid::new_v4().to_string()),
+            ..sender_for(port)
+        };
+        // 两次启动使用同一实例 ID，各自从本次启动的纪元开始编号
+        for text in ["before restart", "after restart"] {
+            let sender = CoreService::with_backend(
+                AppConfig { listen_port: free_port(), ..config.clone() },
+                Box::new(MemoryClipboard::new()),
+            )
+            .unwrap();
+            sender.broadcast(ClipboardItem::Text(text.into())).await.unwrap();
+            wait_for_text(&clipboard, text).await;
+        }
+    }
+
+    #[test]
+    fn read_cache_reuses_recent_read() {
+        let mut cache = ReadCache::new(Duration::from_millis(50));
+        let reads = std::cell::Cell::new(0);
+        let t0 = Instant::now();
+        let read = || {
+            reads.set(reads.get() + 1);
+            Ok(Some(ClipboardItem::Text("x".into())))
+        };
+        assert!(cache.read(t0, read).unwrap().is_some());
+        assert!(cache.read(t0 + Duration::from_millis(5), read).unwrap().is_some());
+        assert_eq!(reads.get(), 1);
+
+        // 过期或写入后失效都会重新读取
+        cache.read(t0 + Duration::from_millis(60), read).unwrap();
+        assert_eq!(reads.get(), 2);
+        cache.invalidate();
+        cache.read(t0 + Duration::from_millis(61), read).unwrap();
+        assert_eq!(reads.get(), 3);
+    }
+}
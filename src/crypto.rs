@@ -1,13 +1,16 @@
-//! 加密工具模块：X25519 密钥交换 + HKDF 会话密钥派生 + ChaCha20-Poly1305 加解密。
+//! 加密工具模块：X25519 密钥交换 + HKDF 会话密钥派生 + ChaCha20-Poly1305 / AES-256-GCM 加解密。
 
+use aes_gcm::Aes256Gcm;
 use anyhow::{anyhow, Result};
-use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
 use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
 use hkdf::Hkdf;
 use rand::RngCore;
 use sha2::Sha256;
 use x25519_dalek::{EphemeralSecret, PublicKey};
 
+use crate::config::AeadAlgorithm;
+
 /// HKDF 的 info 参数，用于绑定会话密钥用途
 const HKDF_INFO: &[u8] = b"lan-clipboard-sync-v1";
 
@@ -20,8 +23,8 @@ pub fn key_from_hex(hex: &str) -> Result<Key> {
     Ok(Key::from_slice(&bytes).to_owned())
 }
 
-/// 从 ECDH 共享密钥与 PSK 派生出 32 字节会话密钥（用于 ChaCha20-Poly1305）
-fn derive_session_key(shared_secret: &[u8], psk: &[u8; 32]) -> Key {
+/// 从 ECDH 共享密钥与 PSK 派生出 32 字节会话密钥（两种 AEAD 算法通用）
+pub fn derive_session_key(shared_secret: &[u8], psk: &[u8; 32]) -> Key {
     let hk = Hkdf::<Sha256>::new(Some(psk), shared_secret);
     let mut key = [0u8; 32];
     hk.expand(HKDF_INFO, &mut key)
@@ -29,6 +32,27 @@ fn derive_session_key(shared_secret: &[u8], psk: &[u8; 32]) -> Key {
     Key::from_slice(&key).to_owned()
 }
 
+/// 由口令派生 32 字节密钥（PBKDF2-HMAC-SHA256），用于加密保存的配置文件
+pub fn derive_passphrase_key(passphrase: &[u8], salt: &[u8], iterations: u32) -> Key {
+    use hmac::{Hmac, Mac};
+
+    let prf = <Hmac<Sha256> as Mac>::new_from_slice(passphrase)
+        .expect("HMAC accepts any key length");
+    // 输出长度等于 SHA-256 摘要长度，只需计算第 1 个块
+    let mut mac = prf.clone();
+    mac.update(salt);
+    mac.update(&1u32.to_be_bytes());
+    let mut u = mac.finalize().into_bytes();
+    let mut key = u;
+    for _ in 1..iterations {
+        let mut mac = prf.clone();
+        mac.update(&u);
+        u = mac.finalize().into_bytes();
+        key.iter_mut().zip(u.iter()).for_each(|(k, b)| *k ^= b);
+    }
+    Key::from_slice(&key).to_owned()
+}
+
 /// 生成随机 nonce（12 字节）
 pub fn random_nonce() -> [u8; 12] {
     let mut bytes = [0u8; 12];
@@ -36,24 +60,74 @@ pub fn random_nonce() -> [u8; 12] {
     bytes
 }
 
-/// 加密：返回 (nonce_bytes, ciphertext)
-pub fn encrypt(key: &Key, plaintext: &[u8]) -> Result<([u8; 12], Vec<u8>)> {
-    let cipher = ChaCha20Poly1305::new(key);
+impl AeadAlgorithm {
+    /// 帧头中携带的算法标识（0 与旧版帧兼容，即 ChaCha20-Poly1305）
+    pub fn id(self) -> u8 {
+        match self {
+            AeadAlgorithm::ChaCha20Poly1305 => 0,
+            AeadAlgorithm::Aes256Gcm => 1,
+        }
+    }
+
+    /// 由帧头中的算法标识解析算法，未知标识报错。
+    pub fn from_id(id: u8) -> Result<Self> {
+        match id {
+            0 => Ok(AeadAlgorithm::ChaCha20Poly1305),
+            1 => Ok(AeadAlgorithm::Aes256Gcm),
+            other => Err(anyhow!("unknown aead algorithm id {}", other)),
+        }
+    }
+
+    /// 与配置文件中写法一致的算法名，用于日志与错误信息。
+    pub fn name(self) -> &'static str {
+        match self {
+            AeadAlgorithm::ChaCha20Poly1305 => "chacha20-poly1305",
+            AeadAlgorithm::Aes256Gcm => "aes-256-gcm",
+        }
+    }
+}
+
+/// 加密：返回 (nonce_bytes, ciphertext)。`aad` 为参与认证但不加密的附加数据（如同步频道）。
+///
+/// 两种算法的密钥均为 32 字节、nonce 均为 12 字节。
+pub fn encrypt(
+    algorithm: AeadAlgorithm,
+    key: &Key,
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Result<([u8; 12], Vec<u8>)> {
     let nonce = random_nonce();
     let nonce_ref = Nonce::from_slice(&nonce);
-    let ct = cipher
-        .encrypt(nonce_ref, plaintext)
-        .map_err(|e| anyhow!("encrypt failed: {e}"))?;
+    let payload = Payload {
+        msg: plaintext,
+        aad,
+    };
+    let ct = match algorithm {
+        AeadAlgorithm::ChaCha20Poly1305 => ChaCha20Poly1305::new(key).encrypt(nonce_ref, payload),
+        AeadAlgorithm::Aes256Gcm => Aes256Gcm::new(key).encrypt(nonce_ref, payload),
+    }
+    .map_err(|e| anyhow!("encrypt failed: {e}"))?;
     Ok((nonce, ct))
 }
 
-/// 解密：传入 nonce 与密文
-pub fn decrypt(key: &Key, nonce: &[u8; 12], ciphertext: &[u8]) -> Result<Vec<u8>> {
-    let cipher = ChaCha20Poly1305::new(key);
+/// 解密：传入 nonce、密文与加密时使用的附加数据，附加数据不一致时认证失败
+pub fn decrypt(
+    algorithm: AeadAlgorithm,
+    key: &Key,
+    nonce: &[u8; 12],
+    ciphertext: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>> {
     let nonce_ref = Nonce::from_slice(nonce);
-    let pt = cipher
-        .decrypt(nonce_ref, ciphertext)
-        .map_err(|e| anyhow!("decrypt failed: {e}"))?;
+    let payload = Payload {
+        msg: ciphertext,
+        aad,
+    };
+    let pt = match algorithm {
+        AeadAlgorithm::ChaCha20Poly1305 => ChaCha20Poly1305::new(key).decrypt(nonce_ref, payload),
+        AeadAlgorithm::Aes256Gcm => Aes256Gcm::new(key).decrypt(nonce_ref, payload),
+    }
+    .map_err(|e| anyhow!("decrypt failed: {e}"))?;
     Ok(pt)
 }
 
@@ -76,8 +150,11 @@ where
     Ok(derive_session_key(shared.as_bytes(), psk))
 }
 
-/// 服务端握手：接收客户端公钥，发送本端公钥，完成 ECDH 并派生会话密钥。
-pub async fn handshake_server<S>(stream: &mut S, psk: &[u8; 32]) -> Result<Key>
+/// 服务端握手：接收客户端公钥，发送本端公钥，完成 ECDH，返回共享密钥。
+///
+/// 服务端可能接受多个 PSK，因此由调用方对每个候选 PSK 调用 `derive_session_key`，
+/// 以能成功解密的那个确认客户端使用的密钥。
+pub async fn handshake_server<S>(stream: &mut S) -> Result<[u8; 32]>
 where
     S: tokio::io::AsyncReadExt + tokio::io::AsyncWriteExt + Unpin,
 {
@@ -89,21 +166,60 @@ where
     stream.flush().await?;
     let peer = PublicKey::from(peer_bytes);
     let shared = secret.diffie_hellman(&peer);
-    Ok(derive_session_key(shared.as_bytes(), psk))
+    Ok(*shared.as_bytes())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const CHACHA: AeadAlgorithm = AeadAlgorithm::ChaCha20Poly1305;
+
+    #[test]
+    fn passphrase_key_matches_pbkdf2_vectors() {
+        let expected = [
+            (1, "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"),
+            (2, "ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43"),
+        ];
+        for (iterations, hex_key) in expected {
+            let key = derive_passphrase_key(b"password", b"salt", iterations);
+            assert_eq!(hex::encode(key), hex_key);
+        }
+    }
+
     #[test]
     fn roundtrip() {
         let key_hex = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
         let key = key_from_hex(key_hex).unwrap();
         let msg = b"hello world";
-        let (nonce, ct) = encrypt(&key, msg).unwrap();
-        let pt = decrypt(&key, &nonce, &ct).unwrap();
+        let (nonce, ct) = encrypt(CHACHA, &key, msg, b"").unwrap();
+        let pt = decrypt(CHACHA, &key, &nonce, &ct, b"").unwrap();
         assert_eq!(&pt, msg);
     }
-}
 
+    #[test]
+    fn aad_mismatch_fails() {
+        let key = key_from_hex(&"ab".repeat(32)).unwrap();
+        let (nonce, ct) = encrypt(CHACHA, &key, b"hello", b"work").unwrap();
+        assert!(decrypt(CHACHA, &key, &nonce, &ct, b"home").is_err());
+        assert!(decrypt(CHACHA, &key, &nonce, &ct, b"").is_err());
+        assert_eq!(
+            decrypt(CHACHA, &key, &nonce, &ct, b"work").unwrap(),
+            b"hello"
+        );
+    }
+
+    #[test]
+    fn both_algorithms_roundtrip() {
+        let key = key_from_hex(&"cd".repeat(32)).unwrap();
+        for alg in [AeadAlgorithm::ChaCha20Poly1305, AeadAlgorithm::Aes256Gcm] {
+            let (nonce, ct) = encrypt(alg, &key, b"hello", b"work").unwrap();
+            assert_eq!(decrypt(alg, &key, &nonce, &ct, b"work").unwrap(), b"hello");
+            assert_eq!(AeadAlgorithm::from_id(alg.id()).unwrap(), alg);
+        }
+        // 不同算法的密文互不兼容
+        let (nonce, ct) = encrypt(AeadAlgorithm::Aes256Gcm, &key, b"hello", b"").unwrap();
+        assert!(decrypt(CHACHA, &key, &nonce, &ct, b"").is_err());
+        assert!(AeadAlgorithm::from_id(7).is_err());
+    }
+}
@@ -0,0 +1,166 @@
+//! 文本增量同步：对反复编辑后再次复制的大段文本，只发送相对上一次内容的行级差异。
+//!
+//! 差异由若干操作组成：从基准文本复制连续若干行，或插入新文本。
+//! 接收端用同一基准文本重建，并通过摘要校验结果。
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// 每行最多尝试匹配的候选位置数，避免大量重复行（如空行）导致退化
+const MAX_CANDIDATES: usize = 8;
+
+/// 单个差异操作。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeltaOp {
+    /// 从基准文本第 `start` 行起复制 `len` 行（行含换行符）
+    Copy { start: usize, len: usize },
+    /// 插入新文本
+    Insert(String),
+}
+
+/// 文本差异：记录基准与目标文本的摘要，便于接收端确认基准一致并校验重建结果。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextDelta {
+    pub base_digest: [u8; 8],
+    pub target_digest: [u8; 8],
+    pub ops: Vec<DeltaOp>,
+}
+
+/// 计算文本的 8 字节摘要（SHA-256 截断，跨平台稳定）。
+pub fn text_digest(text: &str) -> [u8; 8] {
+    let hash = Sha256::digest(text.as_bytes());
+    let mut out = [0u8; 8];
+    out.copy_from_slice(&hash[..8]);
+    out
+}
+
+/// 计算从 `base` 到 `target` 的行级差异。
+pub fn compute_delta(base: &str, target: &str) -> TextDelta {
+    let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+    let target_lines: Vec<&str> = target.split_inclusive('\n').collect();
+
+    let mut index: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, line) in base_lines.iter().enumerate() {
+        index.entry(line).or_default().push(i);
+    }
+
+    let mut ops: Vec<DeltaOp> = Vec::new();
+    let mut next_expected = 0usize;
+    let mut i = 0;
+    while i < target_lines.len() {
+        let line = target_lines[i];
+        let mut best: Option<(usize, usize)> = None;
+        if let Some(starts) = index.get(line) {
+            // 优先尝试紧接上一段复制的位置，再尝试前几个候选
+            let preferred = (next_expected < base_lines.len() && base_lines[next_expected] == line)
+                .then_some(next_expected);
+            for &start in preferred.iter().chain(starts.iter().take(MAX_CANDIDATES)) {
+                let mut len = 0;
+                while start + len < base_lines.len()
+                    && i + len < target_lines.len()
+                    && base_lines[start + len] == target_lines[i + len]
+                {
+                    len += 1;
+                }
+                if best.is_none_or(|(_, l)| len > l) {
+                    best = Some((start, len));
+                }
+            }
+        }
+
+        match best {
+            Some((start, len)) if len > 0 => {
+                match ops.last_mut() {
+                    Some(DeltaOp::Copy { start: s, len: l }) if *s + *l == start => *l += len,
+                    _ => ops.push(DeltaOp::Copy { start, len }),
+                }
+                next_expected = start + len;
+                i += len;
+            }
+            _ => {
+                match ops.last_mut() {
+                    Some(DeltaOp::Insert(text)) => text.push_str(line),
+                    _ => ops.push(DeltaOp::Insert(line.to_string())),
+                }
+                i += 1;
+            }
+        }
+    }
+
+    TextDelta {
+        base_digest: text_digest(base),
+        target_digest: text_digest(target),
+        ops,
+    }
+}
+
+/// 使用基准文本重建目标文本，基准摘要不符或结果校验失败时返回错误。
+pub fn apply_delta(base: &str, delta: &TextDelta) -> Result<String> {
+    if text_digest(base) != delta.base_digest {
+        return Err(anyhow!("delta base does not match local base text"));
+    }
+    let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+    let mut out = String::with_capacity(base.len());
+    for op in &delta.ops {
+        match op {
+            DeltaOp::Copy { start, len } => {
+                let end = start
+                    .checked_add(*len)
+                    .filter(|end| *end <= base_lines.len())
+                    .ok_or_else(|| anyhow!("delta copy range out of bounds"))?;
+                for line in &base_lines[*start..end] {
+                    out.push_str(line);
+                }
+            }
+            DeltaOp::Insert(text) => out.push_str(text),
+        }
+    }
+    if text_digest(&out) != delta.target_digest {
+        return Err(anyhow!("delta reconstruction digest mismatch"));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(lines: usize) -> String {
+        (0..lines)
+            .map(|i| format!("line {i}: lorem ipsum dolor sit amet\n"))
+            .collect()
+    }
+
+    #[test]
+    fn reconstructs_edited_document() {
+        let base = document(500);
+        let mut edited = base.replace("line 10: lorem", "line 10: EDITED");
+        edited.insert_str(0, "new header\n");
+        edited.push_str("trailing line without newline");
+
+        let delta = compute_delta(&base, &edited);
+        assert_eq!(apply_delta(&base, &delta).unwrap(), edited);
+
+        // 差异应远小于全文
+        let encoded = serde_json::to_vec(&delta).unwrap();
+        assert!(encoded.len() * 10 < edited.len());
+    }
+
+    #[test]
+    fn rejects_wrong_base() {
+        let base = document(20);
+        let edited = document(21);
+        let delta = compute_delta(&base, &edited);
+        assert!(apply_delta(&document(19), &delta).is_err());
+    }
+
+    #[test]
+    fn handles_empty_texts() {
+        let delta = compute_delta("", "hello\nworld");
+        assert_eq!(apply_delta("", &delta).unwrap(), "hello\nworld");
+        let delta = compute_delta("hello\n", "");
+        assert_eq!(apply_delta("hello\n", &delta).unwrap(), "");
+    }
+}
@@ -0,0 +1,259 @@
+//! 局域网 mDNS/DNS-SD 对端发现：开启 `discovery` 后以 `discovery_service`（默认 `_lanclip._tcp`）
+//! 服务广播本机监听端口、实例 ID 与同步频道，同时浏览同类服务，发现的对端与 `peers` 中手写的对端
+//! 合并，DHCP 网络中地址变化后无需修改配置。
+//!
+//! 同一局域网中的多个设备组以不同的服务名或频道隔离：TXT 记录中的频道与本机 `channel` 不同的
+//! 服务被忽略，不会自动互加为对端（未携带频道的旧版本视为默认频道）。
+//!
+//! 对端下线（发送 goodbye）或其记录的 TTL 到期时由 mdns-sd 报告移除，随之从对端列表中去掉。
+
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+use anyhow::Result;
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use tokio::sync::mpsc;
+
+use crate::config::{AppConfig, PeerConfig};
+
+/// TXT 记录中携带实例 ID（十六进制）的键，用于忽略自己的广播
+const ID_PROPERTY: &str = "id";
+/// TXT 记录中携带同步频道的键，浏览时只接受频道相同的对端
+const CHANNEL_PROPERTY: &str = "channel";
+
+/// 运行中的发现服务：drop 时停止广播与浏览。
+pub struct Discovery {
+    daemon: ServiceDaemon,
+}
+
+impl Discovery {
+    /// 按 `config` 中的服务名与频道广播本机服务（`port` 为实际监听端口）并开始浏览同类服务，
+    /// 发现的对端集合变化时把完整列表发到 `tx`。
+    pub fn start(
+        config: &AppConfig,
+        instance_id: [u8; 16],
+        port: u16,
+        tx: mpsc::Sender<Vec<PeerConfig>>,
+    ) -> Result<Self> {
+        let daemon = ServiceDaemon::new()?;
+        let info = service_info(config, instance_id, port)?.enable_addr_auto();
+        let service_type = info.get_type().to_string();
+        daemon.register(info)?;
+        let events = daemon.browse(&service_type)?;
+        let mut peers = DiscoveredPeers::new(hex::encode(instance_id), config.channel.clone());
+        tokio::spawn(async move {
+            // 停止服务后 daemon 关闭事件通道，循环随之结束
+            while let Ok(event) = events.recv_async().await {
+                let changed = match event {
+                    ServiceEvent::ServiceResolved(info) => peers.resolved(&info),
+                    ServiceEvent::ServiceRemoved(_, fullname) => peers.removed(&fullname),
+                    _ => false,
+                };
+                if changed {
+                    tracing::info!("mdns discovery now sees {} peer(s)", peers.len());
+                    if tx.send(peers.peers()).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        tracing::info!("advertising {} on port {}", service_type, port);
+        Ok(Self { daemon })
+    }
+}
+
+/// 构造本机广播的服务记录：实例名缺省为实例 ID，TXT 记录携带实例 ID 与同步频道。
+fn service_info(config: &AppConfig, instance_id: [u8; 16], port: u16) -> Result<ServiceInfo> {
+    let id = hex::encode(instance_id);
+    let service_type = format!("{}.local.", config.discovery_service);
+    let instance = config.discovery_instance.as_deref().unwrap_or(&id);
+    let host = format!("lanclip-{id}.local.");
+    let properties = [
+        (ID_PROPERTY, id.as_str()),
+        (CHANNEL_PROPERTY, config.channel.as_str()),
+    ];
+    Ok(ServiceInfo::new(
+        &service_type,
+        instance,
+        &host,
+        "",
+        port,
+        &properties[..],
+    )?)
+}
+
+impl Drop for Discovery {
+    fn drop(&mut self) {
+        let _ = self.daemon.shutdown();
+    }
+}
+
+/// 浏览得到的对端，按服务实例全名记录。
+#[derive(Debug)]
+struct DiscoveredPeers {
+    own_id: String,
+    channel: String,
+    by_name: BTreeMap<String, PeerConfig>,
+}
+
+impl DiscoveredPeers {
+    fn new(own_id: String, channel: String) -> Self {
+        Self {
+            own_id,
+            channel,
+            by_name: BTreeMap::new(),
+        }
+    }
+
+    /// 记录解析出的服务实例，忽略本机的广播与其他频道的对端；对端集合变化时返回 true。
+    fn resolved(&mut self, info: &ServiceInfo) -> bool {
+        if info.get_property_val_str(ID_PROPERTY) == Some(self.own_id.as_str()) {
+            return false;
+        }
+        let channel = info.get_property_val_str(CHANNEL_PROPERTY).unwrap_or_default();
+        if channel != self.channel {
+            tracing::debug!(
+                "ignoring {} from channel {:?}",
+                info.get_fullname(),
+                channel
+            );
+            // 对端改换频道后不再视为本组成员
+            return self.removed(info.get_fullname());
+        }
+        let Some(ip) = preferred_addr(info.get_addresses().iter().copied()) else {
+            return false;
+        };
+        let peer = PeerConfig::new(ip.to_string(), info.get_port());
+        let name = info.get_fullname().to_string();
+        match self.by_name.get(&name) {
+            Some(known) if known.host == peer.host && known.port == peer.port => false,
+            _ => {
+                self.by_name.insert(name, peer);
+                true
+            }
+        }
+    }
+
+    /// 移除下线或过期的服务实例；对端集合变化时返回 true。
+    fn removed(&mut self, fullname: &str) -> bool {
+        self.by_name.remove(fullname).is_some()
+    }
+
+    fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    fn peers(&self) -> Vec<PeerConfig> {
+        self.by_name.values().cloned().collect()
+    }
+}
+
+/// 选择连接对端使用的地址：IPv4 优先，其次是不需要指定网卡的 IPv6（链路本地地址需要 scope id，
+/// 无法写成对端地址）。
+fn preferred_addr(addrs: impl Iterator<Item = IpAddr>) -> Option<IpAddr> {
+    let mut addrs: Vec<IpAddr> = addrs
+        .filter(|ip| match ip {
+            IpAddr::V4(_) => true,
+            IpAddr::V6(v6) => (v6.segments()[0] & 0xffc0) != 0xfe80,
+        })
+        .collect();
+    addrs.sort_by_key(|ip| (ip.is_ipv6(), *ip));
+    addrs.into_iter().next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const SERVICE_TYPE: &str = "_lanclip._tcp.local.";
+
+    fn service(id: &str, channel: Option<&str>, ip: &str, port: u16) -> ServiceInfo {
+        let host = format!("lanclip-{id}.local.");
+        let mut properties = vec![(ID_PROPERTY, id)];
+        properties.extend(channel.map(|c| (CHANNEL_PROPERTY, c)));
+        ServiceInfo::new(SERVICE_TYPE, id, &host, ip, port, &properties[..]).unwrap()
+    }
+
+    #[test]
+    fn discovered_peers_skip_self_and_track_removal() {
+        let mut peers = DiscoveredPeers::new("aa".into(), String::new());
+        assert!(!peers.resolved(&service("aa", Some(""), "192.168.1.2", 5000)));
+        assert!(peers.resolved(&service("bb", Some(""), "fe80::1,192.168.1.3", 5001)));
+        // 重复解析出相同地址不算变化
+        assert!(!peers.resolved(&service("bb", Some(""), "192.168.1.3", 5001)));
+        assert!(peers.resolved(&service("bb", Some(""), "192.168.1.4", 5001)));
+        let found = peers.peers();
+        assert_eq!((found[0].host.as_str(), found[0].port), ("192.168.1.4", 5001));
+
+        assert!(peers.removed(&format!("bb.{SERVICE_TYPE}")));
+        assert!(!peers.removed(&format!("bb.{SERVICE_TYPE}")));
+        assert_eq!(peers.len(), 0);
+    }
+
+    #[test]
+    fn advertised_txt_records_carry_channel() {
+        let config = AppConfig {
+            channel: "design-team".into(),
+            discovery_service: "_clipgroup._tcp".into(),
+            discovery_instance: Some("studio-mac".into()),
+            ..AppConfig::default()
+        };
+        let info = service_info(&config, [7; 16], 5000).unwrap();
+        assert_eq!(info.get_type(), "_clipgroup._tcp.local.");
+        assert_eq!(info.get_fullname(), "studio-mac._clipgroup._tcp.local.");
+        assert_eq!(info.get_property_val_str(CHANNEL_PROPERTY), Some("design-team"));
+        assert_eq!(
+            info.get_property_val_str(ID_PROPERTY),
+            Some(hex::encode([7u8; 16]).as_str())
+        );
+
+        // 缺省实例名为实例 ID
+        let info = service_info(&AppConfig::default(), [7; 16], 5000).unwrap();
+        assert_eq!(info.get_fullname(), format!("{}.{SERVICE_TYPE}", hex::encode([7u8; 16])));
+        assert_eq!(info.get_property_val_str(CHANNEL_PROPERTY), Some(""));
+    }
+
+    #[test]
+    fn discovered_peers_are_filtered_by_channel() {
+        let mut peers = DiscoveredPeers::new("aa".into(), "ops".into());
+        assert!(!peers.resolved(&service("bb", Some("design"), "192.168.1.3", 5001)));
+        assert!(!peers.resolved(&service("cc", None, "192.168.1.4", 5001)));
+        assert!(peers.resolved(&service("dd", Some("ops"), "192.168.1.5", 5001)));
+        assert_eq!(peers.len(), 1);
+        // 已发现的对端改换频道后被移除
+        assert!(peers.resolved(&service("dd", Some("design"), "192.168.1.5", 5001)));
+        assert_eq!(peers.len(), 0);
+
+        // 默认频道接受未携带频道记录的旧版本
+        let mut peers = DiscoveredPeers::new("aa".into(), String::new());
+        assert!(peers.resolved(&service("cc", None, "192.168.1.4", 5001)));
+        assert!(!peers.resolved(&service("bb", Some("design"), "192.168.1.3", 5001)));
+    }
+
+    async fn sees(rx: &mut mpsc::Receiver<Vec<PeerConfig>>, port: u16) -> Vec<PeerConfig> {
+        loop {
+            let peers = rx.recv().await.unwrap();
+            if peers.iter().any(|p| p.port == port) {
+                return peers;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn two_instances_discover_each_other() {
+        let config = AppConfig::default();
+        let (tx_a, mut rx_a) = mpsc::channel(8);
+        let (tx_b, mut rx_b) = mpsc::channel(8);
+        let _a = Discovery::start(&config, [1; 16], 47001, tx_a).unwrap();
+        let _b = Discovery::start(&config, [2; 16], 47002, tx_b).unwrap();
+        let seen_by_a = tokio::time::timeout(Duration::from_secs(10), sees(&mut rx_a, 47002))
+            .await
+            .expect("a did not discover b");
+        let seen_by_b = tokio::time::timeout(Duration::from_secs(10), sees(&mut rx_b, 47001))
+            .await
+            .expect("b did not discover a");
+        assert!(seen_by_a.iter().all(|p| p.port != 47001));
+        assert!(seen_by_b.iter().all(|p| p.port != 47002));
+    }
+}
@@ -0,0 +1,166 @@
+//! 展示窗口：展示模式（`clipboard_backend = "display"`）下以全窗口显示最近收到的文本或图片，
+//! 适合会议室共享屏幕等只接收、不复制的设备。
+
+use crate::clipboard::{ClipboardFile, ClipboardItem, DisplaySink, MIME_PLAIN_TEXT, MIME_PNG};
+use crate::config_ui::{native_options, setup_chinese_font};
+use eframe::egui;
+use std::path::Path;
+use std::time::Duration;
+
+/// 窗口检查新内容的间隔
+const REFRESH_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 收到的内容在窗口中的呈现方式。
+#[derive(Debug, PartialEq)]
+enum View {
+    Waiting,
+    Text(String),
+    /// 解码后的 RGBA 像素：(宽, 高, 数据)
+    Image(usize, usize, Vec<u8>),
+    Files(Vec<String>),
+    Unsupported,
+}
+
+impl View {
+    fn for_item(item: Option<ClipboardItem>) -> Self {
+        match item {
+            None => View::Waiting,
+            Some(ClipboardItem::Text(text) | ClipboardItem::Url(text)) => View::Text(text),
+            Some(ClipboardItem::Image(png)) => decode_png(&png),
+            Some(ClipboardItem::Files(files)) => {
+                let name = |file: ClipboardFile| match Path::new(&file.path).file_name() {
+                    Some(name) => name.to_string_lossy().into_owned(),
+                    None => file.path,
+                };
+                View::Files(files.into_iter().map(name).collect())
+            }
+            // 多格式内容优先显示纯文本，其次显示 PNG
+            Some(ClipboardItem::Multi(formats)) => {
+                let find = |mime: &str| formats.iter().find(|(m, _)| m == mime).map(|(_, d)| d);
+                if let Some(text) = find(MIME_PLAIN_TEXT) {
+                    View::Text(String::from_utf8_lossy(text).into_owned())
+                } else if let Some(png) = find(MIME_PNG) {
+                    decode_png(png)
+                } else {
+                    View::Unsupported
+                }
+            }
+        }
+    }
+}
+
+fn decode_png(png: &[u8]) -> View {
+    match image::load_from_memory(png) {
+        Ok(img) => {
+            let rgba = img.to_rgba8();
+            let (w, h) = (rgba.width() as usize, rgba.height() as usize);
+            View::Image(w, h, rgba.into_raw())
+        }
+        Err(e) => {
+            tracing::warn!("failed to decode received image: {}", e);
+            View::Unsupported
+        }
+    }
+}
+
+/// 展示窗口应用。
+struct DisplayApp {
+    sink: DisplaySink,
+    version: u64,
+    view: View,
+    texture: Option<egui::TextureHandle>,
+}
+
+impl DisplayApp {
+    fn new(sink: DisplaySink) -> Self {
+        Self {
+            sink,
+            version: 0,
+            view: View::Waiting,
+            texture: None,
+        }
+    }
+
+    /// 内容版本变化时重新生成呈现内容与图片纹理。
+    fn refresh(&mut self, ctx: &egui::Context) {
+        let version = self.sink.version();
+        if version == self.version {
+            return;
+        }
+        self.version = version;
+        self.view = View::for_item(self.sink.get());
+        self.texture = match &self.view {
+            View::Image(w, h, rgba) => {
+                let image = egui::ColorImage::from_rgba_unmultiplied([*w, *h], rgba);
+                Some(ctx.load_texture("received", image, egui::TextureOptions::LINEAR))
+            }
+            _ => None,
+        };
+    }
+}
+
+impl eframe::App for DisplayApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.refresh(ctx);
+        egui::CentralPanel::default().show(ctx, |ui| match (&self.view, &self.texture) {
+            (View::Waiting, _) => {
+                ui.centered_and_justified(|ui| ui.heading("等待接收内容…"));
+            }
+            (View::Text(text), _) => {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.label(egui::RichText::new(text).size(28.0));
+                });
+            }
+            (View::Image(..), Some(texture)) => {
+                ui.centered_and_justified(|ui| {
+                    ui.add(egui::Image::new(texture).shrink_to_fit());
+                });
+            }
+            (View::Files(names), _) => {
+                ui.heading(format!("收到 {} 个文件", names.len()));
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for name in names {
+                        ui.label(egui::RichText::new(name).size(22.0));
+                    }
+                });
+            }
+            (View::Image(..) | View::Unsupported, _) => {
+                ui.centered_and_justified(|ui| ui.heading("收到的内容无法显示"));
+            }
+        });
+        ctx.request_repaint_after(REFRESH_INTERVAL);
+    }
+}
+
+/// 运行展示窗口，直到窗口关闭。
+pub fn run(sink: DisplaySink) {
+    let options = native_options([960.0, 640.0], "LAN 剪贴板同步 - 展示");
+
+    let _ = eframe::run_native(
+        "LAN Clipboard Sync Display",
+        options,
+        Box::new(move |cc| {
+            setup_chinese_font(&cc.egui_ctx);
+            Ok(Box::new(DisplayApp::new(sink)))
+        }),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multi_format_prefers_plain_text() {
+        let formats = vec![
+            ("text/html".to_string(), b"<b>hi</b>".to_vec()),
+            (MIME_PLAIN_TEXT.to_string(), b"hi".to_vec()),
+        ];
+        assert_eq!(
+            View::for_item(Some(ClipboardItem::Multi(formats))),
+            View::Text("hi".to_string())
+        );
+        assert_eq!(View::for_item(None), View::Waiting);
+        assert_eq!(View::for_item(Some(ClipboardItem::Image(vec![1, 2]))), View::Unsupported);
+    }
+}
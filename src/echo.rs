@@ -0,0 +1,155 @@
+//! 回显测试：向指定对端发送带时间戳的回显请求，对端立即原样回复，
+//! 用于测量两台设备间经由网络层（握手、加密、分帧）的往返延迟，不涉及剪贴板。
+
+use anyhow::{anyhow, Result};
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+use crate::config::AppConfig;
+use crate::keyring::KeyRing;
+use crate::network::{send_to_peer, NetworkServer};
+use crate::protocol::ProtocolMessage;
+
+/// 等待回显回复的最长时间
+const ECHO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 当前时间（Unix 纪元起的微秒数）。
+fn now_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+/// 由回复中带回的时间戳计算往返延迟。
+pub fn rtt_since(timestamp_us: u64, now_us: u64) -> Duration {
+    Duration::from_micros(now_us.saturating_sub(timestamp_us))
+}
+
+/// 构造回显回复：原样带回请求中的 echo_id 与时间戳。
+pub(crate) fn echo_reply(own_id: [u8; 16], echo_id: u64, timestamp_us: u64) -> ProtocolMessage {
+    ProtocolMessage::EchoReply {
+        sender_id: own_id,
+        echo_id,
+        timestamp_us,
+    }
+}
+
+/// 向对端发送一次回显请求并等待回复，返回往返延迟。
+///
+/// 在系统分配的临时端口上启动监听接收回复，因此可与正在运行的同步服务同时使用。
+pub async fn run_echo(config: &AppConfig, peer: SocketAddr) -> Result<Duration> {
+    let mut local = AppConfig {
+        listen_port: 0,
+        ..config.clone()
+    };
+    let (tx, mut rx) = mpsc::channel(4);
+    let keys = KeyRing::from_config(&local)?;
+    let server = NetworkServer::with_keyring(&local, keys, tx)?;
+    let listener = server.bind().await?;
+    local.listen_port = server
+        .local_port()
+        .ok_or_else(|| anyhow!("listener has no bound port"))?;
+    let server_task = tokio::spawn(server.serve(listener));
+    let result = echo_roundtrip(&local, peer, &mut rx).await;
+    server_task.abort();
+    result
+}
+
+async fn echo_roundtrip(
+    config: &AppConfig,
+    peer: SocketAddr,
+    rx: &mut mpsc::Receiver<crate::network::IncomingMessage>,
+) -> Result<Duration> {
+    let echo_id: u64 = rand::random();
+    let timestamp_us = now_us();
+    let request = ProtocolMessage::EchoRequest {
+        sender_id: *uuid::Uuid::new_v4().as_bytes(),
+        echo_id,
+        timestamp_us,
+        reply_port: config.listen_port,
+    };
+    let sent = send_to_peer(config, peer, &request).await?;
+    if !sent.is_ok() {
+        return Err(anyhow!("send to {} failed: {:?}", peer, sent.outcome));
+    }
+
+    tokio::time::timeout(ECHO_TIMEOUT, async {
+        while let Some(incoming) = rx.recv().await {
+            if let ProtocolMessage::EchoReply {
+                echo_id: id,
+                timestamp_us: ts,
+                ..
+            } = incoming.msg
+            {
+                if id != echo_id {
+                    continue;
+                }
+                if ts != timestamp_us {
+                    return Err(anyhow!("echo reply carries a different timestamp"));
+                }
+                return Ok(rtt_since(ts, now_us()));
+            }
+        }
+        Err(anyhow!("listener stopped before the echo reply arrived"))
+    })
+    .await
+    .map_err(|_| anyhow!("timed out waiting for echo reply from {}", peer))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{decode_message, encode_message};
+
+    #[tokio::test]
+    async fn echo_reply_carries_timestamp_back() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = AppConfig {
+            listen_port: port,
+            ..AppConfig::default()
+        };
+
+        // 最小回显端：只回复回显请求
+        let (tx, mut rx) = mpsc::channel(4);
+        let keys = KeyRing::from_config(&config).unwrap();
+        let server = NetworkServer::with_keyring(&config, keys, tx).unwrap();
+        tokio::spawn(server.run());
+        let responder_config = config.clone();
+        tokio::spawn(async move {
+            while let Some(incoming) = rx.recv().await {
+                if let ProtocolMessage::EchoRequest {
+                    echo_id,
+                    timestamp_us,
+                    reply_port,
+                    ..
+                } = incoming.msg
+                {
+                    let reply = echo_reply([5u8; 16], echo_id, timestamp_us);
+                    let addr = SocketAddr::new(incoming.from.ip(), reply_port);
+                    send_to_peer(&responder_config, addr, &reply).await.unwrap();
+                }
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let peer: SocketAddr = format!("127.0.0.1:{port}").parse().unwrap();
+        let rtt = run_echo(&config, peer).await.unwrap();
+        assert!(rtt < ECHO_TIMEOUT);
+
+        // 回复经编码/解码后仍带回原始时间戳
+        let reply = echo_reply([5u8; 16], 7, 42);
+        let decoded = decode_message(&encode_message(&reply).unwrap()).unwrap();
+        assert!(matches!(
+            decoded,
+            ProtocolMessage::EchoReply { echo_id: 7, timestamp_us: 42, .. }
+        ));
+        assert_eq!(rtt_since(1_000, 3_500), Duration::from_micros(2_500));
+        assert_eq!(rtt_since(5_000, 1_000), Duration::ZERO);
+    }
+}
@@ -0,0 +1,143 @@
+//! 同步事件：核心服务每次收到或发出剪贴板内容时发布一条事件，供 `events` 子命令等外部订阅者使用。
+
+use std::net::IpAddr;
+
+use serde::Serialize;
+
+use crate::protocol::ContentType;
+
+/// 事件方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventDirection {
+    Inbound,
+    Outbound,
+}
+
+/// 传输状态：对端开始或结束发送大负载（见 [`ProtocolMessage::TransferStart`]）
+///
+/// [`ProtocolMessage::TransferStart`]: crate::protocol::ProtocolMessage::TransferStart
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferPhase {
+    Start,
+    End,
+}
+
+/// 一条同步事件，序列化为单行 JSON 输出。
+#[derive(Debug, Clone, Serialize)]
+pub struct CoreEvent {
+    pub direction: EventDirection,
+    /// 内容类型：`text`、`url`、`image`、`files` 或 `rich_text`
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    /// 负载字节数（增量文本为还原后的全文长度）
+    pub size: u64,
+    /// 发送端地址，仅收到的事件携带
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sender: Option<IpAddr>,
+    /// 成功送达的对端数与对端总数，仅发出的事件携带
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delivered: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peers: Option<usize>,
+    /// 传输状态，仅状态事件携带；此时 `size` 为对端宣布的负载字节数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transfer: Option<TransferPhase>,
+    /// 本地时间，RFC 3339 格式
+    pub timestamp: String,
+}
+
+impl CoreEvent {
+    /// 收到来自 `sender` 的内容。
+    pub fn inbound(content_type: ContentType, size: u64, sender: IpAddr) -> Self {
+        Self {
+            direction: EventDirection::Inbound,
+            kind: kind_name(content_type),
+            size,
+            sender: Some(sender),
+            delivered: None,
+            peers: None,
+            transfer: None,
+            timestamp: chrono::Local::now().to_rfc3339(),
+        }
+    }
+
+    /// 向 `peers` 个对端发出内容，其中 `delivered` 个送达。
+    pub fn outbound(content_type: ContentType, size: u64, delivered: usize, peers: usize) -> Self {
+        Self {
+            direction: EventDirection::Outbound,
+            kind: kind_name(content_type),
+            size,
+            sender: None,
+            delivered: Some(delivered),
+            peers: Some(peers),
+            transfer: None,
+            timestamp: chrono::Local::now().to_rfc3339(),
+        }
+    }
+
+    /// `sender` 开始或结束发送 `size` 字节的内容。
+    pub fn transfer(
+        phase: TransferPhase,
+        content_type: ContentType,
+        size: u64,
+        sender: IpAddr,
+    ) -> Self {
+        Self {
+            transfer: Some(phase),
+            ..Self::inbound(content_type, size, sender)
+        }
+    }
+
+    /// 序列化为一行 JSON（不含换行符）。
+    pub fn to_json_line(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+fn kind_name(content_type: ContentType) -> &'static str {
+    match content_type {
+        ContentType::Text | ContentType::TextDelta => "text",
+        ContentType::Url => "url",
+        ContentType::Image => "image",
+        ContentType::Files => "files",
+        ContentType::Multi => "rich_text",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_serializes_to_one_json_line() {
+        let event = CoreEvent::inbound(ContentType::TextDelta, 12, "10.0.0.2".parse().unwrap());
+        let line = event.to_json_line();
+        assert!(!line.contains('\n'));
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["direction"], "inbound");
+        assert_eq!(value["type"], "text");
+        assert_eq!(value["size"], 12);
+        assert_eq!(value["sender"], "10.0.0.2");
+        assert!(value.get("peers").is_none());
+
+        let value: serde_json::Value =
+            serde_json::from_str(&CoreEvent::outbound(ContentType::Image, 3, 1, 2).to_json_line())
+                .unwrap();
+        assert_eq!(value["direction"], "outbound");
+        assert_eq!(value["delivered"], 1);
+        assert!(value.get("sender").is_none());
+        assert!(value.get("transfer").is_none());
+
+        let event = CoreEvent::transfer(
+            TransferPhase::Start,
+            ContentType::Image,
+            1 << 20,
+            "10.0.0.3".parse().unwrap(),
+        );
+        let value: serde_json::Value = serde_json::from_str(&event.to_json_line()).unwrap();
+        assert_eq!(value["transfer"], "start");
+        assert_eq!(value["type"], "image");
+    }
+}
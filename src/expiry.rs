@@ -0,0 +1,96 @@
+//! 收到内容的过期清理：发送端按内容类型为每条更新附带保留时长（`text_ttl` / `image_ttl` /
+//! `files_ttl`），接收端到期后删除收到的文件，并在剪贴板仍是该内容时清空剪贴板。
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::config::AppConfig;
+use crate::protocol::ContentType;
+
+/// 发出 `content_type` 类型的内容时附带的保留秒数，0 表示不过期。
+pub fn ttl_secs(config: &AppConfig, content_type: ContentType) -> u64 {
+    match content_type {
+        ContentType::Text | ContentType::TextDelta | ContentType::Url | ContentType::Multi => {
+            config.text_ttl
+        }
+        ContentType::Image => config.image_ttl,
+        ContentType::Files => config.files_ttl,
+    }
+}
+
+/// 一条等待过期的已接收内容。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expiring {
+    pub deadline: Instant,
+    /// 写入剪贴板的内容哈希，到期时剪贴板仍是该内容才清空
+    pub hash: Option<u64>,
+    /// 到期时删除的已接收文件
+    pub files: Vec<PathBuf>,
+}
+
+/// 按到期时刻排列的待清理内容。
+#[derive(Debug, Default)]
+pub struct ExpiryQueue {
+    entries: Vec<Expiring>,
+}
+
+impl ExpiryQueue {
+    /// 安排 `ttl` 之后清理一条内容。
+    pub fn schedule(&mut self, now: Instant, ttl: Duration, hash: Option<u64>, files: Vec<PathBuf>) {
+        // 远超进程寿命的保留时长视为永不过期
+        let Some(deadline) = now.checked_add(ttl) else {
+            return;
+        };
+        let index = self.entries.partition_point(|e| e.deadline <= deadline);
+        self.entries.insert(index, Expiring { deadline, hash, files });
+    }
+
+    /// 最早的到期时刻，队列为空时返回 None。
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.entries.first().map(|e| e.deadline)
+    }
+
+    /// 取出 `now` 之前（含）到期的全部内容。
+    pub fn take_expired(&mut self, now: Instant) -> Vec<Expiring> {
+        let count = self.entries.partition_point(|e| e.deadline <= now);
+        self.entries.drain(..count).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_content_type_uses_its_ttl() {
+        let config = AppConfig {
+            text_ttl: 10,
+            image_ttl: 0,
+            files_ttl: 3600,
+            ..AppConfig::default()
+        };
+        assert_eq!(ttl_secs(&config, ContentType::Text), 10);
+        assert_eq!(ttl_secs(&config, ContentType::TextDelta), 10);
+        assert_eq!(ttl_secs(&config, ContentType::Url), 10);
+        assert_eq!(ttl_secs(&config, ContentType::Multi), 10);
+        assert_eq!(ttl_secs(&config, ContentType::Image), 0);
+        assert_eq!(ttl_secs(&config, ContentType::Files), 3600);
+    }
+
+    #[test]
+    fn entries_expire_in_deadline_order() {
+        let start = Instant::now();
+        let mut queue = ExpiryQueue::default();
+        queue.schedule(start, Duration::from_secs(60), Some(2), Vec::new());
+        queue.schedule(start, Duration::from_secs(10), Some(1), vec!["a.txt".into()]);
+        assert_eq!(queue.next_deadline(), Some(start + Duration::from_secs(10)));
+
+        assert!(queue.take_expired(start + Duration::from_secs(5)).is_empty());
+        let expired = queue.take_expired(start + Duration::from_secs(10));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].files, vec![PathBuf::from("a.txt")]);
+        assert_eq!(queue.next_deadline(), Some(start + Duration::from_secs(60)));
+        assert_eq!(queue.take_expired(start + Duration::from_secs(61))[0].hash, Some(2));
+        assert_eq!(queue.next_deadline(), None);
+    }
+}
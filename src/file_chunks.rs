@@ -0,0 +1,320 @@
+//! 大文件分块传输：发送端把一组文件按固定大小切块，逐块从磁盘读出发送，不在内存中保留整个文件；
+//! 接收端把每块按偏移直接写入暂存目录中的对应文件，收齐全部块后再移入下载目录。
+//!
+//! 块可以乱序到达。超过 [`FILE_CHUNK_TIMEOUT`] 没有新块到达的传输视为中断（缺块或发送端下线），
+//! 连同暂存文件一起丢弃。
+
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// 超过该时长没有新块到达的传输视为中断并丢弃
+pub const FILE_CHUNK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 待分块发送的文件。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutgoingFile {
+    pub name: String,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// 一块在传输中的位置：所属文件的下标、文件内偏移与长度。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkSpec {
+    pub file_index: u32,
+    pub offset: u64,
+    pub len: usize,
+}
+
+/// 按块大小切分一组文件，块的序号即其在返回列表中的下标。空文件也占一块（数据为空），
+/// 接收端才能得知它的存在。
+pub fn plan_chunks(files: &[OutgoingFile], chunk_size: usize) -> Vec<ChunkSpec> {
+    let chunk_size = chunk_size.max(1) as u64;
+    let mut chunks = Vec::new();
+    for (index, file) in files.iter().enumerate() {
+        let mut offset = 0;
+        loop {
+            let len = (file.size - offset).min(chunk_size);
+            chunks.push(ChunkSpec {
+                file_index: index as u32,
+                offset,
+                len: len as usize,
+            });
+            offset += len;
+            if offset >= file.size {
+                break;
+            }
+        }
+    }
+    chunks
+}
+
+/// 从磁盘读出一块。发送期间文件被截断时报错。
+pub fn read_chunk(file: &OutgoingFile, spec: ChunkSpec) -> Result<Vec<u8>> {
+    let mut f = std::fs::File::open(&file.path)?;
+    f.seek(SeekFrom::Start(spec.offset))?;
+    let mut data = vec![0u8; spec.len];
+    f.read_exact(&mut data)
+        .map_err(|e| anyhow!("failed to read {} at offset {}: {}", file.name, spec.offset, e))?;
+    Ok(data)
+}
+
+/// 收到的一块（[`crate::protocol::ProtocolMessage::FileChunk`] 的内容）。
+#[derive(Debug)]
+pub struct IncomingChunk<'a> {
+    pub seq: u32,
+    pub total: u32,
+    pub file_index: u32,
+    pub name: &'a str,
+    pub file_size: u64,
+    pub offset: u64,
+    pub data: &'a [u8],
+}
+
+/// 收齐的一个文件：发送端的文件名与暂存路径。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StagedFile {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// 已收齐的传输：暂存文件按下标排序。drop 时删除暂存目录，调用方须先把文件移走。
+#[derive(Debug)]
+pub struct CompletedTransfer {
+    dir: PathBuf,
+    pub files: Vec<StagedFile>,
+    pub size: u64,
+}
+
+impl Drop for CompletedTransfer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// 接收中的一个分块传输。
+#[derive(Debug)]
+struct IncomingTransfer {
+    from: IpAddr,
+    dir: PathBuf,
+    total: u32,
+    received: BTreeSet<u32>,
+    /// 文件下标 → (文件名, 文件大小, 已收到的字节数)
+    files: BTreeMap<u32, (String, u64, u64)>,
+    last_chunk: Instant,
+}
+
+/// 接收端的分块传输状态，按发送者与传输 ID 索引；暂存文件位于 `dir` 下每个传输一个子目录。
+#[derive(Debug)]
+pub struct IncomingFileChunks {
+    dir: PathBuf,
+    max_file_size: u64,
+    transfers: HashMap<([u8; 16], u64), IncomingTransfer>,
+}
+
+impl IncomingFileChunks {
+    pub fn new(dir: PathBuf, max_file_size: u64) -> Self {
+        Self {
+            dir,
+            max_file_size,
+            transfers: HashMap::new(),
+        }
+    }
+
+    /// 写入来自 `from` 的一块；收齐全部块后返回完成的传输，重复的块直接忽略。
+    /// 同时丢弃已超时的其他传输。
+    pub fn add(
+        &mut self,
+        sender_id: [u8; 16],
+        transfer_id: u64,
+        from: IpAddr,
+        chunk: &IncomingChunk,
+        now: Instant,
+    ) -> Result<Option<CompletedTransfer>> {
+        self.expire(now);
+        if chunk.seq >= chunk.total {
+            return Err(anyhow!("chunk {} out of range of {}", chunk.seq, chunk.total));
+        }
+        if chunk.file_size > self.max_file_size {
+            return Err(anyhow!("file {} larger than max_file_size", chunk.name));
+        }
+        chunk
+            .offset
+            .checked_add(chunk.data.len() as u64)
+            .filter(|end| *end <= chunk.file_size)
+            .ok_or_else(|| anyhow!("chunk at offset {} exceeds file size", chunk.offset))?;
+
+        let key = (sender_id, transfer_id);
+        let transfer = match self.transfers.entry(key) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                let dir = self
+                    .dir
+                    .join(format!("{}-{}", hex::encode(sender_id), transfer_id));
+                std::fs::create_dir_all(&dir)?;
+                entry.insert(IncomingTransfer {
+                    from,
+                    dir,
+                    total: chunk.total,
+                    received: BTreeSet::new(),
+                    files: BTreeMap::new(),
+                    last_chunk: now,
+                })
+            }
+        };
+        if transfer.from != from || transfer.total != chunk.total {
+            return Err(anyhow!("chunk does not match transfer {}", transfer_id));
+        }
+        transfer.last_chunk = now;
+        if transfer.received.contains(&chunk.seq) {
+            return Ok(None);
+        }
+        let (name, size, received) = transfer
+            .files
+            .entry(chunk.file_index)
+            .or_insert_with(|| (chunk.name.to_string(), chunk.file_size, 0));
+        if name != chunk.name || *size != chunk.file_size {
+            return Err(anyhow!("chunk does not match file {}", chunk.file_index));
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(transfer.dir.join(chunk.file_index.to_string()))?;
+        file.seek(SeekFrom::Start(chunk.offset))?;
+        file.write_all(chunk.data)?;
+        *received += chunk.data.len() as u64;
+        transfer.received.insert(chunk.seq);
+
+        if transfer.received.len() < transfer.total as usize {
+            return Ok(None);
+        }
+        let Some(transfer) = self.transfers.remove(&key) else {
+            return Ok(None);
+        };
+        let mut completed = CompletedTransfer {
+            dir: transfer.dir,
+            files: Vec::with_capacity(transfer.files.len()),
+            size: 0,
+        };
+        for (index, (name, size, received)) in transfer.files {
+            if received != size {
+                return Err(anyhow!("file {} incomplete: {} of {} bytes", name, received, size));
+            }
+            completed.size += size;
+            completed.files.push(StagedFile {
+                name,
+                path: completed.dir.join(index.to_string()),
+            });
+        }
+        Ok(Some(completed))
+    }
+
+    /// 丢弃超过 [`FILE_CHUNK_TIMEOUT`] 没有新块到达的传输及其暂存文件，返回丢弃数量。
+    pub fn expire(&mut self, now: Instant) -> usize {
+        let before = self.transfers.len();
+        self.transfers.retain(|(_, transfer_id), transfer| {
+            let alive = now.saturating_duration_since(transfer.last_chunk) <= FILE_CHUNK_TIMEOUT;
+            if !alive {
+                tracing::warn!(
+                    "dropping incomplete file transfer {}: {} of {} chunk(s) received",
+                    transfer_id,
+                    transfer.received.len(),
+                    transfer.total
+                );
+                let _ = std::fs::remove_dir_all(&transfer.dir);
+            }
+            alive
+        });
+        before - self.transfers.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const FROM: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    fn outgoing(dir: &std::path::Path, name: &str, content: &[u8]) -> OutgoingFile {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        OutgoingFile {
+            name: name.into(),
+            path,
+            size: content.len() as u64,
+        }
+    }
+
+    #[test]
+    fn out_of_order_chunks_reassemble_on_disk() {
+        let src = tempfile::tempdir().unwrap();
+        let big: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+        let files = [outgoing(src.path(), "big.bin", &big), outgoing(src.path(), "empty", b"")];
+        let plan = plan_chunks(&files, 4096);
+        assert_eq!(plan.len(), 4);
+        assert_eq!(plan[2], ChunkSpec { file_index: 0, offset: 8192, len: 1808 });
+
+        let staging = tempfile::tempdir().unwrap();
+        let mut incoming = IncomingFileChunks::new(staging.path().to_path_buf(), u64::MAX);
+        let now = Instant::now();
+        let mut completed = None;
+        for seq in [3usize, 1, 0, 1, 2] {
+            let spec = plan[seq];
+            let file = &files[spec.file_index as usize];
+            let data = read_chunk(file, spec).unwrap();
+            let chunk = IncomingChunk {
+                seq: seq as u32,
+                total: plan.len() as u32,
+                file_index: spec.file_index,
+                name: &file.name,
+                file_size: file.size,
+                offset: spec.offset,
+                data: &data,
+            };
+            assert!(completed.is_none());
+            completed = incoming.add([1; 16], 7, FROM, &chunk, now).unwrap();
+        }
+        let completed = completed.expect("all chunks received");
+        assert_eq!(completed.size, big.len() as u64);
+        let names: Vec<&str> = completed.files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["big.bin", "empty"]);
+        assert_eq!(std::fs::read(&completed.files[0].path).unwrap(), big);
+        assert!(std::fs::read(&completed.files[1].path).unwrap().is_empty());
+        drop(completed);
+        assert_eq!(std::fs::read_dir(staging.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn incomplete_transfer_times_out() {
+        let staging = tempfile::tempdir().unwrap();
+        let mut incoming = IncomingFileChunks::new(staging.path().to_path_buf(), 1024);
+        let chunk = |seq: u32, offset: u64| IncomingChunk {
+            seq,
+            total: 2,
+            file_index: 0,
+            name: "a.txt",
+            file_size: 8,
+            offset,
+            data: b"abcd",
+        };
+        let start = Instant::now();
+        assert!(incoming.add([2; 16], 1, FROM, &chunk(0, 0), start).unwrap().is_none());
+        // 超出文件大小与来自其他地址的块被拒绝
+        assert!(incoming.add([2; 16], 1, FROM, &chunk(1, 6), start).is_err());
+        let other = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 9));
+        assert!(incoming.add([2; 16], 1, other, &chunk(1, 4), start).is_err());
+
+        assert_eq!(incoming.expire(start + FILE_CHUNK_TIMEOUT), 0);
+        let late = start + FILE_CHUNK_TIMEOUT + Duration::from_secs(1);
+        assert_eq!(incoming.expire(late), 1);
+        assert_eq!(std::fs::read_dir(staging.path()).unwrap().count(), 0);
+        // 超时后迟到的块重新开始一个传输，不会凑成完整文件
+        assert!(incoming.add([2; 16], 1, FROM, &chunk(1, 4), late).unwrap().is_none());
+    }
+}
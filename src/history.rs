@@ -0,0 +1,191 @@
+//! 剪贴板历史：在内存中保留最近收到的若干条远端剪贴板记录，供通知与托盘展示。
+//! 开启 `persist_history` 时文本记录同时保存到数据目录，重启后恢复。
+
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+/// 默认保留的历史条数
+pub const DEFAULT_HISTORY_SIZE: usize = 20;
+
+/// 单条历史记录。
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub received_at: chrono::DateTime<chrono::Local>,
+    pub from: IpAddr,
+    /// 简要描述（如文本开头、图片尺寸、文件名）
+    pub summary: String,
+    /// 图片缩略图（PNG），非图片内容为 None
+    pub thumbnail: Option<Vec<u8>>,
+    /// 发送端报告的来源应用名
+    pub source_app: Option<String>,
+    /// 是否允许写入持久化历史：仅文本内容，且发送端未标记「勿同步」提示
+    pub persist: bool,
+}
+
+/// 持久化历史文件中的一条记录（只保存文本摘要，不含缩略图）。
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredEntry {
+    /// RFC 3339 格式的接收时间
+    received_at: String,
+    from: IpAddr,
+    summary: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source_app: Option<String>,
+}
+
+/// 剪贴板历史（可跨线程共享，克隆后指向同一份数据）。
+#[derive(Debug, Clone)]
+pub struct ClipboardHistory {
+    inner: Arc<Mutex<VecDeque<HistoryEntry>>>,
+    capacity: usize,
+    /// 持久化文件路径，未开启持久化时为 None
+    file: Option<PathBuf>,
+}
+
+impl Default for ClipboardHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_SIZE)
+    }
+}
+
+impl ClipboardHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+            file: None,
+        }
+    }
+
+    /// 创建持久化到 `file` 的历史，并载入文件中已保存的记录（文件不存在或损坏时从空历史开始）。
+    pub fn persistent(capacity: usize, file: PathBuf) -> Self {
+        let mut entries = match load_entries(&file) {
+            Ok(entries) => entries,
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    tracing::warn!("failed to load history from {}: {}", file.display(), e);
+                }
+                VecDeque::new()
+            }
+        };
+        while entries.len() > capacity {
+            entries.pop_front();
+        }
+        Self {
+            inner: Arc::new(Mutex::new(entries)),
+            capacity,
+            file: Some(file),
+        }
+    }
+
+    /// 追加一条记录，超出容量时丢弃最旧的记录。
+    pub fn push(&self, entry: HistoryEntry) {
+        let mut entries = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if self.capacity == 0 {
+            return;
+        }
+        while entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+        // 不可持久化的记录也可能挤掉已保存的记录，因此每次变化都重写文件
+        if let Some(file) = &self.file {
+            if let Err(e) = save_entries(file, &entries) {
+                tracing::warn!("failed to save history to {}: {}", file.display(), e);
+            }
+        }
+    }
+
+    /// 返回当前历史的快照（最新的在前）。
+    pub fn snapshot(&self) -> Vec<HistoryEntry> {
+        let entries = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        entries.iter().rev().cloned().collect()
+    }
+}
+
+/// 读取持久化历史文件中的记录（最旧的在前）。
+fn load_entries(file: &Path) -> std::io::Result<VecDeque<HistoryEntry>> {
+    let stored: Vec<StoredEntry> = serde_json::from_slice(&std::fs::read(file)?)?;
+    Ok(stored
+        .into_iter()
+        .filter_map(|e| {
+            let received_at = chrono::DateTime::parse_from_rfc3339(&e.received_at).ok()?;
+            Some(HistoryEntry {
+                received_at: received_at.with_timezone(&chrono::Local),
+                from: e.from,
+                summary: e.summary,
+                thumbnail: None,
+                source_app: e.source_app,
+                persist: true,
+            })
+        })
+        .collect())
+}
+
+/// 将可持久化的记录原子地写入历史文件（按需创建所在目录）。
+fn save_entries(file: &Path, entries: &VecDeque<HistoryEntry>) -> std::io::Result<()> {
+    let stored: Vec<StoredEntry> = entries
+        .iter()
+        .filter(|e| e.persist)
+        .map(|e| StoredEntry {
+            received_at: e.received_at.to_rfc3339(),
+            from: e.from,
+            summary: e.summary.clone(),
+            source_app: e.source_app.clone(),
+        })
+        .collect();
+    if let Some(dir) = file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    crate::core::write_atomically(file, &serde_json::to_vec(&stored)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_entry(summary: &str, persist: bool) -> HistoryEntry {
+        HistoryEntry {
+            received_at: chrono::Local::now(),
+            from: IpAddr::from([127, 0, 0, 1]),
+            summary: summary.to_string(),
+            thumbnail: None,
+            source_app: Some("editor".into()),
+            persist,
+        }
+    }
+
+    #[test]
+    fn history_keeps_most_recent_entries() {
+        let history = ClipboardHistory::new(2);
+        for i in 0..3 {
+            history.push(text_entry(&format!("entry {i}"), false));
+        }
+        let entries = history.snapshot();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].summary, "entry 2");
+        assert_eq!(entries[1].summary, "entry 1");
+    }
+
+    #[test]
+    fn persisted_history_survives_restart() {
+        let dir = std::env::temp_dir().join(format!("lcs-history-{}", uuid::Uuid::new_v4()));
+        let file = dir.join("history.json");
+        let history = ClipboardHistory::persistent(2, file.clone());
+        history.push(text_entry("first", true));
+        history.push(text_entry("secret", false));
+        history.push(text_entry("second", true));
+        history.push(text_entry("third", true));
+
+        let restored = ClipboardHistory::persistent(2, file.clone()).snapshot();
+        let summaries: Vec<_> = restored.iter().map(|e| e.summary.as_str()).collect();
+        assert_eq!(summaries, ["third", "second"]);
+        assert_eq!(restored[0].source_app.as_deref(), Some("editor"));
+        assert!(!std::fs::read_to_string(&file).unwrap().contains("secret"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,102 @@
+//! 全局快捷键（`send_hotkey`）：按下后立即把当前选中的文本（Linux 下为 PRIMARY 选区）
+//! 或剪贴板内容发给所有对端，不必先复制。
+//!
+//! Linux 下通过 X11 注册，Wayland 会话中只在 XWayland 窗口获得焦点时生效。
+
+use global_hotkey::hotkey::{HotKey, Modifiers};
+use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState};
+use std::str::FromStr;
+use tokio::sync::mpsc;
+
+/// 解析快捷键字符串，如 `ctrl+shift+KeyS`、`Alt+F9`（不区分大小写，以 `+` 连接，按键在最后）。
+///
+/// 必须至少包含一个修饰键，避免单个按键被全局占用而无法正常输入。
+pub fn parse_hotkey(text: &str) -> Result<HotKey, String> {
+    let hotkey =
+        HotKey::from_str(text.trim()).map_err(|e| format!("invalid send_hotkey {text:?}: {e}"))?;
+    if hotkey.mods.is_empty() || hotkey.mods == Modifiers::SHIFT {
+        return Err(format!(
+            "send_hotkey {text:?} must include ctrl, alt or super as a modifier"
+        ));
+    }
+    Ok(hotkey)
+}
+
+/// 在后台线程注册快捷键，每次按下向 `tx` 发送一次请求；注册失败时记录日志后放弃。
+pub fn spawn_send_hotkey(text: &str, tx: mpsc::Sender<()>) {
+    let hotkey = match parse_hotkey(text) {
+        Ok(hotkey) => hotkey,
+        Err(e) => {
+            tracing::error!("{e}");
+            return;
+        }
+    };
+    let id = hotkey.id();
+    GlobalHotKeyEvent::set_event_handler(Some(move |event: GlobalHotKeyEvent| {
+        if event.id() == id && event.state() == HotKeyState::Pressed {
+            let _ = tx.try_send(());
+        }
+    }));
+    let text = text.to_string();
+    std::thread::spawn(move || {
+        let manager = match GlobalHotKeyManager::new() {
+            Ok(manager) => manager,
+            Err(e) => {
+                tracing::error!("failed to initialize global hotkeys: {e}");
+                return;
+            }
+        };
+        if let Err(e) = manager.register(hotkey) {
+            tracing::error!("failed to register send hotkey {}: {e}", text);
+            return;
+        }
+        tracing::info!("send hotkey {} registered", text);
+        // 快捷键随管理器注销：本线程持有管理器直到进程退出
+        run_message_loop();
+        drop(manager);
+    });
+}
+
+/// Windows 下快捷键消息投递到注册线程，需要在该线程运行消息循环。
+#[cfg(target_os = "windows")]
+fn run_message_loop() {
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        DispatchMessageW, GetMessageW, TranslateMessage, MSG,
+    };
+
+    let mut msg: MSG = unsafe { std::mem::zeroed() };
+    while unsafe { GetMessageW(&mut msg, 0, 0, 0) } > 0 {
+        unsafe {
+            TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+    }
+}
+
+/// X11 下由管理器自己的线程接收按键事件，这里只需保持管理器存活。
+#[cfg(not(target_os = "windows"))]
+fn run_message_loop() {
+    loop {
+        std::thread::park();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use global_hotkey::hotkey::Code;
+
+    #[test]
+    fn parses_modifier_combinations() {
+        let hotkey = parse_hotkey("ctrl+shift+KeyS").unwrap();
+        assert_eq!(hotkey, HotKey::new(Some(Modifiers::CONTROL | Modifiers::SHIFT), Code::KeyS));
+        assert_eq!(parse_hotkey(" Alt+F9 ").unwrap(), HotKey::new(Some(Modifiers::ALT), Code::F9));
+    }
+
+    #[test]
+    fn rejects_malformed_or_unmodified_hotkeys() {
+        for text in ["", "S", "shift+S", "ctrl+", "ctrl+S+Shift", "ctrl+NotAKey"] {
+            assert!(parse_hotkey(text).is_err(), "{text:?}");
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! 密钥环：维护当前主密钥与可接受的密钥集合，支持通过 KeyRotation 消息在整个设备群中轮换密钥。
+//!
+//! 轮换流程：
+//! 1. 发起方用当前主密钥发送 `KeyRotation`，随后自身切换到新密钥；
+//! 2. 接收方校验消息确实由当前主密钥认证（其他可接受的密钥均不行），将新密钥加入可接受集合
+//!    并标记为待确认；
+//! 3. 接收方之后收到由该新密钥认证的消息时，确认轮换并将其切换为主密钥。
+//!
+//! 被换下的旧主密钥只在 [`RETIRED_KEY_GRACE`] 内仍可用于解密尚未切换的对端发来的消息，
+//! 之后即被丢弃，且不会写入配置文件，泄露的旧密钥无法再参与通信或发起轮换。
+
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::config::AppConfig;
+
+/// 密钥 ID 长度
+pub const KEY_ID_LEN: usize = 8;
+/// 旧主密钥被换下后仍可接受的时长，供尚未切换的对端过渡
+pub const RETIRED_KEY_GRACE: Duration = Duration::from_secs(10 * 60);
+
+/// 计算密钥 ID（不泄露密钥本身，可安全地在日志与协议中使用）。
+pub fn key_id(key: &[u8; 32]) -> [u8; KEY_ID_LEN] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"lan-clipboard-sync-key-id");
+    hasher.update(key);
+    let hash = hasher.finalize();
+    let mut id = [0u8; KEY_ID_LEN];
+    id.copy_from_slice(&hash[..KEY_ID_LEN]);
+    id
+}
+
+fn parse_key(hex_key: &str) -> Result<[u8; 32]> {
+    hex::decode(hex_key)?
+        .try_into()
+        .map_err(|_| anyhow!("secret key must be 32 bytes after decoding"))
+}
+
+#[derive(Debug)]
+struct KeyRingInner {
+    primary: [u8; 32],
+    /// 除主密钥外可接受的密钥（配置中的 `accepted_keys`）
+    others: Vec<[u8; 32]>,
+    /// 已通过轮换消息收到、等待确认后切换为主密钥的新密钥
+    pending: Option<[u8; 32]>,
+    /// 被换下的旧主密钥及其停止接受的时刻
+    retired: Option<([u8; 32], Instant)>,
+}
+
+/// 可跨线程共享的密钥环（克隆后指向同一份数据）。
+#[derive(Debug, Clone)]
+pub struct KeyRing {
+    inner: Arc<RwLock<KeyRingInner>>,
+}
+
+impl KeyRing {
+    /// 从配置的 `secret_key` 与 `accepted_keys` 构造密钥环。
+    pub fn from_config(config: &AppConfig) -> Result<Self> {
+        let primary = parse_key(&config.secret_key)?;
+        let mut others = Vec::new();
+        for k in &config.accepted_keys {
+            let key = parse_key(k)?;
+            if key != primary && !others.contains(&key) {
+                others.push(key);
+            }
+        }
+        Ok(Self {
+            inner: Arc::new(RwLock::new(KeyRingInner {
+                primary,
+                others,
+                pending: None,
+                retired: None,
+            })),
+        })
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, KeyRingInner> {
+        self.inner.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, KeyRingInner> {
+        self.inner.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 当前主密钥（用于发送）。
+    pub fn primary(&self) -> [u8; 32] {
+        self.read().primary
+    }
+
+    /// 所有可接受的密钥，主密钥在前（用于入站解密）。
+    pub fn accepted(&self) -> Vec<[u8; 32]> {
+        self.accepted_at(Instant::now())
+    }
+
+    /// 同 [`KeyRing::accepted`]，以 `now` 判断旧主密钥是否已过宽限期。
+    fn accepted_at(&self, now: Instant) -> Vec<[u8; 32]> {
+        let inner = self.read();
+        let mut keys = Vec::with_capacity(3 + inner.others.len());
+        keys.push(inner.primary);
+        keys.extend(inner.others.iter().copied());
+        keys.extend(inner.pending);
+        keys.extend(
+            inner
+                .retired
+                .filter(|(_, until)| now < *until)
+                .map(|(key, _)| key),
+        );
+        keys
+    }
+
+    /// 配置中除主密钥外的可接受密钥（用于持久化到配置，不含待确认与已换下的密钥）。
+    pub fn others(&self) -> Vec<[u8; 32]> {
+        self.read().others.clone()
+    }
+
+    /// 将新密钥设为主密钥，旧主密钥在 [`RETIRED_KEY_GRACE`] 内仍可接受以兼容尚未切换的对端。
+    pub fn set_primary(&self, key: [u8; 32]) {
+        self.set_primary_at(key, Instant::now());
+    }
+
+    fn set_primary_at(&self, key: [u8; 32], now: Instant) {
+        let mut inner = self.write();
+        if inner.primary == key {
+            return;
+        }
+        let old = inner.primary;
+        inner.others.retain(|k| *k != key && *k != old);
+        inner.retired = Some((old, now + RETIRED_KEY_GRACE));
+        inner.primary = key;
+        if inner.pending == Some(key) {
+            inner.pending = None;
+        }
+    }
+
+    /// 处理收到的轮换消息：`auth_key_id` 为实际认证该消息的密钥 ID。
+    ///
+    /// 只有在认证密钥是当前主密钥、且新密钥与其 ID 一致时才接受，返回新密钥 ID；
+    /// 由 `accepted_keys` 或已换下的旧密钥认证的轮换一律拒绝。
+    pub fn apply_rotation(
+        &self,
+        auth_key_id: &[u8; KEY_ID_LEN],
+        new_key_id: &[u8; KEY_ID_LEN],
+        new_key: [u8; 32],
+    ) -> Result<[u8; KEY_ID_LEN]> {
+        if key_id(&self.primary()) != *auth_key_id {
+            return Err(anyhow!("key rotation not authenticated under the primary key"));
+        }
+        if key_id(&new_key) != *new_key_id {
+            return Err(anyhow!("key rotation key id does not match key material"));
+        }
+        if new_key.iter().all(|b| *b == 0) {
+            return Err(anyhow!("key rotation carries an all-zero key"));
+        }
+        let mut inner = self.write();
+        if inner.primary == new_key {
+            return Err(anyhow!("key rotation announces the current primary key"));
+        }
+        inner.pending = Some(new_key);
+        Ok(*new_key_id)
+    }
+
+    /// 收到由 `auth_key_id` 认证的消息后调用：若其为待确认的新密钥，则切换为主密钥并返回它。
+    pub fn confirm(&self, auth_key_id: &[u8; KEY_ID_LEN]) -> Option<[u8; 32]> {
+        let pending = self.read().pending?;
+        if key_id(&pending) != *auth_key_id {
+            return None;
+        }
+        self.set_primary(pending);
+        Some(pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl KeyRing {
+        fn is_accepted_id(&self, id: &[u8; KEY_ID_LEN]) -> bool {
+            self.accepted().iter().any(|k| key_id(k) == *id)
+        }
+    }
+
+    fn ring() -> KeyRing {
+        let config = AppConfig {
+            secret_key: hex::encode([1u8; 32]),
+            ..AppConfig::default()
+        };
+        KeyRing::from_config(&config).unwrap()
+    }
+
+    #[test]
+    fn valid_rotation_is_confirmed() {
+        let ring = ring();
+        let old_id = key_id(&[1u8; 32]);
+        let new_key = [2u8; 32];
+        let new_id = key_id(&new_key);
+
+        ring.apply_rotation(&old_id, &new_id, new_key).unwrap();
+        // 新密钥已可接受，但尚未切换为主密钥
+        assert!(ring.is_accepted_id(&new_id));
+        assert_eq!(ring.primary(), [1u8; 32]);
+
+        // 旧密钥认证的消息不会触发确认
+        assert!(ring.confirm(&old_id).is_none());
+        assert_eq!(ring.confirm(&new_id), Some(new_key));
+        assert_eq!(ring.primary(), new_key);
+        assert!(ring.is_accepted_id(&old_id));
+        // 旧主密钥不写入配置
+        assert!(ring.others().is_empty());
+    }
+
+    #[test]
+    fn retired_key_expires_and_cannot_rotate() {
+        let ring = ring();
+        let old_key = [1u8; 32];
+        let new_key = [2u8; 32];
+        let start = Instant::now();
+        ring.set_primary_at(new_key, start);
+        assert!(ring.accepted_at(start).contains(&old_key));
+
+        // 宽限期内旧密钥仍可解密，但由它认证的轮换被拒绝
+        let attacker_key = [7u8; 32];
+        let err = ring
+            .apply_rotation(&key_id(&old_key), &key_id(&attacker_key), attacker_key)
+            .unwrap_err();
+        assert!(err.to_string().contains("primary"));
+        assert!(!ring.is_accepted_id(&key_id(&attacker_key)));
+
+        assert!(!ring.accepted_at(start + RETIRED_KEY_GRACE).contains(&old_key));
+        assert_eq!(ring.accepted_at(start + RETIRED_KEY_GRACE), vec![new_key]);
+    }
+
+    #[test]
+    fn rotation_under_non_primary_accepted_key_is_rejected() {
+        let config = AppConfig {
+            secret_key: hex::encode([1u8; 32]),
+            accepted_keys: vec![hex::encode([3u8; 32])],
+            ..AppConfig::default()
+        };
+        let ring = KeyRing::from_config(&config).unwrap();
+        let new_key = [2u8; 32];
+        assert!(ring
+            .apply_rotation(&key_id(&[3u8; 32]), &key_id(&new_key), new_key)
+            .is_err());
+        assert!(ring.apply_rotation(&key_id(&[1u8; 32]), &key_id(&new_key), new_key).is_ok());
+    }
+
+    #[test]
+    fn unauthenticated_rotation_is_rejected() {
+        let ring = ring();
+        let new_key = [2u8; 32];
+        let new_id = key_id(&new_key);
+        let unknown_id = key_id(&[9u8; 32]);
+
+        assert!(ring.apply_rotation(&unknown_id, &new_id, new_key).is_err());
+        assert!(!ring.is_accepted_id(&new_id));
+
+        // 密钥 ID 与密钥材料不符
+        let old_id = key_id(&[1u8; 32]);
+        assert!(ring.apply_rotation(&old_id, &unknown_id, new_key).is_err());
+        assert!(!ring.is_accepted_id(&new_id));
+    }
+}
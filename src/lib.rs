@@ -1,16 +1,58 @@
+mod auto_open;
+pub mod chunk;
 mod clipboard;
 mod config;
 #[cfg(any(target_os = "linux", target_os = "windows"))]
 pub mod config_ui;
 mod core;
 mod crypto;
+mod delta;
+mod discovery;
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+pub mod display_ui;
+mod echo;
+mod events;
+mod expiry;
+mod file_chunks;
+mod history;
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+mod hotkey;
+mod keyring;
+mod lww;
+mod manifest;
 mod network;
+mod notify;
+pub mod pairing;
+pub mod presence;
 pub mod protocol;
+mod relay;
+mod remote_session;
+mod resume;
+mod retention;
+mod selftest;
+mod send_file;
+mod srv;
+mod stats;
 #[cfg(any(target_os = "linux", target_os = "windows"))]
 mod tray;
 
-pub use clipboard::{ClipboardFile, ClipboardItem};
-pub use config::{AppConfig, PeerConfig};
+pub use clipboard::{
+    content_fingerprint, ClipboardBackend, ClipboardFile, ClipboardItem, DisplaySink,
+    MemoryClipboard,
+};
+pub use config::{
+    AeadAlgorithm, AppConfig, ClipboardBackendKind, Compression, DisplayBackend, NameCollisionPolicy,
+    NewlinePolicy, PeerConfig, ReceivedFilesFormat, TextTransform, TrayClickAction,
+    UnicodeNormalization,
+};
 pub use core::CoreService;
+pub use echo::run_echo;
+pub use events::{CoreEvent, EventDirection, TransferPhase};
+pub use network::{broadcast_to_peers, PeerSendOutcome, PeerSendResult, TransferProgress};
+pub use relay::run_relay_server;
+pub use selftest::run_selftest;
+pub use send_file::send_files;
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+pub use hotkey::spawn_send_hotkey;
 #[cfg(any(target_os = "linux", target_os = "windows"))]
-pub use tray::{TrayEvent, TrayManager};
+pub use tray::{config_ui_launch, tray_or_headless, ConfigUiLaunch, TrayEvent, TrayManager};
@@ -0,0 +1,72 @@
+//! 剪贴板冲突解决（最后写入者胜出）：每条剪贴板更新携带混合逻辑时钟，
+//! 接收端忽略比最近一次应用或发出的内容更旧的更新，使几乎同时复制的多台设备
+//! 收敛到同一结果，而不是按到达顺序互相覆盖。
+//!
+//! 时钟取 `max(本机毫秒时间, 已知最大时钟 + 1)`：设备时间正常时接近真实时间，
+//! 即使长时间离线也能胜过旧内容；时间偏差时退化为逻辑计数，仍保持单调。
+
+/// 最近一次被接受的写入：时钟相同时按发送者 ID 决定胜负，保证各设备结论一致。
+#[derive(Debug, Default, Clone)]
+pub struct LastWriter {
+    clock: u64,
+    writer: [u8; 16],
+}
+
+impl LastWriter {
+    /// 为本机发出的更新生成时钟，并将其记为最近一次写入。
+    pub fn stamp(&mut self, own_id: [u8; 16], now_ms: u64) -> u64 {
+        self.clock = now_ms.max(self.clock.saturating_add(1));
+        self.writer = own_id;
+        self.clock
+    }
+
+    /// 判断收到的更新是否胜出：胜出时记为最近一次写入并返回 true；
+    /// 更旧的更新返回 false，应当忽略。
+    pub fn observe(&mut self, clock: u64, sender_id: [u8; 16]) -> bool {
+        if (clock, sender_id) < (self.clock, self.writer) {
+            return false;
+        }
+        self.clock = clock;
+        self.writer = sender_id;
+        true
+    }
+}
+
+/// 本机当前时间（Unix 毫秒），时间早于纪元时为 0。
+pub fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn older_update_is_ignored_and_newer_wins() {
+        let mut last = LastWriter::default();
+        let own = id(1);
+        let sent = last.stamp(own, 1_000);
+        assert_eq!(sent, 1_000);
+        assert!(!last.observe(999, id(2)));
+        assert!(last.observe(1_001, id(2)));
+        // 本机时钟落后时继续按逻辑计数递增，新内容仍然胜出
+        assert_eq!(last.stamp(own, 500), 1_002);
+    }
+
+    #[test]
+    fn equal_clocks_are_broken_by_sender_id() {
+        let mut a = LastWriter::default();
+        let mut b = LastWriter::default();
+        let clock_a = a.stamp(id(1), 5_000);
+        let clock_b = b.stamp(id(2), 5_000);
+        // 两台设备同时复制：双方都采用 ID 较大的一方的内容
+        assert!(a.observe(clock_b, id(2)));
+        assert!(!b.observe(clock_a, id(1)));
+    }
+
+    fn id(n: u8) -> [u8; 16] {
+        [n; 16]
+    }
+}
@@ -1,13 +1,20 @@
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
 use std::path::PathBuf;
 
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use tracing_subscriber::EnvFilter;
 
-use lan_clipboard_sync::{AppConfig, CoreService};
+use lan_clipboard_sync::pairing::PairingInfo;
+use lan_clipboard_sync::{
+    run_echo, run_relay_server, run_selftest, send_files, AppConfig, ClipboardBackendKind,
+    CoreService, PeerSendOutcome,
+};
 
 #[cfg(any(target_os = "linux", target_os = "windows"))]
-use lan_clipboard_sync::{TrayEvent, TrayManager};
+use lan_clipboard_sync::{
+    config_ui_launch, spawn_send_hotkey, tray_or_headless, ConfigUiLaunch, TrayEvent, TrayManager,
+};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -16,20 +23,83 @@ struct Args {
     #[arg(short, long)]
     config: Option<PathBuf>,
 
+    /// 指定配置目录（使用其中的 config.toml，不存在时自动创建）
+    #[arg(long, value_name = "DIR", conflicts_with = "config")]
+    config_dir: Option<PathBuf>,
+
     /// 仅启动配置 UI 窗口（供托盘菜单调用，内部使用）
     #[arg(long, hide = true)]
     config_ui: bool,
+
+    /// 导入其他设备生成的配对字符串（写入共享密钥后退出）
+    #[arg(long, value_name = "STRING")]
+    pair: Option<String>,
+
+    /// 与 --pair 配合：将配对设备的地址加入 peers
+    #[arg(long, value_name = "HOST", requires = "pair")]
+    pair_host: Option<String>,
+
+    /// 运行本机回环自检（加密、分帧、网络与解码），输出 PASS/FAIL 后退出
+    #[arg(long)]
+    selftest: bool,
+
+    /// 向指定对端（HOST 或 HOST:PORT）发送回显请求并输出往返延迟后退出
+    #[arg(long, value_name = "PEER")]
+    echo: Option<String>,
+
+    /// 严格模式：示例密钥、没有对端或关闭了通知时拒绝启动（同配置项 `strict`）
+    #[arg(long)]
+    strict: bool,
+
+    /// 以 TOML 格式将当前配置输出到标准输出后退出
+    #[arg(long)]
+    export_config: bool,
+
+    /// 与 --export-config 配合：导出内容中不包含共享密钥
+    #[arg(long, requires = "export_config")]
+    redact_keys: bool,
+
+    /// 从标准输入读取 TOML 配置，校验后写入配置文件并退出
+    #[arg(long, conflicts_with = "export_config")]
+    import_config: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// 不经过剪贴板，将指定文件直接发送给所有对端，输出每个对端的发送结果后退出
+    SendFile {
+        #[arg(required = true, value_name = "PATH")]
+        paths: Vec<PathBuf>,
+    },
+    /// 不创建托盘，正常运行同步并将每条收发事件以 JSON 行输出到标准输出（日志改写到标准错误）
+    Events,
 }
 
 #[cfg(any(target_os = "linux", target_os = "windows"))]
 fn run_with_tray(config: AppConfig, config_path: PathBuf) -> Result<()> {
-    // 创建托盘管理器
-    let tray = TrayManager::new(config_path.clone())?;
+    // 创建托盘管理器；部分桌面环境（如 KDE Wayland）下可能失败，按配置退化为无托盘模式
+    let Some(tray) = tray_or_headless(TrayManager::new(config_path.clone()), config.require_tray)?
+    else {
+        return run_without_tray(config, config_path);
+    };
     tracing::info!("system tray initialized");
 
     // 创建并运行核心服务（独立线程，退出时随进程结束）
     let rt = tokio::runtime::Runtime::new()?;
+    let send_hotkey = config.send_hotkey.clone();
     let mut core = CoreService::new(config)?;
+    core.set_config_path(config_path.clone());
+    let display_backend = core.display_backend_switcher();
+    let single_peer = core.single_peer_sender();
+    let send_now = core.send_now_sender();
+    let pause_toggle = core.pause_toggle_sender();
+    let rotate_key = core.rotate_key_sender();
+    if let Some(hotkey) = &send_hotkey {
+        spawn_send_hotkey(hotkey, send_now.clone());
+    }
     std::thread::spawn(move || {
         if let Err(e) = rt.block_on(core.run()) {
             tracing::error!("core service error: {e}");
@@ -38,6 +108,8 @@ fn run_with_tray(config: AppConfig, config_path: PathBuf) -> Result<()> {
 
     // 配置 UI 子进程句柄：进程内锁定，确保同时只打开一个配置窗口
     let mut config_ui_child: Option<std::process::Child> = None;
+    // 无法启动子进程时回退到进程内运行的配置窗口线程（每个进程只能使用一次）
+    let mut config_ui_thread: Option<std::thread::JoinHandle<()>> = None;
 
     // 在主线程中监听托盘事件
     loop {
@@ -57,41 +129,198 @@ fn run_with_tray(config: AppConfig, config_path: PathBuf) -> Result<()> {
                     }
                     // 已退出，config_ui_child 保持 None，可重新启动
                 }
+                if config_ui_thread.as_ref().is_some_and(|t| !t.is_finished()) {
+                    tracing::debug!("配置窗口已打开，忽略重复点击");
+                    continue;
+                }
 
                 let path = config_path.clone();
-                if let Ok(exe) = std::env::current_exe() {
-                    match std::process::Command::new(&exe)
+                let in_process_used = config_ui_thread.is_some();
+                let launch = config_ui_launch(std::env::current_exe(), in_process_used);
+                let spawn_in_process = match launch {
+                    ConfigUiLaunch::Subprocess(exe) => match std::process::Command::new(&exe)
                         .arg("--config-ui")
                         .args(["--config", path.to_string_lossy().as_ref()])
                         .spawn()
                     {
-                        Ok(child) => config_ui_child = Some(child),
-                        Err(e) => tracing::error!("无法启动配置窗口: {}", e),
-                    }
-                } else {
-                    tracing::error!("无法获取当前可执行文件路径，无法打开配置窗口");
+                        Ok(child) => {
+                            config_ui_child = Some(child);
+                            false
+                        }
+                        Err(e) => {
+                            tracing::error!("无法启动配置窗口子进程: {}", e);
+                            !in_process_used
+                        }
+                    },
+                    ConfigUiLaunch::InProcess => true,
+                    ConfigUiLaunch::Unavailable => false,
+                };
+                if spawn_in_process {
+                    tracing::info!("opening config UI in-process");
+                    config_ui_thread = Some(std::thread::spawn(move || {
+                        lan_clipboard_sync::config_ui::run(path);
+                    }));
                 }
             }
             TrayEvent::OpenConfig => {
                 // 复制配置路径，无需额外处理
             }
+            TrayEvent::SetDisplayBackend(backend) => {
+                // 先写回配置，重启后保持同样的选择
+                match AppConfig::load(config_path.clone()) {
+                    Ok(mut cfg) => {
+                        cfg.display_backend = backend;
+                        if let Err(e) = cfg.save(&config_path) {
+                            tracing::error!("failed to persist clipboard backend: {}", e);
+                        }
+                    }
+                    Err(e) => tracing::error!("failed to load config: {}", e),
+                }
+                let _ = display_backend.blocking_send(backend);
+            }
+            TrayEvent::SendToPeer(addr) => {
+                let _ = single_peer.blocking_send(addr);
+            }
+            TrayEvent::TogglePause => {
+                let _ = pause_toggle.blocking_send(());
+            }
+            TrayEvent::SyncNow => {
+                let _ = send_now.blocking_send(());
+            }
+            TrayEvent::RotateKey => {
+                let _ = rotate_key.blocking_send(());
+            }
         }
     }
 }
 
-#[cfg(not(any(target_os = "linux", target_os = "windows")))]
-fn run_without_tray(config: AppConfig) -> Result<()> {
+/// 展示模式：核心服务在后台线程运行，主线程显示收到内容的窗口，关闭窗口即退出。
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+fn run_display(config: AppConfig, config_path: PathBuf) -> Result<()> {
     let rt = tokio::runtime::Runtime::new()?;
     let mut core = CoreService::new(config)?;
+    core.set_config_path(config_path);
+    let sink = core
+        .display_sink()
+        .ok_or_else(|| anyhow::anyhow!("display sink is only available in display mode"))?;
+    std::thread::spawn(move || {
+        if let Err(e) = rt.block_on(core.run()) {
+            tracing::error!("core service error: {e}");
+        }
+    });
+    lan_clipboard_sync::display_ui::run(sink);
+    Ok(())
+}
+
+fn run_without_tray(config: AppConfig, config_path: PathBuf) -> Result<()> {
+    let rt = tokio::runtime::Runtime::new()?;
+    let mut core = CoreService::new(config)?;
+    core.set_config_path(config_path);
     rt.block_on(async move { core.run().await })
 }
 
+/// 运行核心服务并逐行输出同步事件，直到核心服务退出。
+fn run_events(config: AppConfig, config_path: PathBuf) -> Result<()> {
+    use std::io::Write;
+    use tokio::sync::broadcast::error::RecvError;
+
+    let rt = tokio::runtime::Runtime::new()?;
+    let mut core = CoreService::new(config)?;
+    core.set_config_path(config_path);
+    let mut events = core.subscribe_events();
+    // 核心服务退出时事件发送端随之释放，下面的循环收到 Closed 后结束
+    let service = std::thread::spawn(move || rt.block_on(core.run()));
+
+    let mut stdout = std::io::stdout();
+    loop {
+        match events.blocking_recv() {
+            Ok(event) => {
+                writeln!(stdout, "{}", event.to_json_line())?;
+                stdout.flush()?;
+            }
+            Err(RecvError::Lagged(n)) => tracing::warn!("dropped {n} event(s)"),
+            Err(RecvError::Closed) => break,
+        }
+    }
+    service
+        .join()
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("core service panicked")))
+}
+
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    init_logging();
+    // events 模式与导出配置时标准输出只用于输出结果
+    init_logging(matches!(args.command, Some(Command::Events)) || args.export_config);
+
+    let config_path = resolve_config_path(
+        args.config
+            .clone()
+            .or_else(|| args.config_dir.as_ref().map(|d| d.join("config.toml"))),
+    );
+
+    if args.selftest {
+        let rt = tokio::runtime::Runtime::new()?;
+        return match rt.block_on(run_selftest()) {
+            Ok(()) => {
+                println!("PASS");
+                Ok(())
+            }
+            Err(e) => {
+                println!("FAIL: {e:#}");
+                std::process::exit(1);
+            }
+        };
+    }
+
+    if let Some(peer) = args.echo.as_deref() {
+        let config = AppConfig::load(config_path)?;
+        let addr = resolve_echo_peer(&config, peer)?;
+        let rt = tokio::runtime::Runtime::new()?;
+        let rtt = rt.block_on(run_echo(&config, addr))?;
+        println!("{} RTT: {:.2} ms", addr, rtt.as_secs_f64() * 1000.0);
+        return Ok(());
+    }
+
+    if let Some(Command::SendFile { paths }) = &args.command {
+        let config = AppConfig::load(config_path)?;
+        let rt = tokio::runtime::Runtime::new()?;
+        let results = rt.block_on(send_files(&config, paths))?;
+        let mut failed = false;
+        for result in &results {
+            match &result.outcome {
+                PeerSendOutcome::Ok => println!("{}: ok", result.addr),
+                PeerSendOutcome::Timeout => println!("{}: timeout", result.addr),
+                PeerSendOutcome::Error(e) => println!("{}: error: {}", result.addr, e),
+                PeerSendOutcome::Coalesced => println!("{}: superseded", result.addr),
+                PeerSendOutcome::Skipped => println!("{}: skipped (recently unreachable)", result.addr),
+            }
+            failed |= !result.is_ok();
+        }
+        if failed {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Events) = &args.command {
+        let config = AppConfig::load(config_path.clone())?;
+        return run_events(config, config_path);
+    }
 
-    let config_path = resolve_config_path(args.config.clone());
+    if args.export_config {
+        let config = AppConfig::load(config_path)?;
+        print!("{}", config.export_toml(args.redact_keys)?);
+        return Ok(());
+    }
+
+    if args.import_config {
+        return import_config(&config_path);
+    }
+
+    if let Some(pairing) = args.pair.as_deref() {
+        return import_pairing(&config_path, pairing, args.pair_host.as_deref());
+    }
 
     #[cfg(any(target_os = "linux", target_os = "windows"))]
     if args.config_ui {
@@ -100,7 +329,35 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
-    let config = AppConfig::load(config_path.clone())?;
+    let (config, created) = AppConfig::load_or_create(config_path.clone())?;
+    if created {
+        tracing::warn!(
+            "config file not found, created a default one with a new secret key at {}; \
+             edit it to add peers (all devices must share the same secret_key)",
+            config_path.display()
+        );
+    }
+
+    if args.strict || config.strict {
+        let failures = config.strict_check_failures();
+        if !failures.is_empty() {
+            for failure in &failures {
+                eprintln!("strict mode: {failure}");
+            }
+            std::process::exit(1);
+        }
+    }
+
+    if config.relay_server {
+        // 中继服务模式通常运行在无图形环境的公网主机上，不创建托盘与剪贴板
+        let rt = tokio::runtime::Runtime::new()?;
+        return rt.block_on(run_relay_server(&config));
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "windows"))]
+    if config.clipboard_backend == ClipboardBackendKind::Display {
+        return run_display(config, config_path);
+    }
 
     #[cfg(any(target_os = "linux", target_os = "windows"))]
     {
@@ -109,18 +366,91 @@ fn main() -> Result<()> {
 
     #[cfg(not(any(target_os = "linux", target_os = "windows")))]
     {
+        if config.clipboard_backend == ClipboardBackendKind::Display {
+            tracing::warn!(
+                "display window not supported on this platform, content will not be shown"
+            );
+        }
         tracing::warn!("system tray not supported on this platform, running without tray");
-        run_without_tray(config)
+        run_without_tray(config, config_path)
+    }
+}
+
+/// 解析回显目标：未指定端口时使用 peers 中同名主机的端口，否则使用本机监听端口。
+fn resolve_echo_peer(config: &AppConfig, peer: &str) -> Result<SocketAddr> {
+    if let Ok(addr) = peer.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    // 不含端口：纯主机名或 IP（IPv6 地址本身含冒号，需先尝试按 IP 解析）
+    let target = if peer.parse::<IpAddr>().is_ok() || !peer.contains(':') {
+        let port = config
+            .peers
+            .iter()
+            .find(|p| p.host == peer)
+            .map_or(config.listen_port, |p| p.port);
+        (peer.to_string(), port).to_socket_addrs()?.next()
+    } else {
+        peer.to_socket_addrs()?.next()
+    };
+    target.ok_or_else(|| anyhow::anyhow!("cannot resolve peer {}", peer))
+}
+
+/// 解析配对字符串并写入配置文件；配置文件不存在时以配对信息新建。
+fn import_pairing(config_path: &PathBuf, pairing: &str, host: Option<&str>) -> Result<()> {
+    let info = PairingInfo::decode(pairing)?;
+    let mut config = if config_path.exists() {
+        AppConfig::load(config_path.clone())?
+    } else {
+        AppConfig {
+            listen_port: info.listen_port,
+            ..AppConfig::default()
+        }
+    };
+    info.apply_to(&mut config, host);
+    config.save(config_path)?;
+    println!(
+        "已导入来自 \"{}\" 的配对信息（对端端口 {}），配置已写入 {}",
+        info.device_name,
+        info.listen_port,
+        config_path.display()
+    );
+    if host.is_none() {
+        println!("提示：未指定 --pair-host，请在配置中手动添加该设备的地址");
     }
+    Ok(())
 }
 
-fn init_logging() {
+/// 从标准输入读取 TOML 配置并写入配置文件；导入内容没有密钥时沿用现有配置中的密钥。
+fn import_config(config_path: &PathBuf) -> Result<()> {
+    use std::io::Read;
+
+    let mut text = String::new();
+    std::io::stdin().read_to_string(&mut text)?;
+    let existing = if config_path.exists() {
+        match AppConfig::load(config_path.clone()) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                tracing::warn!("cannot load existing config, importing as-is: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let config = AppConfig::import_toml(&text, existing.as_ref())?;
+    config.save(config_path)?;
+    println!("配置已导入并写入 {}", config_path.display());
+    Ok(())
+}
+
+fn init_logging(to_stderr: bool) {
     let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-    let ansi = supports_color();
-    tracing_subscriber::fmt()
-        .with_env_filter(filter)
-        .with_ansi(ansi)
-        .init();
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    if to_stderr {
+        builder.with_ansi(false).with_writer(std::io::stderr).init();
+    } else {
+        builder.with_ansi(supports_color()).init();
+    }
 }
 
 /// 检测终端是否支持色彩，避免在不支持 ANSI 的终端中输出转义序列
@@ -0,0 +1,155 @@
+//! 清单优先的文件传输：发送端先广播文件清单（名称、大小、哈希），
+//! 接收端根据本地已收到文件的哈希索引只请求缺少的文件，发送端再按请求回传内容。
+
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::protocol::{FileEntry, ManifestEntry};
+
+/// 清单发出后等待请求（或接收端等待文件数据）的最长时间
+pub const TRANSFER_TTL: Duration = Duration::from_secs(300);
+/// 哈希索引最多记录的文件数
+const FILE_INDEX_CAPACITY: usize = 1024;
+
+/// 计算文件内容的 SHA-256。
+pub fn file_hash(content: &[u8]) -> [u8; 32] {
+    Sha256::digest(content).into()
+}
+
+/// 由待发送的文件生成清单。
+pub fn manifest_entries(files: &[FileEntry]) -> Vec<ManifestEntry> {
+    files
+        .iter()
+        .map(|f| ManifestEntry {
+            name: f.name.clone(),
+            size: f.size,
+            hash: file_hash(&f.content),
+            source_path: f.source_path.clone(),
+        })
+        .collect()
+}
+
+/// 已接收文件的哈希索引：记录内容哈希到本地路径的映射，容量满时淘汰最早记录。
+#[derive(Debug, Default)]
+pub struct FileIndex {
+    paths: HashMap<[u8; 32], PathBuf>,
+    order: VecDeque<[u8; 32]>,
+}
+
+impl FileIndex {
+    pub fn insert(&mut self, hash: [u8; 32], path: PathBuf) {
+        if self.paths.insert(hash, path).is_none() {
+            self.order.push_back(hash);
+        }
+        while self.order.len() > FILE_INDEX_CAPACITY {
+            if let Some(old) = self.order.pop_front() {
+                self.paths.remove(&old);
+            }
+        }
+    }
+
+    /// 查找内容一致的本地文件：文件须仍存在，且重新计算的哈希与清单一致
+    /// （收到后可能被原地修改，大小不变也不能直接复用）。
+    pub fn lookup(&self, entry: &ManifestEntry) -> Option<PathBuf> {
+        let path = self.paths.get(&entry.hash)?;
+        let meta = std::fs::metadata(path).ok()?;
+        if !meta.is_file() || meta.len() != entry.size {
+            return None;
+        }
+        let content = std::fs::read(path).ok()?;
+        (file_hash(&content) == entry.hash).then(|| path.clone())
+    }
+}
+
+/// 发送端已广播清单、等待对端请求的文件集合。
+#[derive(Debug, Default)]
+pub struct OutgoingTransfers {
+    transfers: HashMap<u64, (Instant, Vec<FileEntry>)>,
+}
+
+impl OutgoingTransfers {
+    pub fn insert(&mut self, transfer_id: u64, files: Vec<FileEntry>, now: Instant) {
+        self.transfers
+            .retain(|_, (created, _)| now.saturating_duration_since(*created) <= TRANSFER_TTL);
+        self.transfers.insert(transfer_id, (now, files));
+    }
+
+    /// 取出请求的文件（保留传输记录以响应其他对端），传输不存在或下标越界时返回 None。
+    pub fn select(&self, transfer_id: u64, indices: &[u32]) -> Option<Vec<(u32, FileEntry)>> {
+        let (_, files) = self.transfers.get(&transfer_id)?;
+        indices
+            .iter()
+            .map(|&i| files.get(i as usize).map(|f| (i, f.clone())))
+            .collect()
+    }
+}
+
+/// 接收端已收到清单、等待缺少文件数据的传输。
+#[derive(Debug)]
+pub struct PendingManifest {
+    /// 清单来源地址，只接受同一地址回传的文件数据
+    pub from: IpAddr,
+    pub entries: Vec<ManifestEntry>,
+    /// 与清单一一对应：本地已有的文件路径，缺少的为 None
+    pub local: Vec<Option<PathBuf>>,
+    pub received_at: Instant,
+}
+
+impl PendingManifest {
+    /// 尚缺少的清单下标。
+    pub fn missing(&self) -> Vec<u32> {
+        self.local
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.is_none())
+            .map(|(i, _)| i as u32)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, content: &[u8]) -> FileEntry {
+        FileEntry {
+            name: name.into(),
+            size: content.len() as u64,
+            content: content.to_vec(),
+            source_path: None,
+        }
+    }
+
+    #[test]
+    fn index_lookup_requires_existing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        std::fs::write(&path, b"aaa").unwrap();
+        let manifest = manifest_entries(&[entry("a.txt", b"aaa")]);
+
+        let mut index = FileIndex::default();
+        index.insert(file_hash(b"aaa"), path.clone());
+        assert_eq!(index.lookup(&manifest[0]), Some(path.clone()));
+
+        // 原地修改且大小不变：内容已不一致，不能复用
+        std::fs::write(&path, b"aab").unwrap();
+        assert_eq!(index.lookup(&manifest[0]), None);
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(index.lookup(&manifest[0]), None);
+    }
+
+    #[test]
+    fn outgoing_select_rejects_unknown_index() {
+        let mut transfers = OutgoingTransfers::default();
+        transfers.insert(1, vec![entry("a", b"a"), entry("b", b"b")], Instant::now());
+        let selected = transfers.select(1, &[1]).unwrap();
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].1.name, "b");
+        assert!(transfers.select(1, &[2]).is_none());
+        assert!(transfers.select(2, &[0]).is_none());
+    }
+}
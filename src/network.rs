@@ -1,152 +1,1886 @@
 //! 网络传输层：基于 TCP + 对称加密的剪贴板消息收发。
 
-use crate::config::AppConfig;
-use crate::crypto::{decrypt, encrypt, handshake_client, handshake_server, key_from_hex};
-use crate::protocol::{decode_message, encode_frame, encode_message, ProtocolMessage};
+use crate::chunk::MAX_PREALLOC;
+use crate::config::{AeadAlgorithm, AppConfig};
+use crate::crypto::{
+    decrypt, derive_session_key, encrypt, handshake_client, handshake_server, key_from_hex,
+};
+use crate::keyring::{key_id, KeyRing, KEY_ID_LEN};
+use crate::protocol::{
+    compress_body, decode_message, decompress_body, encode_message, frame_header,
+    gunzip_frame_body, gzip_frame_body, split_frame_header, ProtocolMessage, GZIP_FRAME_FLAG,
+    PLAINTEXT_FRAME_ID,
+};
 use anyhow::{anyhow, Result};
 use chacha20poly1305::Key;
+use std::collections::HashMap;
 use std::net::{IpAddr, SocketAddr};
-use std::time::Duration;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::sync::atomic::{AtomicU16, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::mpsc;
-
-/// 入站帧体的最大字节数（约 50 MiB），防止恶意/异常连接导致 OOM
-const MAX_FRAME_BODY: usize = 50 * 1024 * 1024;
+use tokio::sync::{mpsc, Semaphore};
+use tracing::Instrument;
 
 /// 入站连接读超时，防止慢速连接占用资源
 const CONNECTION_READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// 已完成首帧的长连接两帧之间允许的最长空闲时间
+const CONNECTION_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// 入站连接编号，作为日志 span 字段区分同时处理的多个连接
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// 帧转储的最大字节数，超出部分只记录长度
+const TRACE_DUMP_LIMIT: usize = 256;
+/// 开启帧转储的环境变量
+const TRACE_FRAMES_ENV: &str = "LAN_CLIPBOARD_TRACE_FRAMES";
+
+/// 是否输出帧转储：配置 `trace_frames` 或环境变量开启，且 trace 级别日志已启用。
+fn trace_frames_enabled(config: &AppConfig) -> bool {
+    let env = std::env::var(TRACE_FRAMES_ENV).is_ok_and(|v| !v.is_empty() && v != "0");
+    (config.trace_frames || env) && tracing::enabled!(tracing::Level::TRACE)
+}
+
+/// 生成 `xxd` 风格的十六进制转储（每行 16 字节，含偏移与 ASCII 列），最多转储 `limit` 字节。
+fn hexdump(data: &[u8], limit: usize) -> String {
+    let mut out = String::new();
+    for (i, line) in data[..data.len().min(limit)].chunks(16).enumerate() {
+        let hex: Vec<String> = line.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = line
+            .iter()
+            .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+            .collect();
+        out.push_str(&format!("{:08x}  {:<47}  |{}|\n", i * 16, hex.join(" "), ascii));
+    }
+    if data.len() > limit {
+        out.push_str(&format!("... ({} more bytes)\n", data.len() - limit));
+    }
+    out
+}
+
+/// 输出一帧的转储。`parts` 依次拼接为完整内容，只复制转储所需的前缀。
+///
+/// 只转储线上可见的帧字节（长度前缀、nonce、密文）与解密后的消息明文，
+/// PSK 与会话密钥从不传入此处。
+fn trace_frame(direction: &str, addr: &str, what: &str, parts: &[&[u8]]) {
+    let total: usize = parts.iter().map(|p| p.len()).sum();
+    let mut prefix = Vec::with_capacity(total.min(TRACE_DUMP_LIMIT));
+    for part in parts {
+        let room = TRACE_DUMP_LIMIT - prefix.len();
+        prefix.extend_from_slice(&part[..part.len().min(room)]);
+    }
+    let mut dump = hexdump(&prefix, TRACE_DUMP_LIMIT);
+    if total > prefix.len() {
+        dump.push_str(&format!("... ({} more bytes)\n", total - prefix.len()));
+    }
+    tracing::trace!("{direction} {what} {addr} ({total} bytes):\n{dump}");
+}
+
+/// 入站消息及其来源地址。
+#[derive(Debug, Clone)]
+pub struct IncomingMessage {
+    pub from: SocketAddr,
+    /// 认证（成功解密）该消息所用密钥的 ID
+    pub key_id: [u8; KEY_ID_LEN],
+    /// 是否经中继转发：此时 `from` 是中继的地址，不能据此区分房间内的设备
+    pub relayed: bool,
+    pub msg: ProtocolMessage,
+}
+
+/// 大帧的接收进度（字节），供托盘或通知展示入站传输进度。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferProgress {
+    pub from: SocketAddr,
+    pub received: usize,
+    pub total: usize,
+}
+
+/// 帧体达到该大小才上报接收进度
+const PROGRESS_MIN_FRAME: usize = 256 * 1024;
+/// 帧体按该大小分块读取，每块上报一次进度
+const PROGRESS_CHUNK: usize = 64 * 1024;
+
+/// 服务器实际绑定的端口，监听成功前为 None；可克隆后在其他线程读取。
+#[derive(Debug, Clone, Default)]
+pub struct BoundPort(Arc<AtomicU16>);
+
+impl BoundPort {
+    pub fn get(&self) -> Option<u16> {
+        match self.0.load(Ordering::Relaxed) {
+            0 => None,
+            port => Some(port),
+        }
+    }
+}
+
+/// 收发两端共用的分帧参数。
+#[derive(Debug, Clone)]
+struct FrameOptions {
+    /// 同步频道，作为加解密的附加数据
+    channel: String,
+    /// 帧加密算法，其标识写入长度前缀的高位
+    aead: AeadAlgorithm,
+    trace: bool,
+    /// 单帧帧体上限，收发两端都按此检查
+    max_body: usize,
+    /// 明文模式：帧体不加密，帧头携带 [`PLAINTEXT_FRAME_ID`]
+    insecure: bool,
+    /// 发出的帧体整体以 gzip 包装，帧头带 [`GZIP_FRAME_FLAG`]
+    gzip: bool,
+}
+
+impl FrameOptions {
+    fn from_config(config: &AppConfig) -> Self {
+        Self {
+            channel: config.channel.clone(),
+            aead: config.aead_algorithm,
+            trace: trace_frames_enabled(config),
+            max_body: config.max_frame_body,
+            insecure: config.insecure_no_encryption,
+            gzip: config.gzip_frames,
+        }
+    }
+}
+
+/// 校验对端帧头中的算法标识与本端配置一致，不一致时给出明确的错误信息。
+pub(crate) fn check_frame_algorithm(id: u8, local: AeadAlgorithm) -> Result<()> {
+    let peer = AeadAlgorithm::from_id(id)
+        .map_err(|e| anyhow!("{e} in frame header (peer may run a newer version)"))?;
+    if peer != local {
+        return Err(anyhow!(
+            "aead algorithm mismatch: peer uses {}, local config uses {}",
+            peer.name(),
+            local.name()
+        ));
+    }
+    Ok(())
+}
+
+/// 校验对端帧与本端的加密模式一致：加密与明文的两端互相拒绝，不会静默互通。
+fn check_frame_mode(id: u8, local: AeadAlgorithm, insecure: bool) -> Result<()> {
+    match (id == PLAINTEXT_FRAME_ID, insecure) {
+        (true, true) => Ok(()),
+        (true, false) => Err(anyhow!(
+            "rejected unencrypted frame from peer (insecure_no_encryption is off locally)"
+        )),
+        (false, true) => Err(anyhow!(
+            "rejected encrypted frame from peer (insecure_no_encryption is on locally)"
+        )),
+        (false, false) => check_frame_algorithm(id, local),
+    }
+}
 
 /// 网络层：负责监听远端连接并将解密后的消息推送到核心逻辑。
+#[derive(Clone)]
 pub struct NetworkServer {
     addr: SocketAddr,
-    key: Key,
-    incoming_tx: mpsc::Sender<ProtocolMessage>,
+    keys: KeyRing,
+    frame: FrameOptions,
+    incoming_tx: mpsc::Sender<IncomingMessage>,
+    bound_port: BoundPort,
+    progress_tx: Option<mpsc::Sender<TransferProgress>>,
+    /// 入站连接并发许可，重启后的服务器共享同一组许可
+    connection_limit: Arc<Semaphore>,
 }
 
 impl NetworkServer {
-    pub fn new(config: &AppConfig, incoming_tx: mpsc::Sender<ProtocolMessage>) -> Result<Self> {
-        let key = key_from_hex(&config.secret_key)?;
+    /// 使用共享的密钥环创建服务器，密钥轮换后入站连接立即接受新密钥。
+    pub fn with_keyring(
+        config: &AppConfig,
+        keys: KeyRing,
+        incoming_tx: mpsc::Sender<IncomingMessage>,
+    ) -> Result<Self> {
         let addr = SocketAddr::new(IpAddr::from([0, 0, 0, 0]), config.listen_port);
-        Ok(Self { addr, key, incoming_tx })
+        if config.insecure_no_encryption {
+            tracing::warn!(
+                "INSECURE: insecure_no_encryption is enabled, clipboard content is sent and \
+                 accepted UNENCRYPTED and UNAUTHENTICATED; only use this on an isolated, trusted network"
+            );
+        }
+        Ok(Self {
+            addr,
+            keys,
+            frame: FrameOptions::from_config(config),
+            incoming_tx,
+            bound_port: BoundPort::default(),
+            progress_tx: None,
+            connection_limit: Arc::new(Semaphore::new(config.max_inbound_connections)),
+        })
+    }
+
+    /// 上报大帧的接收进度；接收方处理不及时时丢弃进度事件，不阻塞接收。
+    pub fn with_progress(mut self, progress_tx: mpsc::Sender<TransferProgress>) -> Self {
+        self.progress_tx = Some(progress_tx);
+        self
+    }
+
+    /// 实际监听端口的句柄，配置端口为 0 时可在绑定后读取系统分配的端口。
+    pub fn bound_port(&self) -> BoundPort {
+        self.bound_port.clone()
+    }
+
+    /// 已绑定时返回实际监听端口。
+    pub fn local_port(&self) -> Option<u16> {
+        self.bound_port.get()
     }
 
-    /// 启动 TCP 监听循环，为每个入站连接创建异步任务。
+    /// 绑定监听地址。端口为 0 时由系统分配；重启后复用首次分配到的端口，对端无需重新配置。
+    pub async fn bind(&self) -> Result<TcpListener> {
+        let port = self.bound_port.get().unwrap_or(self.addr.port());
+        let listener = TcpListener::bind(SocketAddr::new(self.addr.ip(), port)).await?;
+        let local = listener.local_addr()?;
+        self.bound_port.0.store(local.port(), Ordering::Relaxed);
+        tracing::info!("listening on {}", local);
+        Ok(listener)
+    }
+
+    /// 绑定端口并启动 TCP 监听循环。
     pub async fn run(self) -> Result<()> {
-        let listener = TcpListener::bind(self.addr).await?;
+        let listener = self.bind().await?;
+        self.serve(listener).await
+    }
+
+    /// 在已绑定的监听器上运行接收循环，为每个入站连接创建异步任务。
+    ///
+    /// 同时处理的连接数达到 `max_inbound_connections` 时暂停接受，
+    /// 新连接留在系统的监听队列中，直到有连接处理完毕。
+    pub async fn serve(self, listener: TcpListener) -> Result<()> {
         loop {
-            let (stream, _) = listener.accept().await?;
-            let key = self.key;
+            let permit = match Arc::clone(&self.connection_limit).try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_) => {
+                    tracing::warn!("inbound connection limit reached, delaying new connections");
+                    Arc::clone(&self.connection_limit).acquire_owned().await?
+                }
+            };
+            let (stream, from) = listener.accept().await?;
+            let keys = self.keys.clone();
+            let frame = self.frame.clone();
             let tx = self.incoming_tx.clone();
-            tokio::spawn(async move {
-                if let Err(e) = handle_connection(stream, key, tx).await {
-                    tracing::warn!("connection error: {e}");
+            let progress = self.progress_tx.clone();
+            // 该连接的所有日志都带上连接编号与对端地址
+            let id = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+            let span = tracing::info_span!("conn", id, peer = %from);
+            tokio::spawn(
+                async move {
+                    let _permit = permit;
+                    tracing::debug!("accepted connection");
+                    if let Err(e) = handle_connection(stream, from, keys, frame, tx, progress).await {
+                        tracing::warn!("connection error: {e}");
+                    }
                 }
-            });
+                .instrument(span),
+            );
+        }
+    }
+
+    /// 在监督下运行服务器：监听任务 panic 或出错退出时按退避重启，不会永久停止同步。
+    /// 单个连接的处理在独立任务中进行，其 panic 只影响该连接。
+    pub async fn run_supervised(self) {
+        supervise_server(move || self.clone().run(), ServerRestartPolicy::default()).await
+    }
+}
+
+/// 网络服务任务的重启退避策略。
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ServerRestartPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for ServerRestartPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
         }
     }
 }
 
-/// 处理单个入站 TCP 连接：先完成密钥交换握手，再读取、解密并解码协议消息后发送到通道。
+/// 监督服务任务：每次由 `make` 创建新任务并等待其结束，panic 或出错时记录日志并按退避重启。
+/// 任务正常结束或被取消时返回；运行超过最大退避时长后退避重置为初始值。
+pub(crate) async fn supervise_server<F, Fut>(mut make: F, policy: ServerRestartPolicy)
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+{
+    let mut backoff = policy.initial_backoff;
+    loop {
+        let started = tokio::time::Instant::now();
+        match tokio::spawn(make()).await {
+            Ok(Ok(())) => return,
+            Ok(Err(e)) => tracing::error!("network server error: {e}"),
+            Err(e) if e.is_panic() => tracing::error!("network server task panicked: {e}"),
+            Err(e) => {
+                tracing::warn!("network server task cancelled: {e}");
+                return;
+            }
+        }
+        if started.elapsed() > policy.max_backoff {
+            backoff = policy.initial_backoff;
+        }
+        tracing::warn!("restarting network server in {:?}", backoff);
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(policy.max_backoff);
+    }
+}
+
+/// 处理单个入站 TCP 连接：先完成密钥交换握手，再依次读取、解密并解码协议消息后发送到通道。
+/// 依次尝试密钥环中所有可接受的 PSK，能成功解密者即为认证该消息的密钥。
 /// 带帧长度上限校验和读超时，防止 OOM 与资源耗尽。
+///
+/// 普通发送端每条消息一个连接，发完即关闭；长连接（隧道友好模式）的发送端在同一连接上
+/// 连续发送多帧，首帧之后允许较长的空闲时间。
 async fn handle_connection(
     mut stream: TcpStream,
-    psk: Key,
-    incoming_tx: mpsc::Sender<ProtocolMessage>,
+    from: SocketAddr,
+    keys: KeyRing,
+    frame: FrameOptions,
+    incoming_tx: mpsc::Sender<IncomingMessage>,
+    progress_tx: Option<mpsc::Sender<TransferProgress>>,
 ) -> Result<()> {
-    let psk_bytes: [u8; 32] = psk
-        .as_slice()
-        .try_into()
-        .map_err(|_| anyhow!("key length mismatch"))?;
-    let key = handshake_server(&mut stream, &psk_bytes).await?;
+    let FrameOptions {
+        channel,
+        aead,
+        trace,
+        max_body,
+        insecure,
+        gzip: _,
+    } = frame;
+    let shared = handshake_server(&mut stream).await?;
 
-    let read_ops = async {
-        // 先读取 4 字节长度
+    let mut first = true;
+    loop {
+        // 先读取 4 字节长度；首帧之后在帧边界处关闭连接属于正常结束
         let mut len_buf = [0u8; 4];
-        stream.read_exact(&mut len_buf).await?;
-        let len = u32::from_be_bytes(len_buf) as usize;
+        let idle_timeout = if first {
+            CONNECTION_READ_TIMEOUT
+        } else {
+            CONNECTION_IDLE_TIMEOUT
+        };
+        match tokio::time::timeout(idle_timeout, stream.read_exact(&mut len_buf)).await {
+            Err(_) if first => return Err(anyhow!("connection read timeout")),
+            Err(_) => {
+                tracing::debug!("closing idle connection from {from}");
+                return Ok(());
+            }
+            Ok(Err(e)) if !first && e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Ok(result) => result?,
+        };
+
+        let read_ops = async {
+            let (frame_id, len) = split_frame_header(u32::from_be_bytes(len_buf));
+            // 是否以 gzip 包装由发送端决定并记录在帧头中，不要求两端配置一致
+            let gzipped = frame_id & GZIP_FRAME_FLAG != 0;
+            check_frame_mode(frame_id & !GZIP_FRAME_FLAG, aead, insecure)?;
+            if len > max_body {
+                return Err(anyhow!("frame body too large: {} > {} bytes", len, max_body));
+            }
+
+            let body = match progress_tx.as_ref().filter(|_| len >= PROGRESS_MIN_FRAME) {
+                Some(tx) => {
+                    read_body_with_progress(&mut stream, len, |received| {
+                        let _ = tx.try_send(TransferProgress {
+                            from,
+                            received,
+                            total: len,
+                        });
+                    })
+                    .await?
+                }
+                None => read_body(&mut stream, len).await?,
+            };
+            if trace {
+                trace_frame("<-", &from.to_string(), "frame", &[&len_buf, &body]);
+            }
+            let body = if gzipped {
+                gunzip_frame_body(&body, max_body)?
+            } else {
+                body
+            };
+
+            let (psk, plaintext) = if insecure {
+                // 明文模式无法认证发送端，消息视为以主密钥发送
+                (keys.primary(), body)
+            } else {
+                if body.len() < 12 {
+                    return Err(anyhow!("frame body too short for nonce"));
+                }
+                let mut nonce = [0u8; 12];
+                nonce.copy_from_slice(&body[..12]);
+                let ciphertext = &body[12..];
+                keys.accepted()
+                    .into_iter()
+                    .find_map(|psk| {
+                        let key = derive_session_key(&shared, &psk);
+                        decrypt(aead, &key, &nonce, ciphertext, channel.as_bytes())
+                            .ok()
+                            .map(|pt| (psk, pt))
+                    })
+                    .ok_or_else(|| anyhow!("decrypt failed: no accepted key matches or channel mismatch"))?
+            };
+            // 是否压缩同样由发送端决定，记录在解密后消息体的首字节
+            let plaintext = decompress_body(plaintext, max_body)?;
+            if trace {
+                trace_frame("<-", &from.to_string(), "plaintext", &[&plaintext]);
+            }
+            let msg = decode_message(&plaintext)?;
+            incoming_tx
+                .send(IncomingMessage {
+                    from,
+                    key_id: key_id(&psk),
+                    relayed: false,
+                    msg,
+                })
+                .await
+                .map_err(|_| anyhow!("channel closed"))?;
+            Ok(())
+        };
+
+        tokio::time::timeout(CONNECTION_READ_TIMEOUT, read_ops)
+            .await
+            .map_err(|_| anyhow!("connection read timeout"))??;
+        first = false;
+    }
+}
 
-        if len > MAX_FRAME_BODY {
+/// 读取声明长度的帧体：缓冲区随实际到达的数据增长，不按对端声明的长度预分配。
+pub(crate) async fn read_body<R>(reader: &mut R, len: usize) -> Result<Vec<u8>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut body = Vec::with_capacity(len.min(MAX_PREALLOC));
+    reader.take(len as u64).read_to_end(&mut body).await?;
+    if body.len() != len {
+        return Err(anyhow!(
+            "connection closed mid-frame: {} of {} bytes",
+            body.len(),
+            len
+        ));
+    }
+    Ok(body)
+}
+
+/// 同 [`read_body`]，按 [`PROGRESS_CHUNK`] 分块读取，每读完一块以已接收字节数回调一次。
+async fn read_body_with_progress<R, F>(
+    reader: &mut R,
+    len: usize,
+    mut on_progress: F,
+) -> Result<Vec<u8>>
+where
+    R: AsyncRead + Unpin,
+    F: FnMut(usize),
+{
+    let mut body = Vec::with_capacity(len.min(MAX_PREALLOC));
+    while body.len() < len {
+        let chunk = (len - body.len()).min(PROGRESS_CHUNK);
+        let n = (&mut *reader).take(chunk as u64).read_to_end(&mut body).await?;
+        if n < chunk {
             return Err(anyhow!(
-                "frame body too large: {} > {} bytes",
-                len,
-                MAX_FRAME_BODY
+                "connection closed mid-frame: {} of {} bytes",
+                body.len(),
+                len
             ));
         }
+        on_progress(body.len());
+    }
+    Ok(body)
+}
+
+/// 单个对端的发送结果类型。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerSendOutcome {
+    Ok,
+    Timeout,
+    Error(String),
+    /// 等待发送期间被同一对端的更新消息取代，未单独发送（长连接模式）
+    Coalesced,
+    /// 对端不久前连接失败，仍在冷却期内，本次未尝试连接
+    Skipped,
+}
+
+/// 单个对端的发送结果。
+#[derive(Debug, Clone)]
+pub struct PeerSendResult {
+    /// 对端地址（host:port）
+    pub addr: String,
+    pub outcome: PeerSendOutcome,
+}
+
+impl PeerSendResult {
+    pub fn is_ok(&self) -> bool {
+        self.outcome == PeerSendOutcome::Ok
+    }
+}
+
+/// 对端发送超时
+const SEND_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 从配置中解析用于发送的 PSK。
+pub(crate) fn sending_psk(config: &AppConfig) -> Result<[u8; 32]> {
+    let psk = key_from_hex(&config.secret_key)?;
+    psk.as_slice()
+        .try_into()
+        .map_err(|_| anyhow!("key length mismatch"))
+}
 
-        let mut body = vec![0u8; len];
-        stream.read_exact(&mut body).await?;
+/// 将加密后的帧（长度前缀 + nonce + 密文）直接写入连接，不再拼接完整的帧缓冲。
+pub(crate) async fn write_frame<W>(
+    writer: &mut W,
+    aead: AeadAlgorithm,
+    nonce: &[u8],
+    ciphertext: &[u8],
+    max_body: usize,
+) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let len = frame_header(nonce.len() + ciphertext.len(), aead.id(), max_body)?;
+    writer.write_all(&len.to_be_bytes()).await?;
+    writer.write_all(nonce).await?;
+    writer.write_all(ciphertext).await?;
+    writer.flush().await?;
+    Ok(())
+}
 
-        if body.len() < 12 {
-            return Err(anyhow!("frame body too short for nonce"));
+/// 在已握手的连接上加密并发送一帧；明文模式下不加密，开启 `gzip_frames` 时整个帧体以 gzip 包装。
+async fn send_frame(
+    stream: &mut TcpStream,
+    key: &Key,
+    body: &[u8],
+    frame: &FrameOptions,
+    addr: &str,
+) -> Result<()> {
+    // 明文模式下帧体就是消息本身，否则为 nonce + 密文
+    let sealed;
+    let (id, parts): (u8, [&[u8]; 2]) = if frame.insecure {
+        (PLAINTEXT_FRAME_ID, [&[], body])
+    } else {
+        sealed = encrypt(frame.aead, key, body, frame.channel.as_bytes())?;
+        (frame.aead.id(), [&sealed.0, &sealed.1])
+    };
+    let wrapped;
+    let (id, parts) = if frame.gzip {
+        wrapped = gzip_frame_body(&parts)?;
+        (id | GZIP_FRAME_FLAG, [&[], wrapped.as_slice()])
+    } else {
+        (id, parts)
+    };
+    let len = frame_header(parts[0].len() + parts[1].len(), id, frame.max_body)?.to_be_bytes();
+    if frame.trace {
+        trace_frame("->", addr, "plaintext", &[body]);
+        trace_frame("->", addr, "frame", &[&len, parts[0], parts[1]]);
+    }
+    stream.write_all(&len).await?;
+    for part in parts {
+        stream.write_all(part).await?;
+    }
+    stream.flush().await?;
+    Ok(())
+}
+
+/// 将单个对端的发送结果转换为 [`PeerSendResult`] 并记录日志。
+fn send_result(addr: String, result: Result<Result<()>, tokio::time::error::Elapsed>) -> PeerSendResult {
+    let outcome = match result {
+        Ok(Ok(())) => {
+            tracing::debug!("successfully sent to {addr}");
+            PeerSendOutcome::Ok
+        }
+        Ok(Err(e)) => {
+            tracing::warn!("send to {addr} failed: {e}");
+            PeerSendOutcome::Error(e.to_string())
+        }
+        Err(_) => {
+            tracing::debug!("send to {addr} timed out after 2s");
+            PeerSendOutcome::Timeout
         }
-        let mut nonce = [0u8; 12];
-        nonce.copy_from_slice(&body[..12]);
-        let ciphertext = &body[12..];
-        let plaintext = decrypt(&key, &nonce, ciphertext)?;
-        let msg = decode_message(&plaintext)?;
-        incoming_tx.send(msg).await.map_err(|_| anyhow!("channel closed"))?;
-        Ok(())
     };
+    PeerSendResult { addr, outcome }
+}
 
-    tokio::time::timeout(CONNECTION_READ_TIMEOUT, read_ops)
-        .await
-        .map_err(|_| anyhow!("connection read timeout"))??;
+/// 连接失败的对端的负缓存：冷却期内广播直接跳过这些对端，不再为离线设备等待连接超时，
+/// 冷却期过后的下一次广播重新尝试。
+///
+/// 只记录连接阶段的失败（拒绝连接或连接超时），已连上后的发送错误不影响后续广播。
+#[derive(Clone)]
+pub struct PeerCooldown {
+    cooldown: Duration,
+    /// 对端地址（host:port）→ 最近一次连接失败的时间
+    failed: Arc<std::sync::Mutex<HashMap<String, Instant>>>,
+}
+
+impl PeerCooldown {
+    pub fn new(cooldown: Duration) -> Self {
+        Self {
+            cooldown,
+            failed: Arc::default(),
+        }
+    }
+
+    /// 对端仍在冷却期内时返回 true；冷却期已过的记录随之移除。
+    fn is_cooling(&self, addr: &str) -> bool {
+        let mut failed = self.failed.lock().unwrap_or_else(|e| e.into_inner());
+        match failed.get(addr) {
+            Some(at) if at.elapsed() < self.cooldown => true,
+            Some(_) => {
+                failed.remove(addr);
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn mark_failed(&self, addr: &str) {
+        tracing::debug!("peer {addr} unreachable, skipping it for {:?}", self.cooldown);
+        self.failed
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(addr.to_string(), Instant::now());
+    }
+}
+
+/// 连接单个对端并发送一条已编码的消息（2秒超时）；未能建立连接时记入 `cooldown`。
+///
+/// 明文由所有对端共享（`Arc`），每个对端只额外持有一份加密后的密文。
+async fn send_body(
+    addr: String,
+    psk: [u8; 32],
+    body: Arc<Vec<u8>>,
+    frame: FrameOptions,
+    cooldown: Option<PeerCooldown>,
+) -> PeerSendResult {
+    let mut connected = false;
+    let result = tokio::time::timeout(SEND_TIMEOUT, async {
+        let mut stream = TcpStream::connect(&addr).await?;
+        connected = true;
+        let key = handshake_client(&mut stream, &psk).await?;
+        send_frame(&mut stream, &key, &body, &frame, &addr).await
+    })
+    .await;
+    if let Some(cooldown) = cooldown.filter(|_| !connected) {
+        cooldown.mark_failed(&addr);
+    }
+    send_result(addr, result)
+}
+
+/// TCP keepalive 参数。
+#[derive(Debug, Clone, Copy)]
+pub struct KeepaliveSettings {
+    /// 连接空闲多久后开始发送探测
+    pub time: Duration,
+    /// 探测间隔
+    pub interval: Duration,
+}
+
+impl KeepaliveSettings {
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self {
+            time: Duration::from_secs(config.keepalive_time_secs),
+            interval: Duration::from_secs(config.keepalive_interval_secs),
+        }
+    }
+}
+
+/// 在连接上开启 TCP keepalive（`SO_KEEPALIVE`）。
+pub(crate) fn enable_keepalive(stream: &TcpStream, settings: KeepaliveSettings) -> Result<()> {
+    let keepalive = socket2::TcpKeepalive::new()
+        .with_time(settings.time)
+        .with_interval(settings.interval);
+    socket2::SockRef::from(stream).set_tcp_keepalive(&keepalive)?;
     Ok(())
 }
 
-/// 将剪贴板更新消息加密后广播到配置中的所有 peers（2秒超时，并行执行）。
+/// 已握手、可复用的对端连接。
+struct PooledConnection {
+    stream: TcpStream,
+    key: Key,
+}
+
+impl PooledConnection {
+    /// 对端从不在连接上回写数据，连接可读（收到 EOF 或意外数据）即说明已失效。
+    fn is_alive(&self) -> bool {
+        let mut probe = [0u8; 1];
+        matches!(
+            self.stream.try_read(&mut probe),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock
+        )
+    }
+}
+
+/// 单个对端的长连接与待发送队列。
+///
+/// 队列只保留最新一条消息：剪贴板以最后写入为准，对端长时间卡住时新消息直接取代
+/// 尚未发出的旧消息，每个对端占用的内存有上限。
+#[derive(Default)]
+struct PeerSlot {
+    conn: tokio::sync::Mutex<Option<PooledConnection>>,
+    /// 待发送的最新消息及其入队序号
+    pending: std::sync::Mutex<Option<(u64, Arc<Vec<u8>>)>>,
+    next_id: AtomicU64,
+    /// 排队消息被取代时唤醒等待中的发送调用，使其立即返回
+    superseded: tokio::sync::Notify,
+}
+
+impl PeerSlot {
+    /// 入队一条消息，返回其序号与被取代的旧消息（如有）。
+    fn enqueue(&self, body: Arc<Vec<u8>>) -> (u64, Option<Arc<Vec<u8>>>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let old = pending.replace((id, body)).map(|(_, body)| body);
+        (id, old)
+    }
+
+    fn is_pending(&self, id: u64) -> bool {
+        let pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending.as_ref().is_some_and(|(pending_id, _)| *pending_id == id)
+    }
+
+    /// 取出序号为 `id` 的排队消息；已被取代时返回 None。
+    fn take(&self, id: u64) -> Option<Arc<Vec<u8>>> {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending.take_if(|(pending_id, _)| *pending_id == id).map(|(_, body)| body)
+    }
+}
+
+type ConnectionSlot = Arc<PeerSlot>;
+
+/// 对端长连接池（隧道友好模式）：每个对端保持一条开启 TCP keepalive 的长连接，
+/// 避免每条消息重新建立连接（经 SSH 转发时还需重新建立 SSH 通道）。
+/// 连接失效时自动重连。
+#[derive(Clone)]
+pub struct ConnectionPool {
+    slots: Arc<std::sync::Mutex<HashMap<String, ConnectionSlot>>>,
+    keepalive: KeepaliveSettings,
+    /// 被更新消息取代而丢弃的排队消息数
+    coalesced_drops: Arc<AtomicU64>,
+}
+
+impl ConnectionPool {
+    pub fn new(keepalive: KeepaliveSettings) -> Self {
+        Self {
+            slots: Arc::default(),
+            keepalive,
+            coalesced_drops: Arc::default(),
+        }
+    }
+
+    /// 至今因被更新消息取代而丢弃的排队消息数。
+    pub fn coalesced_drops(&self) -> u64 {
+        self.coalesced_drops.load(Ordering::Relaxed)
+    }
+
+    fn slot(&self, addr: &str) -> ConnectionSlot {
+        self.slots
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(addr.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// 经长连接发送一条已编码的消息：复用现有连接，失效或发送失败时重连一次。
+    ///
+    /// 连接忙时消息进入该对端的单项队列，取代尚未发出的旧消息（旧消息的发送结果为
+    /// [`PeerSendOutcome::Coalesced`]）。重连失败时记入 `cooldown`。
+    async fn send(
+        &self,
+        addr: String,
+        psk: [u8; 32],
+        body: Arc<Vec<u8>>,
+        frame: FrameOptions,
+        cooldown: Option<PeerCooldown>,
+    ) -> PeerSendResult {
+        let slot = self.slot(&addr);
+        let (id, superseded) = slot.enqueue(body);
+        if superseded.is_some() {
+            let drops = self.coalesced_drops.fetch_add(1, Ordering::Relaxed) + 1;
+            tracing::debug!("superseded queued message to {addr} ({drops} coalesced drops)");
+            slot.superseded.notify_waiters();
+        }
+        let mut connect_failed = false;
+        let result = tokio::time::timeout(SEND_TIMEOUT, async {
+            let mut conn = loop {
+                let notified = slot.superseded.notified();
+                tokio::pin!(notified);
+                notified.as_mut().enable();
+                if !slot.is_pending(id) {
+                    return Ok(false);
+                }
+                tokio::select! {
+                    conn = slot.conn.lock() => break conn,
+                    _ = &mut notified => {}
+                }
+            };
+            let Some(body) = slot.take(id) else {
+                return Ok(false);
+            };
+            if let Some(c) = conn.as_mut().filter(|c| c.is_alive()) {
+                match send_frame(&mut c.stream, &c.key, &body, &frame, &addr).await {
+                    Ok(()) => return Ok(true),
+                    Err(e) => tracing::debug!("pooled connection to {addr} failed: {e}, reconnecting"),
+                }
+            }
+            *conn = None;
+            connect_failed = true;
+            let mut stream = TcpStream::connect(&addr).await?;
+            connect_failed = false;
+            enable_keepalive(&stream, self.keepalive)?;
+            let key = handshake_client(&mut stream, &psk).await?;
+            send_frame(&mut stream, &key, &body, &frame, &addr).await?;
+            tracing::debug!("opened persistent connection to {addr}");
+            *conn = Some(PooledConnection { stream, key });
+            Ok(true)
+        })
+        .await;
+        // 超时发生在等待连接锁期间时不算连接失败
+        if let Some(cooldown) = cooldown.filter(|_| connect_failed) {
+            cooldown.mark_failed(&addr);
+        }
+        match result {
+            Ok(Ok(false)) => PeerSendResult {
+                addr,
+                outcome: PeerSendOutcome::Coalesced,
+            },
+            other => send_result(addr, other.map(|sent| sent.map(|_| ()))),
+        }
+    }
+}
+
+/// 将剪贴板更新消息加密后广播到配置中的所有 peers（2秒超时，并行执行），返回每个对端的发送结果。
 /// 每次连接先完成 X25519 密钥交换握手，再使用派生出的会话密钥加密发送。
-pub async fn broadcast_to_peers(config: &AppConfig, msg: &ProtocolMessage) -> Result<()> {
-    let psk = key_from_hex(&config.secret_key)?;
-    let psk_bytes: [u8; 32] = psk
-        .as_slice()
-        .try_into()
-        .map_err(|_| anyhow!("key length mismatch"))?;
-    let body = encode_message(msg)?;
+pub async fn broadcast_to_peers(
+    config: &AppConfig,
+    msg: &ProtocolMessage,
+) -> Result<Vec<PeerSendResult>> {
+    broadcast_with_pool(config, msg, None).await
+}
 
-    let timeout_duration = Duration::from_secs(2);
-    let mut tasks = Vec::new();
+/// 同 [`broadcast_to_peers`]，提供连接池时经长连接发送。
+pub async fn broadcast_with_pool(
+    config: &AppConfig,
+    msg: &ProtocolMessage,
+    pool: Option<&ConnectionPool>,
+) -> Result<Vec<PeerSendResult>> {
+    broadcast_body(config, Arc::new(encode_message(msg)?), pool, None).await
+}
 
+/// 广播一条已编码的消息：所有对端共享同一份明文缓冲区，不再逐个复制。
+///
+/// 提供 `cooldown` 时跳过冷却期内的对端（结果为 [`PeerSendOutcome::Skipped`]），
+/// 并把本次连接失败的对端记入其中。
+pub async fn broadcast_body(
+    config: &AppConfig,
+    body: Arc<Vec<u8>>,
+    pool: Option<&ConnectionPool>,
+    cooldown: Option<&PeerCooldown>,
+) -> Result<Vec<PeerSendResult>> {
+    let psk = sending_psk(config)?;
+    let frame = FrameOptions::from_config(config);
+    let body = compress_for_send(config, body).await?;
+
+    let mut tasks = Vec::new();
     for peer in &config.peers {
         let addr = format!("{}:{}", peer.host, peer.port);
-        let addr_clone = addr.clone();
-        let body_clone = body.clone();
-        let psk_clone = psk_bytes;
-
-        let task = tokio::spawn(async move {
-            let result = tokio::time::timeout(timeout_duration, async {
-                let mut stream = TcpStream::connect(&addr_clone).await?;
-                let key = handshake_client(&mut stream, &psk_clone).await?;
-                let (nonce, ciphertext) = encrypt(&key, &body_clone)?;
-                let mut frame_body = Vec::with_capacity(12 + ciphertext.len());
-                frame_body.extend_from_slice(&nonce);
-                frame_body.extend_from_slice(&ciphertext);
-                let frame = encode_frame(&frame_body);
-                stream.write_all(&frame).await?;
-                Ok::<_, anyhow::Error>(())
-            })
-            .await;
+        if cooldown.is_some_and(|c| c.is_cooling(&addr)) {
+            tasks.push((addr, None));
+            continue;
+        }
+        let frame = frame.clone();
+        let cooldown = cooldown.cloned();
+        let task = match pool {
+            Some(pool) => {
+                let (pool, addr, body) = (pool.clone(), addr.clone(), body.clone());
+                tokio::spawn(async move { pool.send(addr, psk, body, frame, cooldown).await })
+            }
+            None => tokio::spawn(send_body(addr.clone(), psk, body.clone(), frame, cooldown)),
+        };
+        tasks.push((addr, Some(task)));
+    }
 
-            match result {
-                Ok(Ok(())) => {
-                    tracing::debug!("successfully sent to {addr_clone}");
-                }
-                Ok(Err(e)) => {
-                    tracing::warn!("send to {addr_clone} failed: {e}");
+    let mut results = Vec::with_capacity(tasks.len());
+    for (addr, task) in tasks {
+        let Some(task) = task else {
+            results.push(PeerSendResult {
+                addr,
+                outcome: PeerSendOutcome::Skipped,
+            });
+            continue;
+        };
+        results.push(task.await.unwrap_or_else(|e| PeerSendResult {
+            addr,
+            outcome: PeerSendOutcome::Error(format!("send task failed: {e}")),
+        }));
+    }
+
+    Ok(results)
+}
+
+/// 将消息发送给单个地址（例如回复文件请求），不要求该地址在 peers 中。
+pub async fn send_to_peer(
+    config: &AppConfig,
+    addr: SocketAddr,
+    msg: &ProtocolMessage,
+) -> Result<PeerSendResult> {
+    send_to_addr(config, addr.to_string(), msg).await
+}
+
+/// 同 [`send_to_peer`]，地址为 `host:port` 形式（主机可为域名）。
+pub(crate) async fn send_to_addr(
+    config: &AppConfig,
+    addr: String,
+    msg: &ProtocolMessage,
+) -> Result<PeerSendResult> {
+    let psk = sending_psk(config)?;
+    let body = compress_for_send(config, Arc::new(encode_message(msg)?)).await?;
+    let frame = FrameOptions::from_config(config);
+    Ok(send_body(addr, psk, body, frame, None).await)
+}
+
+/// 按 `compression` 压缩待发送的消息体，所有对端共享压缩结果。压缩无益时原样返回；
+/// 超过帧体上限的不压缩，仍在发送时按原长度报错，与接收端解压后的上限一致。
+async fn compress_for_send(config: &AppConfig, body: Arc<Vec<u8>>) -> Result<Arc<Vec<u8>>> {
+    let Some(level) = config.compression.zstd_level() else {
+        return Ok(body);
+    };
+    if body.len() > config.max_frame_body {
+        return Ok(body);
+    }
+    let input = Arc::clone(&body);
+    let packed = tokio::task::spawn_blocking(move || compress_body(&input, level)).await??;
+    Ok(packed.map(Arc::new).unwrap_or(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Compression, PeerConfig};
+    use crate::protocol::{encode_frame, ContentType, Selection, MAX_FRAME_BODY, MIN_FRAME_BODY};
+
+    fn free_port() -> u16 {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    #[tokio::test]
+    async fn broadcast_reports_per_peer_outcome() {
+        let live_port = free_port();
+        let dead_port = free_port();
+        let (tx, mut rx) = mpsc::channel(4);
+        let live_config = AppConfig {
+            listen_port: live_port,
+            ..AppConfig::default()
+        };
+        let keys = KeyRing::from_config(&live_config).unwrap();
+        tokio::spawn(
+            NetworkServer::with_keyring(&live_config, keys, tx)
+                .unwrap()
+                .run(),
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let config = AppConfig {
+            peers: vec![
+                PeerConfig::new("127.0.0.1", live_port),
+                PeerConfig::new("127.0.0.1", dead_port),
+            ],
+            ..AppConfig::default()
+        };
+        let msg = ProtocolMessage::ClipboardUpdate {
+            sender_id: [1; 16],
+            content_type: ContentType::Text,
+            payload_size: 2,
+            payload: b"hi".to_vec(),
+            seq: 1,
+            selection: Selection::Clipboard,
+            source_app: None,
+            clock: 0,
+            ttl_secs: 0,
+            sensitive: false,
+        };
+        let results = broadcast_to_peers(&config, &msg).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].addr, format!("127.0.0.1:{live_port}"));
+        assert_eq!(results[0].outcome, PeerSendOutcome::Ok);
+        assert!(matches!(results[1].outcome, PeerSendOutcome::Error(_)));
+
+        let received = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(received.msg, ProtocolMessage::ClipboardUpdate { .. }));
+    }
+
+    #[tokio::test]
+    async fn unreachable_peer_is_skipped_until_cooldown_expires() {
+        let port = free_port();
+        let config = AppConfig {
+            peers: vec![PeerConfig::new("127.0.0.1", port)],
+            ..AppConfig::default()
+        };
+        let body = Arc::new(encode_message(&text_update("hi")).unwrap());
+        let cooldown = PeerCooldown::new(Duration::from_millis(500));
+        let send = || broadcast_body(&config, Arc::clone(&body), None, Some(&cooldown));
+
+        let failed = send().await.unwrap();
+        assert!(matches!(failed[0].outcome, PeerSendOutcome::Error(_)));
+
+        // 对端上线后，冷却期内仍然跳过
+        let (tx, mut rx) = mpsc::channel(4);
+        let server_config = AppConfig {
+            listen_port: port,
+            ..AppConfig::default()
+        };
+        let keys = KeyRing::from_config(&server_config).unwrap();
+        tokio::spawn(
+            NetworkServer::with_keyring(&server_config, keys, tx)
+                .unwrap()
+                .run(),
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(send().await.unwrap()[0].outcome, PeerSendOutcome::Skipped);
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(send().await.unwrap()[0].outcome, PeerSendOutcome::Ok);
+        tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn frames_over_configured_limit_are_rejected() {
+        let limit = MIN_FRAME_BODY;
+        // 帧体 = nonce(12) + 消息 + 认证标签(16)，按固定开销算出恰好达到上限的负载长度
+        let update = |len: usize| ProtocolMessage::ClipboardUpdate {
+            sender_id: [1; 16],
+            content_type: ContentType::Text,
+            payload_size: len as u64,
+            payload: vec![b'x'; len],
+            seq: len as u64 + 1,
+            selection: Selection::Clipboard,
+            source_app: None,
+            clock: 0,
+            ttl_secs: 0,
+            sensitive: false,
+        };
+        let overhead = encode_message(&update(0)).unwrap().len() + 12 + 16;
+        let at_limit = update(limit - overhead);
+        let over_limit = update(limit - overhead + 1);
+
+        let port = free_port();
+        let (tx, mut rx) = mpsc::channel(4);
+        let server_config = AppConfig {
+            listen_port: port,
+            max_frame_body: limit,
+            ..AppConfig::default()
+        };
+        let keys = KeyRing::from_config(&server_config).unwrap();
+        tokio::spawn(NetworkServer::with_keyring(&server_config, keys, tx).unwrap().run());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // 入站：发送端上限更大，接收端按自己的上限拒绝
+        let sender = AppConfig {
+            peers: vec![PeerConfig::new("127.0.0.1", port)],
+            ..AppConfig::default()
+        };
+        let _ = broadcast_to_peers(&sender, &over_limit).await.unwrap();
+        let nothing = tokio::time::timeout(Duration::from_millis(300), rx.recv()).await;
+        assert!(nothing.is_err());
+        assert!(broadcast_to_peers(&sender, &at_limit).await.unwrap()[0].is_ok());
+        let received = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(received.msg, ProtocolMessage::ClipboardUpdate { payload_size, .. }
+            if payload_size == (limit - overhead) as u64));
+
+        // 出站：超过本端上限的帧不会发出
+        let limited = AppConfig {
+            max_frame_body: limit,
+            ..sender
+        };
+        let results = broadcast_to_peers(&limited, &over_limit).await.unwrap();
+        let refused = &results[0].outcome;
+        assert!(matches!(refused, PeerSendOutcome::Error(e) if e.contains("too large")));
+        let nothing = tokio::time::timeout(Duration::from_millis(300), rx.recv()).await;
+        assert!(nothing.is_err());
+    }
+
+    #[tokio::test]
+    async fn panicked_server_is_restarted() {
+        let port = free_port();
+        let config = AppConfig {
+            listen_port: port,
+            peers: vec![PeerConfig::new("127.0.0.1", port)],
+            ..AppConfig::default()
+        };
+        let (tx, mut rx) = mpsc::channel(4);
+        let keys = KeyRing::from_config(&config).unwrap();
+        let server = NetworkServer::with_keyring(&config, keys, tx).unwrap();
+        let starts = Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let counter = starts.clone();
+        let policy = ServerRestartPolicy {
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(20),
+        };
+        tokio::spawn(supervise_server(
+            move || {
+                let first = counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0;
+                let server = server.clone();
+                async move {
+                    if first {
+                        panic!("simulated parser bug");
+                    }
+                    server.run().await
                 }
-                Err(_) => {
-                    tracing::debug!("send to {addr_clone} timed out after 2s");
+            },
+            policy,
+        ));
+
+        let msg = ProtocolMessage::ClipboardUpdate {
+            sender_id: [0u8; 16],
+            content_type: ContentType::Text,
+            payload_size: 2,
+            payload: b"ok".to_vec(),
+            seq: 1,
+            selection: Selection::Clipboard,
+            source_app: None,
+            clock: 0,
+            ttl_secs: 0,
+            sensitive: false,
+        };
+        let mut delivered = false;
+        for _ in 0..20 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            if broadcast_to_peers(&config, &msg).await.unwrap()[0].is_ok() {
+                delivered = true;
+                break;
+            }
+        }
+        assert!(delivered);
+        let incoming = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(incoming.msg, ProtocolMessage::ClipboardUpdate { .. }));
+        assert_eq!(starts.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn port_zero_binds_a_free_port() {
+        let config = AppConfig {
+            listen_port: 0,
+            ..AppConfig::default()
+        };
+        assert!(config.validate().is_ok());
+        let (tx, mut rx) = mpsc::channel(4);
+        let keys = KeyRing::from_config(&config).unwrap();
+        let server = NetworkServer::with_keyring(&config, keys, tx).unwrap();
+        let handle = server.bound_port();
+        assert_eq!(server.local_port(), None);
+
+        let listener = server.bind().await.unwrap();
+        let port = server.local_port().unwrap();
+        assert_ne!(port, 0);
+        assert_eq!(handle.get(), Some(port));
+        tokio::spawn(server.serve(listener));
+
+        let sender = AppConfig {
+            peers: vec![PeerConfig::new("127.0.0.1", port)],
+            ..config.clone()
+        };
+        let msg = ProtocolMessage::ClipboardUpdate {
+            sender_id: [0u8; 16],
+            content_type: ContentType::Text,
+            payload_size: 1,
+            payload: b"z".to_vec(),
+            seq: 1,
+            selection: Selection::Clipboard,
+            source_app: None,
+            clock: 0,
+            ttl_secs: 0,
+            sensitive: false,
+        };
+        assert!(broadcast_to_peers(&sender, &msg).await.unwrap()[0].is_ok());
+        let incoming = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(incoming.msg, ProtocolMessage::ClipboardUpdate { .. }));
+    }
+
+    /// 将日志输出收集到共享缓冲区
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap_or_else(|e| e.into_inner()).extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn connection_logs_carry_connection_id_and_peer() {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        // 单线程运行时中连接任务与测试在同一线程，线程内的默认 subscriber 对其生效
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let config = AppConfig {
+            listen_port: 0,
+            ..AppConfig::default()
+        };
+        let (tx, _rx) = mpsc::channel(4);
+        let keys = KeyRing::from_config(&config).unwrap();
+        let server = NetworkServer::with_keyring(&config, keys, tx).unwrap();
+        let listener = server.bind().await.unwrap();
+        let port = server.local_port().unwrap();
+        tokio::spawn(server.serve(listener));
+
+        // 两个未完成握手就关闭的连接，各自记录一条连接错误
+        for _ in 0..2 {
+            let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+            drop(stream);
+        }
+        let errors = tokio::time::timeout(Duration::from_secs(2), async {
+            loop {
+                let text = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+                let errors: Vec<String> = text
+                    .lines()
+                    .filter(|line| line.contains("connection error"))
+                    .map(str::to_string)
+                    .collect();
+                if errors.len() == 2 {
+                    return errors;
                 }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        let ids: Vec<&str> = errors
+            .iter()
+            .map(|line| {
+                assert!(line.contains("peer=127.0.0.1:"), "{line}");
+                let start = line.find("conn{id=").expect(line) + "conn{id=".len();
+                line[start..].split(' ').next().unwrap()
+            })
+            .collect();
+        assert_ne!(ids[0], ids[1]);
+    }
+
+    #[tokio::test]
+    async fn pooled_connection_has_keepalive_and_is_reused() {
+        let config = AppConfig {
+            listen_port: 0,
+            ..AppConfig::default()
+        };
+        let (tx, mut rx) = mpsc::channel(4);
+        let keys = KeyRing::from_config(&config).unwrap();
+        let server = NetworkServer::with_keyring(&config, keys, tx).unwrap();
+        let listener = server.bind().await.unwrap();
+        let port = server.local_port().unwrap();
+        tokio::spawn(server.serve(listener));
+
+        let sender = AppConfig {
+            peers: vec![PeerConfig::new("127.0.0.1", port)],
+            persistent_connections: true,
+            ..config.clone()
+        };
+        let pool = ConnectionPool::new(KeepaliveSettings::from_config(&sender));
+        for seq in 1..=3 {
+            let msg = ProtocolMessage::ClipboardUpdate {
+                sender_id: [0u8; 16],
+                content_type: ContentType::Text,
+                payload_size: 1,
+                payload: b"k".to_vec(),
+                seq,
+                selection: Selection::Clipboard,
+                source_app: None,
+                clock: 0,
+                ttl_secs: 0,
+                sensitive: false,
+            };
+            let results = broadcast_with_pool(&sender, &msg, Some(&pool)).await.unwrap();
+            assert!(results[0].is_ok(), "{:?}", results[0]);
+            let incoming = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(matches!(incoming.msg, ProtocolMessage::ClipboardUpdate { seq: s, .. } if s == seq));
+        }
+
+        let slot = pool.slot(&format!("127.0.0.1:{port}"));
+        let conn = slot.conn.lock().await;
+        let conn = conn.as_ref().expect("connection kept open");
+        assert!(conn.is_alive());
+        assert!(socket2::SockRef::from(&conn.stream).keepalive().unwrap());
+        assert_eq!(pool.slots.lock().unwrap().len(), 1);
+        assert_eq!(pool.coalesced_drops(), 0);
+    }
+
+    #[tokio::test]
+    async fn stalled_peer_queue_keeps_only_latest_message() {
+        // 只接受连接、从不完成握手的对端：第一条消息卡在握手中，占住连接
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
             }
         });
-        tasks.push(task);
+
+        let config = AppConfig::default();
+        let psk = sending_psk(&config).unwrap();
+        let frame = FrameOptions::from_config(&config);
+        let pool = ConnectionPool::new(KeepaliveSettings::from_config(&config));
+        let send = |n: u8| {
+            let (pool, addr, frame) = (pool.clone(), addr.clone(), frame.clone());
+            let body = Arc::new(vec![n; 1024]);
+            tokio::spawn(async move { pool.send(addr, psk, body, frame, None).await })
+        };
+        let first = send(0);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let mut queued = Vec::new();
+        for n in 1..=10 {
+            queued.push(send(n));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // 卡住期间每个对端只保留最新一条待发消息
+        let slot = pool.slot(&addr);
+        let (_, pending) = slot.pending.lock().unwrap().clone().expect("latest message queued");
+        assert_eq!(*pending, vec![10u8; 1024]);
+        assert_eq!(pool.coalesced_drops(), 9);
+
+        // 被取代的消息不再单独发送
+        for task in queued.into_iter().take(9) {
+            assert_eq!(task.await.unwrap().outcome, PeerSendOutcome::Coalesced);
+        }
+        first.abort();
     }
 
-    for task in tasks {
-        let _ = task.await;
+    #[tokio::test]
+    async fn truncated_frame_body_is_rejected() {
+        let mut short: &[u8] = &[1u8; 10];
+        assert!(read_body(&mut short, MAX_FRAME_BODY).await.is_err());
+        let mut exact: &[u8] = &[2u8; 10];
+        assert_eq!(read_body(&mut exact, 10).await.unwrap(), vec![2u8; 10]);
     }
 
-    Ok(())
-}
+    #[tokio::test]
+    async fn inbound_connections_are_bounded() {
+        let port = free_port();
+        let (tx, _rx) = mpsc::channel(4);
+        let config = AppConfig {
+            listen_port: port,
+            max_inbound_connections: 2,
+            ..AppConfig::default()
+        };
+        let keys = KeyRing::from_config(&config).unwrap();
+        let server = NetworkServer::with_keyring(&config, keys, tx).unwrap();
+        tokio::spawn(server.run());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // 每个连接发送握手公钥，只有被接受处理的连接会收到服务器的公钥
+        let mut streams = Vec::new();
+        for _ in 0..4 {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+            stream.write_all(&[9u8; 32]).await.unwrap();
+            streams.push(stream);
+        }
+        let mut answered = Vec::new();
+        for (i, stream) in streams.iter_mut().enumerate() {
+            let mut reply = [0u8; 32];
+            let read = stream.read_exact(&mut reply);
+            if tokio::time::timeout(Duration::from_millis(300), read).await.is_ok() {
+                answered.push(i);
+            }
+        }
+        assert_eq!(answered.len(), 2);
+
+        // 关闭一个已处理的连接后，等待中的连接得到处理
+        streams.remove(answered[0]);
+        let mut served_after_release = 0;
+        for stream in streams.iter_mut() {
+            let mut reply = [0u8; 32];
+            let read = stream.read_exact(&mut reply);
+            if tokio::time::timeout(Duration::from_millis(500), read).await.is_ok() {
+                served_after_release += 1;
+            }
+        }
+        assert_eq!(served_after_release, 1);
+    }
 
+    #[tokio::test]
+    async fn body_progress_is_reported_per_chunk() {
+        let total = PROGRESS_CHUNK * 5 + 100;
+        let (mut writer, mut reader) = tokio::io::duplex(PROGRESS_CHUNK);
+        // 慢速写入端：每次只写入一小段
+        tokio::spawn(async move {
+            for piece in vec![3u8; total].chunks(PROGRESS_CHUNK / 2) {
+                writer.write_all(piece).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        });
+
+        let mut events = Vec::new();
+        let body = read_body_with_progress(&mut reader, total, |received| events.push(received))
+            .await
+            .unwrap();
+        assert_eq!(body, vec![3u8; total]);
+        assert_eq!(events.len(), 6);
+        assert!(events.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(events.last(), Some(&total));
+
+        // 截断的帧仍然报错
+        let mut short: &[u8] = &[1u8; 10];
+        assert!(read_body_with_progress(&mut short, 20, |_| {}).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn server_reports_inbound_progress() {
+        let port = free_port();
+        let (tx, mut rx) = mpsc::channel(4);
+        let (progress_tx, mut progress_rx) = mpsc::channel(64);
+        let config = AppConfig {
+            listen_port: port,
+            peers: vec![PeerConfig::new("127.0.0.1", port)],
+            ..AppConfig::default()
+        };
+        let keys = KeyRing::from_config(&config).unwrap();
+        let server = NetworkServer::with_keyring(&config, keys, tx)
+            .unwrap()
+            .with_progress(progress_tx);
+        tokio::spawn(server.run());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let payload = vec![5u8; PROGRESS_MIN_FRAME * 2];
+        let msg = ProtocolMessage::ClipboardUpdate {
+            sender_id: [1; 16],
+            content_type: ContentType::Image,
+            payload_size: payload.len() as u64,
+            payload,
+            seq: 1,
+            selection: Selection::Clipboard,
+            source_app: None,
+            clock: 0,
+            ttl_secs: 0,
+            sensitive: false,
+        };
+        broadcast_to_peers(&config, &msg).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let mut last = None;
+        while let Ok(progress) = progress_rx.try_recv() {
+            last = Some(progress);
+        }
+        let last = last.expect("progress events emitted");
+        assert_eq!(last.received, last.total);
+        assert!(last.total > PROGRESS_MIN_FRAME * 2);
+    }
+
+    #[test]
+    fn hexdump_formats_known_buffer() {
+        let data: Vec<u8> = b"Hello, frame!\x00\x01\xff".iter().copied().chain(0..4).collect();
+        let dump = hexdump(&data, 256);
+        assert_eq!(
+            dump,
+            "00000000  48 65 6c 6c 6f 2c 20 66 72 61 6d 65 21 00 01 ff  |Hello, frame!...|\n\
+             00000010  00 01 02 03                                      |....|\n"
+        );
+
+        let dump = hexdump(&[0xabu8; 40], 16);
+        assert_eq!(dump.lines().count(), 2);
+        assert!(dump.ends_with("... (24 more bytes)\n"));
+    }
+
+    #[tokio::test]
+    async fn streamed_frame_matches_encoded_frame() {
+        let nonce = [7u8; 12];
+        let ciphertext = vec![42u8; 1000];
+        let mut written = Vec::new();
+        write_frame(
+            &mut written,
+            AeadAlgorithm::ChaCha20Poly1305,
+            &nonce,
+            &ciphertext,
+            MAX_FRAME_BODY,
+        )
+        .await
+        .unwrap();
+        let body = [&nonce[..], &ciphertext[..]].concat();
+        let expected = encode_frame(&body, MAX_FRAME_BODY).unwrap();
+        assert_eq!(written, expected);
+    }
+
+    #[tokio::test]
+    async fn payload_is_shared_across_peers() {
+        let body = Arc::new(vec![0u8; 1024 * 1024]);
+        let frame = FrameOptions::from_config(&AppConfig::default());
+        let tasks: Vec<_> = (0..3)
+            .map(|_| {
+                let addr = format!("127.0.0.1:{}", free_port());
+                tokio::spawn(send_body(addr, [1u8; 32], Arc::clone(&body), frame.clone(), None))
+            })
+            .collect();
+        // 单线程运行时下任务尚未开始执行：每个对端只持有同一份明文的引用
+        assert_eq!(Arc::strong_count(&body), 4);
+        for task in tasks {
+            assert!(!task.await.unwrap().is_ok());
+        }
+        assert_eq!(Arc::strong_count(&body), 1);
+    }
+
+    #[tokio::test]
+    async fn channel_mismatch_is_ignored() {
+        let port = free_port();
+        let (tx, mut rx) = mpsc::channel(4);
+        let server_config = AppConfig {
+            listen_port: port,
+            channel: "work".into(),
+            ..AppConfig::default()
+        };
+        let keys = KeyRing::from_config(&server_config).unwrap();
+        tokio::spawn(
+            NetworkServer::with_keyring(&server_config, keys, tx)
+                .unwrap()
+                .run(),
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let msg = ProtocolMessage::ClipboardUpdate {
+            sender_id: [1; 16],
+            content_type: ContentType::Text,
+            payload_size: 2,
+            payload: b"hi".to_vec(),
+            seq: 1,
+            selection: Selection::Clipboard,
+            source_app: None,
+            clock: 0,
+            ttl_secs: 0,
+            sensitive: false,
+        };
+        let mut config = AppConfig {
+            peers: vec![PeerConfig::new("127.0.0.1", port)],
+            channel: "home".into(),
+            ..AppConfig::default()
+        };
+        broadcast_to_peers(&config, &msg).await.unwrap();
+        let missed = tokio::time::timeout(Duration::from_millis(300), rx.recv()).await;
+        assert!(missed.is_err(), "mismatched channel message must be dropped");
+
+        config.channel = "work".into();
+        broadcast_to_peers(&config, &msg).await.unwrap();
+        let received = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(received.msg, ProtocolMessage::ClipboardUpdate { .. }));
+    }
+
+    #[tokio::test]
+    async fn aead_mismatch_is_reported() {
+        let err = check_frame_algorithm(1, AeadAlgorithm::ChaCha20Poly1305).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "aead algorithm mismatch: peer uses aes-256-gcm, local config uses chacha20-poly1305"
+        );
+        assert!(check_frame_algorithm(9, AeadAlgorithm::ChaCha20Poly1305).is_err());
+        assert!(check_frame_algorithm(0, AeadAlgorithm::ChaCha20Poly1305).is_ok());
+
+        // 端到端：AES 发送端与 ChaCha 接收端不互通，两端同为 AES 时正常收发
+        let port = free_port();
+        let (tx, mut rx) = mpsc::channel(4);
+        let server_config = AppConfig {
+            listen_port: port,
+            ..AppConfig::default()
+        };
+        let keys = KeyRing::from_config(&server_config).unwrap();
+        let server = NetworkServer::with_keyring(&server_config, keys.clone(), tx.clone()).unwrap();
+        let server_task = tokio::spawn(server.run());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let msg = ProtocolMessage::ClipboardUpdate {
+            sender_id: [1; 16],
+            content_type: ContentType::Text,
+            payload_size: 2,
+            payload: b"hi".to_vec(),
+            seq: 1,
+            selection: Selection::Clipboard,
+            source_app: None,
+            clock: 0,
+            ttl_secs: 0,
+            sensitive: false,
+        };
+        let aes_config = AppConfig {
+            peers: vec![PeerConfig::new("127.0.0.1", port)],
+            aead_algorithm: AeadAlgorithm::Aes256Gcm,
+            ..AppConfig::default()
+        };
+        broadcast_to_peers(&aes_config, &msg).await.unwrap();
+        let missed = tokio::time::timeout(Duration::from_millis(300), rx.recv()).await;
+        assert!(missed.is_err(), "mismatched algorithm message must be dropped");
+
+        server_task.abort();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let aes_server = AppConfig {
+            aead_algorithm: AeadAlgorithm::Aes256Gcm,
+            ..server_config
+        };
+        tokio::spawn(NetworkServer::with_keyring(&aes_server, keys, tx).unwrap().run());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        broadcast_to_peers(&aes_config, &msg).await.unwrap();
+        let received = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(received.msg, ProtocolMessage::ClipboardUpdate { .. }));
+    }
+
+    fn text_update(text: &str) -> ProtocolMessage {
+        ProtocolMessage::ClipboardUpdate {
+            sender_id: [2; 16],
+            content_type: ContentType::Text,
+            payload_size: text.len() as u64,
+            payload: text.as_bytes().to_vec(),
+            seq: 1,
+            selection: Selection::Clipboard,
+            source_app: None,
+            clock: 0,
+            ttl_secs: 0,
+            sensitive: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn insecure_mode_sends_plaintext_frames() {
+        // 原始监听端：握手后读到的帧体就是未加密的协议消息
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let config = AppConfig {
+            peers: vec![PeerConfig::new("127.0.0.1", port)],
+            insecure_no_encryption: true,
+            ..AppConfig::default()
+        };
+        let msg = text_update("plain");
+        let send = tokio::spawn({
+            let (config, msg) = (config.clone(), msg.clone());
+            async move { broadcast_to_peers(&config, &msg).await.unwrap() }
+        });
+        let (mut stream, _) = listener.accept().await.unwrap();
+        handshake_server(&mut stream).await.unwrap();
+        let mut header = [0u8; 4];
+        stream.read_exact(&mut header).await.unwrap();
+        let (id, len) = split_frame_header(u32::from_be_bytes(header));
+        assert_eq!(id, PLAINTEXT_FRAME_ID);
+        let body = read_body(&mut stream, len).await.unwrap();
+        assert_eq!(body, encode_message(&msg).unwrap());
+        assert!(send.await.unwrap()[0].is_ok());
+
+        // 两端都开启明文模式时正常收发
+        let port = free_port();
+        let (tx, mut rx) = mpsc::channel(4);
+        let server_config = AppConfig {
+            listen_port: port,
+            insecure_no_encryption: true,
+            ..AppConfig::default()
+        };
+        let keys = KeyRing::from_config(&server_config).unwrap();
+        tokio::spawn(NetworkServer::with_keyring(&server_config, keys, tx).unwrap().run());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let sender = AppConfig {
+            peers: vec![PeerConfig::new("127.0.0.1", port)],
+            ..config
+        };
+        broadcast_to_peers(&sender, &msg).await.unwrap();
+        let received = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            received.msg,
+            ProtocolMessage::ClipboardUpdate { payload, .. } if payload == b"plain"
+        ));
+    }
+
+    #[tokio::test]
+    async fn mixed_encryption_modes_reject_each_other() {
+        let chacha = AeadAlgorithm::ChaCha20Poly1305;
+        assert!(check_frame_mode(PLAINTEXT_FRAME_ID, chacha, false).is_err());
+        assert!(check_frame_mode(0, chacha, true).is_err());
+        assert!(check_frame_mode(PLAINTEXT_FRAME_ID, chacha, true).is_ok());
+        assert!(check_frame_mode(0, chacha, false).is_ok());
+
+        for server_insecure in [false, true] {
+            let port = free_port();
+            let (tx, mut rx) = mpsc::channel(4);
+            let server_config = AppConfig {
+                listen_port: port,
+                insecure_no_encryption: server_insecure,
+                ..AppConfig::default()
+            };
+            let keys = KeyRing::from_config(&server_config).unwrap();
+            let server = NetworkServer::with_keyring(&server_config, keys, tx).unwrap();
+            let server_task = tokio::spawn(server.run());
+            tokio::time::sleep(Duration::from_millis(100)).await;
+
+            let sender = AppConfig {
+                peers: vec![PeerConfig::new("127.0.0.1", port)],
+                insecure_no_encryption: !server_insecure,
+                ..AppConfig::default()
+            };
+            broadcast_to_peers(&sender, &text_update("mixed")).await.unwrap();
+            let missed = tokio::time::timeout(Duration::from_millis(300), rx.recv()).await;
+            assert!(missed.is_err(), "server_insecure={server_insecure}: message must be rejected");
+            server_task.abort();
+        }
+    }
+
+    #[tokio::test]
+    async fn gzip_frames_roundtrip() {
+        for insecure in [false, true] {
+            // 接收端未开启 gzip_frames：是否包装以帧头为准
+            let port = free_port();
+            let (tx, mut rx) = mpsc::channel(4);
+            let server_config = AppConfig {
+                listen_port: port,
+                insecure_no_encryption: insecure,
+                ..AppConfig::default()
+            };
+            let keys = KeyRing::from_config(&server_config).unwrap();
+            tokio::spawn(NetworkServer::with_keyring(&server_config, keys, tx).unwrap().run());
+            tokio::time::sleep(Duration::from_millis(100)).await;
+
+            let sender = AppConfig {
+                peers: vec![PeerConfig::new("127.0.0.1", port)],
+                insecure_no_encryption: insecure,
+                gzip_frames: true,
+                ..AppConfig::default()
+            };
+            let text = "gzip ".repeat(1000);
+            broadcast_to_peers(&sender, &text_update(&text)).await.unwrap();
+            let received = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(
+                matches!(&received.msg, ProtocolMessage::ClipboardUpdate { payload, .. } if *payload == text.as_bytes()),
+                "insecure={insecure}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn compressed_frames_are_smaller_and_roundtrip() {
+        // 只读取帧头的对端，用于测量线上帧体长度
+        let raw = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let raw_port = raw.local_addr().unwrap().port();
+        let wire_len = tokio::spawn(async move {
+            let (mut stream, _) = raw.accept().await.unwrap();
+            handshake_server(&mut stream).await.unwrap();
+            let mut len_buf = [0u8; 4];
+            stream.read_exact(&mut len_buf).await.unwrap();
+            let (_, len) = split_frame_header(u32::from_be_bytes(len_buf));
+            read_body(&mut stream, len).await.unwrap();
+            len
+        });
+
+        // 接收端未开启压缩：是否压缩以消息体首字节为准
+        let port = free_port();
+        let (tx, mut rx) = mpsc::channel(4);
+        let server_config = AppConfig {
+            listen_port: port,
+            ..AppConfig::default()
+        };
+        let keys = KeyRing::from_config(&server_config).unwrap();
+        tokio::spawn(NetworkServer::with_keyring(&server_config, keys, tx).unwrap().run());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let sender = AppConfig {
+            peers: vec![
+                PeerConfig::new("127.0.0.1", raw_port),
+                PeerConfig::new("127.0.0.1", port),
+            ],
+            compression: Compression::Fast,
+            ..AppConfig::default()
+        };
+        let text: String = "lan clipboard sync ".chars().cycle().take(1 << 20).collect();
+        let results = broadcast_to_peers(&sender, &text_update(&text)).await.unwrap();
+        assert!(results.iter().all(|r| r.outcome == PeerSendOutcome::Ok), "{results:?}");
+
+        let wire_len = wire_len.await.unwrap();
+        assert!(wire_len < text.len() / 20, "frame body {wire_len} bytes");
+        let received = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let ProtocolMessage::ClipboardUpdate { payload, payload_size, .. } = received.msg else {
+            panic!("unexpected message {:?}", received.msg);
+        };
+        assert_eq!(payload_size, text.len() as u64);
+        assert!(payload == text.as_bytes(), "payload differs after decompression");
+    }
+
+    #[tokio::test]
+    async fn gzip_header_flag_is_handled_by_mismatched_peers() {
+        // 不认识该标志位的旧版本把它当作未知算法，给出明确的错误而不是误解析帧体
+        let err = check_frame_algorithm(GZIP_FRAME_FLAG, AeadAlgorithm::ChaCha20Poly1305);
+        assert!(err.unwrap_err().to_string().contains("newer version"));
+
+        let port = free_port();
+        let (tx, mut rx) = mpsc::channel(4);
+        let server_config = AppConfig {
+            listen_port: port,
+            gzip_frames: true,
+            ..AppConfig::default()
+        };
+        let keys = KeyRing::from_config(&server_config).unwrap();
+        tokio::spawn(NetworkServer::with_keyring(&server_config, keys, tx).unwrap().run());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // 帧头标记为 gzip 但帧体不是 gzip：只断开该连接
+        let psk = sending_psk(&server_config).unwrap();
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        handshake_client(&mut stream, &psk).await.unwrap();
+        let header = frame_header(16, GZIP_FRAME_FLAG, MAX_FRAME_BODY).unwrap();
+        stream.write_all(&header.to_be_bytes()).await.unwrap();
+        stream.write_all(&[0u8; 16]).await.unwrap();
+        let missed = tokio::time::timeout(Duration::from_millis(300), rx.recv()).await;
+        assert!(missed.is_err(), "corrupt gzip frame must be dropped");
+
+        // 开启 gzip_frames 的接收端照常接受未包装的帧
+        let sender = AppConfig {
+            peers: vec![PeerConfig::new("127.0.0.1", port)],
+            ..AppConfig::default()
+        };
+        broadcast_to_peers(&sender, &text_update("plain frame")).await.unwrap();
+        let received = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            received.msg,
+            ProtocolMessage::ClipboardUpdate { payload, .. } if payload == b"plain frame"
+        ));
+    }
+}
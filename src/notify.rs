@@ -0,0 +1,212 @@
+//! 桌面通知：收到远端剪贴板内容时提示用户，图片附带缩略图预览。
+//!
+//! Linux 下通过 `notify-send` 发送（未安装时仅记录日志），其他平台暂只记录日志。
+
+use image::{GenericImageView, ImageFormat, ImageReader};
+use std::io::Cursor;
+use std::net::IpAddr;
+
+use crate::clipboard::{plain_text_format, ClipboardItem, MIME_PNG, MIME_SVG};
+
+/// 缩略图最大边长（像素）
+pub const THUMBNAIL_SIZE: u32 = 128;
+/// 超过该像素数的图片不生成缩略图，限制解码的 CPU 与内存开销
+const MAX_THUMBNAIL_SOURCE_PIXELS: u64 = 40_000_000;
+/// 通知正文中文本预览的最大字符数
+const TEXT_PREVIEW_CHARS: usize = 80;
+
+/// 一条待展示的通知。
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub summary: String,
+    pub body: String,
+    /// 图片缩略图（PNG）
+    pub thumbnail: Option<Vec<u8>>,
+    /// 收到链接时提供「打开链接」操作
+    pub open_url: Option<String>,
+}
+
+impl Notification {
+    /// 根据收到的剪贴板条目构造通知，图片会生成缩略图；带有来源应用名时一并显示。
+    pub fn for_item(item: &ClipboardItem, from: IpAddr, source_app: Option<&str>) -> Self {
+        let (summary, thumbnail) = match item {
+            ClipboardItem::Text(_) => ("收到文本".to_string(), None),
+            ClipboardItem::Url(_) => ("收到链接".to_string(), None),
+            ClipboardItem::Image(bytes) => ("收到图片".to_string(), make_thumbnail(bytes)),
+            ClipboardItem::Files(files) => (format!("收到 {} 个文件", files.len()), None),
+            ClipboardItem::Multi(formats) => match formats.iter().find(|(m, _)| m == MIME_PNG) {
+                Some((_, png)) if formats.iter().any(|(m, _)| m == MIME_SVG) => {
+                    ("收到矢量图".to_string(), make_thumbnail(png))
+                }
+                _ => ("收到富文本".to_string(), None),
+            },
+        };
+        Self {
+            summary,
+            body: match source_app {
+                Some(app) => format!("{}（来自 {} · {}）", describe_item(item), from, app),
+                None => format!("{}（来自 {}）", describe_item(item), from),
+            },
+            thumbnail,
+            open_url: match item {
+                ClipboardItem::Url(url) => Some(url.clone()),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// 生成剪贴板条目的简要描述，用于通知正文与历史记录。
+pub fn describe_item(item: &ClipboardItem) -> String {
+    match item {
+        ClipboardItem::Text(text) | ClipboardItem::Url(text) => text_preview(text),
+        ClipboardItem::Image(bytes) => match image_dimensions(bytes) {
+            Some((w, h)) => format!("{}x{} 图片", w, h),
+            None => format!("图片 {} 字节", bytes.len()),
+        },
+        ClipboardItem::Files(files) => files
+            .iter()
+            .map(|f| {
+                std::path::Path::new(&f.path)
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_else(|| f.path.clone())
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        ClipboardItem::Multi(formats) => match plain_text_format(formats) {
+            Some(text) => text_preview(text),
+            None => format!("{} 种格式", formats.len()),
+        },
+    }
+}
+
+/// 截取文本开头作为预览，超出部分以省略号表示。
+fn text_preview(text: &str) -> String {
+    let mut preview: String = text.chars().take(TEXT_PREVIEW_CHARS).collect();
+    if text.chars().nth(TEXT_PREVIEW_CHARS).is_some() {
+        preview.push('…');
+    }
+    preview
+}
+
+/// 只解析图片头部获取尺寸，不解码像素。
+fn image_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// 生成最大边长为 [`THUMBNAIL_SIZE`] 的 PNG 缩略图（保持宽高比）。
+///
+/// 图片本身不超过缩略图尺寸时直接复用原数据；无法识别或像素数过大时返回 None。
+pub fn make_thumbnail(bytes: &[u8]) -> Option<Vec<u8>> {
+    let (w, h) = image_dimensions(bytes)?;
+    if w <= THUMBNAIL_SIZE && h <= THUMBNAIL_SIZE {
+        return Some(bytes.to_vec());
+    }
+    if u64::from(w) * u64::from(h) > MAX_THUMBNAIL_SOURCE_PIXELS {
+        tracing::debug!("image {}x{} too large for thumbnail, skipped", w, h);
+        return None;
+    }
+    let img = image::load_from_memory(bytes)
+        .map_err(|e| tracing::debug!("thumbnail decode failed: {e}"))
+        .ok()?;
+    let thumb = img.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+    tracing::debug!("generated thumbnail {:?} from {}x{}", thumb.dimensions(), w, h);
+    let mut out = Vec::new();
+    thumb
+        .write_to(&mut Cursor::new(&mut out), ImageFormat::Png)
+        .map_err(|e| tracing::debug!("thumbnail encode failed: {e}"))
+        .ok()?;
+    Some(out)
+}
+
+/// 展示通知（不阻塞调用方）。
+///
+/// 带链接的通知在 Linux 下附加「打开链接」操作，用户点击后调用 `xdg-open` 打开。
+pub fn show(notification: &Notification) {
+    #[cfg(target_os = "linux")]
+    {
+        let mut cmd = std::process::Command::new("notify-send");
+        cmd.arg("--app-name=lan-clipboard-sync");
+        if let Some(thumb) = &notification.thumbnail {
+            let path = std::env::temp_dir().join("lan-clipboard-sync-thumbnail.png");
+            match std::fs::write(&path, thumb) {
+                Ok(()) => {
+                    cmd.arg("--icon").arg(&path);
+                }
+                Err(e) => tracing::debug!("failed to write thumbnail: {e}"),
+            }
+        }
+        if notification.open_url.is_some() {
+            // --wait 使 notify-send 在通知关闭前不退出，并在标准输出打印被点击的操作名
+            cmd.arg("--action=open=打开链接")
+                .arg("--wait")
+                .stdout(std::process::Stdio::piped());
+        }
+        cmd.arg(&notification.summary).arg(&notification.body);
+        let open_url = notification.open_url.clone();
+        match cmd.spawn() {
+            Ok(child) => {
+                std::thread::spawn(move || {
+                    let Ok(output) = child.wait_with_output() else {
+                        return;
+                    };
+                    if let Some(url) = open_url {
+                        if String::from_utf8_lossy(&output.stdout).trim() == "open" {
+                            let opened = std::process::Command::new("xdg-open").arg(&url).spawn();
+                            if let Err(e) = opened {
+                                tracing::warn!("failed to open url: {e}");
+                            }
+                        }
+                    }
+                });
+            }
+            Err(e) => tracing::debug!("notify-send unavailable: {e}"),
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    tracing::info!("{}: {}", notification.summary, notification.body);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let img = image::RgbaImage::from_pixel(width, height, image::Rgba([10, 20, 30, 255]));
+        let mut out = Vec::new();
+        img.write_to(&mut Cursor::new(&mut out), ImageFormat::Png)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn thumbnail_is_bounded_to_max_dimension() {
+        let thumb = make_thumbnail(&png(1024, 512)).unwrap();
+        let img = image::load_from_memory(&thumb).unwrap();
+        assert_eq!(img.dimensions(), (THUMBNAIL_SIZE, THUMBNAIL_SIZE / 2));
+
+        // 小图直接复用原数据
+        let small = png(32, 16);
+        assert_eq!(make_thumbnail(&small).unwrap(), small);
+
+        assert!(make_thumbnail(b"not an image").is_none());
+    }
+
+    #[test]
+    fn url_notification_offers_open_action() {
+        let from: IpAddr = "192.168.1.2".parse().unwrap();
+        let n = Notification::for_item(&ClipboardItem::Url("https://example.com".into()), from, None);
+        assert_eq!(n.summary, "收到链接");
+        assert_eq!(n.open_url.as_deref(), Some("https://example.com"));
+        assert!(n.body.contains("https://example.com"));
+
+        let n = Notification::for_item(&ClipboardItem::Text("hi".into()), from, Some("firefox"));
+        assert!(n.open_url.is_none());
+        assert!(n.body.ends_with("（来自 192.168.1.2 · firefox）"));
+    }
+}
@@ -0,0 +1,167 @@
+//! 配对字符串：将本机密钥、监听端口与设备名打包成紧凑字符串，便于在新设备上一键导入。
+//!
+//! 编码格式（base64url，无填充）：
+//! `version(1) | secret_key(32) | listen_port(u16 BE) | name_len(1) | device_name(UTF-8)`
+
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+
+use crate::config::{AppConfig, PeerConfig};
+
+/// 配对字符串格式版本
+const PAIRING_VERSION: u8 = 1;
+/// 配对字符串前缀，便于识别与粘贴时校验
+const PAIRING_PREFIX: &str = "lcs:";
+const KEY_LEN: usize = 32;
+/// 设备名最大字节数
+pub const MAX_DEVICE_NAME_LEN: usize = 64;
+
+/// 配对字符串中携带的信息。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PairingInfo {
+    pub secret_key: [u8; KEY_LEN],
+    pub listen_port: u16,
+    pub device_name: String,
+}
+
+impl PairingInfo {
+    /// 从当前配置构造配对信息。
+    pub fn from_config(config: &AppConfig) -> Result<Self> {
+        let bytes = hex::decode(&config.secret_key)?;
+        let secret_key: [u8; KEY_LEN] = bytes
+            .try_into()
+            .map_err(|_| anyhow!("secret_key must be 32 bytes after decoding"))?;
+        let mut device_name = config.resolved_device_name();
+        truncate_utf8(&mut device_name, MAX_DEVICE_NAME_LEN);
+        Ok(Self {
+            secret_key,
+            listen_port: config.listen_port,
+            device_name,
+        })
+    }
+
+    /// 编码为带前缀的配对字符串。
+    pub fn encode(&self) -> String {
+        let name = self.device_name.as_bytes();
+        let name_len = name.len().min(MAX_DEVICE_NAME_LEN);
+        let mut buf = Vec::with_capacity(1 + KEY_LEN + 2 + 1 + name_len);
+        buf.push(PAIRING_VERSION);
+        buf.extend_from_slice(&self.secret_key);
+        buf.extend_from_slice(&self.listen_port.to_be_bytes());
+        buf.push(name_len as u8);
+        buf.extend_from_slice(&name[..name_len]);
+        format!("{}{}", PAIRING_PREFIX, URL_SAFE_NO_PAD.encode(buf))
+    }
+
+    /// 解析配对字符串，并对各字段做合法性检查。
+    pub fn decode(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let body = s.strip_prefix(PAIRING_PREFIX).unwrap_or(s);
+        let data = URL_SAFE_NO_PAD
+            .decode(body)
+            .map_err(|e| anyhow!("invalid pairing string encoding: {e}"))?;
+        if data.len() < 1 + KEY_LEN + 2 + 1 {
+            return Err(anyhow!("pairing string too short"));
+        }
+        if data[0] != PAIRING_VERSION {
+            return Err(anyhow!("unsupported pairing string version {}", data[0]));
+        }
+        let mut secret_key = [0u8; KEY_LEN];
+        secret_key.copy_from_slice(&data[1..1 + KEY_LEN]);
+        let rest = &data[1 + KEY_LEN..];
+        let listen_port = u16::from_be_bytes([rest[0], rest[1]]);
+        if listen_port == 0 {
+            return Err(anyhow!("pairing string has invalid port 0"));
+        }
+        let name_len = rest[2] as usize;
+        let name = &rest[3..];
+        if name_len > MAX_DEVICE_NAME_LEN || name.len() != name_len {
+            return Err(anyhow!("pairing string has invalid device name length"));
+        }
+        let device_name = String::from_utf8(name.to_vec())
+            .map_err(|_| anyhow!("pairing string device name is not valid UTF-8"))?;
+        if device_name.chars().any(char::is_control) {
+            return Err(anyhow!("pairing string device name contains control characters"));
+        }
+        if secret_key.iter().all(|b| *b == 0) {
+            return Err(anyhow!("pairing string carries an all-zero key"));
+        }
+        Ok(Self {
+            secret_key,
+            listen_port,
+            device_name,
+        })
+    }
+
+    /// 将配对信息导入配置：替换共享密钥，若提供了对端地址则追加为 peer（已存在则更新端口）。
+    pub fn apply_to(&self, config: &mut AppConfig, peer_host: Option<&str>) {
+        config.secret_key = hex::encode(self.secret_key);
+        if let Some(host) = peer_host.map(str::trim).filter(|h| !h.is_empty()) {
+            match config.peers.iter_mut().find(|p| p.host == host) {
+                Some(peer) => peer.port = self.listen_port,
+                None => config.peers.push(PeerConfig::new(host, self.listen_port)),
+            }
+        }
+    }
+}
+
+/// 按字符边界截断字符串，保证不超过 `max` 字节。
+fn truncate_utf8(s: &mut String, max: usize) {
+    if s.len() <= max {
+        return;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    s.truncate(end);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> PairingInfo {
+        PairingInfo {
+            secret_key: [0xab; 32],
+            listen_port: 5000,
+            device_name: "办公室电脑".to_string(),
+        }
+    }
+
+    #[test]
+    fn pairing_roundtrip() {
+        let info = sample();
+        let s = info.encode();
+        assert!(s.starts_with(PAIRING_PREFIX));
+        assert_eq!(PairingInfo::decode(&s).unwrap(), info);
+        // 去掉前缀也能解析
+        assert_eq!(
+            PairingInfo::decode(s.strip_prefix(PAIRING_PREFIX).unwrap()).unwrap(),
+            info
+        );
+    }
+
+    #[test]
+    fn pairing_rejects_malformed() {
+        let good = sample().encode();
+        assert!(PairingInfo::decode("lcs:!!!not-base64").is_err());
+        assert!(PairingInfo::decode(&good[..good.len() - 4]).is_err());
+
+        let mut zero_port = sample();
+        zero_port.listen_port = 0;
+        assert!(PairingInfo::decode(&zero_port.encode()).is_err());
+
+        let mut zero_key = sample();
+        zero_key.secret_key = [0; 32];
+        assert!(PairingInfo::decode(&zero_key.encode()).is_err());
+
+        let mut bad_version = URL_SAFE_NO_PAD
+            .decode(good.strip_prefix(PAIRING_PREFIX).unwrap())
+            .unwrap();
+        bad_version[0] = 9;
+        let s = format!("{}{}", PAIRING_PREFIX, URL_SAFE_NO_PAD.encode(bad_version));
+        assert!(PairingInfo::decode(&s).is_err());
+    }
+}
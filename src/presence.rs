@@ -0,0 +1,100 @@
+//! 设备在线状态：根据对端周期性广播的 Presence 消息维护设备注册表。
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Presence 广播间隔
+pub const PRESENCE_INTERVAL: Duration = Duration::from_secs(30);
+/// 超过该时长未收到 Presence 的设备视为离线
+pub const PRESENCE_TTL: Duration = Duration::from_secs(90);
+
+/// 单个在线设备的信息。
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    pub id: [u8; 16],
+    pub name: String,
+    pub capabilities: u32,
+    /// 最近一次 Presence 的来源地址
+    pub addr: IpAddr,
+    pub last_seen: Instant,
+}
+
+/// 设备注册表（可跨线程共享，克隆后指向同一份数据）。
+#[derive(Debug, Clone, Default)]
+pub struct DeviceRegistry {
+    inner: Arc<Mutex<HashMap<[u8; 16], DeviceInfo>>>,
+}
+
+impl DeviceRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录（或刷新）一条设备在线信息。
+    pub fn update(&self, id: [u8; 16], name: String, capabilities: u32, addr: IpAddr, now: Instant) {
+        let mut map = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        map.insert(
+            id,
+            DeviceInfo {
+                id,
+                name,
+                capabilities,
+                addr,
+                last_seen: now,
+            },
+        );
+    }
+
+    /// 地址为 `addr` 的在线设备是否声明了能力位 `capability`；未知设备视为不支持。
+    pub fn supports(&self, addr: IpAddr, capability: u32) -> bool {
+        let map = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        map.values()
+            .any(|d| d.addr == addr && d.capabilities & capability != 0)
+    }
+
+    /// 移除超过 `ttl` 未出现的设备，返回移除数量。
+    pub fn prune(&self, now: Instant, ttl: Duration) -> usize {
+        let mut map = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let before = map.len();
+        map.retain(|_, d| now.saturating_duration_since(d.last_seen) <= ttl);
+        before - map.len()
+    }
+
+    /// 返回当前所有设备的快照（按设备名排序）。
+    pub fn snapshot(&self) -> Vec<DeviceInfo> {
+        let map = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let mut devices: Vec<DeviceInfo> = map.values().cloned().collect();
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
+        devices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_populates_and_expires() {
+        let registry = DeviceRegistry::new();
+        let t0 = Instant::now();
+        let (desk, laptop) = ("10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap());
+        registry.update([1; 16], "desk".into(), 7, desk, t0);
+        registry.update([2; 16], "laptop".into(), 1, laptop, t0 + Duration::from_secs(60));
+        assert!(registry.supports(desk, 4));
+        assert!(!registry.supports(laptop, 4));
+
+        let devices = registry.snapshot();
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].name, "desk");
+        assert_eq!(devices[0].capabilities, 7);
+
+        // desk 已超过 TTL，laptop 仍在线
+        let removed = registry.prune(t0 + PRESENCE_TTL + Duration::from_secs(1), PRESENCE_TTL);
+        assert_eq!(removed, 1);
+        let devices = registry.snapshot();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].id, [2; 16]);
+    }
+}
@@ -1,3 +1,5 @@
+use std::io::{Read, Write};
+
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 
@@ -6,7 +8,14 @@ use serde::{Deserialize, Serialize};
 pub enum ContentType {
     Text = 1,
     Image = 2,
+    /// 文件：负载为二进制文件条目（见 [`FilesPayload`]），旧版本发送端为 JSON 编码的条目列表
     Files = 3,
+    /// 文本增量：负载为相对上一次文本的差异（见 `delta` 模块）
+    TextDelta = 4,
+    /// http(s) 链接：负载为 UTF-8 编码的 URL
+    Url = 5,
+    /// 多格式内容：负载为 JSON 编码的（MIME 类型, 数据）列表
+    Multi = 6,
 }
 
 impl TryFrom<u8> for ContentType {
@@ -17,17 +26,166 @@ impl TryFrom<u8> for ContentType {
             1 => Ok(ContentType::Text),
             2 => Ok(ContentType::Image),
             3 => Ok(ContentType::Files),
+            4 => Ok(ContentType::TextDelta),
+            5 => Ok(ContentType::Url),
+            6 => Ok(ContentType::Multi),
             _ => Err(anyhow!("unknown content type {}", v)),
         }
     }
 }
 
+/// 剪贴板选区类型
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Selection {
+    /// 常规剪贴板（Ctrl+C / Ctrl+V）
+    #[default]
+    Clipboard,
+    /// Linux PRIMARY 选区（选中即复制、中键粘贴）
+    Primary,
+}
+
+/// 内容类型字节中表示 PRIMARY 选区的标志位（常规剪贴板不设置，保持与旧版本兼容）
+const SELECTION_PRIMARY_FLAG: u8 = 0x80;
+/// 内容类型字节中表示携带写入时钟的标志位（时钟为 0 时不设置，保持与旧版本兼容）
+const CLOCK_FLAG: u8 = 0x40;
+/// 内容类型字节中表示携带保留时长的标志位（不过期时不设置，保持与旧版本兼容）
+const TTL_FLAG: u8 = 0x20;
+/// 内容类型字节中表示内容带有「勿同步」提示的标志位（见 `SENSITIVE_HINT_FORMATS`）
+const SENSITIVE_FLAG: u8 = 0x10;
+/// 内容类型字节中表示长度之后带有扩展字节的标志位（不携带序号与来源应用名时不设置，保持与旧版本兼容）
+const EXTENSIONS_FLAG: u8 = 0x08;
+/// 扩展字节中表示携带消息序号（u64）的位
+const EXT_SEQ: u8 = 0x01;
+/// 扩展字节中表示携带来源应用名（u8 长度 + UTF-8）的位
+const EXT_SOURCE_APP: u8 = 0x02;
+
 /// 单个文件条目
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileEntry {
     pub name: String,
     pub size: u64,
     pub content: Vec<u8>,
+    /// 发送端原始所在目录（已脱敏，仅用于展示，接收端绝不能作为写入路径）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_path: Option<String>,
+}
+
+/// 二进制文件负载的首字节；旧版本发送的 JSON 条目列表总以 `[` 开头，据此区分两种格式
+const FILES_PAYLOAD_BINARY: u8 = 0x01;
+
+/// 文件负载编码器：逐个追加条目，文件内容直接写入负载，不再构造完整的条目列表与 JSON 中间结果，
+/// 峰值内存为负载本身加上单个文件。
+///
+/// 负载以 [`FILES_PAYLOAD_BINARY`] 开头，之后每个条目依次为（大端序）：u32 名称长度 + 名称、
+/// u32 来源目录长度 + 来源目录（长度 0 表示未携带）、u64 文件大小、u64 内容长度 + 内容。
+#[derive(Debug)]
+pub struct FilesPayload {
+    buf: Vec<u8>,
+    count: usize,
+}
+
+impl Default for FilesPayload {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FilesPayload {
+    pub fn new() -> Self {
+        Self {
+            buf: vec![FILES_PAYLOAD_BINARY],
+            count: 0,
+        }
+    }
+
+    /// 追加一个文件条目。
+    pub fn push(&mut self, entry: &FileEntry) {
+        let source_path = entry.source_path.as_deref().unwrap_or_default();
+        self.buf.reserve(4 + entry.name.len() + 4 + source_path.len() + 16 + entry.content.len());
+        for text in [entry.name.as_str(), source_path] {
+            self.buf.extend_from_slice(&(text.len() as u32).to_be_bytes());
+            self.buf.extend_from_slice(text.as_bytes());
+        }
+        self.buf.extend_from_slice(&entry.size.to_be_bytes());
+        self.buf.extend_from_slice(&(entry.content.len() as u64).to_be_bytes());
+        self.buf.extend_from_slice(&entry.content);
+        self.count += 1;
+    }
+
+    /// 已追加的条目数
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// 将文件条目编码为二进制文件负载。
+pub fn encode_files_payload(entries: &[FileEntry]) -> Vec<u8> {
+    let mut payload = FilesPayload::new();
+    for entry in entries {
+        payload.push(entry);
+    }
+    payload.into_bytes()
+}
+
+/// 解码文件负载，同时接受二进制格式与旧版本的 JSON 格式。
+pub fn decode_files_payload(payload: &[u8]) -> Result<Vec<FileEntry>> {
+    let Some((&FILES_PAYLOAD_BINARY, mut data)) = payload.split_first() else {
+        return Ok(serde_json::from_slice(payload)?);
+    };
+    let mut entries = Vec::new();
+    while !data.is_empty() {
+        let (name, rest) = decode_files_string(data, "file name")?;
+        let (source_path, rest) = decode_files_string(rest, "source path")?;
+        let (size, rest) = decode_u64(rest, "file size")?;
+        let (len, rest) = decode_u64(rest, "file content length")?;
+        if len > rest.len() as u64 {
+            return Err(anyhow!("file content longer than payload"));
+        }
+        let (content, rest) = rest.split_at(len as usize);
+        entries.push(FileEntry {
+            name,
+            size,
+            content: content.to_vec(),
+            source_path: (!source_path.is_empty()).then_some(source_path),
+        });
+        data = rest;
+    }
+    Ok(entries)
+}
+
+/// 解析文件负载中 u32 长度前缀的 UTF-8 字符串，返回剩余数据。
+fn decode_files_string<'a>(data: &'a [u8], what: &str) -> Result<(String, &'a [u8])> {
+    if data.len() < 4 {
+        return Err(anyhow!("files payload too short for {}", what));
+    }
+    let len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    let data = &data[4..];
+    if data.len() < len {
+        return Err(anyhow!("files payload too short for {}", what));
+    }
+    let text = std::str::from_utf8(&data[..len])
+        .map_err(|_| anyhow!("{} is not valid utf-8", what))?;
+    Ok((text.to_string(), &data[len..]))
+}
+
+/// 文件清单条目：只描述文件，不含内容
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub name: String,
+    pub size: u64,
+    /// 文件内容的 SHA-256
+    pub hash: [u8; 32],
+    /// 发送端原始所在目录（仅用于展示）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_path: Option<String>,
 }
 
 /// 协议消息
@@ -39,11 +197,149 @@ pub enum ProtocolMessage {
         content_type: ContentType,
         payload_size: u64,
         payload: Vec<u8>,
+        /// 发送者内单调递增的消息序号，与 `sender_id` 一起唯一标识消息，
+        /// 接收端据此忽略重复投递；0 表示未携带（旧版本发送端）
+        seq: u64,
+        /// 内容来源的选区，接收端据此写入对应选区
+        selection: Selection,
+        /// 复制内容的来源应用名（如 "firefox"），仅用于历史与通知展示，不参与去重；
+        /// 无法获取或发送端未开启时为 `None`
+        source_app: Option<String>,
+        /// 最后写入者胜出使用的写入时钟（见 `lww` 模块）；0 表示未携带
+        clock: u64,
+        /// 内容在接收端保留的秒数，到期后接收端清理（见 `text_ttl` 等配置）；0 表示不过期
+        ttl_secs: u64,
+        /// 发送端剪贴板带有「勿同步」提示（密码管理器等），接收端不会把它写入持久化历史
+        sensitive: bool,
+    },
+    /// 在线状态广播：周期性告知对端本机设备名与支持的能力
+    Presence {
+        sender_id: [u8; 16],
+        device_name: String,
+        /// 能力位图，见 `CAP_*` 常量
+        capabilities: u32,
+    },
+    /// 密钥轮换：宣布新的共享密钥（必须在当前已接受的密钥下加密认证）
+    KeyRotation {
+        sender_id: [u8; 16],
+        key_id: [u8; 8],
+        new_key: [u8; 32],
+    },
+    /// 文件清单：先告知对端要发送的文件，对端只请求本地没有的文件
+    FileManifest {
+        sender_id: [u8; 16],
+        transfer_id: u64,
+        /// 发送端监听端口，用于接收文件请求
+        reply_port: u16,
+        entries: Vec<ManifestEntry>,
+    },
+    /// 文件请求：按清单中的下标请求缺少的文件
+    FileRequest {
+        sender_id: [u8; 16],
+        transfer_id: u64,
+        /// 请求方监听端口，用于接收文件数据
+        reply_port: u16,
+        indices: Vec<u32>,
+    },
+    /// 文件数据：响应文件请求，携带清单下标与文件内容
+    FileData {
+        sender_id: [u8; 16],
+        transfer_id: u64,
+        files: Vec<(u32, FileEntry)>,
+    },
+    /// 回显请求：对端收到后立即以 [`ProtocolMessage::EchoReply`] 原样带回时间戳，用于测量往返延迟
+    EchoRequest {
+        sender_id: [u8; 16],
+        echo_id: u64,
+        /// 发送端时间戳（Unix 纪元起的微秒数），对端不解析
+        timestamp_us: u64,
+        /// 请求方监听端口，用于接收回显回复
+        reply_port: u16,
+    },
+    /// 回显回复：携带请求中的 echo_id 与原始时间戳
+    EchoReply {
+        sender_id: [u8; 16],
+        echo_id: u64,
+        timestamp_us: u64,
+    },
+    /// 大负载分块：剪贴板内容按块逐个发送，接收端确认后发送端才发送下一块
+    ChunkData {
+        sender_id: [u8; 16],
+        transfer_id: u64,
+        /// 发送端监听端口，用于接收块确认
+        reply_port: u16,
+        content_type: ContentType,
+        /// 完整负载的字节数
+        total: u64,
+        /// 本块在完整负载中的偏移
+        offset: u64,
+        data: Vec<u8>,
+    },
+    /// 块确认：接收端已连续收到 `next_offset` 之前的全部数据，发送端从该偏移继续
+    ChunkAck {
+        sender_id: [u8; 16],
+        transfer_id: u64,
+        next_offset: u64,
+    },
+    /// 传输开始：发送大负载之前告知对端即将发送的内容，接收端据此提前显示“正在接收”
+    TransferStart {
+        sender_id: [u8; 16],
+        content_type: ContentType,
+        /// 即将发送的负载字节数
+        total_size: u64,
+    },
+    /// 传输结束：大负载发送完成（无论成功与否），接收端据此结束“正在接收”状态
+    TransferEnd { sender_id: [u8; 16] },
+    /// 大文件分块：一组文件中有文件超过 `file_chunk_threshold` 时，整组文件按块逐个从磁盘读出发送，
+    /// 接收端直接写入下载目录，收齐全部块后组成文件剪贴板条目；块可乱序到达
+    FileChunk {
+        sender_id: [u8; 16],
+        transfer_id: u64,
+        /// 本块在整个传输中的序号（从 0 开始）
+        seq: u32,
+        /// 整个传输的总块数
+        total: u32,
+        /// 本块所属文件在本组中的下标
+        file_index: u32,
+        name: String,
+        file_size: u64,
+        /// 本块在文件中的偏移
+        offset: u64,
+        data: Vec<u8>,
     },
 }
 
+/// 能力位：支持文本同步
+pub const CAP_TEXT: u32 = 1 << 0;
+/// 能力位：支持图片同步
+pub const CAP_IMAGE: u32 = 1 << 1;
+/// 能力位：支持文件同步
+pub const CAP_FILES: u32 = 1 << 2;
+/// 能力位：支持链接类型（[`ContentType::Url`]）
+pub const CAP_URL: u32 = 1 << 3;
+/// 能力位：支持多格式内容（[`ContentType::Multi`]）
+pub const CAP_MULTI: u32 = 1 << 4;
+/// 能力位：支持分块确认与断点续传（[`ProtocolMessage::ChunkData`]）
+pub const CAP_RESUME: u32 = 1 << 5;
+/// 能力位：支持传输状态通知（[`ProtocolMessage::TransferStart`]）
+pub const CAP_TRANSFER_STATUS: u32 = 1 << 6;
+/// 能力位：支持大文件分块接收（[`ProtocolMessage::FileChunk`]）
+pub const CAP_FILE_CHUNKS: u32 = 1 << 7;
+
 const VERSION: u8 = 1;
 const MSG_TYPE_CLIPBOARD: u8 = 1;
+const MSG_TYPE_PRESENCE: u8 = 2;
+const MSG_TYPE_KEY_ROTATION: u8 = 3;
+const MSG_TYPE_FILE_MANIFEST: u8 = 4;
+const MSG_TYPE_FILE_REQUEST: u8 = 5;
+const MSG_TYPE_FILE_DATA: u8 = 6;
+const MSG_TYPE_ECHO_REQUEST: u8 = 7;
+const MSG_TYPE_ECHO_REPLY: u8 = 8;
+const MSG_TYPE_CHUNK_DATA: u8 = 9;
+const MSG_TYPE_CHUNK_ACK: u8 = 10;
+const MSG_TYPE_TRANSFER_START: u8 = 11;
+const MSG_TYPE_TRANSFER_END: u8 = 12;
+const MSG_TYPE_FILE_CHUNK: u8 = 13;
 const SENDER_ID_LEN: usize = 16;
 
 /// 将 ProtocolMessage 编码为未加密的字节流
@@ -56,17 +352,264 @@ pub fn encode_message(msg: &ProtocolMessage) -> Result<Vec<u8>> {
             content_type,
             payload_size,
             payload,
+            seq,
+            selection,
+            source_app,
+            clock,
+            ttl_secs,
+            sensitive,
         } => {
+            // 一次分配到位，避免大图片在追加负载时反复扩容复制
+            let app_len = source_app.as_ref().map_or(0, |app| app.len() + 1);
+            let clock_len = if *clock != 0 { 8 } else { 0 };
+            let ttl_len = if *ttl_secs != 0 { 8 } else { 0 };
+            let seq_len = if *seq != 0 { 8 } else { 0 };
+            let mut extensions = 0;
+            if seq_len != 0 {
+                extensions |= EXT_SEQ;
+            }
+            if source_app.is_some() {
+                extensions |= EXT_SOURCE_APP;
+            }
+            let ext_len = usize::from(extensions != 0) + seq_len + app_len;
+            buf.reserve_exact(
+                SENDER_ID_LEN + 2 + 8 + clock_len + ttl_len + ext_len + payload.len(),
+            );
             buf.push(MSG_TYPE_CLIPBOARD);
             buf.extend_from_slice(sender_id);
-            buf.push(*content_type as u8);
+            let selection_flag = match selection {
+                Selection::Clipboard => 0,
+                Selection::Primary => SELECTION_PRIMARY_FLAG,
+            };
+            let clock_flag = if clock_len != 0 { CLOCK_FLAG } else { 0 };
+            let ttl_flag = if ttl_len != 0 { TTL_FLAG } else { 0 };
+            let sensitive_flag = if *sensitive { SENSITIVE_FLAG } else { 0 };
+            let ext_flag = if extensions != 0 { EXTENSIONS_FLAG } else { 0 };
+            buf.push(
+                *content_type as u8
+                    | selection_flag
+                    | clock_flag
+                    | ttl_flag
+                    | sensitive_flag
+                    | ext_flag,
+            );
             buf.extend_from_slice(&payload_size.to_be_bytes());
+            // 可选字段都紧跟在长度之后，负载始终位于末尾且长度等于 payload_size
+            if clock_len != 0 {
+                buf.extend_from_slice(&clock.to_be_bytes());
+            }
+            if ttl_len != 0 {
+                buf.extend_from_slice(&ttl_secs.to_be_bytes());
+            }
+            if extensions != 0 {
+                buf.push(extensions);
+            }
+            if seq_len != 0 {
+                buf.extend_from_slice(&seq.to_be_bytes());
+            }
+            if let Some(app) = source_app {
+                let app = app.as_bytes();
+                if app.len() > u8::MAX as usize {
+                    return Err(anyhow!("source app name too long: {} bytes", app.len()));
+                }
+                buf.push(app.len() as u8);
+                buf.extend_from_slice(app);
+            }
             buf.extend_from_slice(payload);
         }
+        ProtocolMessage::Presence {
+            sender_id,
+            device_name,
+            capabilities,
+        } => {
+            let name = device_name.as_bytes();
+            if name.len() > u8::MAX as usize {
+                return Err(anyhow!("device name too long: {} bytes", name.len()));
+            }
+            buf.push(MSG_TYPE_PRESENCE);
+            buf.extend_from_slice(sender_id);
+            buf.extend_from_slice(&capabilities.to_be_bytes());
+            buf.push(name.len() as u8);
+            buf.extend_from_slice(name);
+        }
+        ProtocolMessage::KeyRotation {
+            sender_id,
+            key_id,
+            new_key,
+        } => {
+            buf.push(MSG_TYPE_KEY_ROTATION);
+            buf.extend_from_slice(sender_id);
+            buf.extend_from_slice(key_id);
+            buf.extend_from_slice(new_key);
+        }
+        ProtocolMessage::FileManifest {
+            sender_id,
+            transfer_id,
+            reply_port,
+            entries,
+        } => {
+            buf.push(MSG_TYPE_FILE_MANIFEST);
+            buf.extend_from_slice(sender_id);
+            buf.extend_from_slice(&transfer_id.to_be_bytes());
+            buf.extend_from_slice(&reply_port.to_be_bytes());
+            buf.extend_from_slice(&serde_json::to_vec(entries)?);
+        }
+        ProtocolMessage::FileRequest {
+            sender_id,
+            transfer_id,
+            reply_port,
+            indices,
+        } => {
+            buf.push(MSG_TYPE_FILE_REQUEST);
+            buf.extend_from_slice(sender_id);
+            buf.extend_from_slice(&transfer_id.to_be_bytes());
+            buf.extend_from_slice(&reply_port.to_be_bytes());
+            buf.extend_from_slice(&serde_json::to_vec(indices)?);
+        }
+        ProtocolMessage::FileData {
+            sender_id,
+            transfer_id,
+            files,
+        } => {
+            buf.push(MSG_TYPE_FILE_DATA);
+            buf.extend_from_slice(sender_id);
+            buf.extend_from_slice(&transfer_id.to_be_bytes());
+            buf.extend_from_slice(&serde_json::to_vec(files)?);
+        }
+        ProtocolMessage::EchoRequest {
+            sender_id,
+            echo_id,
+            timestamp_us,
+            reply_port,
+        } => {
+            buf.push(MSG_TYPE_ECHO_REQUEST);
+            buf.extend_from_slice(sender_id);
+            buf.extend_from_slice(&echo_id.to_be_bytes());
+            buf.extend_from_slice(&timestamp_us.to_be_bytes());
+            buf.extend_from_slice(&reply_port.to_be_bytes());
+        }
+        ProtocolMessage::EchoReply {
+            sender_id,
+            echo_id,
+            timestamp_us,
+        } => {
+            buf.push(MSG_TYPE_ECHO_REPLY);
+            buf.extend_from_slice(sender_id);
+            buf.extend_from_slice(&echo_id.to_be_bytes());
+            buf.extend_from_slice(&timestamp_us.to_be_bytes());
+        }
+        ProtocolMessage::ChunkData {
+            sender_id,
+            transfer_id,
+            reply_port,
+            content_type,
+            total,
+            offset,
+            data,
+        } => {
+            buf.reserve_exact(SENDER_ID_LEN + 1 + 8 + 2 + 1 + 8 + 8 + data.len());
+            buf.push(MSG_TYPE_CHUNK_DATA);
+            buf.extend_from_slice(sender_id);
+            buf.extend_from_slice(&transfer_id.to_be_bytes());
+            buf.extend_from_slice(&reply_port.to_be_bytes());
+            buf.push(*content_type as u8);
+            buf.extend_from_slice(&total.to_be_bytes());
+            buf.extend_from_slice(&offset.to_be_bytes());
+            buf.extend_from_slice(data);
+        }
+        ProtocolMessage::ChunkAck {
+            sender_id,
+            transfer_id,
+            next_offset,
+        } => {
+            buf.push(MSG_TYPE_CHUNK_ACK);
+            buf.extend_from_slice(sender_id);
+            buf.extend_from_slice(&transfer_id.to_be_bytes());
+            buf.extend_from_slice(&next_offset.to_be_bytes());
+        }
+        ProtocolMessage::TransferStart {
+            sender_id,
+            content_type,
+            total_size,
+        } => {
+            buf.push(MSG_TYPE_TRANSFER_START);
+            buf.extend_from_slice(sender_id);
+            buf.push(*content_type as u8);
+            buf.extend_from_slice(&total_size.to_be_bytes());
+        }
+        ProtocolMessage::TransferEnd { sender_id } => {
+            buf.push(MSG_TYPE_TRANSFER_END);
+            buf.extend_from_slice(sender_id);
+        }
+        ProtocolMessage::FileChunk {
+            sender_id,
+            transfer_id,
+            seq,
+            total,
+            file_index,
+            name,
+            file_size,
+            offset,
+            data,
+        } => {
+            buf.reserve_exact(SENDER_ID_LEN + 1 + 8 + 4 * 3 + 8 * 2 + 4 + name.len() + data.len());
+            buf.push(MSG_TYPE_FILE_CHUNK);
+            buf.extend_from_slice(sender_id);
+            buf.extend_from_slice(&transfer_id.to_be_bytes());
+            buf.extend_from_slice(&seq.to_be_bytes());
+            buf.extend_from_slice(&total.to_be_bytes());
+            buf.extend_from_slice(&file_index.to_be_bytes());
+            buf.extend_from_slice(&file_size.to_be_bytes());
+            buf.extend_from_slice(&offset.to_be_bytes());
+            let name_len = u32::try_from(name.len()).map_err(|_| anyhow!("file name too long"))?;
+            buf.extend_from_slice(&name_len.to_be_bytes());
+            buf.extend_from_slice(name.as_bytes());
+            buf.extend_from_slice(data);
+        }
     }
     Ok(buf)
 }
 
+/// 解析文件传输与回显类消息的公共头部：sender_id + u64 标识，返回剩余数据。
+fn decode_transfer_header(data: &[u8]) -> Result<([u8; 16], u64, &[u8])> {
+    if data.len() < SENDER_ID_LEN + 8 {
+        return Err(anyhow!("message too short for file transfer header"));
+    }
+    let mut sender_id = [0u8; 16];
+    sender_id.copy_from_slice(&data[..SENDER_ID_LEN]);
+    let mut id_bytes = [0u8; 8];
+    id_bytes.copy_from_slice(&data[SENDER_ID_LEN..SENDER_ID_LEN + 8]);
+    Ok((sender_id, u64::from_be_bytes(id_bytes), &data[SENDER_ID_LEN + 8..]))
+}
+
+/// 解析 u16 回复端口，返回剩余数据。
+fn decode_reply_port(data: &[u8]) -> Result<(u16, &[u8])> {
+    if data.len() < 2 {
+        return Err(anyhow!("message too short for reply port"));
+    }
+    Ok((u16::from_be_bytes([data[0], data[1]]), &data[2..]))
+}
+
+/// 解析 u64 回显时间戳，返回剩余数据。
+fn decode_timestamp(data: &[u8]) -> Result<(u64, &[u8])> {
+    if data.len() < 8 {
+        return Err(anyhow!("message too short for timestamp"));
+    }
+    let mut ts = [0u8; 8];
+    ts.copy_from_slice(&data[..8]);
+    Ok((u64::from_be_bytes(ts), &data[8..]))
+}
+
+/// 解析 u64 字段，返回剩余数据。
+fn decode_u64<'a>(data: &'a [u8], what: &str) -> Result<(u64, &'a [u8])> {
+    if data.len() < 8 {
+        return Err(anyhow!("message too short for {}", what));
+    }
+    let mut value = [0u8; 8];
+    value.copy_from_slice(&data[..8]);
+    Ok((u64::from_be_bytes(value), &data[8..]))
+}
+
 /// 从未加密的字节流解码 ProtocolMessage
 pub fn decode_message(mut data: &[u8]) -> Result<ProtocolMessage> {
     if data.len() < 2 {
@@ -87,31 +630,357 @@ pub fn decode_message(mut data: &[u8]) -> Result<ProtocolMessage> {
             let mut sender_id = [0u8; 16];
             sender_id.copy_from_slice(&data[..SENDER_ID_LEN]);
             data = &data[SENDER_ID_LEN..];
-            let content_type = ContentType::try_from(data[0])?;
+            let selection = if data[0] & SELECTION_PRIMARY_FLAG != 0 {
+                Selection::Primary
+            } else {
+                Selection::Clipboard
+            };
+            let has_clock = data[0] & CLOCK_FLAG != 0;
+            let has_ttl = data[0] & TTL_FLAG != 0;
+            let sensitive = data[0] & SENSITIVE_FLAG != 0;
+            let has_extensions = data[0] & EXTENSIONS_FLAG != 0;
+            let content_type = ContentType::try_from(
+                data[0]
+                    & !(SELECTION_PRIMARY_FLAG
+                        | CLOCK_FLAG
+                        | TTL_FLAG
+                        | SENSITIVE_FLAG
+                        | EXTENSIONS_FLAG),
+            )?;
             data = &data[1..];
             let mut sz_bytes = [0u8; 8];
             sz_bytes.copy_from_slice(&data[..8]);
             let payload_size = u64::from_be_bytes(sz_bytes);
             data = &data[8..];
+            let mut clock = 0;
+            if has_clock {
+                if data.len() < 8 {
+                    return Err(anyhow!("message too short for clock"));
+                }
+                let mut clock_bytes = [0u8; 8];
+                clock_bytes.copy_from_slice(&data[..8]);
+                clock = u64::from_be_bytes(clock_bytes);
+                data = &data[8..];
+            }
+            let mut ttl_secs = 0;
+            if has_ttl {
+                (ttl_secs, data) = decode_u64(data, "ttl")?;
+            }
+            let mut seq = 0;
+            let mut source_app = None;
+            if has_extensions {
+                let (&extensions, rest) = data
+                    .split_first()
+                    .ok_or_else(|| anyhow!("message too short for extensions"))?;
+                data = rest;
+                if extensions & !(EXT_SEQ | EXT_SOURCE_APP) != 0 {
+                    return Err(anyhow!("unknown clipboard update extensions {:#x}", extensions));
+                }
+                if extensions & EXT_SEQ != 0 {
+                    (seq, data) = decode_u64(data, "seq")?;
+                }
+                if extensions & EXT_SOURCE_APP != 0 {
+                    let (&app_len, rest) = data
+                        .split_first()
+                        .ok_or_else(|| anyhow!("message too short for source app"))?;
+                    if rest.len() < app_len as usize {
+                        return Err(anyhow!("message too short for source app"));
+                    }
+                    let (app, rest) = rest.split_at(app_len as usize);
+                    let app = std::str::from_utf8(app)
+                        .map_err(|_| anyhow!("source app name is not valid utf-8"))?;
+                    source_app = Some(app.to_string());
+                    data = rest;
+                }
+            }
+            if payload_size != data.len() as u64 {
+                return Err(anyhow!(
+                    "payload length {} does not match payload_size {}",
+                    data.len(),
+                    payload_size
+                ));
+            }
             let payload = data.to_vec();
             Ok(ProtocolMessage::ClipboardUpdate {
                 sender_id,
                 content_type,
                 payload_size,
                 payload,
+                seq,
+                selection,
+                source_app,
+                clock,
+                ttl_secs,
+                sensitive,
+            })
+        }
+        MSG_TYPE_PRESENCE => {
+            if data.len() < SENDER_ID_LEN + 4 + 1 {
+                return Err(anyhow!("message too short for presence"));
+            }
+            let mut sender_id = [0u8; 16];
+            sender_id.copy_from_slice(&data[..SENDER_ID_LEN]);
+            data = &data[SENDER_ID_LEN..];
+            let mut cap_bytes = [0u8; 4];
+            cap_bytes.copy_from_slice(&data[..4]);
+            let capabilities = u32::from_be_bytes(cap_bytes);
+            let name_len = data[4] as usize;
+            data = &data[5..];
+            if data.len() != name_len {
+                return Err(anyhow!("presence device name length mismatch"));
+            }
+            let device_name = String::from_utf8(data.to_vec())
+                .map_err(|_| anyhow!("presence device name is not valid UTF-8"))?;
+            Ok(ProtocolMessage::Presence {
+                sender_id,
+                device_name,
+                capabilities,
+            })
+        }
+        MSG_TYPE_KEY_ROTATION => {
+            if data.len() != SENDER_ID_LEN + 8 + 32 {
+                return Err(anyhow!("invalid key rotation message length"));
+            }
+            let mut sender_id = [0u8; 16];
+            sender_id.copy_from_slice(&data[..SENDER_ID_LEN]);
+            let mut key_id = [0u8; 8];
+            key_id.copy_from_slice(&data[SENDER_ID_LEN..SENDER_ID_LEN + 8]);
+            let mut new_key = [0u8; 32];
+            new_key.copy_from_slice(&data[SENDER_ID_LEN + 8..]);
+            Ok(ProtocolMessage::KeyRotation {
+                sender_id,
+                key_id,
+                new_key,
+            })
+        }
+        MSG_TYPE_FILE_MANIFEST => {
+            let (sender_id, transfer_id, rest) = decode_transfer_header(data)?;
+            let (reply_port, rest) = decode_reply_port(rest)?;
+            Ok(ProtocolMessage::FileManifest {
+                sender_id,
+                transfer_id,
+                reply_port,
+                entries: serde_json::from_slice(rest)?,
+            })
+        }
+        MSG_TYPE_FILE_REQUEST => {
+            let (sender_id, transfer_id, rest) = decode_transfer_header(data)?;
+            let (reply_port, rest) = decode_reply_port(rest)?;
+            Ok(ProtocolMessage::FileRequest {
+                sender_id,
+                transfer_id,
+                reply_port,
+                indices: serde_json::from_slice(rest)?,
+            })
+        }
+        MSG_TYPE_FILE_DATA => {
+            let (sender_id, transfer_id, rest) = decode_transfer_header(data)?;
+            Ok(ProtocolMessage::FileData {
+                sender_id,
+                transfer_id,
+                files: serde_json::from_slice(rest)?,
+            })
+        }
+        MSG_TYPE_ECHO_REQUEST => {
+            let (sender_id, echo_id, rest) = decode_transfer_header(data)?;
+            let (timestamp_us, rest) = decode_timestamp(rest)?;
+            let (reply_port, _) = decode_reply_port(rest)?;
+            Ok(ProtocolMessage::EchoRequest {
+                sender_id,
+                echo_id,
+                timestamp_us,
+                reply_port,
+            })
+        }
+        MSG_TYPE_ECHO_REPLY => {
+            let (sender_id, echo_id, rest) = decode_transfer_header(data)?;
+            let (timestamp_us, _) = decode_timestamp(rest)?;
+            Ok(ProtocolMessage::EchoReply {
+                sender_id,
+                echo_id,
+                timestamp_us,
+            })
+        }
+        MSG_TYPE_CHUNK_DATA => {
+            let (sender_id, transfer_id, rest) = decode_transfer_header(data)?;
+            let (reply_port, rest) = decode_reply_port(rest)?;
+            let (&content_type, rest) = rest
+                .split_first()
+                .ok_or_else(|| anyhow!("message too short for content type"))?;
+            let (total, rest) = decode_u64(rest, "chunk total")?;
+            let (offset, rest) = decode_u64(rest, "chunk offset")?;
+            Ok(ProtocolMessage::ChunkData {
+                sender_id,
+                transfer_id,
+                reply_port,
+                content_type: ContentType::try_from(content_type)?,
+                total,
+                offset,
+                data: rest.to_vec(),
+            })
+        }
+        MSG_TYPE_CHUNK_ACK => {
+            let (sender_id, transfer_id, rest) = decode_transfer_header(data)?;
+            let (next_offset, _) = decode_u64(rest, "chunk ack offset")?;
+            Ok(ProtocolMessage::ChunkAck {
+                sender_id,
+                transfer_id,
+                next_offset,
+            })
+        }
+        MSG_TYPE_TRANSFER_START => {
+            if data.len() != SENDER_ID_LEN + 1 + 8 {
+                return Err(anyhow!("invalid transfer start message length"));
+            }
+            let mut sender_id = [0u8; 16];
+            sender_id.copy_from_slice(&data[..SENDER_ID_LEN]);
+            let content_type = ContentType::try_from(data[SENDER_ID_LEN])?;
+            let (total_size, _) = decode_u64(&data[SENDER_ID_LEN + 1..], "transfer size")?;
+            Ok(ProtocolMessage::TransferStart {
+                sender_id,
+                content_type,
+                total_size,
+            })
+        }
+        MSG_TYPE_TRANSFER_END => {
+            if data.len() != SENDER_ID_LEN {
+                return Err(anyhow!("invalid transfer end message length"));
+            }
+            let mut sender_id = [0u8; 16];
+            sender_id.copy_from_slice(data);
+            Ok(ProtocolMessage::TransferEnd { sender_id })
+        }
+        MSG_TYPE_FILE_CHUNK => {
+            let (sender_id, transfer_id, rest) = decode_transfer_header(data)?;
+            if rest.len() < 12 {
+                return Err(anyhow!("message too short for file chunk"));
+            }
+            let field = |i: usize| u32::from_be_bytes([rest[i], rest[i + 1], rest[i + 2], rest[i + 3]]);
+            let (seq, total, file_index) = (field(0), field(4), field(8));
+            let (file_size, rest) = decode_u64(&rest[12..], "file size")?;
+            let (offset, rest) = decode_u64(rest, "chunk offset")?;
+            let (name, rest) = decode_files_string(rest, "file name")?;
+            Ok(ProtocolMessage::FileChunk {
+                sender_id,
+                transfer_id,
+                seq,
+                total,
+                file_index,
+                name,
+                file_size,
+                offset,
+                data: rest.to_vec(),
             })
         }
         _ => Err(anyhow!("unknown message type {}", msg_type)),
     }
 }
 
-/// 长度前缀帧编码：u32(长度) + 负载
-pub fn encode_frame(body: &[u8]) -> Vec<u8> {
+/// 帧体最大字节数的默认值（约 50 MiB，可由 `max_frame_body` 配置），收发两端共用，
+/// 防止恶意/异常连接导致 OOM
+pub const MAX_FRAME_BODY: usize = 50 * 1024 * 1024;
+/// `max_frame_body` 允许配置的最小值，保证文件清单与分块等控制消息总能发出
+pub const MIN_FRAME_BODY: usize = 64 * 1024;
+/// `max_frame_body` 允许配置的最大值：长度前缀中只有低 28 位表示帧体长度
+pub const FRAME_BODY_LIMIT: usize = FRAME_LEN_MASK as usize;
+
+/// 校验帧体长度并转换为 u32 长度前缀，超过 `max_body` 或 `u32::MAX` 时报错。
+pub fn frame_len_prefix(len: usize, max_body: usize) -> Result<u32> {
+    if len > max_body {
+        return Err(anyhow!("frame body too large: {} > {} bytes", len, max_body));
+    }
+    u32::try_from(len).map_err(|_| anyhow!("frame body length {} overflows u32", len))
+}
+
+/// 长度前缀中表示明文帧（`insecure_no_encryption`）的算法标识，不对应任何 AEAD 算法
+pub const PLAINTEXT_FRAME_ID: u8 = 0x7;
+/// 算法标识中表示帧体整体以 gzip 包装（`gzip_frames`）的标志位，与算法标识的低 3 位组合使用
+pub const GZIP_FRAME_FLAG: u8 = 0x8;
+
+/// 长度前缀中携带 AEAD 算法标识的位移：高 4 位为算法标识，低 28 位为帧体长度
+const FRAME_ALGORITHM_SHIFT: u32 = 28;
+/// 长度前缀中帧体长度部分的掩码
+const FRAME_LEN_MASK: u32 = (1 << FRAME_ALGORITHM_SHIFT) - 1;
+
+/// 构造携带 AEAD 算法标识的长度前缀。
+///
+/// 算法标识 0（ChaCha20-Poly1305）时与旧版长度前缀完全一致，因此默认配置与旧版本互通。
+pub fn frame_header(len: usize, algorithm_id: u8, max_body: usize) -> Result<u32> {
+    let len = frame_len_prefix(len, max_body.min(FRAME_BODY_LIMIT))?;
+    if u32::from(algorithm_id) >> (32 - FRAME_ALGORITHM_SHIFT) != 0 {
+        return Err(anyhow!(
+            "aead algorithm id {} does not fit frame header",
+            algorithm_id
+        ));
+    }
+    Ok((u32::from(algorithm_id) << FRAME_ALGORITHM_SHIFT) | len)
+}
+
+/// 拆分长度前缀，返回 (算法标识, 帧体长度)。
+pub fn split_frame_header(header: u32) -> (u8, usize) {
+    (
+        (header >> FRAME_ALGORITHM_SHIFT) as u8,
+        (header & FRAME_LEN_MASK) as usize,
+    )
+}
+
+/// 以 gzip 包装整个帧体（依次拼接 `parts`），对端或外部工具可直接用标准 gzip 解开。
+pub fn gzip_frame_body(parts: &[&[u8]]) -> Result<Vec<u8>> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    for part in parts {
+        encoder.write_all(part)?;
+    }
+    Ok(encoder.finish()?)
+}
+
+/// 解开 gzip 包装的帧体；解开后超过 `max_body` 时报错，防止压缩炸弹耗尽内存。
+pub fn gunzip_frame_body(body: &[u8], max_body: usize) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    flate2::read::GzDecoder::new(body)
+        .take(max_body as u64 + 1)
+        .read_to_end(&mut out)
+        .map_err(|e| anyhow!("invalid gzip frame body: {e}"))?;
+    if out.len() > max_body {
+        return Err(anyhow!("gzip frame body exceeds {} bytes when unpacked", max_body));
+    }
+    Ok(out)
+}
+
+/// 消息体以 zstd 压缩（`compression`）时取代协议版本号的首字节，随消息一起加密；
+/// 不认识它的旧版本以不支持的版本拒绝该消息，不会误解析
+pub const COMPRESSED_BODY_MARKER: u8 = 0x5a;
+
+/// 以 zstd 压缩已编码的消息体并加上 [`COMPRESSED_BODY_MARKER`]；压缩后不比原文小时返回 None。
+pub fn compress_body(body: &[u8], level: i32) -> Result<Option<Vec<u8>>> {
+    let mut out = vec![COMPRESSED_BODY_MARKER];
+    zstd::stream::copy_encode(body, &mut out, level)?;
+    Ok((out.len() < body.len()).then_some(out))
+}
+
+/// 解开 [`compress_body`] 压缩的消息体，未压缩的原样返回；解开后超过 `max_body` 时报错，
+/// 防止压缩炸弹耗尽内存。
+pub fn decompress_body(body: Vec<u8>, max_body: usize) -> Result<Vec<u8>> {
+    if body.first() != Some(&COMPRESSED_BODY_MARKER) {
+        return Ok(body);
+    }
+    let mut out = Vec::new();
+    zstd::stream::read::Decoder::new(&body[1..])?
+        .take(max_body as u64 + 1)
+        .read_to_end(&mut out)
+        .map_err(|e| anyhow!("invalid zstd message body: {e}"))?;
+    if out.len() > max_body {
+        return Err(anyhow!("message body exceeds {} bytes when decompressed", max_body));
+    }
+    Ok(out)
+}
+
+/// 长度前缀帧编码：u32(长度) + 负载，负载超过 `max_body` 时报错
+pub fn encode_frame(body: &[u8], max_body: usize) -> Result<Vec<u8>> {
+    let len = frame_len_prefix(body.len(), max_body.min(FRAME_BODY_LIMIT))?;
     let mut buf = Vec::with_capacity(4 + body.len());
-    let len = body.len() as u32;
     buf.extend_from_slice(&len.to_be_bytes());
     buf.extend_from_slice(body);
-    buf
+    Ok(buf)
 }
 
 /// 从缓冲中尝试解出一帧（不消费多余字节）
@@ -133,6 +1002,40 @@ pub fn try_decode_frame(buf: &[u8]) -> Option<(usize, Vec<u8>)> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn files_payload_roundtrip() {
+        let entries = vec![
+            FileEntry {
+                name: "报告.pdf".into(),
+                size: 4,
+                content: b"%PDF".to_vec(),
+                source_path: Some("~/文档".into()),
+            },
+            FileEntry {
+                name: "empty.txt".into(),
+                size: 0,
+                content: Vec::new(),
+                source_path: None,
+            },
+        ];
+        let payload = encode_files_payload(&entries);
+        // 二进制格式不再把每个字节写成 JSON 数字
+        assert!(payload.len() < serde_json::to_vec(&entries).unwrap().len());
+        let decoded = decode_files_payload(&payload).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].name, "报告.pdf");
+        assert_eq!(decoded[0].content, b"%PDF");
+        assert_eq!(decoded[0].source_path.as_deref(), Some("~/文档"));
+        assert_eq!((decoded[1].size, decoded[1].source_path.as_deref()), (0, None));
+
+        // 旧版本发送端的 JSON 负载仍可解码
+        let legacy = decode_files_payload(&serde_json::to_vec(&entries).unwrap()).unwrap();
+        assert_eq!(legacy[0].content, b"%PDF");
+
+        assert!(decode_files_payload(&payload[..payload.len() - 1]).is_err());
+        assert!(decode_files_payload(&[FILES_PAYLOAD_BINARY]).unwrap().is_empty());
+    }
+
     #[test]
     fn encode_decode_roundtrip() {
         let msg = ProtocolMessage::ClipboardUpdate {
@@ -140,6 +1043,12 @@ mod tests {
             content_type: ContentType::Text,
             payload_size: 5,
             payload: b"hello".to_vec(),
+            seq: 42,
+            selection: Selection::Clipboard,
+            source_app: Some("Firefox".into()),
+            clock: 1_700_000_000_000,
+            ttl_secs: 3600,
+            sensitive: true,
         };
         let bytes = encode_message(&msg).unwrap();
         let decoded = decode_message(&bytes).unwrap();
@@ -149,21 +1058,459 @@ mod tests {
                 content_type,
                 payload_size,
                 payload,
+                seq,
+                selection,
+                source_app,
+                clock,
+                ttl_secs,
+                sensitive,
             } => {
                 assert!(matches!(content_type, ContentType::Text));
+                assert_eq!(selection, Selection::Clipboard);
                 assert_eq!(payload_size, 5);
                 assert_eq!(payload, b"hello");
+                assert_eq!(seq, 42);
+                assert_eq!(source_app.as_deref(), Some("Firefox"));
+                assert_eq!(clock, 1_700_000_000_000);
+                assert_eq!(ttl_secs, 3600);
+                assert!(sensitive);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[test]
+    fn legacy_update_without_seq_decodes() {
+        let msg = ProtocolMessage::ClipboardUpdate {
+            sender_id: [0u8; 16],
+            content_type: ContentType::Text,
+            payload_size: 5,
+            payload: b"hello".to_vec(),
+            seq: 0,
+            selection: Selection::Clipboard,
+            source_app: None,
+            clock: 0,
+            ttl_secs: 0,
+            sensitive: false,
+        };
+        let bytes = encode_message(&msg).unwrap();
+        match decode_message(&bytes).unwrap() {
+            ProtocolMessage::ClipboardUpdate { payload, seq, source_app, .. } => {
+                assert_eq!(payload, b"hello");
+                assert_eq!(seq, 0);
+                assert!(source_app.is_none());
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[test]
+    fn clipboard_update_is_encoded_in_one_allocation() {
+        let msg = ProtocolMessage::ClipboardUpdate {
+            sender_id: [2u8; 16],
+            content_type: ContentType::Image,
+            payload_size: 4096,
+            payload: vec![7u8; 4096],
+            seq: 3,
+            selection: Selection::Clipboard,
+            source_app: Some("gimp".into()),
+            clock: 42,
+            ttl_secs: 60,
+            sensitive: false,
+        };
+        let bytes = encode_message(&msg).unwrap();
+        assert_eq!(bytes.capacity(), bytes.len());
+    }
+
+    #[test]
+    fn seq_is_carried_behind_extension_flag() {
+        let msg = ProtocolMessage::ClipboardUpdate {
+            sender_id: [1u8; 16],
+            content_type: ContentType::Text,
+            payload_size: 2,
+            payload: b"hi".to_vec(),
+            seq: 5,
+            selection: Selection::Clipboard,
+            source_app: None,
+            clock: 0,
+            ttl_secs: 0,
+            sensitive: false,
+        };
+        let mut bytes = encode_message(&msg).unwrap();
+        // 旧版本按内容类型字节解析，带标志位的消息以未知类型拒绝，不会把序号当作负载
+        let type_byte = 2 + SENDER_ID_LEN;
+        assert!(ContentType::try_from(bytes[type_byte]).is_err());
+        assert_eq!(&bytes[bytes.len() - 2..], b"hi");
+
+        // 未知的扩展位被拒绝
+        bytes[type_byte + 1 + 8] |= 0x80;
+        assert!(decode_message(&bytes).is_err());
+    }
+
+    #[test]
+    fn payload_length_must_match_flagged_layout() {
+        let msg = |seq, source_app: Option<&str>| ProtocolMessage::ClipboardUpdate {
+            sender_id: [1u8; 16],
+            content_type: ContentType::Text,
+            payload_size: 2,
+            payload: b"hi".to_vec(),
+            seq,
+            selection: Selection::Clipboard,
+            source_app: source_app.map(Into::into),
+            clock: 0,
+            ttl_secs: 0,
+            sensitive: false,
+        };
+        // 旧格式的尾部序号：不再按长度猜测，整条消息被拒绝
+        let mut legacy_trailer = encode_message(&msg(0, None)).unwrap();
+        legacy_trailer.extend_from_slice(&9u64.to_be_bytes());
+        assert!(decode_message(&legacy_trailer).is_err());
+
+        for (seq, app) in [(0, None), (5, None), (0, Some("vim")), (5, Some("vim"))] {
+            let bytes = encode_message(&msg(seq, app)).unwrap();
+            assert!(decode_message(&bytes).is_ok());
+            assert!(decode_message(&bytes[..bytes.len() - 1]).is_err());
+            let mut longer = bytes.clone();
+            longer.push(0);
+            assert!(decode_message(&longer).is_err());
+        }
+    }
+
+    #[test]
+    fn source_app_roundtrips_without_seq() {
+        // 负载末字节恰好像长度字节时也不能被误判为来源应用名
+        let msg = ProtocolMessage::ClipboardUpdate {
+            sender_id: [1u8; 16],
+            content_type: ContentType::Text,
+            payload_size: 3,
+            payload: vec![b'a', b'b', 2],
+            seq: 0,
+            selection: Selection::Clipboard,
+            source_app: Some("终端".into()),
+            clock: 0,
+            ttl_secs: 0,
+            sensitive: false,
+        };
+        let bytes = encode_message(&msg).unwrap();
+        match decode_message(&bytes).unwrap() {
+            ProtocolMessage::ClipboardUpdate { payload, seq, source_app, .. } => {
+                assert_eq!(payload, vec![b'a', b'b', 2]);
+                assert_eq!(seq, 0);
+                assert_eq!(source_app.as_deref(), Some("终端"));
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn primary_selection_tag_roundtrip() {
+        let msg = ProtocolMessage::ClipboardUpdate {
+            sender_id: [3u8; 16],
+            content_type: ContentType::Text,
+            payload_size: 4,
+            payload: b"sel!".to_vec(),
+            seq: 7,
+            selection: Selection::Primary,
+            source_app: None,
+            clock: 0,
+            ttl_secs: 0,
+            sensitive: false,
+        };
+        let bytes = encode_message(&msg).unwrap();
+        match decode_message(&bytes).unwrap() {
+            ProtocolMessage::ClipboardUpdate {
+                content_type,
+                payload,
+                selection,
+                ..
+            } => {
+                assert!(matches!(content_type, ContentType::Text));
+                assert_eq!(payload, b"sel!");
+                assert_eq!(selection, Selection::Primary);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[test]
+    fn url_update_roundtrip() {
+        let url = b"https://example.com/";
+        let msg = ProtocolMessage::ClipboardUpdate {
+            sender_id: [4u8; 16],
+            content_type: ContentType::Url,
+            payload_size: url.len() as u64,
+            payload: url.to_vec(),
+            seq: 2,
+            selection: Selection::Clipboard,
+            source_app: None,
+            clock: 0,
+            ttl_secs: 0,
+            sensitive: false,
+        };
+        let bytes = encode_message(&msg).unwrap();
+        match decode_message(&bytes).unwrap() {
+            ProtocolMessage::ClipboardUpdate {
+                content_type,
+                payload,
+                ..
+            } => {
+                assert!(matches!(content_type, ContentType::Url));
+                assert_eq!(payload, url);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[test]
+    fn file_chunk_roundtrip() {
+        let msg = ProtocolMessage::FileChunk {
+            sender_id: [4u8; 16],
+            transfer_id: 9,
+            seq: 3,
+            total: 7,
+            file_index: 1,
+            name: "报告.pdf".into(),
+            file_size: 4096,
+            offset: 2048,
+            data: vec![5; 16],
+        };
+        match decode_message(&encode_message(&msg).unwrap()).unwrap() {
+            ProtocolMessage::FileChunk {
+                sender_id,
+                transfer_id,
+                seq,
+                total,
+                file_index,
+                name,
+                file_size,
+                offset,
+                data,
+            } => {
+                assert_eq!((sender_id, transfer_id), ([4u8; 16], 9));
+                assert_eq!((seq, total, file_index), (3, 7, 1));
+                assert_eq!(name, "报告.pdf");
+                assert_eq!((file_size, offset), (4096, 2048));
+                assert_eq!(data, vec![5; 16]);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[test]
+    fn chunk_messages_roundtrip() {
+        let msg = ProtocolMessage::ChunkData {
+            sender_id: [6u8; 16],
+            transfer_id: 11,
+            reply_port: 5002,
+            content_type: ContentType::Image,
+            total: 1000,
+            offset: 500,
+            data: vec![1, 2, 3],
+        };
+        match decode_message(&encode_message(&msg).unwrap()).unwrap() {
+            ProtocolMessage::ChunkData {
+                transfer_id,
+                reply_port,
+                content_type,
+                total,
+                offset,
+                data,
+                ..
+            } => {
+                assert_eq!((transfer_id, reply_port), (11, 5002));
+                assert!(matches!(content_type, ContentType::Image));
+                assert_eq!((total, offset), (1000, 500));
+                assert_eq!(data, vec![1, 2, 3]);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+
+        let ack = ProtocolMessage::ChunkAck {
+            sender_id: [6u8; 16],
+            transfer_id: 11,
+            next_offset: 503,
+        };
+        match decode_message(&encode_message(&ack).unwrap()).unwrap() {
+            ProtocolMessage::ChunkAck { transfer_id, next_offset, .. } => {
+                assert_eq!((transfer_id, next_offset), (11, 503));
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[test]
+    fn transfer_status_roundtrip() {
+        let start = ProtocolMessage::TransferStart {
+            sender_id: [8u8; 16],
+            content_type: ContentType::Image,
+            total_size: 5 << 20,
+        };
+        match decode_message(&encode_message(&start).unwrap()).unwrap() {
+            ProtocolMessage::TransferStart { sender_id, content_type, total_size } => {
+                assert_eq!(sender_id, [8u8; 16]);
+                assert!(matches!(content_type, ContentType::Image));
+                assert_eq!(total_size, 5 << 20);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+
+        let end = ProtocolMessage::TransferEnd { sender_id: [8u8; 16] };
+        let bytes = encode_message(&end).unwrap();
+        assert!(matches!(
+            decode_message(&bytes).unwrap(),
+            ProtocolMessage::TransferEnd { sender_id } if sender_id == [8u8; 16]
+        ));
+        assert!(decode_message(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn presence_roundtrip() {
+        let msg = ProtocolMessage::Presence {
+            sender_id: [7u8; 16],
+            device_name: "laptop".into(),
+            capabilities: CAP_TEXT | CAP_FILES,
+        };
+        let bytes = encode_message(&msg).unwrap();
+        match decode_message(&bytes).unwrap() {
+            ProtocolMessage::Presence {
+                sender_id,
+                device_name,
+                capabilities,
+            } => {
+                assert_eq!(sender_id, [7u8; 16]);
+                assert_eq!(device_name, "laptop");
+                assert_eq!(capabilities, CAP_TEXT | CAP_FILES);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[test]
+    fn file_manifest_roundtrip() {
+        let entry = ManifestEntry {
+            name: "a.txt".into(),
+            size: 3,
+            hash: [5u8; 32],
+            source_path: None,
+        };
+        let msg = ProtocolMessage::FileManifest {
+            sender_id: [1u8; 16],
+            transfer_id: 9,
+            reply_port: 5000,
+            entries: vec![entry.clone()],
+        };
+        match decode_message(&encode_message(&msg).unwrap()).unwrap() {
+            ProtocolMessage::FileManifest {
+                transfer_id,
+                reply_port,
+                entries,
+                ..
+            } => {
+                assert_eq!(transfer_id, 9);
+                assert_eq!(reply_port, 5000);
+                assert_eq!(entries, vec![entry]);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+
+        let msg = ProtocolMessage::FileRequest {
+            sender_id: [2u8; 16],
+            transfer_id: 9,
+            reply_port: 5001,
+            indices: vec![1],
+        };
+        match decode_message(&encode_message(&msg).unwrap()).unwrap() {
+            ProtocolMessage::FileRequest { indices, reply_port, .. } => {
+                assert_eq!(indices, vec![1]);
+                assert_eq!(reply_port, 5001);
             }
+            other => panic!("unexpected message {:?}", other),
         }
     }
 
     #[test]
     fn frame_roundtrip() {
         let body = vec![1, 2, 3, 4, 5];
-        let framed = encode_frame(&body);
+        let framed = encode_frame(&body, MAX_FRAME_BODY).unwrap();
         let (used, decoded) = try_decode_frame(&framed).unwrap();
         assert_eq!(used, framed.len());
         assert_eq!(decoded, body);
     }
-}
 
+    #[test]
+    fn oversized_frame_rejected() {
+        let max = MAX_FRAME_BODY;
+        assert_eq!(frame_len_prefix(max, max).unwrap(), max as u32);
+        assert!(frame_len_prefix(max + 1, max).is_err());
+        assert!(frame_len_prefix(u32::MAX as usize + 1, usize::MAX).is_err());
+        assert!(frame_len_prefix(usize::MAX, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn encode_frame_respects_configured_limit() {
+        let limit = MIN_FRAME_BODY;
+        assert!(encode_frame(&vec![0u8; limit], limit).is_ok());
+        assert!(encode_frame(&vec![0u8; limit + 1], limit).is_err());
+        // 超过长度前缀能表示的上限时，即使配置更大也拒绝
+        assert!(frame_header(FRAME_BODY_LIMIT + 1, 0, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn gzip_frame_body_roundtrip() {
+        let body = gzip_frame_body(&[b"nonce-bytes!", &[7u8; 4096]]).unwrap();
+        // 标准 gzip 格式：魔数 1f 8b
+        assert_eq!(&body[..2], &[0x1f, 0x8b]);
+        let unpacked = gunzip_frame_body(&body, MAX_FRAME_BODY).unwrap();
+        assert_eq!(&unpacked[..12], b"nonce-bytes!");
+        assert_eq!(unpacked.len(), 12 + 4096);
+
+        assert!(gunzip_frame_body(&body, 1024).is_err());
+        assert!(gunzip_frame_body(b"not gzip", MAX_FRAME_BODY).is_err());
+    }
+
+    #[test]
+    fn compressed_body_roundtrip() {
+        let body = encode_message(&ProtocolMessage::ClipboardUpdate {
+            sender_id: [3; 16],
+            content_type: ContentType::Text,
+            payload_size: 8192,
+            payload: vec![b'a'; 8192],
+            seq: 1,
+            selection: Selection::Clipboard,
+            source_app: None,
+            clock: 0,
+            ttl_secs: 0,
+            sensitive: false,
+        })
+        .unwrap();
+        let packed = compress_body(&body, 1).unwrap().expect("repetitive body shrinks");
+        assert_eq!(packed[0], COMPRESSED_BODY_MARKER);
+        assert!(packed.len() < body.len() / 10);
+        assert_eq!(decompress_body(packed.clone(), MAX_FRAME_BODY).unwrap(), body);
+        // 解开后超过上限的视为压缩炸弹
+        assert!(decompress_body(packed, 1024).is_err());
+
+        // 不可压缩的内容不压缩，未压缩的消息体原样通过
+        let noise: Vec<u8> = (0..4096).map(|_| rand::random::<u8>()).collect();
+        assert!(compress_body(&noise, 19).unwrap().is_none());
+        assert_eq!(decompress_body(body.clone(), MAX_FRAME_BODY).unwrap(), body);
+        assert!(decompress_body(vec![COMPRESSED_BODY_MARKER, 1, 2, 3], MAX_FRAME_BODY).is_err());
+    }
+
+    #[test]
+    fn frame_header_carries_algorithm() {
+        let max = MAX_FRAME_BODY;
+        assert_eq!(frame_header(1234, 0, max).unwrap(), 1234);
+        assert_eq!(
+            split_frame_header(frame_header(1234, 0, max).unwrap()),
+            (0, 1234)
+        );
+        assert_eq!(
+            split_frame_header(frame_header(max, 1, max).unwrap()),
+            (1, max)
+        );
+        assert!(frame_header(10, 16, max).is_err());
+        assert!(frame_header(max + 1, 0, max).is_err());
+    }
+}
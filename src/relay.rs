@@ -0,0 +1,687 @@
+//! 中继模式：设备之间无法直连（如位于不同 NAT 之后）时，经具有公网地址的中继节点转发。
+//!
+//! 客户端主动连接中继，加入由 PSK 与频道派生的房间；中继只在同一房间内原样转发帧，
+//! 帧内容使用由 PSK 派生的中继密钥端到端加密，中继本身无法解密。
+//!
+//! 中继连接协议：客户端先发送 `LCSR` + 版本(1) + 房间 ID(16)，
+//! 之后双方收发与直连相同格式的帧（长度前缀 + nonce + 密文）。
+//!
+//! 中继密钥是静态的，明文前带有发送时刻（Unix 毫秒）：接收端丢弃超出 [`RELAY_MAX_AGE`]
+//! 的帧，并记住窗口内见过的 nonce，被截获的帧无法重放。
+
+use anyhow::{anyhow, Result};
+use chacha20poly1305::Key;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Semaphore};
+
+use crate::config::{AeadAlgorithm, AppConfig, PeerConfig};
+use crate::crypto::{decrypt, derive_session_key, encrypt};
+use crate::keyring::{key_id, KEY_ID_LEN};
+use crate::lww::now_ms;
+use crate::network::{
+    check_frame_algorithm, enable_keepalive, read_body, sending_psk, write_frame,
+    IncomingMessage, KeepaliveSettings, PeerSendOutcome, PeerSendResult,
+};
+use crate::protocol::{decode_message, split_frame_header, ProtocolMessage, MAX_FRAME_BODY};
+
+/// 中继连接握手的魔数
+const RELAY_MAGIC: &[u8; 4] = b"LCSR";
+/// 中继连接协议版本
+const RELAY_VERSION: u8 = 1;
+/// 房间 ID 长度
+const ROOM_ID_LEN: usize = 16;
+/// 握手长度：魔数 + 版本 + 房间 ID
+const HELLO_LEN: usize = RELAY_MAGIC.len() + 1 + ROOM_ID_LEN;
+/// 中继密钥派生时代替 ECDH 共享密钥的输入
+const RELAY_KEY_INPUT: &[u8] = b"lan-clipboard-sync-relay";
+/// 等待客户端握手的最长时间
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+/// 连接中继失败或断开后的重连间隔
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// 客户端待发送帧队列长度，队列满时丢弃新帧
+const QUEUE_LEN: usize = 32;
+/// 中继为每个成员排队的字节数上限：队列非空且加入新帧会超出时丢弃新帧
+/// （空队列总能接收一帧，因此单个成员最多积压该值加一帧）
+const QUEUE_BYTES: usize = 8 * 1024 * 1024;
+/// 经中继收到的帧距发送时刻的最长时间（双向容许时钟偏差），超出的帧视为重放丢弃
+const RELAY_MAX_AGE: Duration = Duration::from_secs(300);
+/// 明文前的发送时刻长度
+const TIMESTAMP_LEN: usize = 8;
+
+type RoomId = [u8; ROOM_ID_LEN];
+
+/// 由 PSK 与频道派生房间 ID：中继据此分组转发，无法由房间 ID 反推密钥。
+pub fn room_id(psk: &[u8; 32], channel: &str) -> RoomId {
+    let digest = Sha256::new()
+        .chain_update(RELAY_KEY_INPUT)
+        .chain_update(psk)
+        .chain_update(channel.as_bytes())
+        .finalize();
+    let mut id = [0u8; ROOM_ID_LEN];
+    id.copy_from_slice(&digest[..ROOM_ID_LEN]);
+    id
+}
+
+/// 中继帧的端到端加密密钥（中继转发时没有逐连接握手，只能由 PSK 派生）。
+fn relay_key(psk: &[u8; 32]) -> Key {
+    derive_session_key(RELAY_KEY_INPUT, psk)
+}
+
+/// 经中继收到的帧的新鲜度检查：拒绝发送时刻超出 [`RELAY_MAX_AGE`] 的帧，
+/// 并拒绝在该时间窗口内重复出现的 nonce。断线重连后仍沿用，重连也无法绕过。
+#[derive(Default)]
+struct ReplayWindow {
+    seen: HashSet<[u8; 12]>,
+    /// (失效时刻, nonce)：发送时刻超出窗口后同一帧已会因过旧被拒绝，届时移出 `seen`
+    expiry: VecDeque<(u64, [u8; 12])>,
+}
+
+impl ReplayWindow {
+    fn check(&mut self, nonce: [u8; 12], sent_ms: u64, now_ms: u64) -> Result<()> {
+        let max_age = RELAY_MAX_AGE.as_millis() as u64;
+        while let Some(&(expires, old)) = self.expiry.front() {
+            if expires >= now_ms {
+                break;
+            }
+            self.expiry.pop_front();
+            self.seen.remove(&old);
+        }
+        if sent_ms.abs_diff(now_ms) > max_age {
+            return Err(anyhow!(
+                "relayed frame sent at {} is outside the freshness window (now {})",
+                sent_ms,
+                now_ms
+            ));
+        }
+        if !self.seen.insert(nonce) {
+            return Err(anyhow!("relayed frame was replayed"));
+        }
+        self.expiry.push_back((sent_ms + max_age, nonce));
+        Ok(())
+    }
+}
+
+fn hello(room: &RoomId) -> [u8; HELLO_LEN] {
+    let mut buf = [0u8; HELLO_LEN];
+    buf[..RELAY_MAGIC.len()].copy_from_slice(RELAY_MAGIC);
+    buf[RELAY_MAGIC.len()] = RELAY_VERSION;
+    buf[RELAY_MAGIC.len() + 1..].copy_from_slice(room);
+    buf
+}
+
+fn parse_hello(buf: &[u8; HELLO_LEN]) -> Result<RoomId> {
+    if &buf[..RELAY_MAGIC.len()] != RELAY_MAGIC {
+        return Err(anyhow!("not a relay client"));
+    }
+    if buf[RELAY_MAGIC.len()] != RELAY_VERSION {
+        return Err(anyhow!(
+            "unsupported relay protocol version {}",
+            buf[RELAY_MAGIC.len()]
+        ));
+    }
+    let mut room = [0u8; ROOM_ID_LEN];
+    room.copy_from_slice(&buf[RELAY_MAGIC.len() + 1..]);
+    Ok(room)
+}
+
+/// 读取一帧，返回 (长度前缀, 帧体)；在帧边界处连接关闭时返回 None。
+async fn read_frame<R>(reader: &mut R, max_body: usize) -> Result<Option<(u32, Vec<u8>)>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut header = [0u8; 4];
+    match reader.read_exact(&mut header).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let header = u32::from_be_bytes(header);
+    let (_, len) = split_frame_header(header);
+    if len > max_body {
+        return Err(anyhow!("frame body too large: {} > {} bytes", len, max_body));
+    }
+    Ok(Some((header, read_body(reader, len).await?)))
+}
+
+/// 房间成员的待发送帧队列，按排队的字节数而不是帧数限制积压。
+#[derive(Clone)]
+struct MemberQueue {
+    tx: mpsc::UnboundedSender<Arc<Vec<u8>>>,
+    /// 已入队、尚未写出的字节数，由写出任务在写完后扣减
+    queued: Arc<AtomicUsize>,
+}
+
+impl MemberQueue {
+    fn new() -> (Self, mpsc::UnboundedReceiver<Arc<Vec<u8>>>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Self { tx, queued: Arc::default() }, rx)
+    }
+
+    /// 入队一帧，超出 [`QUEUE_BYTES`] 或连接已关闭时返回 false。
+    fn push(&self, frame: Arc<Vec<u8>>) -> bool {
+        let len = frame.len();
+        let queued = self.queued.load(Ordering::Relaxed);
+        if queued > 0 && queued + len > QUEUE_BYTES {
+            return false;
+        }
+        self.queued.fetch_add(len, Ordering::Relaxed);
+        if self.tx.send(frame).is_err() {
+            self.queued.fetch_sub(len, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+}
+
+/// 房间成员：连接序号与其待发送帧队列。
+type Members = Vec<(u64, MemberQueue)>;
+
+/// 中继服务：按房间在客户端之间转发帧，不持有任何密钥。
+#[derive(Clone)]
+pub struct RelayServer {
+    rooms: Arc<Mutex<HashMap<RoomId, Members>>>,
+    next_id: Arc<AtomicU64>,
+    /// 转发的单帧帧体上限
+    max_frame_body: usize,
+    /// 同时处理的客户端连接许可
+    connection_limit: Arc<Semaphore>,
+}
+
+impl Default for RelayServer {
+    fn default() -> Self {
+        Self {
+            rooms: Arc::default(),
+            next_id: Arc::default(),
+            max_frame_body: MAX_FRAME_BODY,
+            connection_limit: Arc::new(Semaphore::new(
+                AppConfig::default_max_inbound_connections(),
+            )),
+        }
+    }
+}
+
+impl RelayServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 设置转发的单帧帧体上限（默认 [`MAX_FRAME_BODY`]）。
+    pub fn with_max_frame_body(mut self, max_frame_body: usize) -> Self {
+        self.max_frame_body = max_frame_body;
+        self
+    }
+
+    /// 设置同时处理的客户端连接数上限（默认同 `max_inbound_connections` 的默认值）。
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.connection_limit = Arc::new(Semaphore::new(max_connections));
+        self
+    }
+
+    /// 在已绑定的监听器上接受中继客户端。
+    ///
+    /// 同时处理的连接数达到上限时暂停接受，新连接留在系统的监听队列中，直到有客户端断开。
+    pub async fn serve(self, listener: TcpListener) -> Result<()> {
+        loop {
+            let permit = match Arc::clone(&self.connection_limit).try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_) => {
+                    tracing::warn!("relay connection limit reached, delaying new clients");
+                    Arc::clone(&self.connection_limit).acquire_owned().await?
+                }
+            };
+            let (stream, from) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                let _permit = permit;
+                if let Err(e) = server.handle_client(stream, from).await {
+                    tracing::warn!("relay client {from} error: {e}");
+                }
+            });
+        }
+    }
+
+    async fn handle_client(&self, mut stream: TcpStream, from: SocketAddr) -> Result<()> {
+        let mut buf = [0u8; HELLO_LEN];
+        tokio::time::timeout(HELLO_TIMEOUT, stream.read_exact(&mut buf))
+            .await
+            .map_err(|_| anyhow!("relay hello timeout"))??;
+        let room = parse_hello(&buf)?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (queue, mut rx) = MemberQueue::new();
+        let queued = queue.queued.clone();
+        self.join(room, id, queue);
+        tracing::info!("relay client {from} joined room {}", hex::encode(&room[..4]));
+
+        let (mut reader, mut writer) = stream.into_split();
+        let writer_task = tokio::spawn(async move {
+            while let Some(frame) = rx.recv().await {
+                if writer.write_all(&frame).await.is_err() {
+                    break;
+                }
+                queued.fetch_sub(frame.len(), Ordering::Relaxed);
+            }
+        });
+
+        let result = async {
+            while let Some((header, body)) = read_frame(&mut reader, self.max_frame_body).await? {
+                let mut frame = Vec::with_capacity(4 + body.len());
+                frame.extend_from_slice(&header.to_be_bytes());
+                frame.extend_from_slice(&body);
+                self.forward(&room, id, Arc::new(frame));
+            }
+            Ok(())
+        }
+        .await;
+
+        self.leave(&room, id);
+        writer_task.abort();
+        tracing::info!("relay client {from} left");
+        result
+    }
+
+    fn join(&self, room: RoomId, id: u64, queue: MemberQueue) {
+        self.rooms
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(room)
+            .or_default()
+            .push((id, queue));
+    }
+
+    fn leave(&self, room: &RoomId, id: u64) {
+        let mut rooms = self.rooms.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(members) = rooms.get_mut(room) {
+            members.retain(|(member, _)| *member != id);
+            if members.is_empty() {
+                rooms.remove(room);
+            }
+        }
+    }
+
+    /// 将帧转发给同一房间内除发送者外的所有客户端；接收方积压超出上限时丢弃该帧。
+    fn forward(&self, room: &RoomId, from: u64, frame: Arc<Vec<u8>>) {
+        let rooms = self.rooms.lock().unwrap_or_else(|e| e.into_inner());
+        for (id, queue) in rooms.get(room).into_iter().flatten() {
+            if *id != from && !queue.push(frame.clone()) {
+                tracing::debug!("relay queue of client {id} full, frame dropped");
+            }
+        }
+    }
+}
+
+/// 以中继服务模式运行：在 `listen_port` 上监听并转发，不同步本机剪贴板。
+pub async fn run_relay_server(config: &AppConfig) -> Result<()> {
+    let addr = SocketAddr::new(IpAddr::from([0, 0, 0, 0]), config.listen_port);
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!("relay server listening on {}", listener.local_addr()?);
+    RelayServer::new()
+        .with_max_frame_body(config.max_frame_body)
+        .with_max_connections(config.max_inbound_connections)
+        .serve(listener)
+        .await
+}
+
+/// 经中继发送消息的句柄，可克隆。
+#[derive(Clone)]
+pub(crate) struct RelayHandle {
+    addr: String,
+    tx: mpsc::Sender<Arc<Vec<u8>>>,
+    connected: Arc<AtomicBool>,
+}
+
+impl RelayHandle {
+    /// 将已编码的消息交给中继连接发送；未连接或队列已满时直接报告失败，不积压过期内容。
+    pub(crate) fn send(&self, body: Arc<Vec<u8>>) -> PeerSendResult {
+        let outcome = if !self.connected.load(Ordering::Relaxed) {
+            PeerSendOutcome::Error("relay not connected".into())
+        } else if self.tx.try_send(body).is_err() {
+            PeerSendOutcome::Error("relay send queue full".into())
+        } else {
+            PeerSendOutcome::Ok
+        };
+        PeerSendResult {
+            addr: self.addr.clone(),
+            outcome,
+        }
+    }
+}
+
+/// 中继客户端：保持与中继的连接（断开后自动重连），收发端到端加密的帧。
+pub(crate) struct RelayClient {
+    addr: String,
+    psk: [u8; 32],
+    channel: String,
+    aead: AeadAlgorithm,
+    keepalive: KeepaliveSettings,
+    max_frame_body: usize,
+    incoming_tx: mpsc::Sender<IncomingMessage>,
+    outgoing_rx: mpsc::Receiver<Arc<Vec<u8>>>,
+    connected: Arc<AtomicBool>,
+    replay: Arc<Mutex<ReplayWindow>>,
+}
+
+impl RelayClient {
+    /// 创建中继客户端及其发送句柄，收到的消息推送到 `incoming_tx`。
+    pub(crate) fn new(
+        config: &AppConfig,
+        relay: &PeerConfig,
+        incoming_tx: mpsc::Sender<IncomingMessage>,
+    ) -> Result<(Self, RelayHandle)> {
+        let addr = format!("{}:{}", relay.host, relay.port);
+        let (tx, outgoing_rx) = mpsc::channel(QUEUE_LEN);
+        let connected = Arc::new(AtomicBool::new(false));
+        let client = Self {
+            addr: addr.clone(),
+            psk: sending_psk(config)?,
+            channel: config.channel.clone(),
+            aead: config.aead_algorithm,
+            keepalive: KeepaliveSettings::from_config(config),
+            max_frame_body: config.max_frame_body,
+            incoming_tx,
+            outgoing_rx,
+            connected: connected.clone(),
+            replay: Arc::default(),
+        };
+        Ok((client, RelayHandle { addr, tx, connected }))
+    }
+
+    /// 连接中继并收发，连接失败或断开后按固定间隔重连。
+    pub(crate) async fn run(mut self) {
+        loop {
+            match self.session().await {
+                Ok(()) => tracing::info!("relay {} closed the connection", self.addr),
+                Err(e) => tracing::warn!("relay {} connection error: {e}", self.addr),
+            }
+            self.connected.store(false, Ordering::Relaxed);
+            // 断开期间积压的消息已过期，丢弃
+            while self.outgoing_rx.try_recv().is_ok() {}
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    async fn session(&mut self) -> Result<()> {
+        let mut stream = TcpStream::connect(&self.addr).await?;
+        enable_keepalive(&stream, self.keepalive)?;
+        stream
+            .write_all(&hello(&room_id(&self.psk, &self.channel)))
+            .await?;
+        let from = stream.peer_addr()?;
+        tracing::info!("connected to relay {}", self.addr);
+        self.connected.store(true, Ordering::Relaxed);
+
+        let (reader, mut writer) = stream.into_split();
+        let key = relay_key(&self.psk);
+        let opener = RelayOpener {
+            key,
+            key_id: key_id(&self.psk),
+            channel: self.channel.clone(),
+            aead: self.aead,
+            replay: self.replay.clone(),
+        };
+        let mut read_task = tokio::spawn(receive_frames(
+            reader,
+            from,
+            opener,
+            self.max_frame_body,
+            self.incoming_tx.clone(),
+        ));
+        let result = loop {
+            tokio::select! {
+                finished = &mut read_task => {
+                    break finished.map_err(|e| anyhow!("relay reader failed: {e}"))?;
+                }
+                body = self.outgoing_rx.recv() => {
+                    let Some(body) = body else { break Ok(()) };
+                    let sent = async {
+                        let mut plaintext = Vec::with_capacity(TIMESTAMP_LEN + body.len());
+                        plaintext.extend_from_slice(&now_ms().to_be_bytes());
+                        plaintext.extend_from_slice(&body);
+                        let (nonce, ciphertext) =
+                            encrypt(self.aead, &key, &plaintext, self.channel.as_bytes())?;
+                        let max = self.max_frame_body;
+                        write_frame(&mut writer, self.aead, &nonce, &ciphertext, max).await
+                    }
+                    .await;
+                    if let Err(e) = sent {
+                        break Err(e);
+                    }
+                }
+            }
+        };
+        read_task.abort();
+        result
+    }
+}
+
+/// 解开中继转发来的帧：解密、检查新鲜度与重放，再解码为协议消息。
+struct RelayOpener {
+    key: Key,
+    key_id: [u8; KEY_ID_LEN],
+    channel: String,
+    aead: AeadAlgorithm,
+    replay: Arc<Mutex<ReplayWindow>>,
+}
+
+impl RelayOpener {
+    fn open(&self, header: u32, body: &[u8], now_ms: u64) -> Result<ProtocolMessage> {
+        check_frame_algorithm(split_frame_header(header).0, self.aead)?;
+        if body.len() < 12 {
+            return Err(anyhow!("frame body too short for nonce"));
+        }
+        let mut nonce = [0u8; 12];
+        nonce.copy_from_slice(&body[..12]);
+        let plaintext = decrypt(self.aead, &self.key, &nonce, &body[12..], self.channel.as_bytes())?;
+        if plaintext.len() < TIMESTAMP_LEN {
+            return Err(anyhow!("relayed frame too short for timestamp"));
+        }
+        let (sent, message) = plaintext.split_at(TIMESTAMP_LEN);
+        let sent = u64::from_be_bytes(sent.try_into().expect("timestamp length"));
+        self.replay
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .check(nonce, sent, now_ms)?;
+        decode_message(message)
+    }
+}
+
+/// 读取中继转发来的帧，解开后推送给核心服务；无法解密的帧（其他设备的配置不一致）
+/// 与过期或重放的帧记录后丢弃。
+async fn receive_frames<R>(
+    mut reader: R,
+    from: SocketAddr,
+    opener: RelayOpener,
+    max_frame_body: usize,
+    incoming_tx: mpsc::Sender<IncomingMessage>,
+) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+{
+    while let Some((header, body)) = read_frame(&mut reader, max_frame_body).await? {
+        let msg = match opener.open(header, &body, now_ms()) {
+            Ok(msg) => msg,
+            Err(e) => {
+                tracing::warn!("dropping relayed frame: {e}");
+                continue;
+            }
+        };
+        incoming_tx
+            .send(IncomingMessage {
+                from,
+                key_id: opener.key_id,
+                relayed: true,
+                msg,
+            })
+            .await
+            .map_err(|_| anyhow!("channel closed"))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{encode_message, ContentType, Selection};
+
+    #[tokio::test]
+    async fn relay_forwards_frame_between_clients() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(RelayServer::new().serve(listener));
+
+        let config = AppConfig {
+            relay: Some(PeerConfig::new("127.0.0.1", port)),
+            channel: "work".into(),
+            ..AppConfig::default()
+        };
+        let relay = config.relay.clone().unwrap();
+        let (a_tx, mut a_rx) = mpsc::channel(4);
+        let (b_tx, mut b_rx) = mpsc::channel(4);
+        let (a, a_handle) = RelayClient::new(&config, &relay, a_tx).unwrap();
+        let (b, _b_handle) = RelayClient::new(&config, &relay, b_tx).unwrap();
+        tokio::spawn(a.run());
+        tokio::spawn(b.run());
+        // 其他房间（不同频道）的客户端收不到该帧
+        let other = AppConfig {
+            channel: "home".into(),
+            ..config.clone()
+        };
+        let (c_tx, mut c_rx) = mpsc::channel(4);
+        let (c, _c_handle) = RelayClient::new(&other, &relay, c_tx).unwrap();
+        tokio::spawn(c.run());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let msg = ProtocolMessage::ClipboardUpdate {
+            sender_id: [1; 16],
+            content_type: ContentType::Text,
+            payload_size: 2,
+            payload: b"hi".to_vec(),
+            seq: 1,
+            selection: Selection::Clipboard,
+            source_app: None,
+            clock: 0,
+            ttl_secs: 0,
+            sensitive: false,
+        };
+        let body = Arc::new(encode_message(&msg).unwrap());
+        assert!(a_handle.send(body).is_ok());
+
+        let received = tokio::time::timeout(Duration::from_secs(2), b_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            received.msg,
+            ProtocolMessage::ClipboardUpdate { ref payload, .. } if payload == b"hi"
+        ));
+        // 发送者自己与其他房间都不会收到
+        let echoed = tokio::time::timeout(Duration::from_millis(200), a_rx.recv()).await;
+        assert!(echoed.is_err());
+        assert!(c_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn relay_connections_are_bounded() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(RelayServer::new().with_max_connections(2).serve(listener));
+
+        let room = room_id(&[3u8; 32], "");
+        let mut clients = Vec::new();
+        for _ in 0..3 {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+            stream.write_all(&hello(&room)).await.unwrap();
+            clients.push(stream);
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let frame = |byte: u8| [&4u32.to_be_bytes()[..], &[byte; 4]].concat();
+        let mut reply = [0u8; 8];
+
+        // 第三个连接等待许可，收不到房间内转发的帧
+        clients[0].write_all(&frame(1)).await.unwrap();
+        clients[1].read_exact(&mut reply).await.unwrap();
+        let waiting = tokio::time::timeout(Duration::from_millis(300), clients[2].read_exact(&mut reply));
+        assert!(waiting.await.is_err());
+
+        // 已接受的连接断开后，等待中的连接得到处理
+        drop(clients.remove(1));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        clients[0].write_all(&frame(2)).await.unwrap();
+        let read = tokio::time::timeout(Duration::from_secs(2), clients[1].read_exact(&mut reply));
+        read.await.unwrap().unwrap();
+        assert_eq!(reply, frame(2)[..]);
+    }
+
+    #[test]
+    fn member_queue_is_bounded_by_bytes() {
+        let (queue, mut rx) = MemberQueue::new();
+        // 空队列总能接收一帧，即使它超过上限
+        assert!(queue.push(Arc::new(vec![0; QUEUE_BYTES + 1])));
+        assert!(!queue.push(Arc::new(vec![0; 1])));
+        let frame = rx.try_recv().unwrap();
+        queue.queued.fetch_sub(frame.len(), Ordering::Relaxed);
+
+        assert!(queue.push(Arc::new(vec![0; QUEUE_BYTES - 10])));
+        assert!(queue.push(Arc::new(vec![0; 10])));
+        assert!(!queue.push(Arc::new(vec![0; 1])));
+        assert_eq!(queue.queued.load(Ordering::Relaxed), QUEUE_BYTES);
+    }
+
+    #[test]
+    fn relayed_frames_are_rejected_when_replayed_or_stale() {
+        let psk = [5u8; 32];
+        let key = relay_key(&psk);
+        let opener = RelayOpener {
+            key,
+            key_id: key_id(&psk),
+            channel: "work".into(),
+            aead: AeadAlgorithm::default(),
+            replay: Arc::default(),
+        };
+        let body = encode_message(&ProtocolMessage::TransferEnd { sender_id: [1; 16] }).unwrap();
+        let seal = |sent_ms: u64| {
+            let plaintext = [&sent_ms.to_be_bytes()[..], &body].concat();
+            let (nonce, ciphertext) =
+                encrypt(opener.aead, &key, &plaintext, opener.channel.as_bytes()).unwrap();
+            let frame = [&nonce[..], &ciphertext].concat();
+            (frame.len() as u32, frame)
+        };
+        let now = 1_000_000_000;
+        let max_age = RELAY_MAX_AGE.as_millis() as u64;
+
+        let (header, frame) = seal(now);
+        assert!(opener.open(header, &frame, now).is_ok());
+        // 同一帧再次到达即为重放，窗口结束前一直会被识别
+        assert!(opener.open(header, &frame, now + 1).is_err());
+        assert!(opener.open(header, &frame, now + max_age).is_err());
+        // 窗口结束后因过旧被拒绝
+        assert!(opener.open(header, &frame, now + max_age + 1).is_err());
+        assert_eq!(opener.replay.lock().unwrap().seen.len(), 0);
+
+        let (header, stale) = seal(now - max_age - 1);
+        assert!(opener.open(header, &stale, now).is_err());
+        let (header, early) = seal(now + max_age + 1);
+        assert!(opener.open(header, &early, now).is_err());
+        let (header, fresh) = seal(now - max_age);
+        assert!(opener.open(header, &fresh, now).is_ok());
+    }
+
+    #[test]
+    fn hello_roundtrip_and_room_separation() {
+        let psk = [7u8; 32];
+        let room = room_id(&psk, "work");
+        assert_eq!(parse_hello(&hello(&room)).unwrap(), room);
+        assert_ne!(room, room_id(&psk, "home"));
+        assert_ne!(room, room_id(&[8u8; 32], "work"));
+
+        let mut bad = hello(&room);
+        bad[0] = b'X';
+        assert!(parse_hello(&bad).is_err());
+    }
+}
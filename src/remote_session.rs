@@ -0,0 +1,77 @@
+//! 远程会话检测：识别 RDP / VNC / X2Go 等远程桌面环境，避免与远程客户端自带的剪贴板同步形成回环。
+
+/// 检测当前进程是否运行在远程桌面会话中，返回命中的原因描述。
+pub fn detect_remote_session() -> Option<String> {
+    detect_remote_session_with(|name| std::env::var(name).ok())
+}
+
+/// 基于给定的环境变量查询函数检测远程会话（便于测试）。
+pub fn detect_remote_session_with<F>(env: F) -> Option<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let present = |name: &str| env(name).is_some_and(|v| !v.trim().is_empty());
+
+    // Windows：远程桌面会话的 SESSIONNAME 形如 "RDP-Tcp#3"，本地控制台为 "Console"
+    if let Some(session) = env("SESSIONNAME") {
+        let upper = session.trim().to_ascii_uppercase();
+        if upper.starts_with("RDP-") || upper.starts_with("ICA-") {
+            return Some(format!("SESSIONNAME={}", session.trim()));
+        }
+    }
+
+    // Linux：常见远程桌面方案设置的环境变量
+    for name in [
+        "XRDP_SESSION",
+        "X2GO_SESSION",
+        "VNCDESKTOP",
+        "NXSESSIONID",
+        "CHROME_REMOTE_DESKTOP_SESSION",
+    ] {
+        if present(name) {
+            return Some(format!("{} is set", name));
+        }
+    }
+
+    // 通过 SSH 转发的图形会话（ssh -X）
+    if present("SSH_CONNECTION") && present("DISPLAY") && !present("WAYLAND_DISPLAY") {
+        return Some("X11 forwarded over SSH (SSH_CONNECTION and DISPLAY set)".to_string());
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn detect(vars: &[(&str, &str)]) -> Option<String> {
+        let map: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        detect_remote_session_with(|name| map.get(name).cloned())
+    }
+
+    #[test]
+    fn detects_remote_sessions() {
+        assert!(detect(&[("SESSIONNAME", "RDP-Tcp#2")]).is_some());
+        assert!(detect(&[("XRDP_SESSION", "1")]).is_some());
+        assert!(detect(&[("VNCDESKTOP", "host:1")]).is_some());
+        assert!(detect(&[
+            ("SSH_CONNECTION", "10.0.0.2 5022 10.0.0.3 22"),
+            ("DISPLAY", "localhost:10.0"),
+        ])
+        .is_some());
+    }
+
+    #[test]
+    fn ignores_local_sessions() {
+        assert!(detect(&[]).is_none());
+        assert!(detect(&[("SESSIONNAME", "Console")]).is_none());
+        assert!(detect(&[("DISPLAY", ":0"), ("XDG_SESSION_TYPE", "x11")]).is_none());
+        assert!(detect(&[("SSH_CONNECTION", "10.0.0.2 5022 10.0.0.3 22")]).is_none());
+        assert!(detect(&[("XRDP_SESSION", "")]).is_none());
+    }
+}
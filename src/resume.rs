@@ -0,0 +1,216 @@
+//! 大负载断点续传：发送端逐块发送并等待接收端确认，连接中断后从最后确认的偏移继续，
+//! 而不是从头重发；接收端把已连续收到的前缀持久化到磁盘，按发送者与传输 ID 索引。
+
+use anyhow::{anyhow, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::chunk::chunk_size_for;
+
+/// 待发送的分块负载及其确认进度。
+#[derive(Debug)]
+pub struct OutgoingChunks {
+    data: Arc<Vec<u8>>,
+    chunk_size: usize,
+    /// 接收端已确认的连续字节数
+    acked: usize,
+}
+
+impl OutgoingChunks {
+    /// 按目标块大小切分负载（实际块大小见 [`chunk_size_for`]）。
+    pub fn new(data: Arc<Vec<u8>>, target_chunk_size: usize) -> Self {
+        let chunk_size = chunk_size_for(data.len(), target_chunk_size);
+        Self {
+            data,
+            chunk_size,
+            acked: 0,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    /// 从已确认的偏移开始的下一块，全部确认后返回 None。
+    pub fn next_chunk(&self) -> Option<(u64, &[u8])> {
+        if self.is_complete() {
+            return None;
+        }
+        let end = (self.acked + self.chunk_size).min(self.data.len());
+        Some((self.acked as u64, &self.data[self.acked..end]))
+    }
+
+    /// 记录接收端的确认。以接收端的进度为准（例如接收端清理了部分文件后需从头重发），
+    /// 超出总长度的确认视为无效。
+    pub fn ack(&mut self, next_offset: u64) -> Result<()> {
+        self.acked = usize::try_from(next_offset)
+            .ok()
+            .filter(|next| *next <= self.data.len())
+            .ok_or_else(|| anyhow!("chunk ack offset {} exceeds payload", next_offset))?;
+        Ok(())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.acked == self.data.len()
+    }
+}
+
+/// 接收端处理一块后的结果。
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChunkOutcome {
+    /// 尚未收齐：确认已连续收到的字节数，发送端从该偏移继续
+    Ack(u64),
+    /// 已收齐，返回完整负载（磁盘上的部分文件已删除）
+    Complete(Vec<u8>),
+}
+
+/// 接收端的部分传输存储：每个传输一个 `.part` 文件，文件长度即已连续收到的字节数，
+/// 因此接收端重启后仍能从上次的进度继续。
+#[derive(Debug, Clone)]
+pub struct PartialStore {
+    dir: PathBuf,
+}
+
+impl PartialStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn path(&self, sender_id: &[u8; 16], transfer_id: u64) -> PathBuf {
+        self.dir.join(format!("{}-{}.part", hex::encode(sender_id), transfer_id))
+    }
+
+    /// 已连续收到的字节数，未开始的传输为 0。
+    pub fn received(&self, sender_id: &[u8; 16], transfer_id: u64) -> u64 {
+        std::fs::metadata(self.path(sender_id, transfer_id)).map_or(0, |meta| meta.len())
+    }
+
+    /// 写入一块。偏移与已收到的进度不连续（重复或跳块）时不写入，只返回当前进度，
+    /// 发送端据此从正确的位置重发。
+    pub fn add(
+        &self,
+        sender_id: &[u8; 16],
+        transfer_id: u64,
+        total: u64,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<ChunkOutcome> {
+        let end = offset
+            .checked_add(data.len() as u64)
+            .filter(|end| *end <= total)
+            .ok_or_else(|| anyhow!("chunk at offset {} exceeds total size {}", offset, total))?;
+        let path = self.path(sender_id, transfer_id);
+        let received = self.received(sender_id, transfer_id);
+        if offset != received || data.is_empty() {
+            return Ok(ChunkOutcome::Ack(received));
+        }
+        std::fs::create_dir_all(&self.dir)?;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        file.write_all(data)?;
+        // 确认之前落盘，确认过的数据在接收端崩溃后也不会丢失
+        file.sync_data()?;
+        if end < total {
+            return Ok(ChunkOutcome::Ack(end));
+        }
+        let payload = std::fs::read(&path)?;
+        let _ = std::fs::remove_file(&path);
+        Ok(ChunkOutcome::Complete(payload))
+    }
+
+    /// 删除修改时间早于 `now - max_age` 的部分文件（发送端已放弃的传输），返回删除数量。
+    pub fn prune(&self, max_age: Duration, now: SystemTime) -> usize {
+        let Some(cutoff) = now.checked_sub(max_age) else {
+            return 0;
+        };
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return 0;
+        };
+        entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "part"))
+            .filter(|path| is_older(path, cutoff) && std::fs::remove_file(path).is_ok())
+            .count()
+    }
+}
+
+fn is_older(path: &Path, cutoff: SystemTime) -> bool {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .is_ok_and(|modified| modified < cutoff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::MIN_CHUNK_SIZE;
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn interrupted_transfer_resumes_from_third_chunk() {
+        let dir = tempfile::tempdir().unwrap();
+        let sender = [4u8; 16];
+        let data = payload(4 * MIN_CHUNK_SIZE);
+        let mut outgoing = OutgoingChunks::new(Arc::new(data.clone()), MIN_CHUNK_SIZE);
+        let store = PartialStore::new(dir.path().to_path_buf());
+
+        // 前两块送达并确认
+        for _ in 0..2 {
+            let (offset, chunk) = outgoing.next_chunk().unwrap();
+            match store.add(&sender, 1, outgoing.total(), offset, chunk).unwrap() {
+                ChunkOutcome::Ack(next) => outgoing.ack(next).unwrap(),
+                ChunkOutcome::Complete(_) => panic!("transfer completed too early"),
+            }
+        }
+        // 第三块发出时连接中断，未送达也未确认；接收端重启后从磁盘恢复进度
+        let store = PartialStore::new(dir.path().to_path_buf());
+        assert_eq!(store.received(&sender, 1), 2 * MIN_CHUNK_SIZE as u64);
+
+        // 重新连接后从第三块继续，不重发前两块
+        let mut sent = Vec::new();
+        while let Some((offset, chunk)) = outgoing.next_chunk() {
+            sent.push(offset);
+            match store.add(&sender, 1, outgoing.total(), offset, chunk).unwrap() {
+                ChunkOutcome::Ack(next) => outgoing.ack(next).unwrap(),
+                ChunkOutcome::Complete(received) => {
+                    assert_eq!(received, data);
+                    outgoing.ack(outgoing.total()).unwrap();
+                }
+            }
+        }
+        let chunk = MIN_CHUNK_SIZE as u64;
+        assert_eq!(sent, vec![2 * chunk, 3 * chunk]);
+        assert_eq!(store.received(&sender, 1), 0);
+    }
+
+    #[test]
+    fn out_of_order_chunk_reports_current_progress() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = PartialStore::new(dir.path().to_path_buf());
+        let sender = [5u8; 16];
+        let data = payload(300);
+        assert_eq!(store.add(&sender, 2, 300, 0, &data[..100]).unwrap(), ChunkOutcome::Ack(100));
+        // 重复的块与跳过的块都不写入，只告知当前进度
+        assert_eq!(store.add(&sender, 2, 300, 0, &data[..100]).unwrap(), ChunkOutcome::Ack(100));
+        assert_eq!(store.add(&sender, 2, 300, 200, &data[200..]).unwrap(), ChunkOutcome::Ack(100));
+        assert!(store.add(&sender, 2, 300, 250, &data[..100]).is_err());
+        // 不同传输互不影响
+        assert_eq!(store.received(&sender, 3), 0);
+
+        let mut outgoing = OutgoingChunks::new(Arc::new(data), MIN_CHUNK_SIZE);
+        outgoing.ack(100).unwrap();
+        assert_eq!(outgoing.next_chunk().unwrap().0, 100);
+        // 接收端进度回退时从接收端的进度重发
+        outgoing.ack(0).unwrap();
+        assert_eq!(outgoing.next_chunk().unwrap().0, 0);
+        assert!(outgoing.ack(301).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+//! 下载目录定期清理：删除超过保留天数的已接收文件。
+//!
+//! 只处理本程序创建的内容：按接收时间戳命名的子目录（`YYYYMMDD-HHMMSS`）中的文件，
+//! 以及下载目录下的 `image-*.png`，用户放在下载目录中的其他文件不会被触碰。
+
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// 两次清理之间的间隔
+pub const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// 一次清理的结果汇总。
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CleanupSummary {
+    pub files_removed: usize,
+    pub dirs_removed: usize,
+    pub bytes_freed: u64,
+}
+
+/// 删除 `dir` 中修改时间早于 `now - max_age` 的已接收文件，并移除因此变空的接收子目录。
+///
+/// 单个文件删除失败只记录警告，不中断清理。
+pub fn cleanup_download_dir(dir: &Path, max_age: Duration, now: SystemTime) -> CleanupSummary {
+    let mut summary = CleanupSummary::default();
+    let Some(cutoff) = now.checked_sub(max_age) else {
+        return summary;
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return summary;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() && is_receive_dir_name(&name) {
+            let Ok(children) = std::fs::read_dir(&path) else {
+                continue;
+            };
+            for child in children.flatten() {
+                if child.file_type().is_ok_and(|t| t.is_file()) {
+                    remove_if_expired(&child.path(), cutoff, &mut summary);
+                }
+            }
+            // 目录非空时删除失败，正好保留仍在保留期内的文件
+            if std::fs::remove_dir(&path).is_ok() {
+                summary.dirs_removed += 1;
+            }
+        } else if file_type.is_file() && name.starts_with("image-") && name.ends_with(".png") {
+            remove_if_expired(&path, cutoff, &mut summary);
+        }
+    }
+    summary
+}
+
+/// 接收子目录以接收时间命名，格式为 `YYYYMMDD-HHMMSS`。
+fn is_receive_dir_name(name: &str) -> bool {
+    chrono::NaiveDateTime::parse_from_str(name, "%Y%m%d-%H%M%S").is_ok()
+}
+
+fn remove_if_expired(path: &Path, cutoff: SystemTime, summary: &mut CleanupSummary) {
+    let Ok(meta) = std::fs::metadata(path) else {
+        return;
+    };
+    if meta.modified().map_or(true, |modified| modified >= cutoff) {
+        return;
+    }
+    match std::fs::remove_file(path) {
+        Ok(()) => {
+            summary.files_removed += 1;
+            summary.bytes_freed += meta.len();
+        }
+        Err(e) => tracing::warn!("failed to remove expired file {}: {}", path.display(), e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    #[test]
+    fn removes_old_received_files_and_keeps_recent_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let old_dir = dir.path().join("20240101-080000");
+        let recent_dir = dir.path().join("20240301-080000");
+        std::fs::create_dir_all(&old_dir).unwrap();
+        std::fs::create_dir_all(&recent_dir).unwrap();
+        std::fs::write(old_dir.join("old.txt"), b"old").unwrap();
+        std::fs::write(recent_dir.join("recent.txt"), b"recent").unwrap();
+        std::fs::write(dir.path().join("image-1.png"), b"png").unwrap();
+        // 不是本程序创建的文件与目录，即使同样过期也不能删除
+        std::fs::write(dir.path().join("user.txt"), b"mine").unwrap();
+        std::fs::create_dir_all(dir.path().join("projects")).unwrap();
+        std::fs::write(dir.path().join("projects").join("a.txt"), b"a").unwrap();
+
+        let set_age = |path: &Path, age: Duration| {
+            let file = std::fs::File::options().write(true).open(path).unwrap();
+            file.set_modified(SystemTime::now() - age).unwrap();
+        };
+        let now = SystemTime::now();
+        set_age(&old_dir.join("old.txt"), 10 * DAY);
+        set_age(&recent_dir.join("recent.txt"), DAY);
+        set_age(&dir.path().join("image-1.png"), 10 * DAY);
+        set_age(&dir.path().join("user.txt"), 10 * DAY);
+        set_age(&dir.path().join("projects").join("a.txt"), 10 * DAY);
+
+        let summary = cleanup_download_dir(dir.path(), 7 * DAY, now);
+        assert_eq!(
+            summary,
+            CleanupSummary {
+                files_removed: 2,
+                dirs_removed: 1,
+                bytes_freed: 6,
+            }
+        );
+        assert!(!old_dir.exists());
+        assert!(recent_dir.join("recent.txt").exists());
+        assert!(!dir.path().join("image-1.png").exists());
+        assert!(dir.path().join("user.txt").exists());
+        assert!(dir.path().join("projects").join("a.txt").exists());
+    }
+}
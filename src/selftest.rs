@@ -0,0 +1,94 @@
+//! 自检：在本机回环地址上启动监听并向自己发送一条测试消息，
+//! 一次性验证 加密 → 分帧 → 解密 → 解码 → 落地 的完整链路，无需第二台设备。
+
+use anyhow::{anyhow, Result};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::clipboard::ClipboardItem;
+use crate::config::{AppConfig, PeerConfig};
+use crate::keyring::KeyRing;
+use crate::network::{broadcast_to_peers, NetworkServer};
+use crate::protocol::{ContentType, ProtocolMessage, Selection};
+
+/// 等待回环消息的最长时间
+const SELFTEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// 监听尚未就绪时的发送重试次数
+const SELFTEST_SEND_ATTEMPTS: usize = 10;
+
+/// 运行回环自检，成功返回 Ok，失败时错误中说明失败的环节。
+pub async fn run_selftest() -> Result<()> {
+    let mut config = AppConfig {
+        listen_port: 0,
+        secret_key: AppConfig::generate_secret_key(),
+        ..AppConfig::default()
+    };
+
+    let (tx, mut rx) = mpsc::channel(4);
+    let keys = KeyRing::from_config(&config)?;
+    let server = NetworkServer::with_keyring(&config, keys, tx)?;
+    let listener = server.bind().await?;
+    let port = server
+        .local_port()
+        .ok_or_else(|| anyhow!("listener has no bound port"))?;
+    config.listen_port = port;
+    config.peers = vec![PeerConfig::new("127.0.0.1", port)];
+    let server_task = tokio::spawn(server.serve(listener));
+    let result = roundtrip(&config, &mut rx).await;
+    server_task.abort();
+    result
+}
+
+async fn roundtrip(
+    config: &AppConfig,
+    rx: &mut mpsc::Receiver<crate::network::IncomingMessage>,
+) -> Result<()> {
+    let text = format!("lan-clipboard-sync selftest {}", uuid::Uuid::new_v4());
+    let msg = ProtocolMessage::ClipboardUpdate {
+        sender_id: [0u8; 16],
+        content_type: ContentType::Text,
+        payload_size: text.len() as u64,
+        payload: text.clone().into_bytes(),
+        seq: 1,
+        selection: Selection::Clipboard,
+        source_app: None,
+        clock: 0,
+        ttl_secs: 0,
+        sensitive: false,
+    };
+
+    let mut last_error = String::new();
+    let mut sent = false;
+    for _ in 0..SELFTEST_SEND_ATTEMPTS {
+        let results = broadcast_to_peers(config, &msg).await?;
+        match results.first() {
+            Some(r) if r.is_ok() => {
+                sent = true;
+                break;
+            }
+            Some(r) => last_error = format!("{:?}", r.outcome),
+            None => return Err(anyhow!("no loopback peer configured")),
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    if !sent {
+        return Err(anyhow!("send to loopback listener failed: {last_error}"));
+    }
+
+    let incoming = tokio::time::timeout(SELFTEST_TIMEOUT, rx.recv())
+        .await
+        .map_err(|_| anyhow!("timed out waiting for loopback message"))?
+        .ok_or_else(|| anyhow!("listener stopped before receiving the message"))?;
+    let item = match incoming.msg {
+        ProtocolMessage::ClipboardUpdate {
+            content_type: ContentType::Text,
+            payload,
+            ..
+        } => ClipboardItem::Text(String::from_utf8(payload)?),
+        other => return Err(anyhow!("unexpected loopback message: {:?}", other)),
+    };
+    match item {
+        ClipboardItem::Text(received) if received == text => Ok(()),
+        _ => Err(anyhow!("loopback payload does not match the sent text")),
+    }
+}
@@ -0,0 +1,44 @@
+//! 命令行发送文件：不经过剪贴板，将指定文件直接推送给所有对端。
+
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+
+use crate::clipboard::ClipboardFile;
+use crate::config::AppConfig;
+use crate::core::read_files_payload;
+use crate::network::{broadcast_to_peers, PeerSendResult};
+use crate::protocol::{ContentType, ProtocolMessage, Selection};
+
+/// 读取给定文件并以文件剪贴板消息广播一次，返回每个对端的发送结果。
+///
+/// 与复制文件时相同，跳过目录、无法读取或超过 `max_file_size` 的文件。
+/// 进程发送后即退出，无法响应对端的文件请求，因此总是直接发送文件内容而不使用文件清单。
+pub async fn send_files(config: &AppConfig, paths: &[PathBuf]) -> Result<Vec<PeerSendResult>> {
+    let files: Vec<ClipboardFile> = paths
+        .iter()
+        .map(|p| ClipboardFile {
+            path: p.to_string_lossy().to_string(),
+        })
+        .collect();
+    let payload = read_files_payload(config, &files)?
+        .ok_or_else(|| anyhow!("no readable files to send"))?;
+    if config.peers.is_empty() {
+        return Err(anyhow!("no peers configured"));
+    }
+    let count = payload.len();
+    let payload = payload.into_bytes();
+    let msg = ProtocolMessage::ClipboardUpdate {
+        sender_id: *uuid::Uuid::new_v4().as_bytes(),
+        content_type: ContentType::Files,
+        payload_size: payload.len() as u64,
+        payload,
+        seq: 1,
+        selection: Selection::Clipboard,
+        source_app: None,
+        clock: 0,
+        ttl_secs: 0,
+        sensitive: false,
+    };
+    tracing::info!("sending {} file(s) to peers", count);
+    broadcast_to_peers(config, &msg).await
+}
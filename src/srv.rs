@@ -0,0 +1,174 @@
+//! DNS SRV 对端发现：配置 `srv = "_lanclip._tcp.example.com"` 后解析 SRV 记录得到对端地址，
+//! 与 `peers` 中手写的对端合并，便于集中管理的部署只维护一条 DNS 记录。
+//!
+//! 启动时解析一次，之后定期重新解析以跟上记录的变化；解析失败时记录日志并按退避重试。
+
+use crate::config::PeerConfig;
+use anyhow::Result;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// 解析成功后重新解析的间隔
+pub const SRV_REFRESH_INTERVAL: Duration = Duration::from_secs(300);
+/// 解析失败后的初始重试间隔，之后逐次加倍直到 [`SRV_REFRESH_INTERVAL`]
+pub const SRV_RETRY_INITIAL: Duration = Duration::from_secs(5);
+
+/// 一条 SRV 记录的目标。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrvTarget {
+    pub priority: u16,
+    pub weight: u16,
+    pub host: String,
+    pub port: u16,
+}
+
+/// 将 SRV 记录转换为对端：按优先级（小者优先）与权重（大者优先）排序，去掉主机名末尾的 `.`
+/// 并去重；目标为 `.` 的记录表示该服务不可用，忽略。
+pub fn peers_from_srv(mut targets: Vec<SrvTarget>) -> Vec<PeerConfig> {
+    targets.sort_by_key(|t| (t.priority, std::cmp::Reverse(t.weight)));
+    let mut peers: Vec<PeerConfig> = Vec::with_capacity(targets.len());
+    for target in targets {
+        let host = target.host.trim_end_matches('.');
+        if host.is_empty() || peers.iter().any(|p| p.host == host && p.port == target.port) {
+            continue;
+        }
+        peers.push(PeerConfig::new(host, target.port));
+    }
+    peers
+}
+
+/// 手写的对端在前，再加入 SRV 解析出的、与手写对端地址不重复的对端。
+pub fn merge_peers(configured: &[PeerConfig], discovered: Vec<PeerConfig>) -> Vec<PeerConfig> {
+    let mut peers = configured.to_vec();
+    for peer in discovered {
+        if !peers.iter().any(|p| p.host == peer.host && p.port == peer.port) {
+            peers.push(peer);
+        }
+    }
+    peers
+}
+
+/// 使用系统 DNS 配置解析 SRV 记录。
+pub async fn resolve_srv(name: String) -> Result<Vec<SrvTarget>> {
+    let resolver = hickory_resolver::TokioAsyncResolver::tokio_from_system_conf()?;
+    let lookup = resolver.srv_lookup(name.as_str()).await?;
+    Ok(lookup
+        .iter()
+        .map(|srv| SrvTarget {
+            priority: srv.priority(),
+            weight: srv.weight(),
+            host: srv.target().to_utf8(),
+            port: srv.port(),
+        })
+        .collect())
+}
+
+/// 持续解析 `name`，每次解析成功时把得到的对端发到 `tx`（与上次相同时不发送）；
+/// 接收端关闭后返回。
+pub async fn refresh_srv_peers<F, Fut>(
+    name: String,
+    mut resolve: F,
+    tx: mpsc::Sender<Vec<PeerConfig>>,
+) where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<Vec<SrvTarget>>>,
+{
+    let mut retry = SRV_RETRY_INITIAL;
+    let mut last: Option<Vec<(String, u16)>> = None;
+    loop {
+        let wait = match resolve(name.clone()).await {
+            Ok(targets) => {
+                retry = SRV_RETRY_INITIAL;
+                let peers = peers_from_srv(targets);
+                let addrs: Vec<(String, u16)> =
+                    peers.iter().map(|p| (p.host.clone(), p.port)).collect();
+                if last.as_ref() != Some(&addrs) {
+                    tracing::info!("srv {} resolved to {} peer(s)", name, peers.len());
+                    if tx.send(peers).await.is_err() {
+                        return;
+                    }
+                    last = Some(addrs);
+                }
+                SRV_REFRESH_INTERVAL
+            }
+            Err(e) => {
+                tracing::warn!("failed to resolve srv {}: {}, retrying in {:?}", name, e, retry);
+                let wait = retry;
+                retry = (retry * 2).min(SRV_REFRESH_INTERVAL);
+                wait
+            }
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = tx.closed() => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    fn target(priority: u16, weight: u16, host: &str, port: u16) -> SrvTarget {
+        SrvTarget {
+            priority,
+            weight,
+            host: host.to_string(),
+            port,
+        }
+    }
+
+    fn addrs(peers: &[PeerConfig]) -> Vec<(&str, u16)> {
+        peers.iter().map(|p| (p.host.as_str(), p.port)).collect()
+    }
+
+    #[test]
+    fn srv_targets_become_ordered_unique_peers() {
+        let peers = peers_from_srv(vec![
+            target(20, 0, "backup.example.com.", 5000),
+            target(10, 5, "b.example.com.", 5001),
+            target(10, 50, "a.example.com.", 5000),
+            target(10, 1, "a.example.com", 5000),
+            target(0, 0, ".", 0),
+        ]);
+        assert_eq!(
+            addrs(&peers),
+            [("a.example.com", 5000), ("b.example.com", 5001), ("backup.example.com", 5000)]
+        );
+
+        let merged = merge_peers(&[PeerConfig::new("b.example.com", 5001)], peers);
+        assert_eq!(
+            addrs(&merged),
+            [("b.example.com", 5001), ("a.example.com", 5000), ("backup.example.com", 5000)]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_resolution_is_retried_until_peers_arrive() {
+        let mut answers: VecDeque<Result<Vec<SrvTarget>>> = VecDeque::from([
+            Err(anyhow::anyhow!("no answer")),
+            Ok(vec![target(0, 0, "10.0.0.2", 5000)]),
+            Ok(vec![target(0, 0, "10.0.0.2", 5000)]),
+            Ok(vec![target(0, 0, "10.0.0.3", 5000)]),
+        ]);
+        let (tx, mut rx) = mpsc::channel(4);
+        tokio::spawn(refresh_srv_peers(
+            "_lanclip._tcp.example.com".into(),
+            move |name| {
+                assert_eq!(name, "_lanclip._tcp.example.com");
+                let answer = answers.pop_front().unwrap_or_else(|| Ok(Vec::new()));
+                async move { answer }
+            },
+            tx,
+        ));
+
+        let started = tokio::time::Instant::now();
+        assert_eq!(addrs(&rx.recv().await.unwrap()), [("10.0.0.2", 5000)]);
+        assert_eq!(started.elapsed(), SRV_RETRY_INITIAL);
+        // 记录未变化的那次刷新不重复发送
+        assert_eq!(addrs(&rx.recv().await.unwrap()), [("10.0.0.3", 5000)]);
+        assert_eq!(started.elapsed(), SRV_RETRY_INITIAL + SRV_REFRESH_INTERVAL * 2);
+    }
+}
@@ -0,0 +1,123 @@
+//! 运行统计：收发的剪贴板消息数与字节数、各对端的发送成功率，
+//! 供长期运行的守护进程按 `stats_log_interval_secs` 定期输出到日志。
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::network::{PeerSendOutcome, PeerSendResult};
+
+/// 单个对端的发送统计。
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PeerStats {
+    pub attempts: u64,
+    pub successes: u64,
+}
+
+/// 自上次重置以来的收发统计。
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub messages_sent: u64,
+    pub bytes_sent: u64,
+    pub messages_received: u64,
+    pub bytes_received: u64,
+    /// 按对端地址（host:port）统计，按地址排序便于日志比对
+    pub peers: BTreeMap<String, PeerStats>,
+}
+
+impl Stats {
+    /// 记录一次发出：`size` 为负载字节数，`results` 为各对端的发送结果。
+    pub fn record_sent(&mut self, size: u64, results: &[PeerSendResult]) {
+        self.messages_sent += 1;
+        self.bytes_sent += size;
+        // 冷却期内跳过的对端没有实际尝试，不计入成功率
+        for result in results.iter().filter(|r| r.outcome != PeerSendOutcome::Skipped) {
+            let peer = self.peers.entry(result.addr.clone()).or_default();
+            peer.attempts += 1;
+            peer.successes += u64::from(result.is_ok());
+        }
+    }
+
+    /// 记录一次收到的剪贴板内容。
+    pub fn record_received(&mut self, size: u64) {
+        self.messages_received += 1;
+        self.bytes_received += size;
+    }
+
+    /// 清零所有计数（包括对端统计）。
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// 生成一行统计摘要，`elapsed` 为统计覆盖的时长，用于计算吞吐量。
+    pub fn summary(&self, elapsed: Duration) -> String {
+        let secs = elapsed.as_secs_f64().max(1.0);
+        let mut line = format!(
+            "stats over {}s: sent {} msg ({} B, {:.0} B/s), received {} msg ({} B, {:.0} B/s)",
+            elapsed.as_secs(),
+            self.messages_sent,
+            self.bytes_sent,
+            self.bytes_sent as f64 / secs,
+            self.messages_received,
+            self.bytes_received,
+            self.bytes_received as f64 / secs,
+        );
+        let peers: Vec<String> = self
+            .peers
+            .iter()
+            .map(|(addr, peer)| {
+                let rate = peer.successes as f64 * 100.0 / peer.attempts.max(1) as f64;
+                format!("{} {}/{} ok ({:.0}%)", addr, peer.successes, peer.attempts, rate)
+            })
+            .collect();
+        if !peers.is_empty() {
+            line.push_str("; peers: ");
+            line.push_str(&peers.join(", "));
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(addr: &str, outcome: PeerSendOutcome) -> PeerSendResult {
+        PeerSendResult {
+            addr: addr.to_string(),
+            outcome,
+        }
+    }
+
+    fn sample() -> Stats {
+        let mut stats = Stats::default();
+        let a = "10.0.0.2:5000";
+        let b = "10.0.0.3:5000";
+        let ok = |addr| result(addr, PeerSendOutcome::Ok);
+        stats.record_sent(1000, &[ok(a), ok(b)]);
+        stats.record_sent(200, &[ok(a), result(b, PeerSendOutcome::Timeout)]);
+        stats.record_received(600);
+        stats
+    }
+
+    #[test]
+    fn reset_zeroes_counters() {
+        let mut stats = sample();
+        assert_eq!(stats.messages_sent, 2);
+        assert_eq!(stats.peers["10.0.0.3:5000"], PeerStats { attempts: 2, successes: 1 });
+        stats.reset();
+        assert_eq!(stats, Stats::default());
+    }
+
+    #[test]
+    fn summary_reports_throughput_and_peer_success_rates() {
+        assert_eq!(
+            sample().summary(Duration::from_secs(60)),
+            "stats over 60s: sent 2 msg (1200 B, 20 B/s), received 1 msg (600 B, 10 B/s); \
+             peers: 10.0.0.2:5000 2/2 ok (100%), 10.0.0.3:5000 1/2 ok (50%)"
+        );
+        assert_eq!(
+            Stats::default().summary(Duration::from_secs(60)),
+            "stats over 60s: sent 0 msg (0 B, 0 B/s), received 0 msg (0 B, 0 B/s)"
+        );
+    }
+}
@@ -133,6 +133,28 @@ pub enum TrayEvent {
     OpenConfigUI,
     /// 复制配置路径（已废弃，保留兼容）
     OpenConfig,
+    /// 切换 Linux 剪贴板后端（自动 / X11 / Wayland）
+    SetDisplayBackend(crate::config::DisplayBackend),
+    /// 把当前剪贴板只发送给指定对端（host:port）
+    SendToPeer(String),
+    /// 暂停或恢复同步
+    TogglePause,
+    /// 立即发送当前剪贴板（同发送快捷键）
+    SyncNow,
+    /// 生成新的共享密钥并宣布给所有对端
+    RotateKey,
+}
+
+/// 左键单击托盘图标时发出的事件，`none` 时为 None。
+pub fn click_event(action: crate::config::TrayClickAction) -> Option<TrayEvent> {
+    use crate::config::TrayClickAction;
+
+    match action {
+        TrayClickAction::None => None,
+        TrayClickAction::OpenConfig => Some(TrayEvent::OpenConfigUI),
+        TrayClickAction::TogglePause => Some(TrayEvent::TogglePause),
+        TrayClickAction::SyncNow => Some(TrayEvent::SyncNow),
+    }
 }
 
 /// 系统托盘管理器。
@@ -201,6 +223,74 @@ impl TrayManager {
         })
         .map_err(|e| anyhow!("failed to add Copy Config Path menu item: {}", e))?;
 
+        let config_path_clone = config_path.clone();
+        tray.add_menu_item("复制配对字符串", move || {
+            tracing::info!("Copy Pairing String menu item clicked");
+            copy_pairing_string_to_clipboard(&config_path_clone);
+        })
+        .map_err(|e| anyhow!("failed to add Copy Pairing String menu item: {}", e))?;
+
+        let config_path_clone = config_path.clone();
+        tray.add_menu_item("打开接收文件夹", move || {
+            tracing::info!("Open Download Folder menu item clicked");
+            open_download_dir(&config_path_clone);
+        })
+        .map_err(|e| anyhow!("failed to add Open Download Folder menu item: {}", e))?;
+
+        let event_tx_clone = event_tx.clone();
+        tray.add_menu_item("轮换共享密钥", move || {
+            tracing::info!("Rotate Key menu item clicked");
+            let _ = event_tx_clone.send(TrayEvent::RotateKey);
+        })
+        .map_err(|e| anyhow!("failed to add Rotate Key menu item: {}", e))?;
+
+        // 单独发送给某个对端（同样以标题加一组菜单项呈现），对端列表与单击动作取自启动时的配置
+        let (peers, click_action) = crate::config::AppConfig::load(config_path.clone())
+            .map(|config| (config.peers, config.tray_click_action))
+            .unwrap_or_default();
+        // tray-item 的各平台实现都不单独报告左键单击（Windows 下左右键均弹出菜单，
+        // ksni 只处理菜单项），单击动作暂时无法生效
+        if let Some(event) = click_event(click_action) {
+            tracing::warn!(
+                "tray backend does not report clicks, tray_click_action ({:?}) has no effect",
+                event
+            );
+        }
+        if !peers.is_empty() {
+            tray.add_label("发送到")
+                .map_err(|e| anyhow!("failed to add send-to label: {}", e))?;
+            for peer in peers {
+                let addr = format!("{}:{}", peer.host, peer.port);
+                let event_tx_clone = event_tx.clone();
+                tray.add_menu_item(&format!("    {}", addr), move || {
+                    tracing::info!("Send to peer menu item clicked: {}", addr);
+                    let _ = event_tx_clone.send(TrayEvent::SendToPeer(addr.clone()));
+                })
+                .map_err(|e| anyhow!("failed to add send-to menu item: {}", e))?;
+            }
+        }
+
+        // 剪贴板后端切换（托盘库不支持子菜单，以标题加一组菜单项的形式呈现）
+        #[cfg(target_os = "linux")]
+        {
+            use crate::config::DisplayBackend;
+
+            tray.add_label("剪贴板后端")
+                .map_err(|e| anyhow!("failed to add backend label: {}", e))?;
+            for (label, backend) in [
+                ("    自动检测", DisplayBackend::Auto),
+                ("    X11", DisplayBackend::X11),
+                ("    Wayland", DisplayBackend::Wayland),
+            ] {
+                let event_tx_clone = event_tx.clone();
+                tray.add_menu_item(label, move || {
+                    tracing::info!("Clipboard backend menu item clicked: {:?}", backend);
+                    let _ = event_tx_clone.send(TrayEvent::SetDisplayBackend(backend));
+                })
+                .map_err(|e| anyhow!("failed to add backend menu item: {}", e))?;
+            }
+        }
+
         let shutdown_clone = Arc::clone(&shutdown);
         let event_tx_clone = event_tx.clone();
         tray.add_menu_item("Quit", move || {
@@ -257,6 +347,51 @@ impl TrayManager {
     }
 }
 
+/// 根据托盘初始化结果决定运行方式：成功返回托盘；失败时若托盘为必需则返回错误，
+/// 否则记录警告并返回 `None`，由调用方以无托盘模式继续运行。
+pub fn tray_or_headless<T>(init: Result<T>, require_tray: bool) -> Result<Option<T>> {
+    match init {
+        Ok(tray) => Ok(Some(tray)),
+        Err(e) if require_tray => Err(e.context("system tray is required (require_tray = true)")),
+        Err(e) => {
+            tracing::warn!("system tray unavailable ({e:#}), continuing without tray");
+            Ok(None)
+        }
+    }
+}
+
+/// 打开配置窗口的方式。
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigUiLaunch {
+    /// 以 `--config-ui` 参数启动当前可执行文件的子进程
+    Subprocess(std::path::PathBuf),
+    /// 在本进程的独立线程中运行配置窗口
+    InProcess,
+    /// 无法打开：窗口事件循环每个进程只能创建一次，进程内窗口已打开过
+    Unavailable,
+}
+
+/// 根据可执行文件路径的解析结果选择配置窗口的打开方式：优先使用子进程，
+/// 无法获取路径时回退为进程内运行（每个进程仅限一次）。
+pub fn config_ui_launch(
+    exe: std::io::Result<std::path::PathBuf>,
+    in_process_used: bool,
+) -> ConfigUiLaunch {
+    match exe {
+        Ok(path) => ConfigUiLaunch::Subprocess(path),
+        Err(e) if !in_process_used => {
+            tracing::warn!("cannot resolve current executable ({e}), opening config UI in-process");
+            ConfigUiLaunch::InProcess
+        }
+        Err(e) => {
+            tracing::error!(
+                "cannot resolve current executable ({e}), in-process config UI was already used"
+            );
+            ConfigUiLaunch::Unavailable
+        }
+    }
+}
+
 /// 将配置文件所在目录的路径复制到剪贴板。
 fn copy_config_dir_to_clipboard(config_path: &std::path::Path) {
     tracing::debug!("config file path: {}", config_path.display());
@@ -278,3 +413,90 @@ fn copy_config_dir_to_clipboard(config_path: &std::path::Path) {
         tracing::error!("config file has no parent directory");
     }
 }
+
+/// 根据当前配置生成配对字符串并复制到剪贴板，供新设备通过 `--pair` 导入。
+fn copy_pairing_string_to_clipboard(config_path: &std::path::Path) {
+    let config = match crate::config::AppConfig::load(config_path.to_path_buf()) {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::error!("failed to load config for pairing string: {}", e);
+            return;
+        }
+    };
+    let info = match crate::pairing::PairingInfo::from_config(&config) {
+        Ok(info) => info,
+        Err(e) => {
+            tracing::error!("failed to build pairing string: {}", e);
+            return;
+        }
+    };
+    match crate::clipboard::write_text_to_clipboard(&info.encode()) {
+        Ok(()) => tracing::info!("pairing string copied to clipboard"),
+        Err(e) => tracing::error!("failed to copy pairing string to clipboard: {}", e),
+    }
+}
+
+/// 在系统文件管理器中打开接收文件目录（不存在时先创建）。
+fn open_download_dir(config_path: &std::path::Path) {
+    let config = crate::config::AppConfig::load(config_path.to_path_buf()).unwrap_or_else(|e| {
+        tracing::warn!("failed to load config, using default download dir: {}", e);
+        crate::config::AppConfig::default()
+    });
+    let dir = config.download_dir();
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+        tracing::error!("failed to create download dir {}: {}", dir.display(), e);
+        return;
+    }
+
+    #[cfg(target_os = "linux")]
+    let opener = "xdg-open";
+    #[cfg(target_os = "windows")]
+    let opener = "explorer";
+    #[cfg(target_os = "macos")]
+    let opener = "open";
+
+    tracing::info!("opening download dir: {}", dir.display());
+    if let Err(e) = std::process::Command::new(opener).arg(&dir).spawn() {
+        tracing::error!("failed to launch {}: {}", opener, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tray_init_failure_falls_back_to_headless() {
+        let failed = || Err::<(), _>(anyhow!("ksni: no StatusNotifierWatcher"));
+        assert!(tray_or_headless(failed(), false).unwrap().is_none());
+        assert!(tray_or_headless(failed(), true).is_err());
+        assert_eq!(tray_or_headless(Ok(1), true).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn click_action_maps_to_tray_event() {
+        use crate::config::{AppConfig, TrayClickAction};
+
+        let action = |value: &str| {
+            serde_json::from_str::<TrayClickAction>(&format!("\"{value}\"")).unwrap()
+        };
+        assert_eq!(click_event(action("none")), None);
+        assert_eq!(click_event(action("open_config")), Some(TrayEvent::OpenConfigUI));
+        assert_eq!(click_event(action("toggle_pause")), Some(TrayEvent::TogglePause));
+        assert_eq!(click_event(action("sync_now")), Some(TrayEvent::SyncNow));
+        assert_eq!(click_event(AppConfig::default().tray_click_action), None);
+        assert!(serde_json::from_str::<TrayClickAction>("\"double\"").is_err());
+    }
+
+    #[test]
+    fn config_ui_falls_back_to_in_process_without_exe() {
+        let exe = || Ok(std::path::PathBuf::from("/usr/bin/lan-clipboard-sync"));
+        let missing = || Err(std::io::Error::new(std::io::ErrorKind::NotFound, "no /proc"));
+        assert_eq!(
+            config_ui_launch(exe(), true),
+            ConfigUiLaunch::Subprocess("/usr/bin/lan-clipboard-sync".into())
+        );
+        assert_eq!(config_ui_launch(missing(), false), ConfigUiLaunch::InProcess);
+        assert_eq!(config_ui_launch(missing(), true), ConfigUiLaunch::Unavailable);
+    }
+}
@@ -0,0 +1,81 @@
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use lan_clipboard_sync::protocol::{ContentType, ProtocolMessage, Selection};
+use lan_clipboard_sync::{broadcast_to_peers, AppConfig, ClipboardBackendKind, PeerConfig};
+
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[tokio::test]
+async fn events_mode_prints_received_message_as_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("config.toml");
+    let port = free_port();
+    let receiver = AppConfig {
+        listen_port: port,
+        clipboard_backend: ClipboardBackendKind::Memory,
+        notifications: false,
+        ..AppConfig::default()
+    };
+    receiver.save(&config_path).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lan-clipboard-sync"))
+        .arg("--config")
+        .arg(&config_path)
+        .arg("events")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let (line_tx, line_rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            let _ = line_tx.send(line);
+        }
+    });
+
+    let sender = AppConfig {
+        peers: vec![PeerConfig::new("127.0.0.1", port)],
+        ..AppConfig::default()
+    };
+    let msg = ProtocolMessage::ClipboardUpdate {
+        sender_id: [9u8; 16],
+        content_type: ContentType::Text,
+        payload_size: 5,
+        payload: b"hello".to_vec(),
+        seq: 1,
+        selection: Selection::Clipboard,
+        source_app: None,
+        clock: 0,
+        ttl_secs: 0,
+        sensitive: false,
+    };
+    // 等待子进程开始监听
+    let mut delivered = false;
+    for _ in 0..50 {
+        if broadcast_to_peers(&sender, &msg).await.unwrap()[0].is_ok() {
+            delivered = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let line = line_rx.recv_timeout(Duration::from_secs(5));
+    let _ = child.kill();
+    let _ = child.wait();
+    assert!(delivered, "events process never accepted the message");
+
+    let value: serde_json::Value = serde_json::from_str(&line.unwrap()).unwrap();
+    assert_eq!(value["direction"], "inbound");
+    assert_eq!(value["type"], "text");
+    assert_eq!(value["size"], 5);
+    assert_eq!(value["sender"], "127.0.0.1");
+    assert!(value["timestamp"].is_string());
+}
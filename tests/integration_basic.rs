@@ -1,4 +1,4 @@
-use lan_clipboard_sync::protocol::{ContentType, ProtocolMessage};
+use lan_clipboard_sync::protocol::{ContentType, ProtocolMessage, Selection};
 use lan_clipboard_sync::protocol::{decode_message, encode_message};
 
 #[test]
@@ -8,6 +8,12 @@ fn protocol_roundtrip_text() {
         content_type: ContentType::Text,
         payload_size: 5,
         payload: b"hello".to_vec(),
+        seq: 1,
+        selection: Selection::Clipboard,
+        source_app: None,
+        clock: 0,
+        ttl_secs: 0,
+        sensitive: false,
     };
     let bytes = encode_message(&msg).unwrap();
     let decoded = decode_message(&bytes).unwrap();
@@ -17,11 +23,13 @@ fn protocol_roundtrip_text() {
             content_type,
             payload_size,
             payload,
+            ..
         } => {
             assert!(matches!(content_type, ContentType::Text));
             assert_eq!(payload_size, 5);
             assert_eq!(payload, b"hello");
         }
+        other => panic!("unexpected message {:?}", other),
     }
 }
 
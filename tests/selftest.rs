@@ -0,0 +1,4 @@
+#[tokio::test]
+async fn selftest_roundtrip_passes() {
+    lan_clipboard_sync::run_selftest().await.unwrap();
+}
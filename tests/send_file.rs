@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use lan_clipboard_sync::{
+    send_files, AppConfig, ClipboardItem, CoreService, MemoryClipboard, PeerConfig,
+};
+
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[tokio::test]
+async fn send_file_reaches_loopback_peer() {
+    let download = tempfile::tempdir().unwrap();
+    let port = free_port();
+    let receiver = AppConfig {
+        listen_port: port,
+        download_dir: Some(download.path().to_path_buf()),
+        ..AppConfig::default()
+    };
+    let memory = MemoryClipboard::new();
+    let mut core = CoreService::with_backend(receiver, Box::new(memory.clone())).unwrap();
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let _ = rt.block_on(core.run());
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let source = tempfile::tempdir().unwrap();
+    let path = source.path().join("report.txt");
+    std::fs::write(&path, b"quarterly numbers").unwrap();
+    let sender = AppConfig {
+        peers: vec![PeerConfig::new("127.0.0.1", port)],
+        ..AppConfig::default()
+    };
+    let results = send_files(&sender, &[path]).await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert!(results[0].is_ok(), "{:?}", results[0].outcome);
+
+    let files = tokio::time::timeout(Duration::from_secs(3), async {
+        loop {
+            if let Some(ClipboardItem::Files(files)) = memory.get() {
+                return files;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(files.len(), 1);
+    assert!(files[0].path.ends_with("report.txt"));
+    assert_eq!(std::fs::read(&files[0].path).unwrap(), b"quarterly numbers");
+
+    // 没有可读取的文件时报错，不发送空消息
+    let missing = source.path().join("missing.txt");
+    assert!(send_files(&sender, &[missing]).await.is_err());
+}